@@ -5,4 +5,6 @@
 #[allow(clippy::unreadable_literal)]
 pub mod codecs;
 
+pub mod test;
+
 extern crate nihav_core;
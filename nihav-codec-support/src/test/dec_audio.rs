@@ -0,0 +1,53 @@
+//! Routines for testing audio decoders.
+use nihav_core::codecs::*;
+use super::md5::MD5;
+use super::dec_video::read_raw_packets;
+
+fn update_samples<T: Clone + Copy, F: Fn(T, &mut MD5)>(md5: &mut MD5, abuf: &NAAudioBuffer<T>, hash_sample: F) {
+    let channels = usize::from(abuf.get_info().get_channels());
+    let step = abuf.get_step();
+    let data = abuf.get_data();
+    for i in 0..abuf.get_length() {
+        for ch in 0..channels {
+            hash_sample(data[abuf.get_offset(ch) + i * step], md5);
+        }
+    }
+}
+
+/// Feeds decoded audio samples into the hash.
+///
+/// Samples are hashed in interleaved order as little-endian values so the result does not depend on the buffer layout.
+/// Packed audio is hashed as is. Frames without audio data are ignored.
+pub fn update_audio_md5(md5: &mut MD5, buf: &NABufferType) {
+    match buf {
+        NABufferType::None => {},
+        NABufferType::AudioU8(ref abuf) => update_samples(md5, abuf, |smp, md5| md5.update_hash(&[smp])),
+        NABufferType::AudioI16(ref abuf) => update_samples(md5, abuf, |smp, md5| md5.update_hash(&smp.to_le_bytes())),
+        NABufferType::AudioF32(ref abuf) => update_samples(md5, abuf, |smp, md5| md5.update_hash(&smp.to_bits().to_le_bytes())),
+        NABufferType::AudioPacked(ref abuf) => {
+            let size = abuf.get_info().get_format().get_audio_size((abuf.get_length() * usize::from(abuf.get_info().get_channels())) as u64);
+            md5.update_hash(&abuf.get_data()[..size]);
+        },
+        _ => panic!("audio buffer expected"),
+    }
+}
+
+/// Decodes raw packet stream with the provided function and compares the hash of all decoded samples with the expected one.
+///
+/// On mismatch the actual hash is printed so it can be easily pasted into the test.
+pub fn test_decoding_audio_md5<F>(name: &str, mut decode: F, expected: &[u32; 4])
+        where F: FnMut(&[u8]) -> DecoderResult<NABufferType> {
+    let packets = read_raw_packets(name);
+    let mut md5 = MD5::new();
+    for (i, pkt) in packets.iter().enumerate() {
+        let buf = decode(pkt).unwrap_or_else(|err| panic!("{}: error {:?} decoding frame {}", name, err, i));
+        update_audio_md5(&mut md5, &buf);
+    }
+    md5.finish();
+    let mut hash = [0; 4];
+    md5.get_hash(&mut hash);
+    if &hash != expected {
+        println!("{} audio hash: [0x{:08x}, 0x{:08x}, 0x{:08x}, 0x{:08x}]", name, hash[0], hash[1], hash[2], hash[3]);
+    }
+    assert_eq!(&hash, expected, "{}: decoded audio differs", name);
+}
@@ -0,0 +1,99 @@
+//! Routines for testing video decoders.
+use std::fs::File;
+use std::io::Read;
+use nihav_core::codecs::*;
+use super::md5::MD5;
+
+/// Reads the whole stream of packets stored each with 32-bit big-endian size prefix.
+///
+/// This is the simplest container possible and it is used for the test fixtures.
+pub fn read_raw_packets(name: &str) -> Vec<Vec<u8>> {
+    let mut file = File::open(name).unwrap_or_else(|_| panic!("cannot open {}", name));
+    let mut data = Vec::new();
+    file.read_to_end(&mut data).unwrap();
+    let mut packets = Vec::new();
+    let mut pos = 0;
+    while pos + 4 <= data.len() {
+        let size = u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]) as usize;
+        pos += 4;
+        assert!(pos + size <= data.len(), "truncated packet in {}", name);
+        packets.push(data[pos..][..size].to_vec());
+        pos += size;
+    }
+    assert_eq!(pos, data.len(), "trailing garbage in {}", name);
+    packets
+}
+
+/// Calculates MD5 hash for every component of the decoded picture.
+///
+/// Only the visible part of each component is hashed so the result does not depend on the buffer alignment and strides.
+/// Packed pictures are hashed as a single plane.
+/// Frames without picture (e.g. the ones that are not shown) produce no hashes.
+pub fn frame_plane_md5(buf: &NABufferType) -> Vec<[u32; 4]> {
+    if let NABufferType::None = buf {
+        return Vec::new();
+    }
+    let vbuf = if let Some(vbuf) = buf.get_vbuf() { vbuf } else { panic!("8-bit video buffer expected"); };
+    let data = vbuf.get_data();
+    if let NABufferType::VideoPacked(_) = buf {
+        let vinfo = vbuf.get_info();
+        let line_size = vinfo.get_width() * usize::from(vinfo.get_format().get_elem_size());
+        let mut md5 = MD5::new();
+        for line in data[vbuf.get_offset(0)..].chunks(vbuf.get_stride(0)).take(vinfo.get_height()) {
+            md5.update_hash(&line[..line_size]);
+        }
+        md5.finish();
+        let mut hash = [0; 4];
+        md5.get_hash(&mut hash);
+        return vec![hash];
+    }
+    let mut hashes = Vec::with_capacity(vbuf.get_num_components());
+    for comp in 0..vbuf.get_num_components() {
+        let (w, h) = vbuf.get_dimensions(comp);
+        let stride = vbuf.get_stride(comp);
+        let mut md5 = MD5::new();
+        for line in data[vbuf.get_offset(comp)..].chunks(stride).take(h) {
+            md5.update_hash(&line[..w]);
+        }
+        md5.finish();
+        let mut hash = [0; 4];
+        md5.get_hash(&mut hash);
+        hashes.push(hash);
+    }
+    hashes
+}
+
+/// Decodes raw packet stream with the provided function and compares per-component hashes of the output frames.
+///
+/// On mismatch the actual hashes for the whole stream are printed so they can be easily pasted into the test.
+pub fn test_decoding_md5<F>(name: &str, mut decode: F, expected: &[Vec<[u32; 4]>])
+        where F: FnMut(&[u8]) -> DecoderResult<NABufferType> {
+    let packets = read_raw_packets(name);
+    let mut hashes = Vec::with_capacity(packets.len());
+    for (i, pkt) in packets.iter().enumerate() {
+        let buf = decode(pkt).unwrap_or_else(|err| panic!("{}: error {:?} decoding frame {}", name, err, i));
+        hashes.push(frame_plane_md5(&buf));
+    }
+    check_frame_hashes(name, &hashes, expected);
+}
+
+/// Compares per-component frame hashes with the expected ones.
+///
+/// On mismatch the actual hashes are printed in the same way as by `test_decoding_md5()`.
+pub fn check_frame_hashes(name: &str, hashes: &[Vec<[u32; 4]>], expected: &[Vec<[u32; 4]>]) {
+    if hashes != expected {
+        println!("{} frame hashes:", name);
+        for frame in hashes.iter() {
+            print!("        vec![");
+            for (i, hash) in frame.iter().enumerate() {
+                if i > 0 { print!(", "); }
+                print!("[0x{:08x}, 0x{:08x}, 0x{:08x}, 0x{:08x}]", hash[0], hash[1], hash[2], hash[3]);
+            }
+            println!("],");
+        }
+        for (i, (actual, exp)) in hashes.iter().zip(expected.iter()).enumerate() {
+            assert_eq!(actual, exp, "{}: frame {} hashes differ", name, i);
+        }
+        assert_eq!(hashes.len(), expected.len(), "{}: number of decoded frames differs", name);
+    }
+}
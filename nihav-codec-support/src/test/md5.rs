@@ -0,0 +1,152 @@
+//! MD5 hash calculation.
+//!
+//! # Examples
+//!
+//! ```
+//! use nihav_codec_support::test::md5::MD5;
+//!
+//! let mut hash = [0u32; 4];
+//! MD5::calculate_hash(b"abc", &mut hash);
+//! assert_eq!(hash, [0x90015098, 0x3cd24fb0, 0xd6963f7d, 0x28e17f72]);
+//! ```
+
+const MD5_SHIFTS: [u8; 64] = [
+    7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22,
+    5,  9, 14, 20, 5,  9, 14, 20, 5,  9, 14, 20, 5,  9, 14, 20,
+    4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23,
+    6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21
+];
+
+const MD5_K: [u32; 64] = [
+    0xD76AA478, 0xE8C7B756, 0x242070DB, 0xC1BDCEEE, 0xF57C0FAF, 0x4787C62A, 0xA8304613, 0xFD469501,
+    0x698098D8, 0x8B44F7AF, 0xFFFF5BB1, 0x895CD7BE, 0x6B901122, 0xFD987193, 0xA679438E, 0x49B40821,
+    0xF61E2562, 0xC040B340, 0x265E5A51, 0xE9B6C7AA, 0xD62F105D, 0x02441453, 0xD8A1E681, 0xE7D3FBC8,
+    0x21E1CDE6, 0xC33707D6, 0xF4D50D87, 0x455A14ED, 0xA9E3E905, 0xFCEFA3F8, 0x676F02D9, 0x8D2A4C8A,
+    0xFFFA3942, 0x8771F681, 0x6D9D6122, 0xFDE5380C, 0xA4BEEA44, 0x4BDECFA9, 0xF6BB4B60, 0xBEBFBC70,
+    0x289B7EC6, 0xEAA127FA, 0xD4EF3085, 0x04881D05, 0xD9D4D039, 0xE6DB99E5, 0x1FA27CF8, 0xC4AC5665,
+    0xF4292244, 0x432AFF97, 0xAB9423A7, 0xFC93A039, 0x655B59C3, 0x8F0CCC92, 0xFFEFF47D, 0x85845DD1,
+    0x6FA87E4F, 0xFE2CE6E0, 0xA3014314, 0x4E0811A1, 0xF7537E82, 0xBD3AF235, 0x2AD7D2BB, 0xEB86D391
+];
+
+const INITIAL_MD5_HASH: [u32; 4] = [ 0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476 ];
+
+fn box0(b: u32, c: u32, d: u32) -> u32 { (b & c) | (!b & d) }
+fn box1(b: u32, c: u32, d: u32) -> u32 { (d & b) | (!d & c) }
+fn box2(b: u32, c: u32, d: u32) -> u32 { b ^ c ^ d }
+fn box3(b: u32, c: u32, d: u32) -> u32 { c ^ (b | !d) }
+
+/// MD5 hash calculator.
+#[derive(Clone)]
+pub struct MD5 {
+    hash:               [u32; 4],
+    inwords:            [u32; 16],
+    buf:                [u8; 64],
+    pos:                usize,
+    count:              usize,
+}
+
+impl Default for MD5 {
+    fn default() -> Self { Self::new() }
+}
+
+impl MD5 {
+    /// Constructs a new instance of `MD5`.
+    pub fn new() -> Self {
+        Self {
+            hash:       INITIAL_MD5_HASH,
+            inwords:    [0; 16],
+            buf:        [0; 64],
+            pos:        0,
+            count:      0,
+        }
+    }
+    fn calc_one_block(&mut self) {
+        let mut a = self.hash[0];
+        let mut b = self.hash[1];
+        let mut c = self.hash[2];
+        let mut d = self.hash[3];
+
+        for (dst, src) in self.inwords.iter_mut().zip(self.buf.chunks(4)) {
+            *dst = u32::from_le_bytes([src[0], src[1], src[2], src[3]]);
+        }
+
+        for k in 0..64 {
+            let (f, g) = match k >> 4 {
+                    0 => (box0(b, c, d), k),
+                    1 => (box1(b, c, d), (5 * k + 1) & 0xF),
+                    2 => (box2(b, c, d), (3 * k + 5) & 0xF),
+                    _ => (box3(b, c, d), (7 * k) & 0xF),
+                };
+            let tmp = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(a.wrapping_add(f).wrapping_add(MD5_K[k]).wrapping_add(self.inwords[g]).rotate_left(u32::from(MD5_SHIFTS[k])));
+            a = tmp;
+        }
+        self.hash[0] = self.hash[0].wrapping_add(a);
+        self.hash[1] = self.hash[1].wrapping_add(b);
+        self.hash[2] = self.hash[2].wrapping_add(c);
+        self.hash[3] = self.hash[3].wrapping_add(d);
+
+        self.pos = 0;
+    }
+    /// Updates hash state with the provided data.
+    pub fn update_hash(&mut self, src: &[u8]) {
+        for byte in src.iter() {
+            self.buf[self.pos] = *byte;
+            self.pos += 1;
+            if self.pos == 64 {
+                self.calc_one_block();
+            }
+        }
+        self.count += src.len();
+    }
+    /// Finalises hash calculation.
+    pub fn finish(&mut self) {
+        self.buf[self.pos] = 0x80;
+        self.pos += 1;
+        if self.pos > 64 - 8 {
+            for i in self.pos..64 {
+                self.buf[i] = 0;
+            }
+            self.calc_one_block();
+        }
+        for i in self.pos..64 - 8 {
+            self.buf[i] = 0;
+        }
+        let bits = (self.count as u64) << 3;
+        self.buf[64 - 8..].copy_from_slice(&bits.to_le_bytes());
+        self.calc_one_block();
+    }
+    /// Stores the calculated hash value into output array.
+    ///
+    /// Hash words are stored in big-endian form so they match the conventional textual MD5 representation.
+    pub fn get_hash(&self, dst: &mut [u32; 4]) {
+        for (dst, src) in dst.iter_mut().zip(self.hash.iter()) {
+            *dst = src.swap_bytes();
+        }
+    }
+    /// Calculates MD5 hash for the provided data.
+    pub fn calculate_hash(src: &[u8], hash: &mut [u32; 4]) {
+        let mut md5 = Self::new();
+        md5.update_hash(src);
+        md5.finish();
+        md5.get_hash(hash);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_md5() {
+        let mut hash = [0u32; 4];
+        MD5::calculate_hash(&[], &mut hash);
+        assert_eq!(hash, [0xd41d8cd9, 0x8f00b204, 0xe9800998, 0xecf8427e]);
+        MD5::calculate_hash(b"The quick brown fox jumps over the lazy dog", &mut hash);
+        assert_eq!(hash, [0x9e107d9d, 0x372bb682, 0x6bd81d35, 0x42a419d6]);
+        MD5::calculate_hash(b"12345678901234567890123456789012345678901234567890123456789012345678901234567890", &mut hash);
+        assert_eq!(hash, [0x57edf4a2, 0x2be3c955, 0xac49da2e, 0x2107b67a]);
+    }
+}
@@ -0,0 +1,6 @@
+//! Decoder testing functionality.
+//!
+//! This module provides the means to check decoder output against known per-frame hashes.
+pub mod dec_audio;
+pub mod dec_video;
+pub mod md5;
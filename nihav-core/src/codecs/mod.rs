@@ -2,6 +2,7 @@
 pub use crate::frame::*;
 use crate::io::bitreader::BitReaderError;
 pub use std::str::FromStr;
+use std::fmt;
 
 /// A list specifying general decoding errors.
 #[derive(Debug,Clone,Copy,PartialEq)]
@@ -25,11 +26,104 @@ pub enum DecoderError {
     NotImplemented,
     /// Some bug in decoder. It should not happen yet it might.
     Bug,
+    /// Stream parameters exceed the configured [`DecoderLimits`].
+    ///
+    /// [`DecoderLimits`]: ../frame/struct.DecoderLimits.html
+    LimitExceeded,
 }
 
+impl fmt::Display for DecoderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecoderError::NoFrame           => write!(f, "no frame"),
+            DecoderError::AllocError        => write!(f, "allocation error"),
+            DecoderError::TryAgain          => write!(f, "try again"),
+            DecoderError::InvalidData       => write!(f, "invalid data"),
+            DecoderError::ChecksumError     => write!(f, "checksum error"),
+            DecoderError::ShortData         => write!(f, "short data"),
+            DecoderError::MissingReference  => write!(f, "missing reference"),
+            DecoderError::NotImplemented    => write!(f, "not implemented"),
+            DecoderError::Bug               => write!(f, "decoder bug"),
+            DecoderError::LimitExceeded     => write!(f, "decoder limits exceeded"),
+        }
+    }
+}
+
+impl std::error::Error for DecoderError {}
+
 /// A specialised `Result` type for decoding operations.
 pub type DecoderResult<T> = Result<T, DecoderError>;
 
+/// A list of decoding stages used to report where an error occurred.
+#[derive(Debug,Clone,Copy,PartialEq,Default)]
+pub enum DecoderStage {
+    /// Frame-level processing (buffer management, references and such).
+    #[default]
+    Frame,
+    /// Frame header.
+    Header,
+    /// Codec-specific stage (e.g. probability model updates) described by its name.
+    Codec(&'static str),
+    /// Macroblock data.
+    Macroblock {
+        /// Macroblock column.
+        mb_x: usize,
+        /// Macroblock row.
+        mb_y: usize,
+    },
+}
+
+impl fmt::Display for DecoderStage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecoderStage::Frame         => write!(f, "frame"),
+            DecoderStage::Header        => write!(f, "header"),
+            DecoderStage::Codec(name)   => write!(f, "{}", name),
+            DecoderStage::Macroblock { mb_x, mb_y } => write!(f, "macroblock ({}, {})", mb_x, mb_y),
+        }
+    }
+}
+
+/// Detailed information about decoding error.
+///
+/// Decoders may provide it in addition to plain [`DecoderError`] returned from the decoding functions.
+///
+/// [`DecoderError`]: ./enum.DecoderError.html
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct DecoderErrorInfo {
+    /// Error type.
+    pub error:  DecoderError,
+    /// Decoding stage where the error occurred.
+    pub stage:  DecoderStage,
+    /// Error occurred while decoding alpha plane.
+    pub alpha:  bool,
+    /// Input packet offset of the data being decoded when the error occurred (if known).
+    pub offset: Option<usize>,
+}
+
+impl fmt::Display for DecoderErrorInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} in {}", self.error, self.stage)?;
+        if self.alpha {
+            write!(f, " of alpha plane")?;
+        }
+        if let Some(offset) = self.offset {
+            write!(f, " at byte {}", offset)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for DecoderErrorInfo {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<DecoderErrorInfo> for DecoderError {
+    fn from(info: DecoderErrorInfo) -> Self { info.error }
+}
+
 impl From<BitReaderError> for DecoderError {
     fn from(e: BitReaderError) -> Self {
         match e {
@@ -40,7 +134,12 @@ impl From<BitReaderError> for DecoderError {
 }
 
 impl From<AllocatorError> for DecoderError {
-    fn from(_: AllocatorError) -> Self { DecoderError::AllocError }
+    fn from(e: AllocatorError) -> Self {
+        match e {
+            AllocatorError::LimitExceeded => DecoderError::LimitExceeded,
+            _ => DecoderError::AllocError,
+        }
+    }
 }
 
 /// Auxiliary structure for storing data used by decoder but also controlled by the caller.
@@ -51,6 +150,8 @@ pub struct NADecoderSupport {
     pub pool_u16:       NAVideoBufferPool<u16>,
     /// Frame buffer pool for 32-bit video frames.
     pub pool_u32:       NAVideoBufferPool<u32>,
+    /// Resource limits the decoder should respect.
+    pub limits:         DecoderLimits,
 }
 
 impl NADecoderSupport {
@@ -60,10 +161,124 @@ impl NADecoderSupport {
             pool_u8:        NAVideoBufferPool::new(0),
             pool_u16:       NAVideoBufferPool::new(0),
             pool_u32:       NAVideoBufferPool::new(0),
+            limits:         DecoderLimits::new(),
         }
     }
+    /// Sets the resource limits for the decoder and its frame pools.
+    pub fn set_limits(&mut self, limits: DecoderLimits) {
+        self.limits = limits;
+        self.pool_u8.set_limits(limits);
+        self.pool_u16.set_limits(limits);
+        self.pool_u32.set_limits(limits);
+    }
 }
 
 impl Default for NADecoderSupport {
     fn default() -> Self { Self::new() }
 }
+
+/// Frame skipping mode for decoders.
+#[derive(Debug,Clone,Copy,PartialEq,Default)]
+pub enum FrameSkipMode {
+    /// Decode and output all frames.
+    #[default]
+    None,
+    /// Skip inter frames that no other frame depends on.
+    ///
+    /// Decoders do not know what follows the current frame so the caller has to tell them with
+    /// [`NADecoder::set_next_is_key`] which frames are followed by a keyframe, other frames are decoded as usual.
+    ///
+    /// [`NADecoder::set_next_is_key`]: ./trait.NADecoder.html#method.set_next_is_key
+    NonReference,
+    /// Decode all frames to keep reference state but do not output them.
+    DecodeOnly,
+    /// Decode only keyframes.
+    KeyframesOnly,
+}
+
+/// Output mode for interlaced frames.
+#[derive(Debug,Clone,Copy,PartialEq,Default)]
+pub enum FieldOutputMode {
+    /// Output frames as decoded, with both fields woven together.
+    #[default]
+    Woven,
+    /// Output the fields separately: the first field (even lines) in the upper half of the picture and the second field in the lower half.
+    Fields,
+    /// Output the first field with the lines of the second field interpolated from the neighbouring lines.
+    Bob,
+    /// Blend every line with the neighbouring lines to hide combing.
+    Blend,
+}
+
+/// Decoder trait.
+pub trait NADecoder {
+    /// Initialises the decoder.
+    ///
+    /// It takes [`NADecoderSupport`] allocated by the caller and `NACodecInfoRef` describing the stream.
+    ///
+    /// [`NADecoderSupport`]: ./struct.NADecoderSupport.html
+    fn init(&mut self, supp: &mut NADecoderSupport, info: NACodecInfoRef) -> DecoderResult<()>;
+    /// Decodes a single frame.
+    ///
+    /// The output frame inherits timestamps and stream index from the input packet.
+    fn decode(&mut self, supp: &mut NADecoderSupport, pkt: &NAPacket) -> DecoderResult<NAFrameRef>;
+    /// Tells decoder to clear internal state (e.g. after error or seeking).
+    fn flush(&mut self);
+    /// Sets frame skipping mode for the following frames.
+    ///
+    /// Skipped frames are reported as frames of `FrameType::Skip` type without data.
+    /// Decoders not supporting frame skipping ignore this call.
+    fn set_skip_mode(&mut self, _mode: FrameSkipMode) {}
+    /// Tells the decoder whether the packet passed to the following `decode()` call is followed by a keyframe.
+    ///
+    /// The hint applies to that call only and it is used by [`FrameSkipMode::NonReference`] mode.
+    ///
+    /// [`FrameSkipMode::NonReference`]: ./enum.FrameSkipMode.html#variant.NonReference
+    fn set_next_is_key(&mut self, _next_is_key: bool) {}
+    /// Sets output mode for interlaced frames.
+    ///
+    /// It should be called before `init()` since the decoder may need additional frame buffers for the output.
+    /// Progressive frames are not affected and decoders not supporting it ignore this call.
+    fn set_field_mode(&mut self, _mode: FieldOutputMode) {}
+}
+
+/// Decoder information used during creating a decoder for requested codec.
+#[derive(Clone,Copy)]
+pub struct DecoderInfo {
+    /// Short decoder name.
+    pub name: &'static str,
+    /// The function that creates a decoder instance.
+    pub get_decoder: fn () -> Box<dyn NADecoder + Send>,
+}
+
+/// Structure for registering known decoders.
+///
+/// It is supposed to be filled using `register_all_decoders()` from some decoders crate and then it can be used to create decoders for the requested codecs.
+#[derive(Default)]
+pub struct RegisteredDecoders {
+    decs:   Vec<DecoderInfo>,
+}
+
+impl RegisteredDecoders {
+    /// Constructs a new instance of `RegisteredDecoders`.
+    pub fn new() -> Self {
+        Self { decs: Vec::new() }
+    }
+    /// Adds another decoder to the registry.
+    pub fn add_decoder(&mut self, dec: DecoderInfo) {
+        self.decs.push(dec);
+    }
+    /// Searches for the decoder for the provided name.
+    pub fn find_decoder(&self, name: &str) -> Option<fn () -> Box<dyn NADecoder + Send>> {
+        for &dec in self.decs.iter() {
+            if dec.name == name {
+                return Some(dec.get_decoder);
+            }
+        }
+        None
+    }
+    /// Provides an iterator over currently registered decoders.
+    pub fn iter(&self) -> std::slice::Iter<'_, DecoderInfo> {
+        self.decs.iter()
+    }
+}
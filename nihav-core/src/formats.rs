@@ -3,6 +3,7 @@
 //! NihAV does not have a fixed list of supported formats but rather accepts format definitions both for audio and video.
 //! In result exotic formats like YUV410+alpha plane that is used by Indeo 4 are supported without any additional case handing.
 //! Some common format definitions are provided as constants for convenience.
+use std::str::FromStr;
 use std::string::*;
 use std::fmt;
 
@@ -10,6 +11,314 @@ use std::fmt;
 #[derive(Clone,Copy,Debug,PartialEq)]
 pub struct FormatParseError {}
 
+/// Audio format definition.
+///
+/// The structure describes how audio samples are stored and what characteristics they have.
+#[derive(Debug,Copy,Clone,PartialEq)]
+pub struct NASoniton {
+    /// Bits per sample.
+    pub bits:       u8,
+    /// Audio format is big-endian.
+    pub be:         bool,
+    /// Audio samples are packed (e.g. 20-bit audio samples).
+    pub packed:     bool,
+    /// Audio data is stored in planar format instead of interleaving samples for different channels.
+    pub planar:     bool,
+    /// Audio data is in floating point format.
+    pub float:      bool,
+    /// Audio data is signed (usually only 8-bit audio is unsigned).
+    pub signed:     bool,
+}
+
+/// Flag for specifying that audio format is big-endian in `NASoniton::`[`new`]`()`. Related to [`be`] field of `NASoniton`.
+///
+/// [`new`]: ./struct.NASoniton.html#method.new
+/// [`be`]: ./struct.NASoniton.html#structfield.be
+pub const SONITON_FLAG_BE       :u32 = 0x01;
+/// Flag for specifying that audio format has packed samples in `NASoniton::`[`new`]`()`. Related to [`packed`] field of `NASoniton`.
+///
+/// [`new`]: ./struct.NASoniton.html#method.new
+/// [`packed`]: ./struct.NASoniton.html#structfield.packed
+pub const SONITON_FLAG_PACKED   :u32 = 0x02;
+/// Flag for specifying that audio data is stored as planar in `NASoniton::`[`new`]`()`. Related to [`planar`] field of `NASoniton`.
+///
+/// [`new`]: ./struct.NASoniton.html#method.new
+/// [`planar`]: ./struct.NASoniton.html#structfield.planar
+pub const SONITON_FLAG_PLANAR   :u32 = 0x04;
+/// Flag for specifying that audio samples are in floating point format in `NASoniton::`[`new`]`()`. Related to [`float`] field of `NASoniton`.
+///
+/// [`new`]: ./struct.NASoniton.html#method.new
+/// [`float`]: ./struct.NASoniton.html#structfield.float
+pub const SONITON_FLAG_FLOAT    :u32 = 0x08;
+/// Flag for specifying that audio format is signed in `NASoniton::`[`new`]`()`. Related to [`signed`] field of `NASoniton`.
+///
+/// [`new`]: ./struct.NASoniton.html#method.new
+/// [`signed`]: ./struct.NASoniton.html#structfield.signed
+pub const SONITON_FLAG_SIGNED   :u32 = 0x10;
+
+/// Predefined format for interleaved 8-bit unsigned audio.
+pub const SND_U8_FORMAT: NASoniton = NASoniton { bits: 8, be: false, packed: false, planar: false, float: false, signed: false };
+/// Predefined format for interleaved 16-bit signed audio.
+pub const SND_S16_FORMAT: NASoniton = NASoniton { bits: 16, be: false, packed: false, planar: false, float: false, signed: true };
+/// Predefined format for planar 16-bit signed audio.
+pub const SND_S16P_FORMAT: NASoniton = NASoniton { bits: 16, be: false, packed: false, planar: true, float: false, signed: true };
+/// Predefined format for interleaved 32-bit floating point audio.
+pub const SND_F32_FORMAT: NASoniton = NASoniton { bits: 32, be: false, packed: false, planar: false, float: true, signed: true };
+/// Predefined format for planar 32-bit floating point audio.
+pub const SND_F32P_FORMAT: NASoniton = NASoniton { bits: 32, be: false, packed: false, planar: true, float: true, signed: true };
+
+impl NASoniton {
+    /// Constructs a new audio format definition using flags like [`SONITON_FLAG_BE`].
+    ///
+    /// [`SONITON_FLAG_BE`]: ./constant.SONITON_FLAG_BE.html
+    pub fn new(bits: u8, flags: u32) -> Self {
+        let is_be = (flags & SONITON_FLAG_BE) != 0;
+        let is_pk = (flags & SONITON_FLAG_PACKED) != 0;
+        let is_pl = (flags & SONITON_FLAG_PLANAR) != 0;
+        let is_fl = (flags & SONITON_FLAG_FLOAT) != 0;
+        let is_sg = (flags & SONITON_FLAG_SIGNED) != 0;
+        NASoniton { bits, be: is_be, packed: is_pk, planar: is_pl, float: is_fl, signed: is_sg }
+    }
+
+    /// Returns the number of bits per sample.
+    pub fn get_bits(self)   -> u8   { self.bits }
+    /// Reports whether the format is big-endian.
+    pub fn is_be(self)      -> bool { self.be }
+    /// Reports whether the format has packed samples.
+    pub fn is_packed(self)  -> bool { self.packed }
+    /// Reports whether audio data is planar instead of interleaved.
+    pub fn is_planar(self)  -> bool { self.planar }
+    /// Reports whether audio samples are in floating point format.
+    pub fn is_float(self)   -> bool { self.float }
+    /// Reports whether audio samples are signed.
+    pub fn is_signed(self)  -> bool { self.signed }
+
+    /// Returns the amount of bytes needed to store the audio of requested length (in samples).
+    pub fn get_audio_size(self, length: u64) -> usize {
+        if self.packed {
+            ((length * u64::from(self.bits) + 7) >> 3) as usize
+        } else {
+            (length * u64::from((self.bits + 7) >> 3)) as usize
+        }
+    }
+}
+
+impl fmt::Display for NASoniton {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fmt = if self.float { "float" } else if self.signed { "int" } else { "uint" };
+        let end = if self.be { "BE" } else { "LE" };
+        write!(f, "({} bit {} {} packed {} planar {})", self.bits, end, fmt, self.packed, self.planar)
+    }
+}
+
+/// Known channel types.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum NAChannelType {
+    /// Center
+    C,
+    /// Left
+    L,
+    /// Right
+    R,
+    /// Center surround
+    Cs,
+    /// Left surround
+    Ls,
+    /// Right surround
+    Rs,
+    /// Left side surround
+    Lss,
+    /// Right side surround
+    Rss,
+    /// Low Frequency Effect
+    LFE,
+    /// Left center
+    Lc,
+    /// Right center
+    Rc,
+    /// Left height
+    Lh,
+    /// Right height
+    Rh,
+    /// Center height
+    Ch,
+    /// Second Low Frequency Effect
+    LFE2,
+    /// Left wide
+    Lw,
+    /// Right wide
+    Rw,
+    /// Overhead
+    Ov,
+    /// Left height side
+    Lhs,
+    /// Right height side
+    Rhs,
+    /// Center height side
+    Chs,
+    /// Left lower
+    Ll,
+    /// Right lower
+    Rl,
+    /// Center lower
+    Cl,
+    /// Left total
+    Lt,
+    /// Right total
+    Rt,
+    /// Left-only downmix
+    Lo,
+    /// Right-only downmix
+    Ro,
+}
+
+impl NAChannelType {
+    /// Reports whether this is some center channel.
+    pub fn is_center(self) -> bool {
+        matches!(self, NAChannelType::C | NAChannelType::Ch | NAChannelType::Cl | NAChannelType::Ov | NAChannelType::LFE | NAChannelType::LFE2 | NAChannelType::Cs | NAChannelType::Chs)
+    }
+    /// Reports whether this is some left channel.
+    pub fn is_left(self) -> bool {
+        matches!(self, NAChannelType::L | NAChannelType::Ls | NAChannelType::Lss | NAChannelType::Lc | NAChannelType::Lh | NAChannelType::Lw | NAChannelType::Lhs | NAChannelType::Ll | NAChannelType::Lt | NAChannelType::Lo)
+    }
+    /// Reports whether this is some right channel.
+    pub fn is_right(self) -> bool {
+        matches!(self, NAChannelType::R | NAChannelType::Rs | NAChannelType::Rss | NAChannelType::Rc | NAChannelType::Rh | NAChannelType::Rw | NAChannelType::Rhs | NAChannelType::Rl | NAChannelType::Rt | NAChannelType::Ro)
+    }
+}
+
+const CHANNEL_NAMES: [(&str, NAChannelType); 28] = [
+    ("C",    NAChannelType::C),
+    ("L",    NAChannelType::L),
+    ("R",    NAChannelType::R),
+    ("Cs",   NAChannelType::Cs),
+    ("Ls",   NAChannelType::Ls),
+    ("Rs",   NAChannelType::Rs),
+    ("Lss",  NAChannelType::Lss),
+    ("Rss",  NAChannelType::Rss),
+    ("LFE",  NAChannelType::LFE),
+    ("Lc",   NAChannelType::Lc),
+    ("Rc",   NAChannelType::Rc),
+    ("Lh",   NAChannelType::Lh),
+    ("Rh",   NAChannelType::Rh),
+    ("Ch",   NAChannelType::Ch),
+    ("LFE2", NAChannelType::LFE2),
+    ("Lw",   NAChannelType::Lw),
+    ("Rw",   NAChannelType::Rw),
+    ("Ov",   NAChannelType::Ov),
+    ("Lhs",  NAChannelType::Lhs),
+    ("Rhs",  NAChannelType::Rhs),
+    ("Chs",  NAChannelType::Chs),
+    ("Ll",   NAChannelType::Ll),
+    ("Rl",   NAChannelType::Rl),
+    ("Cl",   NAChannelType::Cl),
+    ("Lt",   NAChannelType::Lt),
+    ("Rt",   NAChannelType::Rt),
+    ("Lo",   NAChannelType::Lo),
+    ("Ro",   NAChannelType::Ro),
+];
+
+impl FromStr for NAChannelType {
+    type Err = FormatParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        for (name, ch) in CHANNEL_NAMES.iter() {
+            if *name == s {
+                return Ok(*ch);
+            }
+        }
+        Err(FormatParseError{})
+    }
+}
+
+impl fmt::Display for NAChannelType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = CHANNEL_NAMES.iter().find(|(_, ch)| ch == self).map(|(name, _)| *name).unwrap_or("?");
+        write!(f, "{}", name)
+    }
+}
+
+/// Channel map.
+///
+/// This is essentially an ordered sequence of channels.
+#[derive(Clone,Default)]
+pub struct NAChannelMap {
+    ids:    Vec<NAChannelType>,
+}
+
+/// Channel order used by WAVEFORMATEXTENSIBLE channel mask bits.
+const MS_CHANNEL_MAP: [NAChannelType; 11] = [
+    NAChannelType::L,
+    NAChannelType::R,
+    NAChannelType::C,
+    NAChannelType::LFE,
+    NAChannelType::Ls,
+    NAChannelType::Rs,
+    NAChannelType::Lss,
+    NAChannelType::Rss,
+    NAChannelType::Cs,
+    NAChannelType::Lc,
+    NAChannelType::Rc,
+];
+
+impl NAChannelMap {
+    /// Constructs a new `NAChannelMap` instance.
+    pub fn new() -> Self { NAChannelMap { ids: Vec::new() } }
+    /// Adds a new channel to the map.
+    pub fn add_channel(&mut self, ch: NAChannelType) {
+        self.ids.push(ch);
+    }
+    /// Adds several channels to the map at once.
+    pub fn add_channels(&mut self, chs: &[NAChannelType]) {
+        self.ids.extend_from_slice(chs);
+    }
+    /// Returns the total number of channels.
+    pub fn num_channels(&self) -> usize {
+        self.ids.len()
+    }
+    /// Reports channel type for a requested index.
+    pub fn get_channel(&self, idx: usize) -> NAChannelType {
+        self.ids[idx]
+    }
+    /// Tries to find position of the channel with requested type.
+    pub fn find_channel_id(&self, t: NAChannelType) -> Option<u8> {
+        self.ids.iter().position(|&ch| ch == t).map(|pos| pos as u8)
+    }
+    /// Creates a new `NAChannelMap` using the channel mapping flags from WAVE format.
+    pub fn from_ms_mapping(chmap: u32) -> Self {
+        let mut cm = NAChannelMap::new();
+        for (i, ch) in MS_CHANNEL_MAP.iter().enumerate() {
+            if ((chmap >> i) & 1) != 0 {
+                cm.add_channel(*ch);
+            }
+        }
+        cm
+    }
+}
+
+impl fmt::Display for NAChannelMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut map = String::new();
+        for el in self.ids.iter() {
+            if !map.is_empty() { map.push(','); }
+            map.push_str(&el.to_string());
+        }
+        write!(f, "{}", map)
+    }
+}
+
+impl FromStr for NAChannelMap {
+    type Err = FormatParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chm = NAChannelMap::new();
+        for tok in s.split(',') {
+            chm.add_channel(NAChannelType::from_str(tok)?);
+        }
+        Ok(chm)
+    }
+}
+
 /// A list of RGB colour model variants.
 #[derive(Debug,Clone,Copy,PartialEq)]
 pub enum RGBSubmodel {
@@ -403,7 +712,7 @@ impl NAPixelFormaton {
                                 if planar {
                                     start_off += 1;
                                 } else {
-                                    start_off += (comp.depth + 7) / 8;
+                                    start_off += comp.depth.div_ceil(8);
                                 }
                             }
                         }
@@ -503,6 +812,16 @@ mod test {
 
     #[test]
     fn test_fmt() {
+        println!("soniton s16- {}", SND_S16_FORMAT);
+        assert_eq!(NASoniton::new(16, SONITON_FLAG_SIGNED | SONITON_FLAG_PLANAR), SND_S16P_FORMAT);
+        assert_eq!(SND_S16_FORMAT.get_audio_size(3), 6);
+        assert_eq!(NASoniton::new(20, SONITON_FLAG_PACKED).get_audio_size(3), 8);
+        assert_eq!(NASoniton::new(32, SONITON_FLAG_FLOAT | SONITON_FLAG_SIGNED), SND_F32_FORMAT);
+        let chmap = NAChannelMap::from_str("L,R,C,LFE,Ls,Rs").unwrap();
+        assert_eq!(chmap.num_channels(), 6);
+        assert_eq!(chmap.find_channel_id(NAChannelType::LFE), Some(3));
+        assert_eq!(chmap.to_string(), NAChannelMap::from_ms_mapping(0x3F).to_string());
+        assert!(NAChannelMap::from_str("L,X").is_err());
         println!("formaton yuv- {}", YUV420_FORMAT);
         println!("formaton pal- {}", PAL8_FORMAT);
         println!("formaton rgb565- {}", RGB565_FORMAT);
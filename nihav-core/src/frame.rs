@@ -6,6 +6,41 @@ pub use std::sync::Arc;
 pub use crate::formats::*;
 pub use crate::refs::*;
 
+/// Audio stream information.
+#[allow(dead_code)]
+#[derive(Clone,Copy,PartialEq)]
+pub struct NAAudioInfo {
+    /// Sample rate.
+    pub sample_rate:    u32,
+    /// Number of channels.
+    pub channels:       u8,
+    /// Audio sample format.
+    pub format:         NASoniton,
+    /// Length of one audio block in samples.
+    pub block_len:      usize,
+}
+
+impl NAAudioInfo {
+    /// Constructs a new `NAAudioInfo` instance.
+    pub fn new(sr: u32, ch: u8, fmt: NASoniton, bl: usize) -> Self {
+        NAAudioInfo { sample_rate: sr, channels: ch, format: fmt, block_len: bl }
+    }
+    /// Returns audio sample rate.
+    pub fn get_sample_rate(&self) -> u32 { self.sample_rate }
+    /// Returns the number of channels.
+    pub fn get_channels(&self) -> u8 { self.channels }
+    /// Returns sample format.
+    pub fn get_format(&self) -> NASoniton { self.format }
+    /// Returns one audio block duration in samples.
+    pub fn get_block_len(&self) -> usize { self.block_len }
+}
+
+impl fmt::Display for NAAudioInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} Hz, {} ch", self.sample_rate, self.channels)
+    }
+}
+
 /// Video stream information.
 #[allow(dead_code)]
 #[derive(Clone,Copy,PartialEq)]
@@ -53,6 +88,8 @@ impl fmt::Display for NAVideoInfo {
 pub enum NACodecTypeInfo {
     /// No codec present.
     None,
+    /// Audio codec information.
+    Audio(NAAudioInfo),
     /// Video codec information.
     Video(NAVideoInfo),
 }
@@ -65,16 +102,28 @@ impl NACodecTypeInfo {
             _ => None,
         }
     }
+    /// Returns audio stream information.
+    pub fn get_audio_info(&self) -> Option<NAAudioInfo> {
+        match *self {
+            NACodecTypeInfo::Audio(ainfo) => Some(ainfo),
+            _ => None,
+        }
+    }
     /// Reports whether the current stream is video stream.
     pub fn is_video(&self) -> bool {
         matches!(*self, NACodecTypeInfo::Video(_))
     }
+    /// Reports whether the current stream is audio stream.
+    pub fn is_audio(&self) -> bool {
+        matches!(*self, NACodecTypeInfo::Audio(_))
+    }
 }
 
 impl fmt::Display for NACodecTypeInfo {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let ret = match *self {
             NACodecTypeInfo::None       => "".to_string(),
+            NACodecTypeInfo::Audio(fmt) => format!("{}", fmt),
             NACodecTypeInfo::Video(fmt) => format!("{}", fmt),
         };
         write!(f, "{}", ret)
@@ -154,6 +203,63 @@ impl<T: Clone> NAVideoBuffer<T> {
 /// A specialised type for reference-counted `NAVideoBuffer`.
 pub type NAVideoBufferRef<T> = NABufferRef<NAVideoBuffer<T>>;
 
+/// Decoded audio frame.
+///
+/// NihAV frames are stored in native type (8/16/32-bit elements) inside a single buffer.
+/// In case of planar audio samples for each channel are stored sequentially and can be accessed in the buffer starting at corresponding channel offset.
+#[derive(Clone)]
+pub struct NAAudioBuffer<T> {
+    info:   NAAudioInfo,
+    data:   NABufferRef<Vec<T>>,
+    offs:   Vec<usize>,
+    chmap:  NAChannelMap,
+    stride: usize,
+    step:   usize,
+    len:    usize,
+}
+
+impl<T: Clone> NAAudioBuffer<T> {
+    /// Returns the start position of requested channel data.
+    pub fn get_offset(&self, idx: usize) -> usize {
+        if idx >= self.offs.len() { 0 }
+        else { self.offs[idx] }
+    }
+    /// Returns the distance between the start of one channel and the next one.
+    pub fn get_stride(&self) -> usize { self.stride }
+    /// Returns the distance between the samples in one channel.
+    pub fn get_step(&self) -> usize { self.step }
+    /// Returns audio format information.
+    pub fn get_info(&self) -> NAAudioInfo { self.info }
+    /// Returns channel map.
+    pub fn get_chmap(&self) -> &NAChannelMap { &self.chmap }
+    /// Returns an immutable reference to the data.
+    pub fn get_data(&self) -> &Vec<T> { self.data.as_ref() }
+    /// Returns reference to the data.
+    pub fn get_data_ref(&self) -> NABufferRef<Vec<T>> { self.data.clone() }
+    /// Returns a mutable reference to the data.
+    pub fn get_data_mut(&mut self) -> Option<&mut Vec<T>> { self.data.as_mut() }
+    /// Clones current `NAAudioBuffer` into a new one.
+    pub fn copy_buffer(&self) -> Self {
+        let mut data: Vec<T> = Vec::with_capacity(self.data.len());
+        data.clone_from(self.data.as_ref());
+        let mut offs: Vec<usize> = Vec::with_capacity(self.offs.len());
+        offs.clone_from(&self.offs);
+        NAAudioBuffer { info: self.info, data: NABufferRef::new(data), offs, chmap: self.get_chmap().clone(), stride: self.stride, step: self.step, len: self.len }
+    }
+    /// Returns the length of frame in samples.
+    pub fn get_length(&self) -> usize { self.len }
+    /// Prints internal buffer layout.
+    pub fn print_contents(&self, datatype: &str) {
+        println!("{} audio buffer, {} samples of {} [{}], stride {} step {}", datatype, self.len, self.info.get_format(), self.chmap, self.stride, self.step);
+    }
+    /// Truncates the buffer to the requested number of samples.
+    ///
+    /// The data is not reallocated so only the reported length changes.
+    pub fn truncate(&mut self, new_len: usize) {
+        self.len = self.len.min(new_len);
+    }
+}
+
 /// A list of possible decoded frame types.
 #[derive(Clone)]
 pub enum NABufferType {
@@ -165,6 +271,14 @@ pub enum NABufferType {
     Video32    (NAVideoBufferRef<u32>),
     /// Packed video buffer.
     VideoPacked(NAVideoBufferRef<u8>),
+    /// Audio buffer with 8-bit unsigned samples.
+    AudioU8    (NAAudioBuffer<u8>),
+    /// Audio buffer with 16-bit signed samples.
+    AudioI16   (NAAudioBuffer<i16>),
+    /// Audio buffer with 32-bit floating point samples.
+    AudioF32   (NAAudioBuffer<f32>),
+    /// Packed audio buffer (e.g. 24-bit or 20-bit samples stored as raw bytes).
+    AudioPacked(NAAudioBuffer<u8>),
     /// Buffer with generic data (e.g. subtitles).
     Data       (NABufferRef<Vec<u8>>),
     /// No data present.
@@ -179,6 +293,10 @@ impl NABufferType {
             NABufferType::Video16(ref vb)     => vb.get_offset(idx),
             NABufferType::Video32(ref vb)     => vb.get_offset(idx),
             NABufferType::VideoPacked(ref vb) => vb.get_offset(idx),
+            NABufferType::AudioU8(ref ab)     => ab.get_offset(idx),
+            NABufferType::AudioI16(ref ab)    => ab.get_offset(idx),
+            NABufferType::AudioF32(ref ab)    => ab.get_offset(idx),
+            NABufferType::AudioPacked(ref ab) => ab.get_offset(idx),
             _ => 0,
         }
     }
@@ -214,6 +332,78 @@ impl NABufferType {
             _ => None,
         }
     }
+    /// Returns information for audio frames.
+    pub fn get_audio_info(&self) -> Option<NAAudioInfo> {
+        match *self {
+            NABufferType::AudioU8(ref ab)     => Some(ab.get_info()),
+            NABufferType::AudioI16(ref ab)    => Some(ab.get_info()),
+            NABufferType::AudioF32(ref ab)    => Some(ab.get_info()),
+            NABufferType::AudioPacked(ref ab) => Some(ab.get_info()),
+            _ => None,
+        }
+    }
+    /// Returns audio frame duration in samples.
+    pub fn get_audio_length(&self) -> usize {
+        match *self {
+            NABufferType::AudioU8(ref ab)     => ab.get_length(),
+            NABufferType::AudioI16(ref ab)    => ab.get_length(),
+            NABufferType::AudioF32(ref ab)    => ab.get_length(),
+            NABufferType::AudioPacked(ref ab) => ab.get_length(),
+            _ => 0,
+        }
+    }
+    /// Returns the distance between starts of two channels.
+    pub fn get_audio_stride(&self) -> usize {
+        match *self {
+            NABufferType::AudioU8(ref ab)     => ab.get_stride(),
+            NABufferType::AudioI16(ref ab)    => ab.get_stride(),
+            NABufferType::AudioF32(ref ab)    => ab.get_stride(),
+            NABufferType::AudioPacked(ref ab) => ab.get_stride(),
+            _ => 0,
+        }
+    }
+    /// Returns the distance between two samples in one channel.
+    pub fn get_audio_step(&self) -> usize {
+        match *self {
+            NABufferType::AudioU8(ref ab)     => ab.get_step(),
+            NABufferType::AudioI16(ref ab)    => ab.get_step(),
+            NABufferType::AudioF32(ref ab)    => ab.get_step(),
+            NABufferType::AudioPacked(ref ab) => ab.get_step(),
+            _ => 0,
+        }
+    }
+    /// Returns channel map for audio frames.
+    pub fn get_chmap(&self) -> Option<&NAChannelMap> {
+        match *self {
+            NABufferType::AudioU8(ref ab)     => Some(ab.get_chmap()),
+            NABufferType::AudioI16(ref ab)    => Some(ab.get_chmap()),
+            NABufferType::AudioF32(ref ab)    => Some(ab.get_chmap()),
+            NABufferType::AudioPacked(ref ab) => Some(ab.get_chmap()),
+            _ => None,
+        }
+    }
+    /// Returns reference to 8-bit (or packed) audio buffer.
+    pub fn get_abuf_u8(&self) -> Option<NAAudioBuffer<u8>> {
+        match *self {
+            NABufferType::AudioU8(ref ab) => Some(ab.clone()),
+            NABufferType::AudioPacked(ref ab) => Some(ab.clone()),
+            _ => None,
+        }
+    }
+    /// Returns reference to 16-bit audio buffer.
+    pub fn get_abuf_i16(&self) -> Option<NAAudioBuffer<i16>> {
+        match *self {
+            NABufferType::AudioI16(ref ab) => Some(ab.clone()),
+            _ => None,
+        }
+    }
+    /// Returns reference to 32-bit floating point audio buffer.
+    pub fn get_abuf_f32(&self) -> Option<NAAudioBuffer<f32>> {
+        match *self {
+            NABufferType::AudioF32(ref ab) => Some(ab.clone()),
+            _ => None,
+        }
+    }
     /// Prints internal buffer layout.
     pub fn print_buffer_metadata(&self) {
         match *self {
@@ -221,6 +411,10 @@ impl NABufferType {
             NABufferType::Video16(ref buf)      => buf.print_contents("16-bit"),
             NABufferType::Video32(ref buf)      => buf.print_contents("32-bit"),
             NABufferType::VideoPacked(ref buf)  => buf.print_contents("packed"),
+            NABufferType::AudioU8(ref buf)      => buf.print_contents("8-bit"),
+            NABufferType::AudioI16(ref buf)     => buf.print_contents("16-bit"),
+            NABufferType::AudioF32(ref buf)     => buf.print_contents("float"),
+            NABufferType::AudioPacked(ref buf)  => buf.print_contents("packed"),
             NABufferType::Data(ref buf) => { println!("Data buffer, len = {}", buf.len()); },
             NABufferType::None          => { println!("No buffer"); },
         };
@@ -248,6 +442,8 @@ pub struct NASimpleVideoFrame<'a, T: Copy> {
 
 impl<'a, T:Copy> NASimpleVideoFrame<'a, T> {
     /// Constructs a new instance of `NASimpleVideoFrame` from `NAVideoBuffer`.
+    ///
+    /// Returns `None` if the buffer data is shared with another buffer.
     pub fn from_video_buf(vbuf: &'a mut NAVideoBuffer<T>) -> Option<Self> {
         let vinfo = vbuf.get_info();
         let components = vinfo.format.components as usize;
@@ -273,9 +469,117 @@ impl<'a, T:Copy> NASimpleVideoFrame<'a, T> {
             stride: s,
             offset: o,
             components,
-            data: vbuf.data.as_mut_slice(),
+            data: vbuf.data.as_mut()?.as_mut_slice(),
+            })
+    }
+    /// Constructs a new instance of `NASimpleVideoFrame` for the caller-provided memory.
+    ///
+    /// Component strides and offsets are arbitrary but every component described by `vinfo` should fit into `data`.
+    pub fn from_slice(data: &'a mut [T], vinfo: &NAVideoInfo, strides: &[usize], offsets: &[usize]) -> Option<Self> {
+        let components = vinfo.format.components as usize;
+        if components > NA_SIMPLE_VFRAME_COMPONENTS || strides.len() < components || offsets.len() < components {
+            return None;
+        }
+        let mut w: [usize; NA_SIMPLE_VFRAME_COMPONENTS] = [0; NA_SIMPLE_VFRAME_COMPONENTS];
+        let mut h: [usize; NA_SIMPLE_VFRAME_COMPONENTS] = [0; NA_SIMPLE_VFRAME_COMPONENTS];
+        let mut s: [usize; NA_SIMPLE_VFRAME_COMPONENTS] = [0; NA_SIMPLE_VFRAME_COMPONENTS];
+        let mut o: [usize; NA_SIMPLE_VFRAME_COMPONENTS] = [0; NA_SIMPLE_VFRAME_COMPONENTS];
+        for comp in 0..components {
+            let (width, height) = get_plane_size(vinfo, comp);
+            if height > 0 {
+                if strides[comp] < width {
+                    return None;
+                }
+                let end = strides[comp].checked_mul(height - 1)
+                            .and_then(|size| size.checked_add(width))
+                            .and_then(|size| size.checked_add(offsets[comp]));
+                match end {
+                    Some(end) if end <= data.len() => {},
+                    _ => return None,
+                };
+            }
+            w[comp] = width;
+            h[comp] = height;
+            s[comp] = strides[comp];
+            o[comp] = offsets[comp];
+        }
+        Some(NASimpleVideoFrame {
+            width:  w,
+            height: h,
+            flip:   vinfo.flipped,
+            stride: s,
+            offset: o,
+            components,
+            data,
+            })
+    }
+    /// Reports whether the frame components are large enough for a picture with the provided parameters.
+    ///
+    /// Only the components present in both the frame and the picture format are checked.
+    pub fn fits(&self, vinfo: &NAVideoInfo) -> bool {
+        let ncomp = self.components.min(vinfo.format.components as usize);
+        (0..ncomp).all(|comp| {
+                let (width, height) = get_plane_size(vinfo, comp);
+                width <= self.width[comp] && height <= self.height[comp]
             })
     }
+    /// Copies picture data from `NAVideoBuffer` into the frame.
+    ///
+    /// Only the components present in both source and destination are copied and lines are reordered if the source orientation differs from the frame one.
+    /// Returns `false` if the frame components are too small for the source picture.
+    pub fn copy_from_buf(&mut self, src: &NAVideoBuffer<T>) -> bool {
+        let ncomp = self.components.min(src.get_num_components());
+        for comp in 0..ncomp {
+            let (width, height) = src.get_dimensions(comp);
+            if width > self.width[comp] || height > self.height[comp] {
+                return false;
+            }
+        }
+        let flip = self.flip != src.get_info().is_flipped();
+        let sdata = src.get_data();
+        for comp in 0..ncomp {
+            let (width, height) = src.get_dimensions(comp);
+            let sstride = src.get_stride(comp);
+            let dstride = self.stride[comp];
+            let soff = src.get_offset(comp);
+            let doff = self.offset[comp];
+            for y in 0..height {
+                let dy = if !flip { y } else { height - 1 - y };
+                self.data[doff + dy * dstride..][..width].copy_from_slice(&sdata[soff + y * sstride..][..width]);
+            }
+        }
+        true
+    }
+    /// Copies picture data from the frame into `NAVideoBuffer`.
+    ///
+    /// This is the counterpart of [`copy_from_buf`] with the same rules, the buffer picture size determines the copied area.
+    /// Returns `false` if the frame components are too small for the buffer picture or the buffer data is shared.
+    ///
+    /// [`copy_from_buf`]: #method.copy_from_buf
+    pub fn copy_to_buf(&self, dst: &mut NAVideoBuffer<T>) -> bool {
+        let ncomp = self.components.min(dst.get_num_components());
+        let mut dims = [(0, 0); NA_SIMPLE_VFRAME_COMPONENTS];
+        let mut doffs = [0; NA_SIMPLE_VFRAME_COMPONENTS];
+        let mut dstrides = [0; NA_SIMPLE_VFRAME_COMPONENTS];
+        for comp in 0..ncomp {
+            dims[comp] = dst.get_dimensions(comp);
+            if dims[comp].0 > self.width[comp] || dims[comp].1 > self.height[comp] {
+                return false;
+            }
+            doffs[comp] = dst.get_offset(comp);
+            dstrides[comp] = dst.get_stride(comp);
+        }
+        let flip = self.flip != dst.get_info().is_flipped();
+        let ddata = if let Some(data) = dst.get_data_mut() { data } else { return false; };
+        for comp in 0..ncomp {
+            let (width, height) = dims[comp];
+            for y in 0..height {
+                let dy = if !flip { y } else { height - 1 - y };
+                ddata[doffs[comp] + dy * dstrides[comp]..][..width].copy_from_slice(&self.data[self.offset[comp] + y * self.stride[comp]..][..width]);
+            }
+        }
+        true
+    }
 }
 
 /// A list of possible frame allocator errors.
@@ -285,12 +589,68 @@ pub enum AllocatorError {
     TooLargeDimensions,
     /// Invalid input format.
     FormatError,
+    /// Requested allocation exceeds the configured [`DecoderLimits`].
+    ///
+    /// [`DecoderLimits`]: ./struct.DecoderLimits.html
+    LimitExceeded,
+}
+
+/// Resource limits for decoding untrusted streams.
+///
+/// By default no limits are imposed. Decoders check stream parameters against these limits
+/// and report an error instead of allocating memory for e.g. absurdly large frames.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct DecoderLimits {
+    /// Maximum picture width.
+    pub max_width:      usize,
+    /// Maximum picture height.
+    pub max_height:     usize,
+    /// Maximum number of pixels in a picture.
+    pub max_pixels:     usize,
+    /// Maximum number of buffers a frame pool may hold.
+    pub max_pool_bufs:  usize,
+    /// Maximum input packet size in bytes.
+    pub max_packet_size: usize,
+}
+
+impl DecoderLimits {
+    /// Constructs a new instance of `DecoderLimits` without any limits set.
+    pub fn new() -> Self {
+        Self {
+            max_width:      usize::MAX,
+            max_height:     usize::MAX,
+            max_pixels:     usize::MAX,
+            max_pool_bufs:  usize::MAX,
+            max_packet_size: usize::MAX,
+        }
+    }
+    /// Reports whether picture of provided dimensions fits into the limits.
+    pub fn check_dimensions(&self, width: usize, height: usize) -> bool {
+        width <= self.max_width && height <= self.max_height && width.saturating_mul(height) <= self.max_pixels
+    }
+    /// Reports whether the packet of provided size fits into the limits.
+    pub fn check_packet_size(&self, size: usize) -> bool {
+        size <= self.max_packet_size
+    }
+}
+
+impl Default for DecoderLimits {
+    fn default() -> Self { Self::new() }
 }
 
 /// Constructs a new video buffer with requested format.
 ///
 /// `align` is power of two alignment for image. E.g. the value of 5 means that frame dimensions will be padded to be multiple of 32.
 pub fn alloc_video_buffer(vinfo: NAVideoInfo, align: u8) -> Result<NABufferType, AllocatorError> {
+    alloc_video_buffer_limited(vinfo, align, &DecoderLimits::new())
+}
+
+/// Constructs a new video buffer with requested format if its dimensions fit into provided limits.
+///
+/// See [`alloc_video_buffer`] for the meaning of `align`.
+///
+/// [`alloc_video_buffer`]: ./fn.alloc_video_buffer.html
+pub fn alloc_video_buffer_limited(vinfo: NAVideoInfo, align: u8, limits: &DecoderLimits) -> Result<NABufferType, AllocatorError> {
     let fmt = &vinfo.format;
     let mut new_size: usize = 0;
     let mut offs:    Vec<usize> = Vec::new();
@@ -303,6 +663,9 @@ pub fn alloc_video_buffer(vinfo: NAVideoInfo, align: u8) -> Result<NABufferType,
     let align_mod = ((1 << align) as usize) - 1;
     let width  = (vinfo.width  + align_mod) & !align_mod;
     let height = (vinfo.height + align_mod) & !align_mod;
+    if !limits.check_dimensions(vinfo.width, vinfo.height) {
+        return Err(AllocatorError::LimitExceeded);
+    }
     let mut max_depth = 0;
     let mut all_packed = true;
     let mut all_bytealigned = true;
@@ -396,6 +759,46 @@ pub fn alloc_video_buffer(vinfo: NAVideoInfo, align: u8) -> Result<NABufferType,
     }
 }
 
+/// Constructs a new audio buffer for the requested format and length.
+///
+/// 8-bit unsigned, 16-bit signed and 32-bit floating point samples (both interleaved and planar) get the corresponding
+/// buffer type while the rest of the formats (e.g. packed 20-bit or 24-bit samples) are stored as raw bytes in `AudioPacked` buffer.
+/// The channel map should contain exactly as many channels as the audio information specifies.
+pub fn alloc_audio_buffer(ainfo: NAAudioInfo, nsamples: usize, chmap: NAChannelMap) -> Result<NABufferType, AllocatorError> {
+    let fmt = ainfo.format;
+    let channels = usize::from(ainfo.channels);
+    if channels == 0 || chmap.num_channels() != channels {
+        return Err(AllocatorError::FormatError);
+    }
+    let length = nsamples.checked_mul(channels).ok_or(AllocatorError::TooLargeDimensions)?;
+    let (offs, stride, step) = if fmt.planar {
+            ((0..channels).map(|ch| ch * nsamples).collect(), nsamples, 1)
+        } else {
+            ((0..channels).collect(), 1, channels)
+        };
+    if fmt.float && !fmt.packed && fmt.bits == 32 {
+        let data: Vec<f32> = vec![0.0; length];
+        let buf: NAAudioBuffer<f32> = NAAudioBuffer { data: NABufferRef::new(data), info: ainfo, offs, chmap, stride, step, len: nsamples };
+        Ok(NABufferType::AudioF32(buf))
+    } else if !fmt.float && !fmt.packed && fmt.bits == 16 && fmt.signed {
+        let data: Vec<i16> = vec![0; length];
+        let buf: NAAudioBuffer<i16> = NAAudioBuffer { data: NABufferRef::new(data), info: ainfo, offs, chmap, stride, step, len: nsamples };
+        Ok(NABufferType::AudioI16(buf))
+    } else if !fmt.float && !fmt.packed && fmt.bits == 8 && !fmt.signed {
+        let data: Vec<u8> = vec![0; length];
+        let buf: NAAudioBuffer<u8> = NAAudioBuffer { data: NABufferRef::new(data), info: ainfo, offs, chmap, stride, step, len: nsamples };
+        Ok(NABufferType::AudioU8(buf))
+    } else {
+        if fmt.bits == 0 || (fmt.float && fmt.bits != 32 && fmt.bits != 64) {
+            return Err(AllocatorError::FormatError);
+        }
+        let size = fmt.get_audio_size(length as u64);
+        let data: Vec<u8> = vec![0; size];
+        let buf: NAAudioBuffer<u8> = NAAudioBuffer { data: NABufferRef::new(data), info: ainfo, offs: vec![0], chmap, stride: 0, step: 0, len: nsamples };
+        Ok(NABufferType::AudioPacked(buf))
+    }
+}
+
 /// Constructs a new buffer for generic data.
 pub fn alloc_data_buffer(size: usize) -> Result<NABufferType, AllocatorError> {
     let data: Vec<u8> = vec![0; size];
@@ -408,14 +811,55 @@ pub fn copy_buffer(buf: &NABufferType) -> NABufferType {
     buf.clone()
 }
 
+/// Frame pool behaviour when all its frames are in use.
+#[derive(Debug,Clone,Copy,PartialEq,Default)]
+pub enum NAPoolGrowth {
+    /// Only the preallocated (or manually added) frames are used.
+    #[default]
+    Fixed,
+    /// New frames are allocated on demand until the pool contains the provided number of frames.
+    ///
+    /// The pool size is also restricted by [`DecoderLimits`]`::max_pool_bufs`.
+    ///
+    /// [`DecoderLimits`]: ./struct.DecoderLimits.html
+    UpTo(usize),
+}
+
+/// Frame pool usage statistics.
+#[derive(Debug,Clone,Copy,PartialEq,Default)]
+pub struct NAPoolStats {
+    /// Number of frames allocated by the pool, both preallocated and allocated on demand.
+    pub allocations:    usize,
+    /// Number of frames released by trimming the pool.
+    pub releases:       usize,
+    /// Number of requests for a free frame.
+    pub requests:       usize,
+    /// Number of requests when all pool frames were in use.
+    pub misses:         usize,
+    /// Number of requests that could not be satisfied even by allocating a new frame.
+    pub failures:       usize,
+}
+
+type PoolAllocFunc<T> = fn(NAVideoInfo, u8, &DecoderLimits) -> Result<NAVideoBufferRef<T>, AllocatorError>;
+
 /// Video frame pool.
 ///
 /// This structure allows codec to effectively reuse old frames instead of allocating and de-allocating frames every time.
 /// Caller can also reserve some frames for its own purposes e.g. display queue.
+/// If the caller may hold frames for longer, the pool can be allowed to grow with [`set_growth`] and trimmed back later with [`trim`].
+///
+/// [`set_growth`]: #method.set_growth
+/// [`trim`]: #method.trim
 pub struct NAVideoBufferPool<T:Copy> {
-    pool:       Vec<NAVideoBufferRef<T>>,
+    pool:       Vec<NABufferHandle<NAVideoBuffer<T>>>,
+    info:       Option<NAVideoInfo>,
     max_len:    usize,
     add_len:    usize,
+    limits:     DecoderLimits,
+    growth:     NAPoolGrowth,
+    alloc:      Option<PoolAllocFunc<T>>,
+    align:      u8,
+    stats:      NAPoolStats,
 }
 
 impl<T:Copy> NAVideoBufferPool<T> {
@@ -423,48 +867,156 @@ impl<T:Copy> NAVideoBufferPool<T> {
     pub fn new(max_len: usize) -> Self {
         Self {
             pool:       Vec::with_capacity(max_len),
+            info:       None,
             max_len,
             add_len: 0,
+            limits:  DecoderLimits::new(),
+            growth:  NAPoolGrowth::Fixed,
+            alloc:   None,
+            align:   0,
+            stats:   NAPoolStats::default(),
         }
     }
     /// Sets the number of buffers reserved for the user.
     pub fn set_dec_bufs(&mut self, add_len: usize) {
         self.add_len = add_len;
     }
+    /// Sets the limits for the frames allocated by the pool.
+    pub fn set_limits(&mut self, limits: DecoderLimits) {
+        self.limits = limits;
+    }
+    /// Returns the limits for the frames allocated by the pool.
+    pub fn get_limits(&self) -> DecoderLimits { self.limits }
+    /// Sets the pool behaviour for the case when all frames are in use.
+    ///
+    /// Frames can be allocated on demand only after [`prealloc_video`] was called.
+    ///
+    /// [`prealloc_video`]: #method.prealloc_video
+    pub fn set_growth(&mut self, growth: NAPoolGrowth) {
+        self.growth = growth;
+    }
+    /// Returns the current pool growth policy.
+    pub fn get_growth(&self) -> NAPoolGrowth { self.growth }
+    /// Returns the pool usage statistics.
+    pub fn get_stats(&self) -> NAPoolStats { self.stats }
+    /// Resets the pool usage statistics.
+    pub fn reset_stats(&mut self) {
+        self.stats = NAPoolStats::default();
+    }
+    fn check_num_bufs(&self) -> Result<usize, AllocatorError> {
+        let total = self.max_len.saturating_add(self.add_len);
+        if total > self.limits.max_pool_bufs {
+            return Err(AllocatorError::LimitExceeded);
+        }
+        Ok(total.saturating_sub(self.pool.len()))
+    }
+    fn prealloc_frames(&mut self, vinfo: NAVideoInfo, align: u8, alloc: PoolAllocFunc<T>) -> Result<(), AllocatorError> {
+        let nbufs = self.check_num_bufs()?;
+        self.alloc = Some(alloc);
+        self.align = align;
+        for _ in 0..nbufs {
+            let buf = alloc(vinfo, align, &self.limits)?;
+            self.stats.allocations += 1;
+            self.add_frame(buf);
+        }
+        Ok(())
+    }
+    fn grow(&mut self) -> Option<NAVideoBufferRef<T>> {
+        let max_bufs = match self.growth {
+                NAPoolGrowth::Fixed        => return None,
+                NAPoolGrowth::UpTo(nbufs)  => nbufs.min(self.limits.max_pool_bufs),
+            };
+        if self.pool.len() >= max_bufs {
+            return None;
+        }
+        let alloc = self.alloc?;
+        let buf = alloc(self.info?, self.align, &self.limits).ok()?;
+        self.stats.allocations += 1;
+        self.pool.push(buf.new_handle());
+        Some(buf)
+    }
     /// Returns an unused buffer from the pool.
+    ///
+    /// The returned reference is the only one to the buffer so its contents can be modified.
+    /// If all frames are in use, a new one may be allocated depending on the growth policy.
     pub fn get_free(&mut self) -> Option<NAVideoBufferRef<T>> {
-        for e in self.pool.iter() {
-            if e.get_num_refs() == 1 {
-                return Some(e.clone());
-            }
+        self.stats.requests += 1;
+        if let Some(buf) = self.pool.iter().find_map(|e| e.get_ref()) {
+            return Some(buf);
         }
-        None
+        self.stats.misses += 1;
+        let ret = self.grow();
+        if ret.is_none() {
+            self.stats.failures += 1;
+        }
+        ret
     }
     /// Clones provided frame data into a free pool frame.
     pub fn get_copy(&mut self, rbuf: &NAVideoBufferRef<T>) -> Option<NAVideoBufferRef<T>> {
         let mut dbuf = self.get_free()?;
-        dbuf.data.copy_from_slice(&rbuf.data);
+        dbuf.as_mut()?.get_data_mut()?.copy_from_slice(rbuf.get_data());
         Some(dbuf)
     }
     /// Clears the pool from all frames.
     pub fn reset(&mut self) {
         self.pool.clear();
+        self.info = None;
+    }
+    /// Releases unused frames until the pool contains no more than `keep` frames.
+    ///
+    /// Returns the number of released frames. Frames still in use are kept regardless of `keep`.
+    pub fn trim(&mut self, keep: usize) -> usize {
+        let mut excess = self.pool.len().saturating_sub(keep);
+        let old_len = self.pool.len();
+        self.pool.retain(|el| {
+                if excess > 0 && el.get_num_refs() == 0 {
+                    excess -= 1;
+                    false
+                } else {
+                    true
+                }
+            });
+        let released = old_len - self.pool.len();
+        self.stats.releases += released;
+        released
     }
     /// Returns the number of frames currently in use.
     pub fn get_num_used(&self) -> usize {
-        self.pool.iter().filter(|el| el.get_num_refs() != 1).count()
+        self.pool.iter().filter(|el| el.get_num_refs() != 0).count()
     }
+    /// Returns the total number of frames in the pool.
+    pub fn get_num_frames(&self) -> usize { self.pool.len() }
     /// Adds a manually allocated frame to the pool.
+    ///
+    /// The frame becomes available from the pool once all references to it are dropped.
     pub fn add_frame(&mut self, buf: NAVideoBufferRef<T>) {
-        self.pool.push(buf);
+        if self.info.is_none() {
+            self.info = Some(buf.get_info());
+        }
+        self.pool.push(buf.new_handle());
     }
     /// Returns current video format (if available).
-    pub fn get_info(&self) -> Option<NAVideoInfo> {
-        if !self.pool.is_empty() {
-            Some(self.pool[0].get_info())
-        } else {
-            None
-        }
+    pub fn get_info(&self) -> Option<NAVideoInfo> { self.info }
+}
+
+fn alloc_pool_frame_u8(vinfo: NAVideoInfo, align: u8, limits: &DecoderLimits) -> Result<NAVideoBufferRef<u8>, AllocatorError> {
+    match alloc_video_buffer_limited(vinfo, align, limits)? {
+        NABufferType::Video(buf) | NABufferType::VideoPacked(buf) => Ok(buf),
+        _ => Err(AllocatorError::FormatError),
+    }
+}
+
+fn alloc_pool_frame_u16(vinfo: NAVideoInfo, align: u8, limits: &DecoderLimits) -> Result<NAVideoBufferRef<u16>, AllocatorError> {
+    match alloc_video_buffer_limited(vinfo, align, limits)? {
+        NABufferType::Video16(buf) => Ok(buf),
+        _ => Err(AllocatorError::FormatError),
+    }
+}
+
+fn alloc_pool_frame_u32(vinfo: NAVideoInfo, align: u8, limits: &DecoderLimits) -> Result<NAVideoBufferRef<u32>, AllocatorError> {
+    match alloc_video_buffer_limited(vinfo, align, limits)? {
+        NABufferType::Video32(buf) => Ok(buf),
+        _ => Err(AllocatorError::FormatError),
     }
 }
 
@@ -473,18 +1025,7 @@ impl NAVideoBufferPool<u8> {
     ///
     /// [`alloc_video_buffer`]: ./fn.alloc_video_buffer.html
     pub fn prealloc_video(&mut self, vinfo: NAVideoInfo, align: u8) -> Result<(), AllocatorError> {
-        let nbufs = self.max_len + self.add_len - self.pool.len();
-        for _ in 0..nbufs {
-            let vbuf = alloc_video_buffer(vinfo, align)?;
-            if let NABufferType::Video(buf) = vbuf {
-                self.pool.push(buf);
-            } else if let NABufferType::VideoPacked(buf) = vbuf {
-                self.pool.push(buf);
-            } else {
-                return Err(AllocatorError::FormatError);
-            }
-        }
-        Ok(())
+        self.prealloc_frames(vinfo, align, alloc_pool_frame_u8)
     }
 }
 
@@ -493,16 +1034,7 @@ impl NAVideoBufferPool<u16> {
     ///
     /// [`alloc_video_buffer`]: ./fn.alloc_video_buffer.html
     pub fn prealloc_video(&mut self, vinfo: NAVideoInfo, align: u8) -> Result<(), AllocatorError> {
-        let nbufs = self.max_len + self.add_len - self.pool.len();
-        for _ in 0..nbufs {
-            let vbuf = alloc_video_buffer(vinfo, align)?;
-            if let NABufferType::Video16(buf) = vbuf {
-                self.pool.push(buf);
-            } else {
-                return Err(AllocatorError::FormatError);
-            }
-        }
-        Ok(())
+        self.prealloc_frames(vinfo, align, alloc_pool_frame_u16)
     }
 }
 
@@ -511,16 +1043,7 @@ impl NAVideoBufferPool<u32> {
     ///
     /// [`alloc_video_buffer`]: ./fn.alloc_video_buffer.html
     pub fn prealloc_video(&mut self, vinfo: NAVideoInfo, align: u8) -> Result<(), AllocatorError> {
-        let nbufs = self.max_len + self.add_len - self.pool.len();
-        for _ in 0..nbufs {
-            let vbuf = alloc_video_buffer(vinfo, align)?;
-            if let NABufferType::Video32(buf) = vbuf {
-                self.pool.push(buf);
-            } else {
-                return Err(AllocatorError::FormatError);
-            }
-        }
-        Ok(())
+        self.prealloc_frames(vinfo, align, alloc_pool_frame_u32)
     }
 }
 
@@ -561,6 +1084,11 @@ impl NACodecInfo {
         if let NACodecTypeInfo::Video(_) = self.properties { return true; }
         false
     }
+    /// Reports whether it is an audio codec.
+    pub fn is_audio(&self) -> bool {
+        if let NACodecTypeInfo::Audio(_) = self.properties { return true; }
+        false
+    }
     /// Constructs a new empty reference-counted instance of `NACodecInfo`.
     pub fn new_dummy() -> Arc<Self> {
         Arc::new(DUMMY_CODEC_INFO)
@@ -621,6 +1149,225 @@ impl fmt::Display for FrameType {
     }
 }
 
+/// Timestamp information.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct NATimeInfo {
+    /// Presentation timestamp.
+    pub pts:            Option<u64>,
+    /// Decode timestamp.
+    pub dts:            Option<u64>,
+    /// Duration (in timebase units).
+    pub duration:       Option<u64>,
+    /// Timebase numerator.
+    pub tb_num:         u32,
+    /// Timebase denominator.
+    pub tb_den:         u32,
+}
+
+impl NATimeInfo {
+    /// Constructs a new `NATimeInfo` instance.
+    pub fn new(pts: Option<u64>, dts: Option<u64>, duration: Option<u64>, tb_num: u32, tb_den: u32) -> Self {
+        NATimeInfo { pts, dts, duration, tb_num, tb_den }
+    }
+    /// Returns presentation timestamp.
+    pub fn get_pts(&self) -> Option<u64> { self.pts }
+    /// Returns decoding timestamp.
+    pub fn get_dts(&self) -> Option<u64> { self.dts }
+    /// Returns duration.
+    pub fn get_duration(&self) -> Option<u64> { self.duration }
+    /// Sets new presentation timestamp.
+    pub fn set_pts(&mut self, pts: Option<u64>) { self.pts = pts; }
+    /// Sets new decoding timestamp.
+    pub fn set_dts(&mut self, dts: Option<u64>) { self.dts = dts; }
+    /// Sets new duration.
+    pub fn set_duration(&mut self, dur: Option<u64>) { self.duration = dur; }
+
+    /// Converts time in given scale into timestamp in given base.
+    #[allow(clippy::collapsible_if)]
+    #[allow(clippy::collapsible_else_if)]
+    pub fn time_to_ts(time: u64, base: u64, tb_num: u32, tb_den: u32) -> u64 {
+        let tb_num = u64::from(tb_num);
+        let tb_den = u64::from(tb_den);
+        let tmp = time.checked_mul(tb_den);
+        if let Some(tmp) = tmp {
+            tmp / base / tb_num
+        } else {
+            if tb_num < base {
+                let coarse = time / tb_num;
+                if let Some(tmp) = coarse.checked_mul(tb_den) {
+                    tmp / base
+                } else {
+                    (coarse / base) * tb_den
+                }
+            } else {
+                let coarse = time / base;
+                if let Some(tmp) = coarse.checked_mul(tb_den) {
+                    tmp / tb_num
+                } else {
+                    (coarse / tb_num) * tb_den
+                }
+            }
+        }
+    }
+    /// Converts timestamp in given base into time in given scale.
+    pub fn ts_to_time(ts: u64, base: u64, tb_num: u32, tb_den: u32) -> u64 {
+        let tb_num = u64::from(tb_num);
+        let tb_den = u64::from(tb_den);
+        let tmp = ts.checked_mul(base);
+        if let Some(tmp) = tmp {
+            let tmp2 = tmp.checked_mul(tb_num);
+            if let Some(tmp2) = tmp2 {
+                tmp2 / tb_den
+            } else {
+                (tmp / tb_den) * tb_num
+            }
+        } else {
+            let tmp = ts.checked_mul(tb_num);
+            if let Some(tmp) = tmp {
+                (tmp / tb_den) * base
+            } else {
+                (ts / tb_den) * base * tb_num
+            }
+        }
+    }
+}
+
+impl Default for NATimeInfo {
+    fn default() -> Self { NATimeInfo::new(None, None, None, 0, 0) }
+}
+
+/// Packet with compressed data.
+#[derive(Clone)]
+pub struct NAPacket {
+    stream_id:          usize,
+    /// Packet timestamp.
+    pub ts:             NATimeInfo,
+    /// Packet contents.
+    pub buffer:         NABufferRef<Vec<u8>>,
+    /// Keyframe flag.
+    pub keyframe:       bool,
+}
+
+impl NAPacket {
+    /// Constructs a new `NAPacket` instance.
+    pub fn new(stream_id: usize, ts: NATimeInfo, kf: bool, vec: Vec<u8>) -> Self {
+        NAPacket { stream_id, ts, keyframe: kf, buffer: NABufferRef::new(vec) }
+    }
+    /// Constructs a new `NAPacket` instance reusing a buffer reference.
+    pub fn new_from_refbuf(stream_id: usize, ts: NATimeInfo, kf: bool, buffer: NABufferRef<Vec<u8>>) -> Self {
+        NAPacket { stream_id, ts, keyframe: kf, buffer }
+    }
+    /// Returns the index of the stream packet belongs to.
+    pub fn get_stream_id(&self) -> usize { self.stream_id }
+    /// Returns packet timestamp.
+    pub fn get_time_information(&self) -> NATimeInfo { self.ts }
+    /// Returns packet presentation timestamp.
+    pub fn get_pts(&self) -> Option<u64> { self.ts.get_pts() }
+    /// Returns packet decoding timestamp.
+    pub fn get_dts(&self) -> Option<u64> { self.ts.get_dts() }
+    /// Returns packet duration.
+    pub fn get_duration(&self) -> Option<u64> { self.ts.get_duration() }
+    /// Reports whether this is a keyframe packet.
+    pub fn is_keyframe(&self) -> bool { self.keyframe }
+    /// Returns a reference to packet data.
+    pub fn get_buffer(&self) -> NABufferRef<Vec<u8>> { self.buffer.clone() }
+}
+
+impl fmt::Display for NAPacket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut ostr = format!("[pkt for {} size {}", self.stream_id, self.buffer.len());
+        if let Some(pts) = self.ts.pts { ostr = format!("{} pts {}", ostr, pts); }
+        if let Some(dts) = self.ts.dts { ostr = format!("{} dts {}", ostr, dts); }
+        if let Some(dur) = self.ts.duration { ostr = format!("{} duration {}", ostr, dur); }
+        if self.keyframe { ostr = format!("{} kf", ostr); }
+        ostr += "]";
+        write!(f, "{}", ostr)
+    }
+}
+
+/// Decoded frame information.
+#[derive(Clone)]
+pub struct NAFrame {
+    /// Frame timestamp.
+    pub ts:             NATimeInfo,
+    /// Index of the stream the frame belongs to.
+    pub stream_id:      usize,
+    buffer:             NABufferType,
+    info:               NACodecInfoRef,
+    /// Frame type.
+    pub frame_type:     FrameType,
+    /// Keyframe flag.
+    pub key:            bool,
+    /// Flag signalling that the frame comes from an interlaced stream.
+    pub interlaced:     bool,
+}
+
+/// A specialised type for reference-counted `NAFrame`.
+pub type NAFrameRef = Arc<NAFrame>;
+
+impl NAFrame {
+    /// Constructs a new `NAFrame` instance.
+    pub fn new(ts:             NATimeInfo,
+               ftype:          FrameType,
+               keyframe:       bool,
+               info:           NACodecInfoRef,
+               buffer:         NABufferType) -> Self {
+        NAFrame { ts, stream_id: 0, buffer, info, frame_type: ftype, key: keyframe, interlaced: false }
+    }
+    /// Constructs a new `NAFrame` instance inheriting timestamp and stream index from the packet.
+    ///
+    /// Frame type is set to `FrameType::Other` and keyframe flag is taken from the packet, the decoder is expected to update them.
+    pub fn new_from_pkt(pkt: &NAPacket, info: NACodecInfoRef, buffer: NABufferType) -> Self {
+        NAFrame { ts: pkt.ts, stream_id: pkt.stream_id, buffer, info, frame_type: FrameType::Other, key: pkt.keyframe, interlaced: false }
+    }
+    /// Returns frame format information.
+    pub fn get_info(&self) -> NACodecInfoRef { self.info.clone() }
+    /// Returns the index of the stream frame belongs to.
+    pub fn get_stream_id(&self) -> usize { self.stream_id }
+    /// Returns frame type.
+    pub fn get_frame_type(&self) -> FrameType { self.frame_type }
+    /// Reports whether the frame is a keyframe.
+    pub fn is_keyframe(&self) -> bool { self.key }
+    /// Sets new frame type.
+    pub fn set_frame_type(&mut self, ftype: FrameType) { self.frame_type = ftype; }
+    /// Sets keyframe flag.
+    pub fn set_keyframe(&mut self, key: bool) { self.key = key; }
+    /// Reports whether the frame comes from an interlaced stream.
+    pub fn is_interlaced(&self) -> bool { self.interlaced }
+    /// Sets interlaced flag.
+    pub fn set_interlaced(&mut self, interlaced: bool) { self.interlaced = interlaced; }
+    /// Returns frame timestamp.
+    pub fn get_time_information(&self) -> NATimeInfo { self.ts }
+    /// Returns frame presentation time.
+    pub fn get_pts(&self) -> Option<u64> { self.ts.get_pts() }
+    /// Returns frame decoding time.
+    pub fn get_dts(&self) -> Option<u64> { self.ts.get_dts() }
+    /// Returns frame duration.
+    pub fn get_duration(&self) -> Option<u64> { self.ts.get_duration() }
+    /// Sets new presentation timestamp.
+    pub fn set_pts(&mut self, pts: Option<u64>) { self.ts.set_pts(pts); }
+    /// Sets new decoding timestamp.
+    pub fn set_dts(&mut self, dts: Option<u64>) { self.ts.set_dts(dts); }
+    /// Sets new duration.
+    pub fn set_duration(&mut self, dur: Option<u64>) { self.ts.set_duration(dur); }
+    /// Returns a reference to the frame data.
+    pub fn get_buffer(&self) -> NABufferType { self.buffer.clone() }
+    /// Converts current instance into a reference-counted one.
+    pub fn into_ref(self) -> NAFrameRef { Arc::new(self) }
+}
+
+impl fmt::Display for NAFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut ostr = format!("frame type {}", self.frame_type);
+        if let Some(pts) = self.ts.pts { ostr = format!("{} pts {}", ostr, pts); }
+        if let Some(dts) = self.ts.dts { ostr = format!("{} dts {}", ostr, dts); }
+        if let Some(dur) = self.ts.duration { ostr = format!("{} duration {}", ostr, dur); }
+        if self.key { ostr = format!("{} kf", ostr); }
+        if self.interlaced { ostr = format!("{} interlaced", ostr); }
+        write!(f, "[{}]", ostr)
+    }
+}
+
 fn get_plane_size(info: &NAVideoInfo, idx: usize) -> (usize, usize) {
     let chromaton = info.get_format().get_chromaton(idx);
     if chromaton.is_none() { return (0, 0); }
@@ -629,3 +1376,99 @@ fn get_plane_size(info: &NAVideoInfo, idx: usize) -> (usize, usize) {
     let h = (info.get_height() + ((1 << vs) - 1)) >> vs;
     (w, h)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pool_growth() {
+        let vinfo = NAVideoInfo::new(16, 16, false, YUV420_FORMAT);
+        let mut pool: NAVideoBufferPool<u8> = NAVideoBufferPool::new(2);
+        pool.prealloc_video(vinfo, 4).unwrap();
+        let mut held = vec![pool.get_free().unwrap(), pool.get_free().unwrap()];
+        assert!(pool.get_free().is_none());
+        assert_eq!(pool.get_stats(), NAPoolStats { allocations: 2, releases: 0, requests: 3, misses: 1, failures: 1 });
+
+        pool.set_growth(NAPoolGrowth::UpTo(4));
+        held.push(pool.get_free().unwrap());
+        held.push(pool.get_free().unwrap());
+        assert!(pool.get_free().is_none());
+        assert_eq!(pool.get_num_frames(), 4);
+        assert_eq!(pool.get_num_used(), 4);
+        assert!(held[3].get_info() == vinfo);
+        assert_eq!(pool.get_stats(), NAPoolStats { allocations: 4, releases: 0, requests: 6, misses: 4, failures: 2 });
+
+        // frames in use are never released
+        held.truncate(1);
+        assert_eq!(pool.trim(0), 3);
+        assert_eq!(pool.get_num_frames(), 1);
+        assert_eq!(pool.get_stats().releases, 3);
+
+        pool.reset_stats();
+        pool.set_limits(DecoderLimits { max_pool_bufs: 2, ..DecoderLimits::new() });
+        held.push(pool.get_free().unwrap());
+        assert!(pool.get_free().is_none());
+        assert_eq!(pool.get_stats(), NAPoolStats { allocations: 1, releases: 0, requests: 2, misses: 2, failures: 1 });
+    }
+    #[test]
+    fn test_simple_frame_copy() {
+        let vinfo = NAVideoInfo::new(8, 4, false, YUV420_FORMAT);
+        let mut data: Vec<u8> = (0..24 * 8).map(|i| i as u8).collect();
+        let frm = NASimpleVideoFrame::from_slice(&mut data, &vinfo, &[16, 8, 8], &[0, 128, 160]).unwrap();
+        assert!(frm.fits(&vinfo));
+        assert!(!frm.fits(&NAVideoInfo::new(8, 6, false, YUV420_FORMAT)));
+
+        let mut vbuf = alloc_video_buffer(NAVideoInfo::new(8, 4, true, YUV420_FORMAT), 0).unwrap().get_vbuf().unwrap();
+        assert!(frm.copy_to_buf(vbuf.as_mut().unwrap()));
+        let (ystride, yoff) = (vbuf.get_stride(0), vbuf.get_offset(0));
+        assert_eq!(&vbuf.get_data()[yoff..][..8], &frm.data[3 * 16..][..8]);
+        assert_eq!(&vbuf.get_data()[yoff + 3 * ystride..][..8], &frm.data[..8]);
+
+        let mut data2 = vec![0u8; 24 * 8];
+        let mut frm2 = NASimpleVideoFrame::from_slice(&mut data2, &vinfo, &[16, 8, 8], &[0, 128, 160]).unwrap();
+        assert!(frm2.copy_from_buf(&vbuf));
+        for comp in 0..3 {
+            let (w, h) = get_plane_size(&vinfo, comp);
+            for y in 0..h {
+                let off = frm.offset[comp] + y * frm.stride[comp];
+                assert_eq!(&frm2.data[off..][..w], &frm.data[off..][..w]);
+            }
+        }
+
+        let small = NAVideoInfo::new(4, 2, false, YUV420_FORMAT);
+        let mut data3 = vec![0u8; 16];
+        let frm3 = NASimpleVideoFrame::from_slice(&mut data3, &small, &[4, 2, 2], &[0, 8, 10]).unwrap();
+        assert!(!frm3.copy_to_buf(vbuf.as_mut().unwrap()));
+    }
+    #[test]
+    fn test_alloc_audio() {
+        let stereo = NAChannelMap::from_ms_mapping(0x3);
+        let ainfo = NAAudioInfo::new(22050, 2, SND_S16_FORMAT, 0);
+        let buf = alloc_audio_buffer(ainfo, 10, stereo.clone()).unwrap();
+        let abuf = buf.get_abuf_i16().unwrap();
+        assert_eq!((abuf.get_offset(1), abuf.get_stride(), abuf.get_step(), abuf.get_length()), (1, 1, 2, 10));
+        assert_eq!(abuf.get_data().len(), 20);
+        assert_eq!(buf.get_chmap().unwrap().get_channel(1), NAChannelType::R);
+
+        let ainfo = NAAudioInfo::new(22050, 3, SND_F32P_FORMAT, 0);
+        let buf = alloc_audio_buffer(ainfo, 10, NAChannelMap::from_ms_mapping(0x7)).unwrap();
+        let abuf = buf.get_abuf_f32().unwrap();
+        assert_eq!((abuf.get_offset(2), abuf.get_stride(), abuf.get_step()), (20, 10, 1));
+
+        let ainfo = NAAudioInfo::new(22050, 2, SND_U8_FORMAT, 0);
+        let abuf = alloc_audio_buffer(ainfo, 10, stereo.clone()).unwrap().get_abuf_u8().unwrap();
+        assert_eq!(abuf.get_data().len(), 20);
+
+        let ainfo = NAAudioInfo::new(22050, 2, NASoniton::new(20, SONITON_FLAG_PACKED | SONITON_FLAG_SIGNED), 0);
+        let buf = alloc_audio_buffer(ainfo, 10, stereo.clone()).unwrap();
+        assert!(matches!(buf, NABufferType::AudioPacked(_)));
+        assert_eq!(buf.get_abuf_u8().unwrap().get_data().len(), 50);
+
+        let ainfo = NAAudioInfo::new(22050, 2, NASoniton::new(16, SONITON_FLAG_FLOAT), 0);
+        assert_eq!(alloc_audio_buffer(ainfo, 10, stereo.clone()).err(), Some(AllocatorError::FormatError));
+        let ainfo = NAAudioInfo::new(22050, 2, SND_S16_FORMAT, 0);
+        assert_eq!(alloc_audio_buffer(ainfo, 10, NAChannelMap::from_ms_mapping(0x4)).err(), Some(AllocatorError::FormatError));
+        assert_eq!(alloc_audio_buffer(ainfo, usize::MAX, stereo).err(), Some(AllocatorError::TooLargeDimensions));
+    }
+}
@@ -187,9 +187,10 @@ impl<'a> BitReader<'a> {
 
     #[inline(always)]
     fn skip_cache(&mut self, nbits: u8) {
+        // the cache may be emptied completely and shifting 64-bit value by 64 is not allowed
         match self.mode {
-            BitReaderMode::LE => self.cache >>= nbits,
-            _                 => self.cache <<= nbits,
+            BitReaderMode::LE => self.cache = self.cache.checked_shr(u32::from(nbits)).unwrap_or(0),
+            _                 => self.cache = self.cache.checked_shl(u32::from(nbits)).unwrap_or(0),
         };
         self.bits -= nbits;
     }
@@ -283,7 +284,7 @@ impl<'a> BitReader<'a> {
     /// ```
     #[inline(always)]
     pub fn peek(&mut self, nbits: u8) -> u32 {
-        if nbits > 32 { return 0 }
+        if nbits == 0 || nbits > 32 { return 0 }
         if self.bits < nbits { let _ = self.refill(); }
         self.read_cache(nbits)
     }
@@ -335,7 +336,7 @@ impl<'a> BitReader<'a> {
     ///
     /// [`TooManyBitsRequested`]: ./enum.BitReaderError.html#variant.TooManyBitsRequested
     pub fn seek(&mut self, nbits: u32) -> BitReaderResult<()> {
-        if ((nbits + 7) >> 3) as usize > self.src.len() { return Err(TooManyBitsRequested); }
+        if ((u64::from(nbits) + 7) >> 3) as usize > self.src.len() { return Err(TooManyBitsRequested); }
         self.reset_cache();
         self.pos = ((nbits / 32) * 4) as usize;
         self.skip(nbits & 0x1F)
@@ -407,4 +408,18 @@ mod test {
         let mut br = BitReader::new(src, BitReaderMode::LE);
         assert_eq!(br.read_s(5).unwrap(), -5);
     }
+    #[test]
+    fn br_edge_cases() {
+        const DATA: [u8; 8] = [0xFF; 8];
+        for mode in [BitReaderMode::BE, BitReaderMode::LE, BitReaderMode::LE16MSB, BitReaderMode::LE32MSB].iter() {
+            let mut br = BitReader::new(&DATA, *mode);
+            assert_eq!(br.peek(0), 0);
+            assert_eq!(br.read(0).unwrap(), 0);
+            assert_eq!(br.peek(1), 1);
+            br.skip(64).unwrap();
+            assert_eq!(br.left(), 0);
+            assert!(br.read_bool().is_err());
+            assert!(br.seek(u32::MAX).is_err());
+        }
+    }
 }
@@ -0,0 +1,174 @@
+//! Bitstream writer functionality.
+//!
+//! Bitstream writer produces `Vec<u8>` with bits stored in the same modes [`BitReader`] can read.
+//!
+//! # Examples
+//!
+//! Writing several values and reading them back:
+//! ```
+//! use nihav_core::io::bitwriter::{BitWriter,BitWriterMode};
+//! use nihav_core::io::bitreader::{BitReader,BitReaderMode};
+//!
+//! let mut bw = BitWriter::new(Vec::new(), BitWriterMode::BE);
+//! bw.write(5, 3);
+//! bw.write1();
+//! bw.write(0x1234, 17);
+//! let data = bw.end();
+//! let mut br = BitReader::new(&data, BitReaderMode::BE);
+//! assert_eq!(br.read(3).unwrap(), 5);
+//! assert!(br.read_bool().unwrap());
+//! assert_eq!(br.read(17).unwrap(), 0x1234);
+//! ```
+//!
+//! [`BitReader`]: ../bitreader/struct.BitReader.html
+
+/// Bitstream writing modes.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum BitWriterMode {
+    /// The stream is big endian MSB first.
+    BE,
+    /// The stream is little endian LSB first.
+    LE,
+    /// The stream is packed into 16-bit little-endian words MSB first.
+    LE16MSB,
+    /// The stream is packed into 32-bit little-endian words MSB first.
+    LE32MSB,
+}
+
+/// Bitstream writer.
+#[derive(Debug,Clone)]
+pub struct BitWriter {
+    dst:    Vec<u8>,
+    cache:  u64,
+    bits:   u8,
+    mode:   BitWriterMode,
+}
+
+impl BitWriter {
+    /// Constructs a new instance of bitstream writer appending data to the provided vector.
+    pub fn new(dst: Vec<u8>, mode: BitWriterMode) -> Self {
+        Self { dst, cache: 0, bits: 0, mode }
+    }
+    /// Returns the number of bits written so far (including the data already present in the output vector).
+    pub fn tell(&self) -> usize {
+        self.dst.len() * 8 + usize::from(self.bits)
+    }
+    /// Writes the lower `bits` bits of the value (up to 32 bits).
+    pub fn write(&mut self, val: u32, bits: u8) {
+        debug_assert!(bits <= 32);
+        if bits == 0 {
+            return;
+        }
+        let val = u64::from(val) & ((1u64 << bits) - 1);
+        match self.mode {
+            BitWriterMode::LE => {
+                self.cache |= val << self.bits;
+                self.bits += bits;
+                while self.bits >= 8 {
+                    self.dst.push(self.cache as u8);
+                    self.cache >>= 8;
+                    self.bits -= 8;
+                }
+            },
+            _ => {
+                let word_bits = match self.mode {
+                        BitWriterMode::LE16MSB => 16,
+                        BitWriterMode::LE32MSB => 32,
+                        _ => 8,
+                    };
+                self.cache = (self.cache << bits) | val;
+                self.bits += bits;
+                while self.bits >= word_bits {
+                    self.bits -= word_bits;
+                    self.flush_word(word_bits, (self.cache >> self.bits) as u32);
+                }
+                self.cache &= (1u64 << self.bits) - 1;
+            },
+        };
+    }
+    fn flush_word(&mut self, word_bits: u8, word: u32) {
+        match word_bits {
+            8  => self.dst.push(word as u8),
+            16 => self.dst.extend_from_slice(&(word as u16).to_le_bytes()),
+            _  => self.dst.extend_from_slice(&word.to_le_bytes()),
+        };
+    }
+    /// Writes a signed value in two's complement form using `bits` bits.
+    pub fn write_s(&mut self, val: i32, bits: u8) {
+        self.write(val as u32, bits);
+    }
+    /// Writes a single bit.
+    pub fn write_bit(&mut self, bit: bool) {
+        self.write(bit as u32, 1);
+    }
+    /// Writes a single zero bit.
+    pub fn write0(&mut self) {
+        self.write(0, 1);
+    }
+    /// Writes a single one bit.
+    pub fn write1(&mut self) {
+        self.write(1, 1);
+    }
+    /// Pads the output with zero bits to the next byte (or word for word-oriented modes) boundary.
+    pub fn align(&mut self) {
+        let word_bits = match self.mode {
+                BitWriterMode::LE16MSB => 16,
+                BitWriterMode::LE32MSB => 32,
+                _ => 8,
+            };
+        if self.bits > 0 {
+            self.write(0, word_bits - self.bits);
+        }
+    }
+    /// Finishes writing and returns the output data.
+    ///
+    /// The last byte (or word) is padded with zero bits.
+    pub fn end(mut self) -> Vec<u8> {
+        self.align();
+        self.dst
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::bitreader::*;
+
+    #[test]
+    fn bw_roundtrip() {
+        let vals: Vec<(u32, u8)> = (0..200u32).map(|i| (i.wrapping_mul(0x9E37_79B9), (i % 33) as u8)).collect();
+        for &(wmode, rmode) in [(BitWriterMode::BE, BitReaderMode::BE), (BitWriterMode::LE, BitReaderMode::LE),
+                                (BitWriterMode::LE16MSB, BitReaderMode::LE16MSB), (BitWriterMode::LE32MSB, BitReaderMode::LE32MSB)].iter() {
+            let mut bw = BitWriter::new(Vec::new(), wmode);
+            let mut nbits = 0;
+            for &(val, bits) in vals.iter() {
+                bw.write(val, bits);
+                nbits += usize::from(bits);
+                assert_eq!(bw.tell(), nbits);
+            }
+            bw.write_s(-5, 5);
+            let data = bw.end();
+            let mut br = BitReader::new(&data, rmode);
+            for &(val, bits) in vals.iter() {
+                let mask = if bits < 32 { (1u32 << bits) - 1 } else { u32::MAX };
+                assert_eq!(br.read(bits).unwrap(), val & mask, "mode {:?}", wmode);
+            }
+            assert_eq!(br.read_s(5).unwrap(), -5);
+        }
+    }
+    #[test]
+    fn bw_padding() {
+        let mut bw = BitWriter::new(vec![0xAA], BitWriterMode::BE);
+        bw.write(0b101, 3);
+        assert_eq!(bw.tell(), 11);
+        assert_eq!(bw.end(), vec![0xAA, 0xA0]);
+
+        let mut bw = BitWriter::new(Vec::new(), BitWriterMode::LE);
+        bw.write(0b101, 3);
+        assert_eq!(bw.end(), vec![0x05]);
+
+        let mut bw = BitWriter::new(Vec::new(), BitWriterMode::LE32MSB);
+        bw.write1();
+        assert_eq!(bw.end(), vec![0x00, 0x00, 0x00, 0x80]);
+    }
+}
@@ -1,3 +1,4 @@
 //! Byte- and bitstream reading/writing functionality.
 pub mod bitreader;
+pub mod bitwriter;
 
@@ -1,12 +1,11 @@
 //! Reference-counted buffer data type.
 //!
 //! NihAV requires some reference-counted type especially for frame buffer pools.
-//! `Arc` does not allow mutability with several references present, `RwLock` does not work reliably in a single thread mode (at least for me) so I ended up NIHing something.
+//! The data behind `NABufferRef` is immutable while it is shared and mutable access is granted only to the sole reference (like with `Arc::get_mut()`).
+//! In addition to that there is `NABufferHandle` which keeps the buffer allocated without providing access to its contents.
+//! Frame pools use it to hand out a buffer again once all references to it are gone, this time with exclusive access to the data.
 //!
-//! Currently it does not prevent code from reading the data that is being written to.
-//! Maybe in the future this will be replaced by something better and using more standard components.
-//!
-//! Also it contains `unsafe{}` so I should not write in Rust at all.
+//! Both types can be sent between threads if the underlying data allows it.
 //!
 //! # Examples
 //!
@@ -14,78 +13,102 @@
 //! use nihav_core::refs::NABufferRef;
 //!
 //! let vec = vec![42u8; 16];
-//! let vec_ref = NABufferRef::new(vec);
+//! let mut vec_ref = NABufferRef::new(vec);
 //! let vec_ref2 = vec_ref.clone();
 //! let ref_count = vec_ref.get_num_refs(); // should be 2
 //! println!("vector element 4 is {}", vec_ref[4]); // should print the fourth vector element
+//! assert!(vec_ref.as_mut().is_none()); // data is shared
+//! drop(vec_ref2);
+//! vec_ref.as_mut().unwrap()[4] = 0; // and now it is not
 //! ```
-use std::ops::{Deref, DerefMut};
+use std::cell::UnsafeCell;
 use std::convert::AsRef;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::ptr::NonNull;
 use std::sync::atomic::*;
 
+const MAX_REFS: usize = isize::MAX as usize;
+
+fn inc_count(count: &AtomicUsize) {
+    // the same protection against overflow as in `Arc`
+    if count.fetch_add(1, Ordering::Relaxed) >= MAX_REFS {
+        std::process::abort();
+    }
+}
+
 struct NABufferData<T> {
-    data:       T,
+    data:       UnsafeCell<T>,
+    // number of references providing access to the data
     refs:       AtomicUsize,
+    // number of handles plus one for all references together, the buffer is freed once it reaches zero
+    holders:    AtomicUsize,
 }
 
 impl<T> NABufferData<T> {
     fn new(data: T) -> Self {
         Self {
-            data,
+            data:       UnsafeCell::new(data),
             refs:       AtomicUsize::new(1),
+            holders:    AtomicUsize::new(1),
         }
     }
-    fn inc_refs(obj: &mut Self) {
-        obj.refs.fetch_add(1, Ordering::SeqCst);
-    }
-    fn dec_refs(obj: &mut Self) -> bool {
-        obj.refs.fetch_sub(1, Ordering::SeqCst) == 1
-    }
-    fn get_num_refs(obj: &Self) -> usize {
-        obj.refs.load(Ordering::Relaxed)
-    }
-    fn get_read_ptr(obj: &Self) -> &T {
-        &obj.data
-    }
-    fn get_write_ptr(obj: &mut Self) -> Option<&mut T> {
-        Some(&mut obj.data)
+    unsafe fn release_holder(ptr: NonNull<Self>) {
+        if ptr.as_ref().holders.fetch_sub(1, Ordering::Release) == 1 {
+            fence(Ordering::Acquire);
+            drop(Box::from_raw(ptr.as_ptr()));
+        }
     }
 }
 
 /// Reference-counted buffer reference.
 pub struct NABufferRef<T> {
-    ptr: *mut NABufferData<T>,
+    ptr:        NonNull<NABufferData<T>>,
+    _data:      PhantomData<NABufferData<T>>,
 }
 
-unsafe impl<T> Sync for NABufferRef<T> {}
-unsafe impl<T> Send for NABufferRef<T> {}
+unsafe impl<T: Send + Sync> Sync for NABufferRef<T> {}
+unsafe impl<T: Send + Sync> Send for NABufferRef<T> {}
 
 impl<T> NABufferRef<T> {
     /// Constructs a new instance of `NABufferRef`.
     pub fn new(val: T) -> Self {
-        let bdata = NABufferData::new(val);
-        let nbox: Box<_> = Box::new(bdata);
-        Self { ptr: Box::into_raw(nbox) }
+        let nbox = Box::new(NABufferData::new(val));
+        Self { ptr: NonNull::from(Box::leak(nbox)), _data: PhantomData }
+    }
+    fn inner(&self) -> &NABufferData<T> {
+        unsafe { self.ptr.as_ref() }
     }
     /// Reports the number of references for the current instance.
+    ///
+    /// Handles are not counted.
     pub fn get_num_refs(&self) -> usize {
-        unsafe {
-            NABufferData::get_num_refs(self.ptr.as_mut().unwrap())
-        }
+        self.inner().refs.load(Ordering::Acquire)
     }
-    /// Returns a mutable pointer to the underlying data if possible.
+    /// Returns a mutable reference to the underlying data if the current instance is the only reference to it.
     pub fn as_mut(&mut self) -> Option<&mut T> {
-        unsafe {
-            NABufferData::get_write_ptr(self.ptr.as_mut().unwrap())
+        // handles can create new references only when there are none left
+        // so no other reference can appear while this one is borrowed mutably
+        if self.inner().refs.load(Ordering::Acquire) == 1 {
+            unsafe { Some(&mut *self.inner().data.get()) }
+        } else {
+            None
         }
     }
+    /// Reports whether two references point to the same buffer.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        this.ptr == other.ptr
+    }
+    /// Creates a handle that keeps the buffer allocated.
+    pub fn new_handle(&self) -> NABufferHandle<T> {
+        inc_count(&self.inner().holders);
+        NABufferHandle { ptr: self.ptr, _data: PhantomData }
+    }
 }
 
 impl<T> AsRef<T> for NABufferRef<T> {
     fn as_ref(&self) -> &T {
-        unsafe {
-            NABufferData::get_read_ptr(self.ptr.as_mut().unwrap())
-        }
+        unsafe { &*self.inner().data.get() }
     }
 }
 
@@ -94,26 +117,17 @@ impl<T> Deref for NABufferRef<T> {
     fn deref(&self) -> &T { self.as_ref() }
 }
 
-impl<T> DerefMut for NABufferRef<T> {
-    fn deref_mut(&mut self) -> &mut T { self.as_mut().unwrap() }
-}
-
 impl<T> Clone for NABufferRef<T> {
     fn clone(&self) -> Self {
-        unsafe {
-            NABufferData::inc_refs(self.ptr.as_mut().unwrap());
-        }
-        Self { ptr: self.ptr }
+        inc_count(&self.inner().refs);
+        Self { ptr: self.ptr, _data: PhantomData }
     }
 }
 
 impl<T> Drop for NABufferRef<T> {
     fn drop(&mut self) {
-        unsafe {
-            if NABufferData::dec_refs(self.ptr.as_mut().unwrap()) {
-                let data = Box::from_raw(self.ptr);
-                std::mem::drop(data);
-            }
+        if self.inner().refs.fetch_sub(1, Ordering::Release) == 1 {
+            unsafe { NABufferData::release_holder(self.ptr); }
         }
     }
 }
@@ -123,3 +137,126 @@ impl<T:Default> Default for NABufferRef<T> {
         Self::new(T::default())
     }
 }
+
+/// Handle keeping the buffer allocated without providing access to its data.
+///
+/// Once there are no references left the handle can be used to obtain a new exclusive reference to the buffer.
+pub struct NABufferHandle<T> {
+    ptr:        NonNull<NABufferData<T>>,
+    _data:      PhantomData<NABufferData<T>>,
+}
+
+unsafe impl<T: Send + Sync> Sync for NABufferHandle<T> {}
+unsafe impl<T: Send + Sync> Send for NABufferHandle<T> {}
+
+impl<T> NABufferHandle<T> {
+    fn inner(&self) -> &NABufferData<T> {
+        unsafe { self.ptr.as_ref() }
+    }
+    /// Reports the number of references for the buffer.
+    pub fn get_num_refs(&self) -> usize {
+        self.inner().refs.load(Ordering::Acquire)
+    }
+    /// Returns a new reference to the buffer if it is not referenced by anything else.
+    pub fn get_ref(&self) -> Option<NABufferRef<T>> {
+        let inner = self.inner();
+        if inner.refs.compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed).is_ok() {
+            // the handle itself keeps the buffer alive so the holder can be safely re-added
+            inc_count(&inner.holders);
+            Some(NABufferRef { ptr: self.ptr, _data: PhantomData })
+        } else {
+            None
+        }
+    }
+}
+
+impl<T> Clone for NABufferHandle<T> {
+    fn clone(&self) -> Self {
+        inc_count(&self.inner().holders);
+        Self { ptr: self.ptr, _data: PhantomData }
+    }
+}
+
+impl<T> Drop for NABufferHandle<T> {
+    fn drop(&mut self) {
+        unsafe { NABufferData::release_holder(self.ptr); }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicUsize;
+    use std::thread;
+
+    struct DropCounter(Arc<AtomicUsize>);
+
+    impl Drop for DropCounter {
+        fn drop(&mut self) { self.0.fetch_add(1, Ordering::SeqCst); }
+    }
+
+    #[test]
+    fn test_refs_unique_access() {
+        let mut buf = NABufferRef::new(vec![0u8; 4]);
+        buf.as_mut().unwrap()[0] = 1;
+        let buf2 = buf.clone();
+        assert_eq!(buf.get_num_refs(), 2);
+        assert!(buf.as_mut().is_none());
+        assert_eq!(buf2[0], 1);
+        drop(buf2);
+        buf.as_mut().unwrap()[1] = 2;
+        assert_eq!(buf.as_slice(), &[1, 2, 0, 0]);
+    }
+    #[test]
+    fn test_refs_handles() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let buf = NABufferRef::new(DropCounter(drops.clone()));
+        let handle = buf.new_handle();
+        assert!(handle.get_ref().is_none());
+        drop(buf);
+        assert_eq!(drops.load(Ordering::SeqCst), 0);
+        assert_eq!(handle.get_num_refs(), 0);
+
+        let mut buf = handle.get_ref().unwrap();
+        assert!(handle.get_ref().is_none());
+        assert!(buf.as_mut().is_some());
+        let handle2 = handle.clone();
+        drop(handle);
+        drop(handle2);
+        assert_eq!(drops.load(Ordering::SeqCst), 0);
+        drop(buf);
+        assert_eq!(drops.load(Ordering::SeqCst), 1);
+    }
+    #[test]
+    fn test_refs_threads() {
+        let handle;
+        {
+            let mut buf = NABufferRef::new(vec![0u32; 16]);
+            handle = buf.new_handle();
+            for (i, el) in buf.as_mut().unwrap().iter_mut().enumerate() {
+                *el = i as u32;
+            }
+            let mut workers = Vec::new();
+            for _ in 0..4 {
+                let buf = buf.clone();
+                workers.push(thread::spawn(move || buf.iter().sum::<u32>()));
+            }
+            for worker in workers {
+                assert_eq!(worker.join().unwrap(), 120);
+            }
+        }
+
+        let writer = {
+            let handle = handle.clone();
+            thread::spawn(move || {
+                    let mut buf = handle.get_ref().unwrap();
+                    buf.as_mut().unwrap()[0] = 42;
+                })
+        };
+        writer.join().unwrap();
+        let buf = handle.get_ref().unwrap();
+        drop(handle);
+        assert_eq!(thread::spawn(move || buf[0]).join().unwrap(), 42);
+    }
+}
@@ -0,0 +1,36 @@
+//! Exercises bitstream reader in all modes on arbitrary input.
+//!
+//! The first input byte selects reading mode, the following bytes form a list of operations
+//! that is applied to the whole input.
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use nihav_core::io::bitreader::*;
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 2 {
+        return;
+    }
+    let mode = match data[0] & 3 {
+            0 => BitReaderMode::BE,
+            1 => BitReaderMode::LE,
+            2 => BitReaderMode::LE16MSB,
+            _ => BitReaderMode::LE32MSB,
+        };
+    let mut br = BitReader::new(data, mode);
+    for &op in data[1..].iter() {
+        let arg = op >> 2;
+        let _ = match op & 3 {
+                0 => br.read(arg).map(|_| ()),
+                1 => br.read_s(arg).map(|_| ()),
+                2 => { br.peek(arg); br.read_bool().map(|_| ()) },
+                _ => if (arg & 1) == 0 {
+                        br.skip(u32::from(arg) * 3)
+                    } else {
+                        br.seek(u32::from(arg) * 5)
+                    },
+            };
+        br.align();
+        let _ = br.tell();
+        let _ = br.left();
+    }
+});
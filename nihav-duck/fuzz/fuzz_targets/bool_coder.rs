@@ -0,0 +1,24 @@
+//! Exercises the bool decoder on arbitrary input.
+//!
+//! Probabilities for reading are taken from the input data itself.
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use nihav_duck::codecs::vpcommon::BoolCoder;
+
+fuzz_target!(|data: &[u8]| {
+    let mut bc = match BoolCoder::new(data) {
+            Ok(bc) => bc,
+            Err(_) => return,
+        };
+    for (i, &prob) in data.iter().enumerate().cycle().take(data.len() * 4) {
+        match i & 7 {
+            0 => { bc.read_bool(); },
+            1 => { bc.read_bits(prob & 0x1F); },
+            2 => { bc.read_byte(); },
+            3 => { bc.read_sbits((prob & 0x1F).max(1)); },
+            4 => { bc.read_probability(); },
+            5 => { bc.skip_bytes(usize::from(prob & 7)); },
+            _ => { bc.read_prob(prob); },
+        };
+    }
+});
@@ -0,0 +1,45 @@
+//! Feeds arbitrary packets to VP6 decoder.
+//!
+//! The first input byte selects the decoder flavour (plain VP6 or VP6 with alpha), output scale and decoded planes,
+//! the rest is split into packets using the same 32-bit big-endian size prefix as the test fixtures in `assets/`,
+//! so those can be used as the initial corpus.
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use nihav_core::codecs::*;
+use nihav_core::formats::YUV420_FORMAT;
+use nihav_duck::codecs::vp6::*;
+use nihav_duck::codecs::vpcommon::VP_YUVA420_FORMAT;
+
+fuzz_target!(|data: &[u8]| {
+    if data.is_empty() {
+        return;
+    }
+    let has_alpha = (data[0] & 1) != 0;
+    let fmt = if !has_alpha { YUV420_FORMAT } else { VP_YUVA420_FORMAT };
+    let scale = match (data[0] >> 1) & 3 {
+            0 => VP56Scale::Full,
+            1 => VP56Scale::Half,
+            2 => VP56Scale::Quarter,
+            _ => VP56Scale::Eighth,
+        };
+    let planes = match (data[0] >> 3) & 3 {
+            1 => VP56Planes::Colour,
+            2 => VP56Planes::Alpha,
+            _ => VP56Planes::All,
+        };
+    let mut dec = VP56Decoder::new(6, has_alpha, true);
+    dec.set_scale(scale);
+    let mut br = VP6BR::new();
+    let mut supp = NADecoderSupport::new();
+    if dec.init(&mut supp, NAVideoInfo::new(64, 48, true, fmt)).is_err() {
+        return;
+    }
+
+    let mut src = &data[1..];
+    while src.len() >= 4 {
+        let size = u32::from_be_bytes([src[0], src[1], src[2], src[3]]) as usize;
+        let size = size.min(src.len() - 4);
+        let _ = dec.decode_frame_planes(&mut supp, &src[4..][..size], &mut br, planes);
+        src = &src[4 + size..];
+    }
+});
@@ -0,0 +1,450 @@
+//! Simple VP6 decoding tool.
+//!
+//! It takes either FLV file or a stream of raw VP6 packets (each prefixed with 32-bit big-endian size),
+//! decodes it with the same decoder the library provides and optionally stores decoded frames
+//! as YUV4MPEG2 or PNG files. For every frame a short report line is printed.
+//! Diagnostics go to standard error and the exit status is non-zero if anything failed.
+extern crate nihav_core;
+extern crate nihav_duck;
+
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::process::ExitCode;
+use nihav_core::codecs::*;
+use nihav_duck::codecs::vp6::*;
+use nihav_duck::codecs::vpcommon::*;
+
+const FLV_CODEC_VP6:  u8 = 4;
+const FLV_CODEC_VP6A: u8 = 5;
+
+struct VP6Packet {
+    data:       Vec<u8>,
+    ts:         Option<u32>,
+    crop_w:     usize,
+    crop_h:     usize,
+}
+
+struct PacketSource {
+    src:        Vec<u8>,
+    pos:        usize,
+    is_flv:     bool,
+    has_alpha:  bool,
+}
+
+fn read_u24be(src: &[u8]) -> usize {
+    ((src[0] as usize) << 16) | ((src[1] as usize) << 8) | (src[2] as usize)
+}
+
+fn read_u32be(src: &[u8]) -> usize {
+    ((src[0] as usize) << 24) | read_u24be(&src[1..])
+}
+
+impl PacketSource {
+    fn new(src: Vec<u8>, raw: bool, has_alpha: bool) -> Result<Self, String> {
+        if raw {
+            return Ok(Self { src, pos: 0, is_flv: false, has_alpha });
+        }
+        if src.len() < 13 || &src[..3] != b"FLV" {
+            return Err("not an FLV file".to_string());
+        }
+        let hdr_size = read_u32be(&src[5..]);
+        if hdr_size < 9 || hdr_size + 4 > src.len() {
+            return Err("invalid FLV header".to_string());
+        }
+        let mut source = Self { src, pos: hdr_size + 4, is_flv: true, has_alpha: false };
+        // peek at the first video tag to find out whether the stream has alpha
+        let start = source.pos;
+        if let Some(codec_id) = source.find_video_tag()? {
+            source.has_alpha = codec_id == FLV_CODEC_VP6A;
+        }
+        source.pos = start;
+        Ok(source)
+    }
+    fn find_video_tag(&mut self) -> Result<Option<u8>, String> {
+        while self.pos + 11 <= self.src.len() {
+            let tag_type = self.src[self.pos];
+            let size     = read_u24be(&self.src[self.pos + 1..]);
+            if self.pos + 11 + size + 4 > self.src.len() {
+                return Err(format!("truncated FLV tag at {}", self.pos));
+            }
+            if tag_type == 9 && size > 0 {
+                let codec_id = self.src[self.pos + 11] & 0xF;
+                if codec_id == FLV_CODEC_VP6 || codec_id == FLV_CODEC_VP6A {
+                    return Ok(Some(codec_id));
+                }
+            }
+            self.pos += 11 + size + 4;
+        }
+        Ok(None)
+    }
+    fn get_packet(&mut self) -> Result<Option<VP6Packet>, String> {
+        if !self.is_flv {
+            if self.pos + 4 > self.src.len() {
+                return Ok(None);
+            }
+            let size = read_u32be(&self.src[self.pos..]);
+            self.pos += 4;
+            if self.pos + size > self.src.len() {
+                return Err("truncated packet".to_string());
+            }
+            let data = self.src[self.pos..][..size].to_vec();
+            self.pos += size;
+            return Ok(Some(VP6Packet { data, ts: None, crop_w: 0, crop_h: 0 }));
+        }
+        if self.find_video_tag()?.is_none() {
+            return Ok(None);
+        }
+        let size = read_u24be(&self.src[self.pos + 1..]);
+        let ts   = (read_u24be(&self.src[self.pos + 4..]) as u32) | (u32::from(self.src[self.pos + 7]) << 24);
+        let tag  = &self.src[self.pos + 11..][..size];
+        self.pos += 11 + size + 4;
+        if tag.len() < 2 {
+            return Err("video tag is too short".to_string());
+        }
+        let adj = tag[1];
+        Ok(Some(VP6Packet {
+                data:   tag[2..].to_vec(),
+                ts:     Some(ts),
+                crop_w: usize::from(adj >> 4),
+                crop_h: usize::from(adj & 0xF),
+            }))
+    }
+}
+
+fn get_dimensions(pkt: &[u8], has_alpha: bool) -> Option<(usize, usize)> {
+    let src = if has_alpha { pkt.get(3..)? } else { pkt };
+    let mut bc = BoolCoder::new(src).ok()?;
+    let mut br = VP6BR::new();
+    let hdr = br.parse_header(&mut bc).ok()?;
+    if !hdr.is_intra {
+        return None;
+    }
+    Some((usize::from(hdr.mb_w) * 16, usize::from(hdr.mb_h) * 16))
+}
+
+struct OutputFrame {
+    width:      usize,
+    height:     usize,
+    planes:     Vec<Vec<u8>>,
+}
+
+impl OutputFrame {
+    fn new(buf: &NAVideoBuffer<u8>, crop_w: usize, crop_h: usize) -> Self {
+        let info = buf.get_info();
+        let width  = info.get_width().saturating_sub(crop_w).max(1);
+        let height = info.get_height().saturating_sub(crop_h).max(1);
+        let data = buf.get_data();
+        let mut planes = Vec::with_capacity(buf.get_num_components());
+        for comp in 0..buf.get_num_components() {
+            let (pw, ph) = if comp == 1 || comp == 2 { ((width + 1) >> 1, (height + 1) >> 1) } else { (width, height) };
+            let stride = buf.get_stride(comp);
+            let mut plane = Vec::with_capacity(pw * ph);
+            let src = &data[buf.get_offset(comp)..];
+            if !info.is_flipped() {
+                for line in src.chunks(stride).take(ph) {
+                    plane.extend_from_slice(&line[..pw]);
+                }
+            } else {
+                for y in (0..ph).rev() {
+                    plane.extend_from_slice(&src[y * stride..][..pw]);
+                }
+            }
+            planes.push(plane);
+        }
+        Self { width, height, planes }
+    }
+    fn write_y4m_frame(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        out.write_all(b"FRAME\n")?;
+        for plane in self.planes.iter().take(3) {
+            out.write_all(plane)?;
+        }
+        Ok(())
+    }
+    fn write_png(&self, name: &str) -> std::io::Result<()> {
+        let has_alpha = self.planes.len() > 3;
+        let bpp = if has_alpha { 4 } else { 3 };
+        let cw = (self.width + 1) >> 1;
+        let mut raw = Vec::with_capacity((self.width * bpp + 1) * self.height);
+        for y in 0..self.height {
+            raw.push(0); // no filtering
+            for x in 0..self.width {
+                let luma = i32::from(self.planes[0][x + y * self.width]);
+                let u    = i32::from(self.planes[1][(x >> 1) + (y >> 1) * cw]) - 128;
+                let v    = i32::from(self.planes[2][(x >> 1) + (y >> 1) * cw]) - 128;
+                // full-range BT.601 conversion, coefficients are scaled by 2^16
+                let r = luma + ((91881 * v + 32768) >> 16);
+                let g = luma - ((22554 * u + 46802 * v + 32768) >> 16);
+                let b = luma + ((116130 * u + 32768) >> 16);
+                raw.push(r.clamp(0, 255) as u8);
+                raw.push(g.clamp(0, 255) as u8);
+                raw.push(b.clamp(0, 255) as u8);
+                if has_alpha {
+                    raw.push(self.planes[3][x + y * self.width]);
+                }
+            }
+        }
+
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&(self.width  as u32).to_be_bytes());
+        ihdr.extend_from_slice(&(self.height as u32).to_be_bytes());
+        ihdr.push(8);
+        ihdr.push(if has_alpha { 6 } else { 2 });
+        ihdr.extend_from_slice(&[0, 0, 0]);
+
+        let mut out = BufWriter::new(File::create(name)?);
+        out.write_all(b"\x89PNG\r\n\x1A\n")?;
+        write_png_chunk(&mut out, b"IHDR", &ihdr)?;
+        write_png_chunk(&mut out, b"IDAT", &zlib_store(&raw))?;
+        write_png_chunk(&mut out, b"IEND", &[])?;
+        out.flush()
+    }
+}
+
+fn crc32(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &b in data.iter() {
+        crc ^= u32::from(b);
+        for _ in 0..8 {
+            crc = if (crc & 1) != 0 { (crc >> 1) ^ 0xEDB88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn write_png_chunk(out: &mut dyn Write, name: &[u8; 4], data: &[u8]) -> std::io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(name)?;
+    out.write_all(data)?;
+    let crc = crc32(crc32(0, name), data);
+    out.write_all(&crc.to_be_bytes())
+}
+
+// wraps data into zlib stream consisting of uncompressed blocks
+fn zlib_store(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + data.len() / 65535 * 5 + 16);
+    out.push(0x78);
+    out.push(0x01);
+    if data.is_empty() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    let mut chunks = data.chunks(65535).peekable();
+    while let Some(chunk) = chunks.next() {
+        out.push(if chunks.peek().is_none() { 1 } else { 0 });
+        let len = chunk.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(chunk);
+    }
+    let (mut a, mut b) = (1u32, 0u32);
+    for &el in data.iter() {
+        a = (a + u32::from(el)) % 65521;
+        b = (b + a) % 65521;
+    }
+    out.extend_from_slice(&((b << 16) | a).to_be_bytes());
+    out
+}
+
+const USAGE: &str = "usage: vp6dec [options] input
+  -raw           input is a sequence of VP6 packets each prefixed with 32-bit big-endian size
+  -alpha         raw packets are VP6 with alpha (VP6A)
+  -y4m file      write decoded frames into YUV4MPEG2 file (alpha plane is not stored)
+  -png prefix    write every decoded frame into prefixNNNNNN.png
+  -frames N      stop after decoding N frames
+  -deint mode    output mode for interlaced frames: fields, bob or blend (default is to keep the fields woven)
+  -noreport      do not print per-frame information";
+
+fn main() -> ExitCode {
+    let args: Vec<_> = std::env::args().collect();
+
+    let mut raw = false;
+    let mut has_alpha = false;
+    let mut y4m_name: Option<String> = None;
+    let mut png_prefix: Option<String> = None;
+    let mut max_frames = usize::MAX;
+    let mut report = true;
+    let mut field_mode = FieldOutputMode::Woven;
+    let mut input: Option<String> = None;
+
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            "-raw"      => { raw = true; },
+            "-alpha"    => { has_alpha = true; },
+            "-noreport" => { report = false; },
+            "-y4m" | "-png" | "-frames" | "-deint" => {
+                if i + 1 == args.len() {
+                    eprintln!("option {} requires an argument", args[i]);
+                    return ExitCode::FAILURE;
+                }
+                let arg = args[i + 1].clone();
+                match args[i].as_str() {
+                    "-y4m" => { y4m_name = Some(arg); },
+                    "-png" => { png_prefix = Some(arg); },
+                    "-deint" => {
+                        field_mode = match arg.as_str() {
+                                "fields" => FieldOutputMode::Fields,
+                                "bob"    => FieldOutputMode::Bob,
+                                "blend"  => FieldOutputMode::Blend,
+                                _ => {
+                                    eprintln!("invalid deinterlacing mode {}", arg);
+                                    return ExitCode::FAILURE;
+                                },
+                            };
+                    },
+                    _ => {
+                        if let Ok(val) = arg.parse::<usize>() {
+                            max_frames = val;
+                        } else {
+                            eprintln!("invalid frame count {}", arg);
+                            return ExitCode::FAILURE;
+                        }
+                    },
+                };
+                i += 1;
+            },
+            "-h" | "-help" | "--help" => {
+                println!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            },
+            _ => {
+                if input.is_some() {
+                    eprintln!("only one input file is supported");
+                    return ExitCode::FAILURE;
+                }
+                input = Some(args[i].clone());
+            },
+        };
+        i += 1;
+    }
+    let input = if let Some(name) = input {
+            name
+        } else {
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        };
+
+    let mut data = Vec::new();
+    if let Err(err) = File::open(&input).and_then(|mut f| f.read_to_end(&mut data)) {
+        eprintln!("cannot read {}: {}", input, err);
+        return ExitCode::FAILURE;
+    }
+    let mut src = match PacketSource::new(data, raw, has_alpha) {
+            Ok(src) => src,
+            Err(err) => { eprintln!("{}", err); return ExitCode::FAILURE; },
+        };
+    let has_alpha = src.has_alpha;
+
+    let mut dec = VP56Decoder::new(6, has_alpha, false);
+    dec.set_field_mode(field_mode);
+    let mut br = VP6BR::new();
+    let mut supp = NADecoderSupport::new();
+    let mut initialised = false;
+    let mut y4m_out: Option<BufWriter<File>> = None;
+
+    let mut pkt_no = 0;
+    let mut nframes = 0;
+    let mut failed = false;
+    while nframes < max_frames {
+        let pkt = match src.get_packet() {
+                Ok(Some(pkt)) => pkt,
+                Ok(None) => break,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    failed = true;
+                    break;
+                },
+            };
+        pkt_no += 1;
+        if !initialised {
+            if let Some((w, h)) = get_dimensions(&pkt.data, has_alpha) {
+                let fmt = if has_alpha { VP_YUVA420_FORMAT } else { YUV420_FORMAT };
+                if let Err(err) = dec.init(&mut supp, NAVideoInfo::new(w, h, false, fmt)) {
+                    eprintln!("decoder initialisation failed: {:?}", err);
+                    return ExitCode::FAILURE;
+                }
+                initialised = true;
+            } else {
+                eprintln!("packet {}: skipping, waiting for a keyframe", pkt_no - 1);
+                continue;
+            }
+        }
+        let ts_str = if let Some(ts) = pkt.ts { format!(" ts {}ms", ts) } else { String::new() };
+        let (buf, ftype) = match dec.decode_frame(&mut supp, &pkt.data, &mut br) {
+                Ok(ret) => ret,
+                Err(err) => {
+                    if let Some(info) = dec.get_last_error() {
+                        eprintln!("packet {}:{} size {} decoding error: {}", pkt_no - 1, ts_str, pkt.data.len(), info);
+                    } else {
+                        eprintln!("packet {}:{} size {} decoding error: {}", pkt_no - 1, ts_str, pkt.data.len(), err);
+                    }
+                    failed = true;
+                    continue;
+                },
+            };
+        if report {
+            let mut line = format!("frame {}:{} type {} size {}", nframes, ts_str, ftype, pkt.data.len());
+            if let Some(hdr) = dec.get_last_header() {
+                line += format!(" quant {} golden {} {}", hdr.quant, if hdr.is_golden { "yes" } else { "no" },
+                                if hdr.use_huffman { "huffman" } else { "bool" }).as_str();
+                if hdr.is_intra {
+                    line += format!(" profile {} {}x{}{}", if hdr.profile == 0 { "simple" } else { "advanced" },
+                                    usize::from(hdr.mb_w) * 16, usize::from(hdr.mb_h) * 16,
+                                    if hdr.interlaced { " interlaced" } else { "" }).as_str();
+                }
+            }
+            if let Some(ahdr) = dec.get_last_alpha_header() {
+                line += format!(" | alpha type {} quant {} golden {}", if ahdr.is_intra { "I" } else { "P" },
+                                ahdr.quant, if ahdr.is_golden { "yes" } else { "no" }).as_str();
+            }
+            println!("{}", line);
+        }
+
+        if y4m_name.is_some() || png_prefix.is_some() {
+            let vbuf = if let Some(vbuf) = buf.get_vbuf() { vbuf } else { continue; };
+            let frame = OutputFrame::new(&vbuf, pkt.crop_w, pkt.crop_h);
+            if let Some(ref name) = y4m_name {
+                if y4m_out.is_none() {
+                    let file = match File::create(name) {
+                            Ok(file) => file,
+                            Err(err) => { eprintln!("cannot create {}: {}", name, err); return ExitCode::FAILURE; },
+                        };
+                    let mut out = BufWriter::new(file);
+                    let hdr = format!("YUV4MPEG2 W{} H{} F30:1 Ip A1:1 C420jpeg\n", frame.width, frame.height);
+                    if out.write_all(hdr.as_bytes()).is_err() {
+                        eprintln!("error writing {}", name);
+                        return ExitCode::FAILURE;
+                    }
+                    y4m_out = Some(out);
+                }
+                if let Some(ref mut out) = y4m_out {
+                    if frame.write_y4m_frame(out).is_err() {
+                        eprintln!("error writing {}", name);
+                        return ExitCode::FAILURE;
+                    }
+                }
+            }
+            if let Some(ref prefix) = png_prefix {
+                let name = format!("{}{:06}.png", prefix, nframes);
+                if let Err(err) = frame.write_png(&name) {
+                    eprintln!("error writing {}: {}", name, err);
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+        nframes += 1;
+    }
+    if let Some(mut out) = y4m_out {
+        if out.flush().is_err() {
+            eprintln!("error writing {}", y4m_name.unwrap_or_default());
+            return ExitCode::FAILURE;
+        }
+    }
+    if report {
+        println!("{} packet(s) read, {} frame(s) decoded", pkt_no, nframes);
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
@@ -0,0 +1,263 @@
+//! Duck DK3 and DK4 IMA ADPCM decoders.
+//!
+//! Both codecs are IMA ADPCM variants with a small header in every block. DK4 stores the initial predictor and step
+//! index for each channel and then interleaves nibbles for the channels (high nibble first). DK3 is stereo-only and codes
+//! half-sum and half-difference of the channels instead, with the sum channel having twice as many codes as the difference
+//! channel. Output is interleaved 16-bit PCM.
+use nihav_core::codecs::*;
+use nihav_codec_support::codecs::imaadpcm::*;
+
+const DK3_HEADER_SIZE: usize = 16;
+const DK4_CH_HEADER_SIZE: usize = 4;
+
+fn read_i16le(src: &[u8]) -> i16 {
+    i16::from_le_bytes([src[0], src[1]])
+}
+
+struct DuckADPCMDecoder {
+    ainfo:      NAAudioInfo,
+    info:       NACodecInfoRef,
+    chmap:      NAChannelMap,
+    ch_state:   [IMAState; 2],
+    block_len:  usize,
+    is_dk3:     bool,
+}
+
+impl DuckADPCMDecoder {
+    fn new(is_dk3: bool) -> Self {
+        Self {
+            ainfo:      NAAudioInfo::new(0, 1, SND_S16_FORMAT, 0),
+            info:       NACodecInfo::new_dummy(),
+            chmap:      NAChannelMap::new(),
+            ch_state:   [IMAState::new(), IMAState::new()],
+            block_len:  0,
+            is_dk3,
+        }
+    }
+    fn dk3_samples(len: usize) -> usize {
+        (len - DK3_HEADER_SIZE) * 2 / 3 * 2
+    }
+    fn dk4_samples(len: usize, channels: usize) -> usize {
+        1 + (len - DK4_CH_HEADER_SIZE * channels) * 2 / channels
+    }
+    fn decode_dk3(&mut self, src: &[u8], dst: &mut [i16]) -> DecoderResult<()> {
+        let sum_pred  = read_i16le(&src[10..]);
+        let diff_pred = read_i16le(&src[12..]);
+        let sum_step  = src[14];
+        let diff_step = src[15];
+        validate!(sum_step <= IMA_MAX_STEP && diff_step <= IMA_MAX_STEP);
+        self.ch_state[0].reset(sum_pred, sum_step);
+        self.ch_state[1].reset(diff_pred, diff_step);
+
+        // nibbles are read starting from the low one
+        let data = &src[DK3_HEADER_SIZE..];
+        let get_nibble = |idx: usize| if (idx & 1) == 0 { data[idx >> 1] & 0xF } else { data[idx >> 1] >> 4 };
+        for (i, quad) in dst.chunks_exact_mut(4).enumerate() {
+            self.ch_state[0].expand_sample(get_nibble(i * 3));
+            self.ch_state[1].expand_sample(get_nibble(i * 3 + 1));
+            let diff = self.ch_state[1].predictor;
+            quad[0] = (self.ch_state[0].predictor + diff) as i16;
+            quad[1] = (self.ch_state[0].predictor - diff) as i16;
+            self.ch_state[0].expand_sample(get_nibble(i * 3 + 2));
+            quad[2] = (self.ch_state[0].predictor + diff) as i16;
+            quad[3] = (self.ch_state[0].predictor - diff) as i16;
+        }
+        Ok(())
+    }
+    fn decode_dk4(&mut self, src: &[u8], dst: &mut [i16], channels: usize) -> DecoderResult<()> {
+        for (ch, hdr) in src.chunks_exact(DK4_CH_HEADER_SIZE).take(channels).enumerate() {
+            let pred = read_i16le(hdr);
+            let step = read_i16le(&hdr[2..]);
+            validate!((0..=i16::from(IMA_MAX_STEP)).contains(&step));
+            self.ch_state[ch].reset(pred, step as u8);
+            dst[ch] = pred;
+        }
+        let data = &src[DK4_CH_HEADER_SIZE * channels..];
+        if channels == 1 {
+            for (pair, &b) in dst[1..].chunks_exact_mut(2).zip(data.iter()) {
+                pair[0] = self.ch_state[0].expand_sample(b >> 4);
+                pair[1] = self.ch_state[0].expand_sample(b & 0xF);
+            }
+        } else {
+            for (pair, &b) in dst[2..].chunks_exact_mut(2).zip(data.iter()) {
+                pair[0] = self.ch_state[0].expand_sample(b >> 4);
+                pair[1] = self.ch_state[1].expand_sample(b & 0xF);
+            }
+        }
+        Ok(())
+    }
+    fn decode_block(&mut self, src: &[u8]) -> DecoderResult<NABufferType> {
+        let src = if self.block_len > 0 && src.len() > self.block_len { &src[..self.block_len] } else { src };
+        let channels = usize::from(self.ainfo.get_channels());
+        let nsamples = if self.is_dk3 {
+                validate!(src.len() > DK3_HEADER_SIZE);
+                Self::dk3_samples(src.len())
+            } else {
+                validate!(src.len() >= DK4_CH_HEADER_SIZE * channels);
+                Self::dk4_samples(src.len(), channels)
+            };
+        validate!(nsamples > 0);
+        let mut abuf = alloc_audio_buffer(self.ainfo, nsamples, self.chmap.clone())?;
+        if let NABufferType::AudioI16(ref mut adata) = abuf {
+            let dst = adata.get_data_mut().ok_or(DecoderError::AllocError)?;
+            if self.is_dk3 {
+                self.decode_dk3(src, dst)?;
+            } else {
+                self.decode_dk4(src, dst, channels)?;
+            }
+        } else {
+            return Err(DecoderError::Bug);
+        }
+        Ok(abuf)
+    }
+}
+
+impl NADecoder for DuckADPCMDecoder {
+    fn init(&mut self, _supp: &mut NADecoderSupport, info: NACodecInfoRef) -> DecoderResult<()> {
+        if let NACodecTypeInfo::Audio(ainfo) = info.get_properties() {
+            let channels = ainfo.get_channels();
+            validate!(ainfo.get_sample_rate() > 0);
+            if self.is_dk3 {
+                validate!(channels == 2);
+            } else {
+                validate!(channels == 1 || channels == 2);
+            }
+            self.block_len = ainfo.get_block_len();
+            let out_block_len = if self.is_dk3 {
+                    if self.block_len > DK3_HEADER_SIZE { Self::dk3_samples(self.block_len) } else { 0 }
+                } else if self.block_len >= DK4_CH_HEADER_SIZE * usize::from(channels) {
+                    Self::dk4_samples(self.block_len, usize::from(channels))
+                } else {
+                    0
+                };
+            self.chmap = NAChannelMap::new();
+            if channels == 1 {
+                self.chmap.add_channel(NAChannelType::C);
+            } else {
+                self.chmap.add_channels(&[NAChannelType::L, NAChannelType::R]);
+            }
+            self.ainfo = NAAudioInfo::new(ainfo.get_sample_rate(), channels, SND_S16_FORMAT, out_block_len);
+            self.info = NACodecInfo::new_ref(info.get_name(), NACodecTypeInfo::Audio(self.ainfo), info.get_extradata()).into_ref();
+            Ok(())
+        } else {
+            Err(DecoderError::InvalidData)
+        }
+    }
+    fn decode(&mut self, _supp: &mut NADecoderSupport, pkt: &NAPacket) -> DecoderResult<NAFrameRef> {
+        let src = pkt.get_buffer();
+        let abuf = self.decode_block(&src)?;
+        let duration = abuf.get_audio_length() as u64;
+        let mut frm = NAFrame::new_from_pkt(pkt, self.info.clone(), abuf);
+        frm.set_duration(Some(duration));
+        frm.set_keyframe(true);
+        frm.set_frame_type(FrameType::I);
+        Ok(frm.into_ref())
+    }
+    fn flush(&mut self) {
+    }
+}
+
+/// Constructs a new instance of DK3 ADPCM decoder.
+pub fn get_decoder_dk3() -> Box<dyn NADecoder + Send> {
+    Box::new(DuckADPCMDecoder::new(true))
+}
+
+/// Constructs a new instance of DK4 ADPCM decoder.
+pub fn get_decoder_dk4() -> Box<dyn NADecoder + Send> {
+    Box::new(DuckADPCMDecoder::new(false))
+}
+
+#[cfg(test)]
+mod test {
+    use nihav_core::codecs::*;
+    use nihav_codec_support::test::dec_audio::*;
+    use nihav_codec_support::test::dec_video::read_raw_packets;
+    use super::*;
+
+    fn asset_name(name: &str) -> String {
+        format!("{}/assets/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+    fn init_decoder(is_dk3: bool, channels: u8, block_len: usize) -> DecoderResult<DuckADPCMDecoder> {
+        let mut dec = DuckADPCMDecoder::new(is_dk3);
+        let mut supp = NADecoderSupport::new();
+        let ainfo = NAAudioInfo::new(22050, channels, SND_S16_FORMAT, block_len);
+        let info = NACodecInfo::new(if is_dk3 { "adpcm-dk3" } else { "adpcm-dk4" }, NACodecTypeInfo::Audio(ainfo), None).into_ref();
+        dec.init(&mut supp, info)?;
+        Ok(dec)
+    }
+    fn test_dk(name: &str, codec: &'static str, channels: u8, block_len: usize, expected: &[u32; 4]) {
+        let mut dec_reg = RegisteredDecoders::new();
+        crate::codecs::duck_register_all_decoders(&mut dec_reg);
+        let mut dec = (dec_reg.find_decoder(codec).unwrap())();
+        let mut supp = NADecoderSupport::new();
+        let ainfo = NAAudioInfo::new(22050, channels, SND_S16_FORMAT, block_len);
+        let info = NACodecInfo::new(codec, NACodecTypeInfo::Audio(ainfo), None).into_ref();
+        dec.init(&mut supp, info).unwrap();
+        let mut pts = 0;
+        test_decoding_audio_md5(&asset_name(name), |src| {
+                let pkt = NAPacket::new(0, NATimeInfo::new(Some(pts), None, None, 1, 22050), true, src.to_vec());
+                let frm = dec.decode(&mut supp, &pkt)?;
+                let ainfo = frm.get_info().get_properties().get_audio_info().unwrap();
+                assert_eq!(ainfo.get_format(), SND_S16_FORMAT);
+                assert_eq!(ainfo.get_channels(), channels);
+                let buf = frm.get_buffer();
+                assert_eq!(buf.get_chmap().unwrap().num_channels(), usize::from(channels));
+                assert_eq!(frm.get_duration(), Some(buf.get_audio_length() as u64));
+                pts += buf.get_audio_length() as u64;
+                Ok(buf)
+            }, expected);
+    }
+    #[test]
+    fn test_dk_errors() {
+        assert_eq!(init_decoder(true, 1, 0).err(), Some(DecoderError::InvalidData));
+        assert_eq!(init_decoder(false, 3, 0).err(), Some(DecoderError::InvalidData));
+        let mut dec = DuckADPCMDecoder::new(false);
+        let info = NACodecInfo::new("adpcm-dk4", NACodecTypeInfo::None, None).into_ref();
+        assert_eq!(dec.init(&mut NADecoderSupport::new(), info).err(), Some(DecoderError::InvalidData));
+
+        let mut dec = init_decoder(true, 2, 0).unwrap();
+        assert_eq!(dec.decode_block(&[0; 16]).err(), Some(DecoderError::InvalidData));
+        let mut hdr = [0; 20];
+        hdr[14] = 89;
+        assert_eq!(dec.decode_block(&hdr).err(), Some(DecoderError::InvalidData));
+        hdr[14] = 88;
+        assert_eq!(dec.decode_block(&hdr).unwrap().get_audio_length(), 4);
+
+        let mut dec = init_decoder(false, 2, 0).unwrap();
+        assert_eq!(dec.decode_block(&[0; 7]).err(), Some(DecoderError::InvalidData));
+        let hdr = [0x34, 0x12, 0, 0, 0xCD, 0xAB, 0xFF, 0xFF];
+        assert_eq!(dec.decode_block(&hdr).err(), Some(DecoderError::InvalidData));
+        let hdr = [0x34, 0x12, 0, 0, 0xCD, 0xAB, 0, 0];
+        let abuf = dec.decode_block(&hdr).unwrap().get_abuf_i16().unwrap();
+        assert_eq!(abuf.get_data().as_slice(), &[0x1234, -0x5433]);
+    }
+    #[test]
+    fn test_dk_block_len() {
+        let pkts = read_raw_packets(&asset_name("dk4_mono.adpcm"));
+        let mut dec = init_decoder(false, 1, 256).unwrap();
+        assert_eq!(dec.info.get_properties().get_audio_info().unwrap().get_block_len(), 505);
+        let ref_buf = dec.decode_block(&pkts[0]).unwrap().get_abuf_i16().unwrap();
+        let mut long_pkt = pkts[0].clone();
+        long_pkt.extend_from_slice(&[0x5A; 64]);
+        let buf = dec.decode_block(&long_pkt).unwrap().get_abuf_i16().unwrap();
+        assert_eq!(buf.get_length(), 505);
+        assert_eq!(buf.get_data(), ref_buf.get_data());
+
+        let dec = init_decoder(true, 2, 256).unwrap();
+        assert_eq!(dec.info.get_properties().get_audio_info().unwrap().get_block_len(), 320);
+    }
+    // Fixtures are generated by dkadpcmsynth, the hashes are the decoder's own output
+    // recorded to catch regressions rather than values verified against FFmpeg.
+    #[test]
+    fn test_dk3() {
+        test_dk("dk3.adpcm", "adpcm-dk3", 2, 256, &[0xe0619309, 0x898544bc, 0xec7bca80, 0x2bc6a5fc]);
+    }
+    #[test]
+    fn test_dk4_mono() {
+        test_dk("dk4_mono.adpcm", "adpcm-dk4", 1, 256, &[0x2bc29d4e, 0x3cd81c87, 0x6fc7b727, 0x9187992e]);
+    }
+    #[test]
+    fn test_dk4_stereo() {
+        test_dk("dk4_stereo.adpcm", "adpcm-dk4", 2, 512, &[0x15f8573c, 0x074a21c9, 0x38640385, 0x0dd670ae]);
+    }
+}
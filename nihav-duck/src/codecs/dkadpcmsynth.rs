@@ -0,0 +1,168 @@
+//! Synthetic DK3/DK4 ADPCM stream generator for the decoder tests.
+//!
+//! The signal is a mix of triangle waves and noise with occasional loud passages that make the encoder
+//! hit the sample range limits. Each block is stored as a separate packet and the stream ends with a short block.
+use nihav_codec_support::codecs::imaadpcm::*;
+use super::testsynth::*;
+
+const DK3_HEADER_SIZE: usize = 16;
+
+/// Stream parameters for the generator.
+#[derive(Clone,Copy)]
+pub struct DKSynthConfig {
+    pub dk3:        bool,
+    pub channels:   u8,
+    pub block_len:  usize,
+    pub nblocks:    usize,
+    pub seed:       u32,
+}
+
+struct Oscillator {
+    phase:  u32,
+    inc:    u32,
+    amp:    i32,
+}
+
+impl Oscillator {
+    fn new(rng: &mut RNG) -> Self {
+        Self { phase: rng.next(), inc: 0x100_0000 + rng.range(0x2000_0000), amp: 1000 + rng.range(12000) as i32 }
+    }
+    fn next(&mut self) -> i32 {
+        let pos = (self.phase >> 16) as i32;
+        self.phase = self.phase.wrapping_add(self.inc);
+        ((pos - 0x8000).abs() - 0x4000) * self.amp / 0x4000
+    }
+}
+
+struct SignalGen {
+    rng:    RNG,
+    osc:    Vec<[Oscillator; 2]>,
+    loud:   usize,
+}
+
+impl SignalGen {
+    fn new(seed: u32, channels: usize) -> Self {
+        let mut rng = RNG::new(seed);
+        let mut osc = Vec::with_capacity(channels);
+        for _ in 0..channels {
+            osc.push([Oscillator::new(&mut rng), Oscillator::new(&mut rng)]);
+        }
+        Self { rng, osc, loud: 0 }
+    }
+    fn next(&mut self, dst: &mut [i32]) {
+        if self.loud > 0 {
+            self.loud -= 1;
+        } else if self.rng.chance(1, 400) {
+            self.loud = 20 + self.rng.range(60) as usize;
+        }
+        let scale = if self.loud > 0 { 4 } else { 1 };
+        for (el, osc) in dst.iter_mut().zip(self.osc.iter_mut()) {
+            let noise = self.rng.range(401) as i32 - 200;
+            let val = (osc[0].next() + osc[1].next()) * scale + noise;
+            *el = val.max(i32::from(i16::MIN)).min(i32::from(i16::MAX));
+        }
+    }
+}
+
+fn encode_sample(state: &mut IMAState, sample: i32) -> u8 {
+    let step = IMA_STEP_TABLE[state.step];
+    let diff = sample - state.predictor;
+    let sign = if diff < 0 { 8 } else { 0 };
+    let nibble = sign | ((diff.abs() * 4 / step).min(7) as u8);
+    state.expand_sample(nibble);
+    nibble
+}
+
+struct DKSynth {
+    cfg:    DKSynthConfig,
+    sig:    SignalGen,
+    state:  [IMAState; 2],
+}
+
+impl DKSynth {
+    fn new(cfg: DKSynthConfig) -> Self {
+        Self {
+            cfg,
+            sig:    SignalGen::new(cfg.seed, usize::from(cfg.channels)),
+            state:  [IMAState::new(), IMAState::new()],
+        }
+    }
+    fn gen_dk3_block(&mut self, len: usize) -> Vec<u8> {
+        let mut block = vec![0; DK3_HEADER_SIZE];
+        block[10..12].copy_from_slice(&(self.state[0].predictor as i16).to_le_bytes());
+        block[12..14].copy_from_slice(&(self.state[1].predictor as i16).to_le_bytes());
+        block[14] = self.state[0].step as u8;
+        block[15] = self.state[1].step as u8;
+
+        let iterations = (len - DK3_HEADER_SIZE) * 2 / 3;
+        let mut nibbles = Vec::with_capacity(iterations * 3 + 1);
+        let mut smp = [0; 2];
+        for _ in 0..iterations {
+            self.sig.next(&mut smp);
+            nibbles.push(encode_sample(&mut self.state[0], (smp[0] + smp[1]) >> 1));
+            nibbles.push(encode_sample(&mut self.state[1], (smp[0] - smp[1]) >> 1));
+            self.sig.next(&mut smp);
+            nibbles.push(encode_sample(&mut self.state[0], (smp[0] + smp[1]) >> 1));
+        }
+        if (nibbles.len() & 1) != 0 {
+            nibbles.push(0);
+        }
+        for pair in nibbles.chunks_exact(2) {
+            block.push(pair[0] | (pair[1] << 4));
+        }
+        block.resize(len, 0);
+        block
+    }
+    fn gen_dk4_block(&mut self, len: usize) -> Vec<u8> {
+        let channels = usize::from(self.cfg.channels);
+        let mut block = Vec::with_capacity(len);
+        let mut smp = [0; 2];
+        self.sig.next(&mut smp[..channels]);
+        for (state, &sample) in self.state.iter_mut().zip(smp.iter()).take(channels) {
+            let step = state.step as u8;
+            state.reset(sample as i16, step);
+            block.extend_from_slice(&(sample as i16).to_le_bytes());
+            block.extend_from_slice(&u16::from(step).to_le_bytes());
+        }
+        while block.len() < len {
+            if channels == 1 {
+                self.sig.next(&mut smp[..1]);
+                let hi = encode_sample(&mut self.state[0], smp[0]);
+                self.sig.next(&mut smp[..1]);
+                let lo = encode_sample(&mut self.state[0], smp[0]);
+                block.push((hi << 4) | lo);
+            } else {
+                self.sig.next(&mut smp);
+                let hi = encode_sample(&mut self.state[0], smp[0]);
+                let lo = encode_sample(&mut self.state[1], smp[1]);
+                block.push((hi << 4) | lo);
+            }
+        }
+        block
+    }
+    fn gen_block(&mut self, len: usize) -> Vec<u8> {
+        if self.cfg.dk3 {
+            self.gen_dk3_block(len)
+        } else {
+            self.gen_dk4_block(len)
+        }
+    }
+}
+
+/// Generates the whole stream in raw packet format (32-bit big-endian size followed by packet data).
+pub fn gen_stream(cfg: DKSynthConfig) -> Vec<u8> {
+    let mut synth = DKSynth::new(cfg);
+    let mut data = Vec::new();
+    for i in 0..=cfg.nblocks {
+        let len = if i < cfg.nblocks { cfg.block_len } else { cfg.block_len / 2 + 1 };
+        put_packet(&mut data, &synth.gen_block(len));
+    }
+    data
+}
+
+/// Test fixtures stored in `assets/` and the parameters they are generated from.
+pub const TEST_STREAMS: [(&str, DKSynthConfig); 3] = [
+    ("dk3.adpcm",        DKSynthConfig { dk3: true,  channels: 2, block_len: 256, nblocks: 12, seed: 1 }),
+    ("dk4_mono.adpcm",   DKSynthConfig { dk3: false, channels: 1, block_len: 256, nblocks: 12, seed: 2 }),
+    ("dk4_stereo.adpcm", DKSynthConfig { dk3: false, channels: 2, block_len: 512, nblocks: 12, seed: 3 }),
+];
@@ -1,3 +1,4 @@
+use nihav_core::codecs::*;
 
 #[cfg(debug_assertions)]
 macro_rules! validate {
@@ -14,10 +15,62 @@ macro_rules! validate {
 #[allow(clippy::too_many_arguments)]
 #[allow(clippy::useless_let_if_seq)]
 pub mod vpcommon;
+#[cfg(test)]
+mod testsynth;
 #[allow(clippy::needless_range_loop)]
 #[allow(clippy::useless_let_if_seq)]
 #[allow(clippy::too_many_arguments)]
 mod vp56;
+#[allow(clippy::needless_range_loop)]
+pub mod vp3;
+mod vp3data;
+#[cfg(test)]
+mod vp3synth;
 mod vp6data;
 #[allow(clippy::needless_range_loop)]
 pub mod vp6;
+pub mod vp6enc;
+#[cfg(test)]
+#[allow(clippy::needless_range_loop)]
+mod vp6synth;
+mod vp78;
+mod vp78data;
+mod vp78dsp;
+#[allow(clippy::needless_range_loop)]
+pub mod vp7;
+mod vp7data;
+mod vp7dsp;
+#[cfg(test)]
+mod vp7synth;
+#[allow(clippy::needless_range_loop)]
+pub mod vp8;
+mod vp8data;
+mod vp8dsp;
+#[cfg(test)]
+mod vp8synth;
+mod vp8l;
+pub mod webp;
+#[cfg(test)]
+mod webpsynth;
+pub mod dkadpcm;
+#[cfg(test)]
+mod dkadpcmsynth;
+
+const DUCK_CODECS: &[DecoderInfo] = &[
+    DecoderInfo { name: "vp6", get_decoder: vp6::get_decoder_vp6 },
+    DecoderInfo { name: "vp6f", get_decoder: vp6::get_decoder_vp6f },
+    DecoderInfo { name: "vp6a", get_decoder: vp6::get_decoder_vp6_alpha },
+    DecoderInfo { name: "vp3", get_decoder: vp3::get_decoder_vp3 },
+    DecoderInfo { name: "theora", get_decoder: vp3::get_decoder_theora },
+    DecoderInfo { name: "vp7", get_decoder: vp7::get_decoder_vp7 },
+    DecoderInfo { name: "vp8", get_decoder: vp8::get_decoder_vp8 },
+    DecoderInfo { name: "adpcm-dk3", get_decoder: dkadpcm::get_decoder_dk3 },
+    DecoderInfo { name: "adpcm-dk4", get_decoder: dkadpcm::get_decoder_dk4 },
+];
+
+/// Registers all available codecs provided by this crate.
+pub fn duck_register_all_decoders(rd: &mut RegisteredDecoders) {
+    for decoder in DUCK_CODECS.iter() {
+        rd.add_decoder(*decoder);
+    }
+}
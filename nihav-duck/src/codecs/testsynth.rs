@@ -0,0 +1,164 @@
+//! Common parts of the synthetic stream generators used by the decoder tests.
+//!
+//! Generators store their output in `assets/` as test fixtures, those are listed in [`all_fixtures`]
+//! so that checking and regenerating them is done in one place.
+use super::vpcommon::BoolCoder;
+
+pub(super) struct BoolEncoder {
+    dst:        Vec<u8>,
+    range:      u32,
+    bottom:     u32,
+    bit_count:  u32,
+}
+
+impl BoolEncoder {
+    pub(super) fn new() -> Self {
+        Self { dst: Vec::new(), range: 255, bottom: 0, bit_count: 24 }
+    }
+    fn add_one(&mut self) {
+        for el in self.dst.iter_mut().rev() {
+            if *el == 0xFF {
+                *el = 0;
+            } else {
+                *el += 1;
+                return;
+            }
+        }
+    }
+    pub(super) fn put_prob(&mut self, bit: bool, prob: u8) {
+        let split = 1 + (((self.range - 1) * u32::from(prob)) >> 8);
+        if bit {
+            self.bottom += split;
+            self.range  -= split;
+        } else {
+            self.range   = split;
+        }
+        while self.range < 128 {
+            self.range <<= 1;
+            if (self.bottom & (1 << 31)) != 0 {
+                self.add_one();
+            }
+            self.bottom <<= 1;
+            self.bit_count -= 1;
+            if self.bit_count == 0 {
+                self.dst.push((self.bottom >> 24) as u8);
+                self.bottom &= (1 << 24) - 1;
+                self.bit_count = 8;
+            }
+        }
+    }
+    pub(super) fn put_bool(&mut self, bit: bool) {
+        self.put_prob(bit, 128);
+    }
+    pub(super) fn put_bits(&mut self, val: u32, bits: u8) {
+        for i in (0..bits).rev() {
+            self.put_bool(((val >> i) & 1) != 0);
+        }
+    }
+    pub(super) fn put_probability(&mut self, prob: u8) {
+        self.put_bits(u32::from(prob >> 1), 7);
+    }
+    pub(super) fn finish(mut self) -> Vec<u8> {
+        let mut c = self.bit_count;
+        let mut v = self.bottom;
+        if (v & (1 << (32 - c))) != 0 {
+            self.add_one();
+        }
+        v <<= c & 7;
+        c >>= 3;
+        for _ in 0..c {
+            v <<= 8;
+        }
+        for _ in 0..4 {
+            self.dst.push((v >> 24) as u8);
+            v <<= 8;
+        }
+        self.dst
+    }
+}
+
+pub(super) struct RNG {
+    state:  u32,
+}
+
+impl RNG {
+    pub(super) fn new(seed: u32) -> Self { Self { state: seed | 1 } }
+    pub(super) fn next(&mut self) -> u32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        self.state
+    }
+    pub(super) fn range(&mut self, max: u32) -> u32 { self.next() % max }
+    pub(super) fn chance(&mut self, num: u32, den: u32) -> bool { self.range(den) < num }
+    pub(super) fn value(&mut self, max: i16) -> i16 {
+        let mag = match self.range(16) {
+                0..=9   => 1 + self.range(2),
+                10..=13 => 3 + self.range(4),
+                14      => 7 + self.range(60),
+                _       => 67 + self.range(2048),
+            } as i16;
+        let mag = mag.min(max);
+        if self.chance(1, 2) { mag } else { -mag }
+    }
+}
+
+pub fn asset_name(name: &str) -> String {
+    format!("{}/assets/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+/// Appends a packet to raw stream in the format of the test fixtures (32-bit big-endian size followed by the data).
+pub fn put_packet(dst: &mut Vec<u8>, pkt: &[u8]) {
+    dst.extend_from_slice(&(pkt.len() as u32).to_be_bytes());
+    dst.extend_from_slice(pkt);
+}
+
+fn gen_fixtures<C: Copy>(list: &[(&'static str, C)], gen: impl Fn(C) -> Vec<u8>) -> Vec<(&'static str, Vec<u8>)> {
+    list.iter().map(|&(name, cfg)| (name, gen(cfg))).collect()
+}
+
+/// Returns names and contents of all test fixtures produced by the generators.
+pub fn all_fixtures() -> Vec<(&'static str, Vec<u8>)> {
+    let mut fixtures = Vec::new();
+    fixtures.extend(gen_fixtures(&super::vp3synth::TEST_STREAMS, super::vp3synth::gen_stream));
+    fixtures.extend(gen_fixtures(&super::vp6synth::TEST_STREAMS, super::vp6synth::gen_stream));
+    fixtures.extend(gen_fixtures(&super::vp7synth::TEST_STREAMS, super::vp7synth::gen_stream));
+    fixtures.extend(gen_fixtures(&super::vp8synth::TEST_STREAMS, super::vp8synth::gen_stream));
+    fixtures.extend(gen_fixtures(&super::webpsynth::TEST_IMAGES, |cfg| super::webpsynth::gen_image(&cfg).0));
+    fixtures.extend(gen_fixtures(&super::dkadpcmsynth::TEST_STREAMS, super::dkadpcmsynth::gen_stream));
+    fixtures
+}
+
+#[test]
+fn test_bool_encoder() {
+    let mut rng = RNG::new(42);
+    let mut bits = Vec::new();
+    let mut bc = BoolEncoder::new();
+    for _ in 0..10000 {
+        let prob = (rng.range(255) + 1) as u8;
+        let bit = rng.range(256) >= u32::from(prob);
+        bc.put_prob(bit, prob);
+        bits.push((bit, prob));
+    }
+    let data = bc.finish();
+    let mut br = BoolCoder::new(&data).unwrap();
+    for &(bit, prob) in bits.iter() {
+        assert_eq!(br.read_prob(prob), bit);
+    }
+}
+
+#[test]
+fn test_fixtures_up_to_date() {
+    for (name, data) in all_fixtures().into_iter() {
+        let stored = std::fs::read(asset_name(name)).unwrap_or_else(|_| panic!("cannot read {}", name));
+        assert!(stored == data, "{} is outdated, run the ignored regenerate_fixtures test", name);
+    }
+}
+
+#[test]
+#[ignore]
+fn regenerate_fixtures() {
+    for (name, data) in all_fixtures().into_iter() {
+        std::fs::write(asset_name(name), data).unwrap();
+    }
+}
@@ -0,0 +1,98 @@
+use super::vpcommon::VPMBType;
+
+// block positions inside a superblock (rows go bottom-up)
+pub const VP3_HILBERT_ORDER: [(usize, usize); 16] = [
+    (0, 0), (1, 0), (1, 1), (0, 1), (0, 2), (0, 3), (1, 3), (1, 2),
+    (2, 2), (2, 3), (3, 3), (3, 2), (3, 1), (2, 1), (2, 0), (3, 0)
+];
+pub const VP3_MB_ORDER: [(usize, usize); 4] = [ (0, 0), (0, 1), (1, 1), (1, 0) ];
+
+pub const VP3_MODES: [VPMBType; 8] = [
+    VPMBType::InterNoMV, VPMBType::Intra, VPMBType::InterMV, VPMBType::InterNearest,
+    VPMBType::InterNear, VPMBType::GoldenNoMV, VPMBType::GoldenMV, VPMBType::InterFourMV
+];
+
+pub const VP3_MODE_ALPHABETS: [[u8; 8]; 6] = [
+    [ 3, 4, 2, 0, 1, 5, 6, 7 ],
+    [ 3, 4, 0, 2, 1, 5, 6, 7 ],
+    [ 3, 2, 4, 0, 1, 5, 6, 7 ],
+    [ 3, 2, 0, 4, 1, 5, 6, 7 ],
+    [ 0, 3, 4, 2, 1, 5, 6, 7 ],
+    [ 0, 5, 3, 4, 2, 1, 6, 7 ],
+];
+
+// weights for down-left, down, down-right and left neighbours indexed by their availability
+// (bit 0 - left, bit 1 - down-right, bit 2 - down, bit 3 - down-left)
+pub const VP3_DC_WEIGHTS: [[i32; 4]; 16] = [
+    [    0,   0,   0,   0 ],
+    [    0,   0,   0, 128 ],
+    [    0,   0, 128,   0 ],
+    [    0,   0,  53,  75 ],
+    [    0, 128,   0,   0 ],
+    [    0,  64,   0,  64 ],
+    [    0, 128,   0,   0 ],
+    [    0,   0,  53,  75 ],
+    [  128,   0,   0,   0 ],
+    [    0,   0,   0, 128 ],
+    [   64,   0,  64,   0 ],
+    [    0,   0,  53,  75 ],
+    [    0, 128,   0,   0 ],
+    [ -104, 116,   0, 116 ],
+    [   24,  80,  24,   0 ],
+    [ -104, 116,   0, 116 ],
+];
+
+pub const VP3_LONG_RUN_BASE: [usize; 7] = [ 1, 2, 4, 6, 10, 18, 34 ];
+pub const VP3_LONG_RUN_BITS: [u8; 7] = [ 0, 1, 1, 2, 3, 4, 12 ];
+pub const VP3_SHORT_RUN_BASE: [usize; 6] = [ 1, 3, 5, 7, 11, 15 ];
+pub const VP3_SHORT_RUN_BITS: [u8; 6] = [ 1, 1, 1, 2, 2, 4 ];
+
+// VP3.1 defaults, Theora streams transmit these in the setup header
+pub const VP31_LOOP_LIMITS: [u8; 64] = [
+    30, 25, 20, 20, 15, 15, 14, 14, 13, 13, 12, 12, 11, 11, 10, 10,
+     9,  9,  8,  8,  7,  7,  7,  7,  6,  6,  6,  6,  5,  5,  5,  5,
+     4,  4,  4,  4,  3,  3,  3,  3,  2,  2,  2,  2,  2,  2,  2,  2,
+     0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0,  0
+];
+pub const VP31_AC_SCALE: [u16; 64] = [
+    500, 450, 400, 370, 340, 310, 285, 265, 245, 225, 210, 195, 185, 180, 170, 160,
+    150, 145, 135, 130, 125, 115, 110, 107, 100,  96,  93,  89,  85,  82,  75,  74,
+     70,  68,  64,  60,  57,  56,  52,  50,  49,  45,  44,  43,  40,  38,  37,  35,
+     33,  32,  30,  29,  28,  25,  24,  22,  21,  19,  18,  17,  15,  13,  12,  10
+];
+pub const VP31_DC_SCALE: [u16; 64] = [
+    220, 200, 190, 180, 170, 170, 160, 160, 150, 150, 140, 140, 130, 130, 120, 120,
+    110, 110, 100, 100,  90,  90,  90,  80,  80,  80,  70,  70,  70,  60,  60,  60,
+     60,  50,  50,  50,  50,  40,  40,  40,  40,  40,  30,  30,  30,  30,  30,  30,
+     30,  20,  20,  20,  20,  20,  20,  20,  20,  10,  10,  10,  10,  10,  10,  10
+];
+pub const VP31_BASE_MATRICES: [[u8; 64]; 3] = [
+  [
+    16, 11, 10, 16,  24,  40,  51,  61,
+    12, 12, 14, 19,  26,  58,  60,  55,
+    14, 13, 16, 24,  40,  57,  69,  56,
+    14, 17, 22, 29,  51,  87,  80,  62,
+    18, 22, 37, 58,  68, 109, 103,  77,
+    24, 35, 55, 64,  81, 104, 113,  92,
+    49, 64, 78, 87, 103, 121, 120, 101,
+    72, 92, 95, 98, 112, 100, 103,  99
+  ], [
+    17, 18, 24, 47, 99, 99, 99, 99,
+    18, 21, 26, 66, 99, 99, 99, 99,
+    24, 26, 56, 99, 99, 99, 99, 99,
+    47, 66, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99
+  ], [
+    16, 16, 16, 20, 24,  28,  32,  40,
+    16, 16, 20, 24, 28,  32,  40,  48,
+    16, 20, 24, 28, 32,  40,  48,  64,
+    20, 24, 28, 32, 40,  48,  64,  64,
+    24, 28, 32, 40, 48,  64,  64,  64,
+    28, 32, 40, 48, 64,  64,  64,  96,
+    32, 40, 48, 64, 64,  64,  96, 128,
+    40, 48, 64, 64, 64,  96, 128, 128
+  ]
+];
@@ -0,0 +1,560 @@
+//! Synthetic Theora stream generator for the decoder tests.
+//!
+//! Like the VP6 generator this is not an encoder: coded blocks, modes, motion vectors and tokens
+//! are picked at random and written with the same syntax the decoder expects.
+//! Headers use VP3.1 quantisers and loop filter limits while the token Huffman tables are built from random statistics.
+//! Besides Theora 3.2 the generator can produce streams with the older Theora header layout and raw VP3.1 frames
+//! (with the Huffman tables in a setup header).
+use nihav_core::io::bitwriter::*;
+use super::vp3data::*;
+use super::testsynth::*;
+
+/// Stream parameters for the generator.
+#[derive(Clone,Copy)]
+pub struct TheoraSynthConfig {
+    pub mb_w:           usize,
+    pub mb_h:           usize,
+    pub nframes:        usize,
+    pub key_int:        usize,
+    /// Maximum number of quantisers per frame (1-3, Theora 3.2 only).
+    pub max_qis:        usize,
+    pub seed:           u32,
+    /// Theora version, zero means raw VP3.1 stream.
+    pub version:        u32,
+}
+
+const THEORA_VERSION_3_2: u32 = 0x030201;
+
+// Huffman code built from random symbol weights
+struct HuffCode {
+    // children for internal nodes (the first 32 nodes are leaves)
+    nodes:  Vec<[usize; 2]>,
+    codes:  [(u32, u8); 32],
+}
+
+impl HuffCode {
+    fn new(rng: &mut RNG) -> Self {
+        let mut weights: Vec<(u32, usize)> = (0..32).map(|i| (rng.range(1000) + 1, i)).collect();
+        let mut nodes = Vec::new();
+        while weights.len() > 1 {
+            weights.sort_by_key(|w| std::cmp::Reverse(w.0));
+            let (w0, n0) = weights.pop().unwrap();
+            let (w1, n1) = weights.pop().unwrap();
+            nodes.push([n0, n1]);
+            weights.push((w0 + w1, 32 + nodes.len() - 1));
+        }
+        let mut huff = Self { nodes, codes: [(0, 0); 32] };
+        huff.assign_codes(weights[0].1, 0, 0);
+        huff
+    }
+    fn assign_codes(&mut self, node: usize, code: u32, len: u8) {
+        if node < 32 {
+            self.codes[node] = (code, len);
+        } else {
+            let [zero, one] = self.nodes[node - 32];
+            self.assign_codes(zero, code << 1, len + 1);
+            self.assign_codes(one, (code << 1) | 1, len + 1);
+        }
+    }
+    fn write_tree(&self, bw: &mut BitWriter, node: usize) {
+        if node < 32 {
+            bw.write(1, 1);
+            bw.write(node as u32, 5);
+        } else {
+            bw.write(0, 1);
+            let [zero, one] = self.nodes[node - 32];
+            self.write_tree(bw, zero);
+            self.write_tree(bw, one);
+        }
+    }
+    fn write(&self, bw: &mut BitWriter, token: u8) {
+        let (code, len) = self.codes[usize::from(token)];
+        bw.write(code, len);
+    }
+}
+
+pub(super) fn write_long_runs(bw: &mut BitWriter, bits: &[bool]) {
+    if bits.is_empty() {
+        return;
+    }
+    let mut bit = bits[0];
+    bw.write(bit as u32, 1);
+    let mut pos = 0;
+    loop {
+        let run = bits[pos..].iter().take(4129).take_while(|&&b| b == bit).count();
+        let idx = VP3_LONG_RUN_BASE.iter().rposition(|&base| base <= run).unwrap();
+        for _ in 0..idx {
+            bw.write(1, 1);
+        }
+        if idx < VP3_LONG_RUN_BASE.len() - 1 {
+            bw.write(0, 1);
+        }
+        bw.write((run - VP3_LONG_RUN_BASE[idx]) as u32, VP3_LONG_RUN_BITS[idx]);
+        pos += run;
+        if pos == bits.len() {
+            break;
+        }
+        if run == 4129 {
+            bit = bits[pos];
+            bw.write(bit as u32, 1);
+        } else {
+            bit = !bit;
+        }
+    }
+}
+
+// runs longer than 30 cannot be coded so they should be broken by the caller
+pub(super) fn write_short_runs(bw: &mut BitWriter, bits: &[bool]) {
+    if bits.is_empty() {
+        return;
+    }
+    let mut bit = bits[0];
+    bw.write(bit as u32, 1);
+    let mut pos = 0;
+    while pos < bits.len() {
+        let run = bits[pos..].iter().take_while(|&&b| b == bit).count();
+        assert!(run <= 30);
+        let idx = VP3_SHORT_RUN_BASE.iter().rposition(|&base| base <= run).unwrap();
+        for _ in 0..idx {
+            bw.write(1, 1);
+        }
+        if idx < VP3_SHORT_RUN_BASE.len() - 1 {
+            bw.write(0, 1);
+        }
+        bw.write((run - VP3_SHORT_RUN_BASE[idx]) as u32, VP3_SHORT_RUN_BITS[idx]);
+        pos += run;
+        bit = !bit;
+    }
+}
+
+fn write_mv_comp(bw: &mut BitWriter, val: i16, raw: bool) {
+    let mag = val.unsigned_abs() as u32;
+    let sign = (val < 0) as u32;
+    if raw {
+        bw.write(mag, 5);
+        bw.write(sign, 1);
+        return;
+    }
+    match mag {
+        0 => bw.write(0, 3),
+        1 => bw.write(1 + sign, 3),
+        2 => bw.write(6 + sign, 4),
+        3 => bw.write(8 + sign, 4),
+        4..=7   => { bw.write(5, 3); bw.write(mag - 4, 2);  bw.write(sign, 1); },
+        8..=15  => { bw.write(6, 3); bw.write(mag - 8, 3);  bw.write(sign, 1); },
+        _       => { bw.write(7, 3); bw.write(mag - 16, 4); bw.write(sign, 1); },
+    }
+}
+
+/// Theora stream generator.
+pub struct TheoraSynth {
+    cfg:        TheoraSynthConfig,
+    rng:        RNG,
+    huffs:      Vec<HuffCode>,
+    frameno:    usize,
+    // blocks in coded order with the superblock lengths
+    sb_order:   Vec<usize>,
+    sb_len:     Vec<usize>,
+    mb_order:   Vec<(usize, usize)>,
+    nblocks:    usize,
+}
+
+impl TheoraSynth {
+    pub fn new(cfg: TheoraSynthConfig) -> Self {
+        let mut rng = RNG::new(cfg.seed);
+        let huffs = (0..80).map(|_| HuffCode::new(&mut rng)).collect();
+        let mut sb_order = Vec::new();
+        let mut sb_len = Vec::new();
+        let mut start = 0;
+        for (bw, bh) in [(cfg.mb_w * 2, cfg.mb_h * 2), (cfg.mb_w, cfg.mb_h), (cfg.mb_w, cfg.mb_h)] {
+            for sb_y in 0..bh.div_ceil(4) {
+                for sb_x in 0..bw.div_ceil(4) {
+                    let mut len = 0;
+                    for &(x, y) in VP3_HILBERT_ORDER.iter() {
+                        let bx = sb_x * 4 + x;
+                        let by = sb_y * 4 + y;
+                        if bx < bw && by < bh {
+                            sb_order.push(start + bx + by * bw);
+                            len += 1;
+                        }
+                    }
+                    sb_len.push(len);
+                }
+            }
+            start += bw * bh;
+        }
+        let mut mb_order = Vec::new();
+        for sb_y in 0..cfg.mb_h.div_ceil(2) {
+            for sb_x in 0..cfg.mb_w.div_ceil(2) {
+                for &(x, y) in VP3_MB_ORDER.iter() {
+                    if sb_x * 2 + x < cfg.mb_w && sb_y * 2 + y < cfg.mb_h {
+                        mb_order.push((sb_x * 2 + x, sb_y * 2 + y));
+                    }
+                }
+            }
+        }
+        Self { cfg, rng, huffs, frameno: 0, sb_order, sb_len, mb_order, nblocks: start }
+    }
+    fn is_old(&self) -> bool { self.cfg.version != 0 && self.cfg.version < THEORA_VERSION_3_2 }
+    /// Generates identification, comment and setup header packets (only the setup header for raw VP3.1).
+    pub fn gen_headers(&self) -> Vec<Vec<u8>> {
+        let cfg = &self.cfg;
+        let old = self.is_old();
+        let mut id = vec![0x80];
+        id.extend_from_slice(b"theora");
+        let mut bw = BitWriter::new(Vec::new(), BitWriterMode::BE);
+        bw.write(cfg.version, 24);
+        bw.write(cfg.mb_w as u32, 16);
+        bw.write(cfg.mb_h as u32, 16);
+        if !old {
+            bw.write((cfg.mb_w * 16) as u32, 24);
+            bw.write((cfg.mb_h * 16) as u32, 24);
+            bw.write(0, 8);
+            bw.write(0, 8);
+        }
+        bw.write(30, 32);
+        bw.write(1, 32);
+        bw.write(1, 24);
+        bw.write(1, 24);
+        if old {
+            bw.write(6, 5);
+        }
+        bw.write(0, 8);
+        bw.write(0, 24);
+        bw.write(32, 6);
+        if !old {
+            bw.write(6, 5);
+            bw.write(0, 2);
+            bw.write(0, 3);
+        }
+        id.extend_from_slice(&bw.end());
+
+        let mut comment = vec![0x81];
+        comment.extend_from_slice(b"theora");
+        comment.extend_from_slice(&5u32.to_le_bytes());
+        comment.extend_from_slice(b"synth");
+        comment.extend_from_slice(&0u32.to_le_bytes());
+
+        let mut setup = vec![0x82];
+        setup.extend_from_slice(b"theora");
+        let mut bw = BitWriter::new(Vec::new(), BitWriterMode::BE);
+        // old versions use the default loop filter limits and fixed-size tables
+        if !old {
+            bw.write(5, 3);
+            for &lim in VP31_LOOP_LIMITS.iter() {
+                bw.write(u32::from(lim), 5);
+            }
+        }
+        let scale_bits = if !old { bw.write(9 - 1, 4); 9 } else { 16 };
+        for &scale in VP31_AC_SCALE.iter() {
+            bw.write(u32::from(scale), scale_bits);
+        }
+        let scale_bits = if !old { bw.write(8 - 1, 4); 8 } else { 16 };
+        for &scale in VP31_DC_SCALE.iter() {
+            bw.write(u32::from(scale), scale_bits);
+        }
+        if !old {
+            bw.write(3 - 1, 9);
+        }
+        for mat in VP31_BASE_MATRICES.iter() {
+            for &el in mat.iter() {
+                bw.write(u32::from(el), 8);
+            }
+        }
+        // intra luma: two ranges using the same matrix
+        bw.write(0, 2);
+        bw.write(31 - 1, 6);
+        bw.write(0, 2);
+        bw.write(32 - 1, 5);
+        bw.write(0, 2);
+        // intra chroma: new range set
+        bw.write(1, 1);
+        bw.write(1, 2);
+        bw.write(63 - 1, 6);
+        bw.write(1, 2);
+        // intra chroma: copied from the previous plane
+        bw.write(0, 1);
+        // inter luma: new range set
+        bw.write(1, 1);
+        bw.write(2, 2);
+        bw.write(63 - 1, 6);
+        bw.write(2, 2);
+        // inter chroma: copied from inter luma
+        bw.write(0, 1);
+        bw.write(0, 1);
+        // inter chroma: copied from intra chroma
+        bw.write(0, 1);
+        bw.write(1, 1);
+        for huff in self.huffs.iter() {
+            huff.write_tree(&mut bw, 32 + huff.nodes.len() - 1);
+        }
+        setup.extend_from_slice(&bw.end());
+
+        if cfg.version == 0 {
+            return vec![setup];
+        }
+        vec![id, comment, setup]
+    }
+    /// Generates the next frame packet.
+    pub fn gen_frame(&mut self) -> Vec<u8> {
+        let is_intra = self.frameno.is_multiple_of(self.cfg.key_int);
+        self.frameno += 1;
+        let raw = self.cfg.version == 0;
+        let mut bw = BitWriter::new(Vec::new(), BitWriterMode::BE);
+        bw.write(0, 1);
+        bw.write(!is_intra as u32, 1);
+        if raw {
+            bw.write(0, 1);
+        }
+        let nqis = 1 + self.rng.range(self.cfg.max_qis as u32) as usize;
+        let multi_qi = !raw && !self.is_old();
+        assert!(multi_qi || nqis == 1);
+        for i in 0..nqis {
+            // keep quantisers high enough for the picture not to be garbage
+            bw.write(20 + self.rng.range(44), 6);
+            if i < 2 && multi_qi {
+                bw.write((i + 1 < nqis) as u32, 1);
+            }
+        }
+        if is_intra {
+            if raw {
+                bw.write(self.cfg.mb_w as u32, 4);
+                bw.write(self.cfg.mb_h as u32, 4);
+                bw.write(1, 5);
+            }
+            bw.write(0, 3);
+        }
+
+        let mut coded = vec![true; self.nblocks];
+        if !is_intra {
+            let nsbs = self.sb_len.len();
+            // 0 - not coded, 1 - fully coded, 2 - partially coded
+            let sb_state: Vec<u32> = (0..nsbs).map(|_| self.rng.range(3)).collect();
+            let partial: Vec<bool> = sb_state.iter().map(|&st| st == 2).collect();
+            write_long_runs(&mut bw, &partial);
+            let full: Vec<bool> = sb_state.iter().filter(|&&st| st != 2).map(|&st| st == 1).collect();
+            write_long_runs(&mut bw, &full);
+            let mut block_flags = Vec::new();
+            let mut pos = 0;
+            for (&len, &st) in self.sb_len.iter().zip(sb_state.iter()) {
+                for &idx in self.sb_order[pos..][..len].iter() {
+                    coded[idx] = st == 1;
+                    if st == 2 {
+                        let flag = self.rng.chance(1, 2);
+                        block_flags.push((idx, flag));
+                    }
+                }
+                pos += len;
+            }
+            let mut run = 0;
+            for i in 0..block_flags.len() {
+                if i > 0 && block_flags[i].1 == block_flags[i - 1].1 {
+                    run += 1;
+                    if run == 30 {
+                        block_flags[i].1 = !block_flags[i].1;
+                        run = 0;
+                    }
+                } else {
+                    run = 0;
+                }
+            }
+            for &(idx, flag) in block_flags.iter() {
+                coded[idx] = flag;
+            }
+            let flags: Vec<bool> = block_flags.iter().map(|&(_, flag)| flag).collect();
+            write_short_runs(&mut bw, &flags);
+
+            self.gen_modes(&mut bw, &coded);
+        }
+
+        let coded_list: Vec<usize> = self.sb_order.iter().cloned().filter(|&idx| coded[idx]).collect();
+        if nqis > 1 {
+            let qidx: Vec<usize> = coded_list.iter().map(|_| self.rng.range(nqis as u32) as usize).collect();
+            for qj in 0..nqis - 1 {
+                let bits: Vec<bool> = qidx.iter().filter(|&&q| q >= qj).map(|&q| q > qj).collect();
+                write_long_runs(&mut bw, &bits);
+            }
+        }
+        self.gen_tokens(&mut bw, &coded_list);
+
+        bw.end()
+    }
+    fn gen_modes(&mut self, bw: &mut BitWriter, coded: &[bool]) {
+        let scheme = self.rng.range(8) as usize;
+        bw.write(scheme as u32, 3);
+        let mut alphabet = [0u8; 8];
+        if scheme == 0 {
+            let mut order: Vec<u8> = (0..8).collect();
+            for i in (1..8).rev() {
+                order.swap(i, self.rng.range(i as u32 + 1) as usize);
+            }
+            alphabet.copy_from_slice(&order);
+            for mode in 0..8 {
+                bw.write(alphabet.iter().position(|&m| m == mode).unwrap() as u32, 3);
+            }
+        } else if scheme < 7 {
+            alphabet = VP3_MODE_ALPHABETS[scheme - 1];
+        }
+        let bw_luma = self.cfg.mb_w * 2;
+        let mut modes = Vec::new();
+        for &(mb_x, mb_y) in self.mb_order.iter() {
+            let idx = mb_x * 2 + mb_y * 2 * bw_luma;
+            let luma = [idx, idx + 1, idx + bw_luma, idx + bw_luma + 1];
+            let luma_coded = [coded[luma[0]], coded[luma[1]], coded[luma[2]], coded[luma[3]]];
+            if !luma_coded.iter().any(|&c| c) {
+                continue;
+            }
+            let mode = self.rng.range(8) as u8;
+            if scheme == 7 {
+                bw.write(u32::from(mode), 3);
+            } else {
+                let idx = alphabet.iter().position(|&m| m == mode).unwrap();
+                for _ in 0..idx {
+                    bw.write(1, 1);
+                }
+                if idx < 7 {
+                    bw.write(0, 1);
+                }
+            }
+            modes.push((mode, luma_coded));
+        }
+        let raw = self.rng.chance(1, 3);
+        bw.write(raw as u32, 1);
+        for &(mode, luma_coded) in modes.iter() {
+            let nmvs = match mode {
+                    2 | 6 => 1,
+                    7 => luma_coded.iter().filter(|&&c| c).count(),
+                    _ => 0,
+                };
+            for _ in 0..nmvs * 2 {
+                let val = if self.rng.chance(1, 2) { self.rng.range(7) as i16 - 3 } else { self.rng.range(63) as i16 - 31 };
+                write_mv_comp(bw, val, raw);
+            }
+        }
+    }
+    fn gen_tokens(&mut self, bw: &mut BitWriter, coded: &[usize]) {
+        let tabs = [self.rng.range(16) as usize, self.rng.range(16) as usize, self.rng.range(16) as usize, self.rng.range(16) as usize];
+        for &tab in tabs.iter() {
+            bw.write(tab as u32, 4);
+        }
+        let chroma_start = self.cfg.mb_w * self.cfg.mb_h * 4;
+        let mut cur = vec![0usize; self.nblocks];
+        let mut eob_run = 0usize;
+        for ti in 0..64 {
+            let group = match ti {
+                    0       => 0,
+                    1..=5   => 1,
+                    6..=14  => 2,
+                    15..=27 => 3,
+                    _       => 4,
+                };
+            for &idx in coded.iter() {
+                if cur[idx] != ti {
+                    continue;
+                }
+                if eob_run > 0 {
+                    cur[idx] = 64;
+                    eob_run -= 1;
+                    continue;
+                }
+                let is_luma = idx < chroma_start;
+                let tab = group * 16 + if group == 0 { tabs[if is_luma { 0 } else { 1 }] } else { tabs[if is_luma { 2 } else { 3 }] };
+                let left = 64 - ti;
+                let kind = self.rng.range(if ti == 0 { 24 } else { 10 });
+                match kind {
+                    0..=2 => {
+                        let run = match self.rng.range(8) {
+                                0..=3 => 1 + self.rng.range(3),
+                                4     => 4 + self.rng.range(4),
+                                5     => 8 + self.rng.range(8),
+                                6     => 16 + self.rng.range(16),
+                                _     => self.rng.range(100),
+                            } as usize;
+                        let (token, bits, extra) = match run {
+                                0       => (6, 12, 0),
+                                1..=3   => (run as u8 - 1, 0, 0),
+                                4..=7   => (3, 2, run - 4),
+                                8..=15  => (4, 3, run - 8),
+                                16..=31 => (5, 4, run - 16),
+                                _       => (6, 12, run),
+                            };
+                        self.huffs[tab].write(bw, token);
+                        bw.write(extra as u32, bits);
+                        eob_run = if run == 0 { usize::MAX } else { run - 1 };
+                        cur[idx] = 64;
+                    },
+                    3 => {
+                        let max_run = if self.rng.chance(1, 2) { 8 } else { 64 };
+                        let run = (1 + self.rng.range(max_run) as usize).min(left);
+                        if run <= 8 {
+                            self.huffs[tab].write(bw, 7);
+                            bw.write(run as u32 - 1, 3);
+                        } else {
+                            self.huffs[tab].write(bw, 8);
+                            bw.write(run as u32 - 1, 6);
+                        }
+                        cur[idx] += run;
+                    },
+                    4 | 5 if left > 18 => {
+                        let token = 23 + self.rng.range(9) as u8;
+                        self.huffs[tab].write(bw, token);
+                        bw.write(self.rng.range(2), 1);
+                        let run = match token {
+                                23..=27 => usize::from(token) - 22,
+                                28 => { let r = self.rng.range(4); bw.write(r, 2); 6 + r as usize },
+                                29 => { let r = self.rng.range(8); bw.write(r, 3); 10 + r as usize },
+                                30 => { bw.write(self.rng.range(2), 1); 1 },
+                                _  => {
+                                    bw.write(self.rng.range(2), 1);
+                                    let r = self.rng.range(2);
+                                    bw.write(r, 1);
+                                    2 + r as usize
+                                },
+                            };
+                        cur[idx] += run + 1;
+                    },
+                    _ => {
+                        let token = match self.rng.range(16) {
+                                0..=5   => 9 + self.rng.range(4),
+                                6..=10  => 13 + self.rng.range(4),
+                                11..=13 => 17 + self.rng.range(2),
+                                14      => 19 + self.rng.range(2),
+                                _       => 21 + self.rng.range(2),
+                            } as u8;
+                        self.huffs[tab].write(bw, token);
+                        match token {
+                            13..=16 => bw.write(self.rng.range(2), 1),
+                            17..=22 => {
+                                let bits = [1, 2, 3, 4, 5, 9][usize::from(token - 17)];
+                                bw.write(self.rng.range(2), 1);
+                                bw.write(self.rng.range(1 << bits), bits);
+                            },
+                            _ => {},
+                        };
+                        cur[idx] += 1;
+                    },
+                }
+            }
+        }
+    }
+}
+
+/// Generates headers and frames as a raw packet stream.
+pub fn gen_stream(cfg: TheoraSynthConfig) -> Vec<u8> {
+    let mut synth = TheoraSynth::new(cfg);
+    let mut data = Vec::new();
+    let mut pkts = synth.gen_headers();
+    for _ in 0..cfg.nframes {
+        pkts.push(synth.gen_frame());
+    }
+    for pkt in pkts.iter() {
+        put_packet(&mut data, pkt);
+    }
+    data
+}
+
+/// Test fixtures stored in `assets/` and the parameters they are generated from.
+pub const TEST_STREAMS: [(&str, TheoraSynthConfig); 2] = [
+    ("theora.vp3",          TheoraSynthConfig { mb_w: 4, mb_h: 3, nframes: 10, key_int: 5, max_qis: 1, seed: 1, version: THEORA_VERSION_3_2 }),
+    ("theora_multiqi.vp3",  TheoraSynthConfig { mb_w: 5, mb_h: 3, nframes: 10, key_int: 10, max_qis: 3, seed: 2, version: THEORA_VERSION_3_2 }),
+];
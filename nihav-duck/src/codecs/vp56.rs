@@ -1,5 +1,6 @@
 use nihav_core::codecs::*;
 use nihav_core::io::bitreader::*;
+use nihav_core::io::bitwriter::*;
 use nihav_codec_support::codecs::{MV, ZERO_MV};
 use super::vpcommon::*;
 
@@ -47,6 +48,7 @@ pub struct VP56CoeffModel {
     pub ac_val_probs:   [[[u8; 11]; 6]; 3],
 }
 
+#[derive(Clone)]
 pub struct VP6Models {
     pub scan_order:         [usize; 64],
     pub scan:               [usize; 64],
@@ -55,10 +57,23 @@ pub struct VP6Models {
 }
 
 const MAX_HUFF_ELEMS: usize = 12;
-#[derive(Clone,Copy,Default)]
+const HUFF_LUT_BITS: u8 = 8;
+#[derive(Clone,Copy)]
 pub struct VP6Huff {
     pub codes:  [u16; MAX_HUFF_ELEMS],
     pub bits:   [u8; MAX_HUFF_ELEMS],
+    // symbol and code length for codes not longer than HUFF_LUT_BITS indexed by the next bits, zero otherwise
+    lut:        [u8; 1 << HUFF_LUT_BITS],
+}
+
+impl Default for VP6Huff {
+    fn default() -> Self {
+        Self {
+            codes:  [0; MAX_HUFF_ELEMS],
+            bits:   [0; MAX_HUFF_ELEMS],
+            lut:    [0; 1 << HUFF_LUT_BITS],
+        }
+    }
 }
 
 #[derive(Clone,Copy,Default)]
@@ -79,7 +94,7 @@ fn prob2weight(a: u8, b: u8) -> u8 {
 }
 
 impl VP6Huff {
-    fn build_codes(&mut self, probs: &[u8; 11]) {
+    pub fn build_codes(&mut self, probs: &[u8; 11]) {
         let mut weights = [0u8; 12];
 
         weights[11] = prob2weight( probs[0],  probs[ 1]);
@@ -105,7 +120,7 @@ impl VP6Huff {
 
         self.build(&weights);
     }
-    fn build_codes_zero_run(&mut self, probs: &[u8; 14]) {
+    pub fn build_codes_zero_run(&mut self, probs: &[u8; 14]) {
         let mut weights = [0u8; 9];
 
         let root   = prob2weight( probs[0],  probs[1]);
@@ -172,6 +187,22 @@ impl VP6Huff {
             self.codes[i]   = self.codes[0];
             self.bits[i]    = self.bits[0];
         }
+        self.build_lut();
+    }
+    fn build_lut(&mut self) {
+        for el in self.lut.iter_mut() {
+            *el = 0;
+        }
+        // unused symbols repeat the first code so go backwards to make the first symbol win like in a linear search
+        for (sym, (&code, &len)) in self.codes.iter().zip(self.bits.iter()).enumerate().rev() {
+            if len <= HUFF_LUT_BITS {
+                let shift = HUFF_LUT_BITS - len;
+                let start = usize::from(code) << shift;
+                for el in self.lut[start..][..1 << shift].iter_mut() {
+                    *el = ((sym as u8) << 4) | len;
+                }
+            }
+        }
     }
     fn get_codes(&mut self, nodes: &[Node], pos: usize, code: u16, len: u8) {
         if nodes[pos].sym >= 0 {
@@ -191,7 +222,12 @@ pub trait ReadHuff {
 impl<'a> ReadHuff for BitReader<'a> {
     fn read_huff(&mut self, huff: &VP6Huff) -> DecoderResult<u8> {
         let peekval                             = self.peek(16);
-        for (i, (code, bit)) in huff.codes.iter().zip(huff.bits.iter()).enumerate() {
+        let entry = huff.lut[(peekval >> (16 - HUFF_LUT_BITS)) as usize];
+        if entry != 0 {
+            self.skip(u32::from(entry & 0xF))?;
+            return Ok(entry >> 4);
+        }
+        for (i, (code, bit)) in huff.codes.iter().zip(huff.bits.iter()).enumerate().filter(|(_, (_, &bit))| bit > HUFF_LUT_BITS) {
             if (peekval >> (16 - *bit)) == u32::from(*code) {
                 self.skip(u32::from(*bit))?;
                 return Ok(i as u8);
@@ -201,6 +237,22 @@ impl<'a> ReadHuff for BitReader<'a> {
     }
 }
 
+/// Writing symbols with [`VP6Huff`] codes.
+pub trait WriteHuff {
+    /// Writes the code for the symbol.
+    ///
+    /// Valid symbols are 0-11 for coefficient token trees (11 being end of block) and 0-8 for zero run trees.
+    fn write_huff(&mut self, huff: &VP6Huff, sym: u8);
+}
+
+impl WriteHuff for BitWriter {
+    fn write_huff(&mut self, huff: &VP6Huff, sym: u8) {
+        let sym = usize::from(sym);
+        debug_assert!(sym < MAX_HUFF_ELEMS, "symbol {} is out of range", sym);
+        self.write(u32::from(huff.codes[sym]), huff.bits[sym]);
+    }
+}
+
 #[derive(Clone,Copy,Default)]
 pub struct VP6HuffModels {
     pub dc_token_tree:      [VP6Huff; 2],
@@ -209,7 +261,7 @@ pub struct VP6HuffModels {
 }
 
 impl VP6Models {
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
         Self {
             scan_order:         [0; 64],
             scan:               [0; 64],
@@ -219,6 +271,7 @@ impl VP6Models {
     }
 }
 
+#[derive(Clone)]
 pub struct VP56Models {
     pub mv_models:          [VP56MVModel; 2],
     pub mbtype_models:      [[VP56MBTypeModel; 10]; 3],
@@ -242,6 +295,8 @@ impl VP56Models {
 }
 
 pub trait VP56Parser {
+    // VP6A colour and alpha streams are coded independently so the parser should keep separate state for them
+    fn select_stream(&mut self, alpha: bool);
     fn parse_header(&mut self, bc: &mut BoolCoder) -> DecoderResult<VP56Header>;
     fn reset_models(&self, models: &mut VP56Models);
     fn decode_mv(&self, bc: &mut BoolCoder, model: &VP56MVModel) -> i16;
@@ -249,7 +304,7 @@ pub trait VP56Parser {
     fn decode_coeff_models(&self, bc: &mut BoolCoder, models: &mut VP56Models, is_intra: bool) -> DecoderResult<()>;
     fn decode_block(&self, bc: &mut BoolCoder, coeffs: &mut [i16; 64], model: &VP56CoeffModel, vp6model: &VP6Models, fstate: &mut FrameState) -> DecoderResult<()>;
     fn decode_block_huff(&self, br: &mut BitReader, coeffs: &mut [i16; 64], vp6model: &VP6Models, model: &VP6HuffModels, fstate: &mut FrameState) -> DecoderResult<()>;
-    fn mc_block(&self, dst: &mut NASimpleVideoFrame<u8>, mc_buf: NAVideoBufferRef<u8>, src: NAVideoBufferRef<u8>, plane: usize, x: usize, y: usize, mv: MV, loop_thr: i16);
+    fn mc_block(&self, dst: &mut NASimpleVideoFrame<u8>, mc_buf: &mut NAVideoBuffer<u8>, src: NAVideoBufferRef<u8>, plane: usize, x: usize, y: usize, mv: MV, loop_thr: i16);
 }
 
 enum CoeffReader<'a> {
@@ -264,6 +319,7 @@ struct MBInfo {
     mv:         MV,
 }
 
+#[derive(Clone)]
 pub struct FrameState {
     pub mb_x:           usize,
     pub mb_y:           usize,
@@ -279,7 +335,7 @@ pub struct FrameState {
 }
 
 impl FrameState {
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
         Self {
             mb_x:           0,
             mb_y:           0,
@@ -296,7 +352,7 @@ impl FrameState {
     }
 }
 
-#[derive(Default)]
+#[derive(Clone,Default)]
 pub struct VP56DCPred {
     dc_y:       Vec<i16>,
     dc_u:       Vec<i16>,
@@ -342,6 +398,102 @@ impl VP56DCPred {
     }
 }
 
+// planes to decode for streams with alpha, streams without alpha always have colour planes decoded.
+// Skipping a pass drops the references for those planes until their next intra frame,
+// see VP56Decoder::get_ref_planes() for checking which planes can be decoded.
+#[derive(Clone,Copy,Debug,PartialEq,Default)]
+pub enum VP56Planes {
+    #[default]
+    All,
+    Colour,
+    Alpha,
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Default)]
+pub enum VP56Scale {
+    #[default]
+    Full,
+    Half,
+    Quarter,
+    Eighth,
+}
+
+// interlaced frames are output according to the requested mode, references are always kept as decoded
+// a view of the caller frame with a shorter lifetime so it can be used in place of a pool buffer frame
+fn reborrow_frame<'a>(frm: &'a mut NASimpleVideoFrame<'_, u8>) -> NASimpleVideoFrame<'a, u8> {
+    NASimpleVideoFrame {
+        width:      frm.width,
+        height:     frm.height,
+        flip:       frm.flip,
+        stride:     frm.stride,
+        offset:     frm.offset,
+        components: frm.components,
+        data:       &mut frm.data[..],
+    }
+}
+
+fn output_fields(src: &NAVideoBuffer<u8>, dst: &mut NAVideoBuffer<u8>, mode: FieldOutputMode) {
+    let mut doffs = [0; 4];
+    let mut dstrides = [0; 4];
+    for comp in 0..dst.get_num_components().min(4) {
+        doffs[comp] = dst.get_offset(comp);
+        dstrides[comp] = dst.get_stride(comp);
+    }
+    let sdata = src.get_data();
+    let ddata = if let Some(data) = dst.get_data_mut() { data } else { return; };
+    for comp in 0..src.get_num_components().min(4) {
+        let (w, h) = src.get_dimensions(comp);
+        let sstride = src.get_stride(comp);
+        let sbuf = &sdata[src.get_offset(comp)..];
+        let dstride = dstrides[comp];
+        let dbuf = &mut ddata[doffs[comp]..];
+        for y in 0..h {
+            let dline = &mut dbuf[y * dstride..][..w];
+            match mode {
+                FieldOutputMode::Woven => {
+                    dline.copy_from_slice(&sbuf[y * sstride..][..w]);
+                },
+                FieldOutputMode::Fields => {
+                    let half = (h + 1) >> 1;
+                    let sy = if y < half { y * 2 } else { (y - half) * 2 + 1 };
+                    dline.copy_from_slice(&sbuf[sy * sstride..][..w]);
+                },
+                FieldOutputMode::Bob => {
+                    if (y & 1) == 0 || y + 1 == h {
+                        let sy = y & !1;
+                        dline.copy_from_slice(&sbuf[sy * sstride..][..w]);
+                    } else {
+                        let top = &sbuf[(y - 1) * sstride..][..w];
+                        let bot = &sbuf[(y + 1) * sstride..][..w];
+                        for (dst, (&a, &b)) in dline.iter_mut().zip(top.iter().zip(bot.iter())) {
+                            *dst = ((u16::from(a) + u16::from(b) + 1) >> 1) as u8;
+                        }
+                    }
+                },
+                FieldOutputMode::Blend => {
+                    let top = &sbuf[y.saturating_sub(1) * sstride..][..w];
+                    let cur = &sbuf[y * sstride..][..w];
+                    let bot = &sbuf[(y + 1).min(h - 1) * sstride..][..w];
+                    for (dst, ((&a, &b), &c)) in dline.iter_mut().zip(top.iter().zip(cur.iter()).zip(bot.iter())) {
+                        *dst = ((u16::from(a) + 2 * u16::from(b) + u16::from(c) + 2) >> 2) as u8;
+                    }
+                },
+            };
+        }
+    }
+}
+
+impl VP56Scale {
+    fn get_shift(self) -> u8 {
+        match self {
+            VP56Scale::Full     => 0,
+            VP56Scale::Half     => 1,
+            VP56Scale::Quarter  => 2,
+            VP56Scale::Eighth   => 3,
+        }
+    }
+}
+
 pub struct VP56Decoder {
     version:    u8,
     has_alpha:  bool,
@@ -366,7 +518,26 @@ pub struct VP56Decoder {
     last_dc:    [[i16; 4]; 3],
     top_ctx:    [Vec<u8>; 4],
 
-    mc_buf:     NAVideoBufferRef<u8>,
+    mc_buf:     NAVideoBuffer<u8>,
+    lowres:     Option<VPLowResIDCT>,
+
+    last_hdr:   Option<VP56Header>,
+    last_ahdr:  Option<VP56Header>,
+    skip_mode:  FrameSkipMode,
+    next_is_key: bool,
+    // references for these planes were dropped by skipping their decoding pass
+    colour_dropped: bool,
+    alpha_dropped:  bool,
+    field_mode: FieldOutputMode,
+
+    err_info:   DecoderErrorInfo,
+    err_base:   usize,
+    last_err:   Option<DecoderErrorInfo>,
+}
+
+// motion vectors are not limited by the bitstream so they may wrap around on malformed input
+fn add_mv(a: MV, b: MV) -> MV {
+    MV { x: a.x.wrapping_add(b.x), y: a.y.wrapping_add(b.y) }
 }
 
 fn rescale_mb_mode_prob(prob: u32, total: u32) -> u8 {
@@ -423,10 +594,63 @@ pub fn expand_token_bc(bc: &mut BoolCoder, val_probs: &[u8; 11], token: u8, vers
     }
 }
 
+fn alloc_mc_buf() -> NAVideoBuffer<u8> {
+    let vt = alloc_video_buffer(NAVideoInfo::new(24, 24, false, VP_YUVA420_FORMAT), 4).unwrap();
+    // the data stays shared only until the allocated buffer reference goes out of scope
+    let vbuf = vt.get_vbuf().unwrap();
+    NAVideoBuffer::clone(&vbuf)
+}
+
+// A clone serves as a decoder state snapshot: together with a clone of the parser it allows
+// to resume decoding from the same point (e.g. for seeking without going back to a keyframe).
+// Reference frames are copied so snapshots do not keep frame pool buffers in use.
+impl Clone for VP56Decoder {
+    fn clone(&self) -> Self {
+        Self {
+            version:    self.version,
+            has_alpha:  self.has_alpha,
+            flip:       self.flip,
+            shuf:       self.shuf.copy_frames(),
+            width:      self.width,
+            height:     self.height,
+            mb_w:       self.mb_w,
+            mb_h:       self.mb_h,
+            models:     self.models.clone(),
+            amodels:    self.amodels.clone(),
+            coeffs:     self.coeffs,
+            last_mbt:   self.last_mbt,
+
+            loop_thr:   self.loop_thr,
+            ilace_prob: self.ilace_prob,
+            ilace_mb:   self.ilace_mb,
+
+            mb_info:    self.mb_info.clone(),
+            fstate:     self.fstate.clone(),
+            dc_pred:    self.dc_pred.clone(),
+            last_dc:    self.last_dc,
+            top_ctx:    self.top_ctx.clone(),
+
+            mc_buf:     alloc_mc_buf(),
+            lowres:     self.lowres.clone(),
+
+            last_hdr:   self.last_hdr,
+            last_ahdr:  self.last_ahdr,
+            skip_mode:  self.skip_mode,
+            next_is_key: self.next_is_key,
+            colour_dropped: self.colour_dropped,
+            alpha_dropped:  self.alpha_dropped,
+            field_mode: self.field_mode,
+
+            err_info:   self.err_info,
+            err_base:   self.err_base,
+            last_err:   self.last_err,
+        }
+    }
+}
+
 impl VP56Decoder {
     pub fn new(version: u8, has_alpha: bool, flip: bool) -> Self {
-        let vt = alloc_video_buffer(NAVideoInfo::new(24, 24, false, VP_YUVA420_FORMAT), 4).unwrap();
-        let mc_buf = vt.get_vbuf().unwrap();
+        let mc_buf = alloc_mc_buf();
         Self {
             version, has_alpha, flip,
             shuf:       VPShuffler::new(),
@@ -450,6 +674,19 @@ impl VP56Decoder {
             top_ctx:    [Vec::new(), Vec::new(), Vec::new(), Vec::new()],
 
             mc_buf,
+            lowres:     None,
+
+            last_hdr:   None,
+            last_ahdr:  None,
+            skip_mode:  FrameSkipMode::None,
+            next_is_key: false,
+            colour_dropped: false,
+            alpha_dropped:  false,
+            field_mode: FieldOutputMode::Woven,
+
+            err_info:   DecoderErrorInfo { error: DecoderError::Bug, stage: DecoderStage::Frame, alpha: false, offset: None },
+            err_base:   0,
+            last_err:   None,
         }
     }
     fn set_dimensions(&mut self, width: usize, height: usize) {
@@ -459,18 +696,118 @@ impl VP56Decoder {
         self.mb_h   = (self.height + 15) >> 4;
         self.mb_info.resize(self.mb_w * self.mb_h, MBInfo::default());
         self.top_ctx = [vec![0; self.mb_w * 2], vec![0; self.mb_w], vec![0; self.mb_w], vec![0; self.mb_w * 2]];
+        self.dc_pred.resize(self.mb_w);
     }
     pub fn init(&mut self, supp: &mut NADecoderSupport, vinfo: NAVideoInfo) -> DecoderResult<()> {
-        supp.pool_u8.set_dec_bufs(3 + if vinfo.get_format().has_alpha() { 1 } else { 0 });
+        if !supp.limits.check_dimensions(vinfo.get_width(), vinfo.get_height()) {
+            return Err(DecoderError::LimitExceeded);
+        }
+        supp.pool_u8.set_limits(supp.limits);
+        // interlaced frames are output in a separate buffer when the fields are not woven
+        let out_bufs = if self.field_mode != FieldOutputMode::Woven { 1 } else { 0 };
+        supp.pool_u8.set_dec_bufs(3 + out_bufs + if vinfo.get_format().has_alpha() { 1 } else { 0 });
         supp.pool_u8.prealloc_video(NAVideoInfo::new(vinfo.get_width(), vinfo.get_height(), false, vinfo.get_format()), 4)?;
         self.set_dimensions(vinfo.get_width(), vinfo.get_height());
-        self.dc_pred.resize(self.mb_w);
         Ok(())
     }
-    pub fn flush(&mut self) {
+    fn clear_refs(&mut self) {
         self.shuf.clear();
+        self.colour_dropped = false;
+        self.alpha_dropped  = false;
+    }
+    pub fn flush(&mut self) {
+        self.clear_refs();
+    }
+    pub fn get_last_header(&self) -> Option<VP56Header> { self.last_hdr }
+    pub fn get_last_alpha_header(&self) -> Option<VP56Header> { self.last_ahdr }
+    pub fn get_last_error(&self) -> Option<DecoderErrorInfo> { self.last_err }
+    // planes that can be decoded from the next inter frame (None means that an intra frame is needed).
+    // After colour-only or alpha-only decoding the other planes are missing here and requesting them fails with
+    // MissingReference reported at DecoderStage::Codec("skipped planes") stage until the next intra frame for them.
+    pub fn get_ref_planes(&self) -> Option<VP56Planes> {
+        match (self.shuf.has_refs(), self.has_alpha && self.shuf.has_alpha_refs()) {
+            (true, true) => Some(VP56Planes::All),
+            (true, false) if !self.has_alpha => Some(VP56Planes::All),
+            (true, false) => Some(VP56Planes::Colour),
+            (false, true) => Some(VP56Planes::Alpha),
+            (false, false) => None,
+        }
+    }
+    pub fn set_skip_mode(&mut self, mode: FrameSkipMode) { self.skip_mode = mode; }
+    // hint for the next decode_frame() call only and used only in NonReference mode: non-golden inter frames followed
+    // by a keyframe are skipped (for VP6 with alpha the alpha part has to be non-golden inter too)
+    pub fn set_next_is_key(&mut self, next_is_key: bool) { self.next_is_key = next_is_key; }
+    // reduced resolution decoding, references are kept at the output scale so switching it requires a keyframe
+    pub fn set_scale(&mut self, scale: VP56Scale) {
+        if scale != self.get_scale() {
+            self.lowres = if scale != VP56Scale::Full { Some(VPLowResIDCT::new(scale.get_shift())) } else { None };
+            self.clear_refs();
+        }
+    }
+    pub fn get_scale(&self) -> VP56Scale {
+        match self.lowres.as_ref().map(|idct| idct.get_shift()) {
+            Some(1) => VP56Scale::Half,
+            Some(2) => VP56Scale::Quarter,
+            Some(3) => VP56Scale::Eighth,
+            _ => VP56Scale::Full,
+        }
+    }
+    pub fn get_skip_mode(&self) -> FrameSkipMode { self.skip_mode }
+    // should be set before init() so the frame pool reserves a buffer for the output
+    pub fn set_field_mode(&mut self, mode: FieldOutputMode) { self.field_mode = mode; }
+    pub fn get_field_mode(&self) -> FieldOutputMode { self.field_mode }
+    pub fn is_interlaced(&self) -> bool { self.last_hdr.is_some_and(|hdr| hdr.interlaced) }
+    fn set_err_stage(&mut self, stage: DecoderStage, bc: &BoolCoder) {
+        self.err_info.stage  = stage;
+        self.err_info.offset = Some(self.err_base + bc.get_pos());
     }
     pub fn decode_frame(&mut self, supp: &mut NADecoderSupport, src: &[u8], br: &mut dyn VP56Parser) -> DecoderResult<(NABufferType, FrameType)> {
+        self.decode_frame_planes(supp, src, br, VP56Planes::All)
+    }
+    // skipping colour or alpha planes leaves them undefined in the output and invalidates their references
+    // until the next intra frame for them (reported by get_ref_planes())
+    pub fn decode_frame_planes(&mut self, supp: &mut NADecoderSupport, src: &[u8], br: &mut dyn VP56Parser, planes: VP56Planes) -> DecoderResult<(NABufferType, FrameType)> {
+        self.decode_frame_wrapper(supp, src, br, planes, None)
+    }
+    // Reconstruction goes straight into the caller frame if it has the decoder orientation and all the planes,
+    // the picture is copied into a pool buffer afterwards unless FrameSkipMode::NonReference is set and the frame
+    // is followed by a keyframe according to the hint.
+    // Otherwise (and for interlaced frames output as separate fields) the frame is decoded as usual and copied into the caller frame.
+    // A caller frame that is too small for the picture is rejected with InvalidData before the decoder state is changed.
+    pub fn decode_frame_into(&mut self, supp: &mut NADecoderSupport, src: &[u8], br: &mut dyn VP56Parser, dst: &mut NASimpleVideoFrame<u8>) -> DecoderResult<FrameType> {
+        self.decode_frame_wrapper(supp, src, br, VP56Planes::All, Some(dst)).map(|(_, ftype)| ftype)
+    }
+    fn decode_frame_wrapper(&mut self, supp: &mut NADecoderSupport, src: &[u8], br: &mut dyn VP56Parser, planes: VP56Planes, dst: Option<&mut NASimpleVideoFrame<u8>>) -> DecoderResult<(NABufferType, FrameType)> {
+        self.err_info.stage  = DecoderStage::Frame;
+        self.err_info.alpha  = false;
+        self.err_info.offset = None;
+        self.last_err = None;
+        let ret = self.decode_frame_int(supp, src, br, planes, dst);
+        self.next_is_key = false;
+        if let Err(err) = ret {
+            self.err_info.error = err;
+            self.last_err = Some(self.err_info);
+        }
+        ret
+    }
+    // alpha stream has its own frame types so a packet is a reference if either part is
+    // (broken alpha header is treated as a reference so the frame is decoded and the error is reported there)
+    fn is_alpha_reference(&self, br: &mut dyn VP56Parser, src: &[u8], aoffset: usize) -> bool {
+        if !self.has_alpha {
+            return false;
+        }
+        let mut bc = if let Ok(bc) = BoolCoder::new(&src[aoffset + 3..]) { bc } else { return true; };
+        br.select_stream(true);
+        let ret = br.parse_header(&mut bc);
+        br.select_stream(false);
+        ret.map_or(true, |ahdr| ahdr.is_intra || ahdr.is_golden)
+    }
+    fn decode_frame_int(&mut self, supp: &mut NADecoderSupport, src: &[u8], br: &mut dyn VP56Parser, planes: VP56Planes, mut out: Option<&mut NASimpleVideoFrame<u8>>) -> DecoderResult<(NABufferType, FrameType)> {
+        let want_colour = !self.has_alpha || planes != VP56Planes::Alpha;
+        let want_alpha  = self.has_alpha && planes != VP56Planes::Colour;
+        if !supp.limits.check_packet_size(src.len()) {
+            return Err(DecoderError::LimitExceeded);
+        }
         let aoffset;
         let mut bc;
         if self.has_alpha {
@@ -478,97 +815,209 @@ impl VP56Decoder {
             aoffset = ((src[0] as usize) << 16) | ((src[1] as usize) << 8) | (src[2] as usize);
             validate!((aoffset > 0) && (aoffset < src.len() - 3));
             bc = BoolCoder::new(&src[3..])?;
+            self.err_base = 3;
         } else {
             validate!(src.len() >= 4);
             aoffset = src.len();
             bc = BoolCoder::new(src)?;
+            self.err_base = 0;
         }
+        self.last_hdr = None;
+        self.last_ahdr = None;
+        self.set_err_stage(DecoderStage::Header, &bc);
+        br.select_stream(false);
         let hdr = br.parse_header(&mut bc)?;
-        validate!((hdr.offset as usize) < aoffset); //XXX: take alpha 3 byte offset into account?
-
-        if hdr.mb_w != 0 && (usize::from(hdr.mb_w) != self.mb_w || usize::from(hdr.mb_h) != self.mb_h) {
-            self.set_dimensions((hdr.mb_w as usize) * 16, (hdr.mb_h as usize) * 16);
+        validate!((hdr.offset as usize) < aoffset);
+        self.last_hdr = Some(hdr);
+        self.err_info.stage  = DecoderStage::Frame;
+        self.err_info.offset = None;
+
+        // the hint is used only for skipping, a frame followed by a keyframe is not needed as a reference
+        // unless its alpha part is intra or golden
+        let non_ref = self.skip_mode == FrameSkipMode::NonReference && self.next_is_key && !self.is_alpha_reference(br, src, aoffset);
+        if !hdr.is_intra {
+            match self.skip_mode {
+                FrameSkipMode::NonReference if !hdr.is_golden && non_ref => {
+                    // golden references stay valid, the last frame ones should not be used in case the hint was wrong
+                    self.shuf.clear_last();
+                    return Ok((NABufferType::None, FrameType::Skip));
+                },
+                FrameSkipMode::KeyframesOnly => {
+                    // the following inter frames cannot be decoded properly without this one
+                    self.clear_refs();
+                    return Ok((NABufferType::None, FrameType::Skip));
+                },
+                _ => {},
+            };
         }
+
         let fmt = if !self.has_alpha {
                 YUV420_FORMAT
             } else {
                 VP_YUVA420_FORMAT
                 };
-        let vinfo = NAVideoInfo::new(self.width, self.height, self.flip, fmt);
-        let ret = supp.pool_u8.get_free();
-        if ret.is_none() {
-            return Err(DecoderError::AllocError);
-        }
-        let mut buf = ret.unwrap();
-        if buf.get_info() != vinfo {
-            self.shuf.clear();
-            supp.pool_u8.reset();
-            supp.pool_u8.prealloc_video(vinfo, 4)?;
+        let shift = self.get_scale().get_shift();
+        let (width, height) = if hdr.mb_w != 0 {
+                (usize::from(hdr.mb_w) * 16, usize::from(hdr.mb_h) * 16)
+            } else {
+                (self.width, self.height)
+            };
+        let vinfo = NAVideoInfo::new(width >> shift, height >> shift, self.flip, fmt);
+        if out.as_ref().is_some_and(|dst| !dst.fits(&vinfo)) {
+            return Err(DecoderError::InvalidData);
+        }
+        let direct = out.as_ref().is_some_and(|dst| dst.flip == self.flip && dst.components >= usize::from(fmt.components))
+                     && (!hdr.interlaced || self.field_mode == FieldOutputMode::Woven);
+        let keep_ref = !direct || !non_ref;
+
+        if hdr.mb_w != 0 && (usize::from(hdr.mb_w) != self.mb_w || usize::from(hdr.mb_h) != self.mb_h) {
+            if !supp.limits.check_dimensions(usize::from(hdr.mb_w) * 16, usize::from(hdr.mb_h) * 16) {
+                return Err(DecoderError::LimitExceeded);
+            }
+            self.clear_refs();
+            self.set_dimensions((hdr.mb_w as usize) * 16, (hdr.mb_h as usize) * 16);
+        }
+        // a frame that is not going to be stored does not need a pool buffer
+        let mut pool_buf = None;
+        if keep_ref {
             let ret = supp.pool_u8.get_free();
             if ret.is_none() {
                 return Err(DecoderError::AllocError);
             }
-            buf = ret.unwrap();
+            let mut buf = ret.unwrap();
+            if buf.get_info() != vinfo {
+                self.clear_refs();
+                supp.pool_u8.reset();
+                supp.pool_u8.set_limits(supp.limits);
+                supp.pool_u8.prealloc_video(vinfo, 4)?;
+                let ret = supp.pool_u8.get_free();
+                if ret.is_none() {
+                    return Err(DecoderError::AllocError);
+                }
+                buf = ret.unwrap();
+            }
+            pool_buf = Some(buf);
         }
-        let mut dframe = NASimpleVideoFrame::from_video_buf(&mut buf).unwrap();
+        let mut pool_frame;
+        let mut out_frame;
+        let dframe = if let (true, Some(dst)) = (direct, out.as_mut()) {
+                out_frame = reborrow_frame(dst);
+                &mut out_frame
+            } else if let Some(frm) = pool_buf.as_mut().and_then(|buf| buf.as_mut()).and_then(NASimpleVideoFrame::from_video_buf) {
+                pool_frame = frm;
+                &mut pool_frame
+            } else {
+                return Err(DecoderError::AllocError);
+            };
 
-        if hdr.is_intra {
-            self.shuf.clear();
-        } else {
-            if !self.shuf.has_refs() {
-                return Err(DecoderError::MissingReference);
+        if !hdr.is_intra && want_colour && !self.shuf.has_refs() {
+            if self.colour_dropped {
+                self.err_info.stage = DecoderStage::Codec("skipped planes");
             }
+            return Err(DecoderError::MissingReference);
         }
-
-        let psrc = &src[if self.has_alpha { 3 } else { 0 }..aoffset];
-        self.decode_planes(br, &mut dframe, &mut bc, &hdr, psrc, false)?;
+        // check alpha references in advance so that the colour state is not updated for a frame that cannot be decoded
+        // (the frame type is stored in the top bit of the first header byte)
+        if want_alpha && (src[aoffset + 3] & 0x80) != 0 && !self.shuf.has_alpha_refs() {
+            self.err_info.stage  = if self.alpha_dropped { DecoderStage::Codec("skipped planes") } else { DecoderStage::Header };
+            self.err_info.alpha  = true;
+            self.err_info.offset = Some(aoffset + 3);
+            return Err(DecoderError::MissingReference);
+        }
+        if hdr.is_intra || !want_colour {
+            self.shuf.clear_colour();
+        }
+        if want_colour {
+            let psrc = if self.has_alpha { &src[3..][..aoffset] } else { src };
+            self.decode_planes(br, dframe, &mut bc, &hdr, psrc, false)?;
+        }
+        self.colour_dropped = !want_colour || (self.colour_dropped && !hdr.is_intra);
 
         if self.has_alpha {
             let asrc = &src[aoffset + 3..];
+            self.err_info.alpha = true;
+            self.err_info.offset = Some(aoffset + 3);
+            self.err_base = aoffset + 3;
             let mut bc = BoolCoder::new(asrc)?;
+            self.set_err_stage(DecoderStage::Header, &bc);
+            br.select_stream(true);
             let ahdr = br.parse_header(&mut bc)?;
-            validate!(ahdr.mb_w == hdr.mb_w && ahdr.mb_h == hdr.mb_h);
-            std::mem::swap(&mut self.models, &mut self.amodels);
-            let ret = self.decode_planes(br, &mut dframe, &mut bc, &ahdr, asrc, true);
-            std::mem::swap(&mut self.models, &mut self.amodels);
-            ret?;
-            match (hdr.is_golden, ahdr.is_golden) {
-                (true, true) => { self.shuf.add_golden_frame(buf.clone()); },
-                (true, false) => {
-                    let cur_golden = self.shuf.get_golden().unwrap();
-                    let off    = cur_golden.get_offset(3);
-                    let stride = cur_golden.get_stride(3);
-                    let mut new_golden = supp.pool_u8.get_copy(&buf).unwrap();
-                    let dst = new_golden.get_data_mut().unwrap();
-                    let src = cur_golden.get_data();
-                    dst[off..][..stride * self.mb_h * 16].copy_from_slice(&src[off..][..stride * self.mb_h * 16]);
-                    self.shuf.add_golden_frame(new_golden);
-                },
-                (false, true) => {
-                    let cur_golden = self.shuf.get_golden().unwrap();
-                    let off    = cur_golden.get_offset(3);
-                    let stride = cur_golden.get_stride(3);
-                    let mut new_golden = supp.pool_u8.get_copy(&cur_golden).unwrap();
-                    let dst = new_golden.get_data_mut().unwrap();
-                    let src = buf.get_data();
-                    dst[off..][..stride * self.mb_h * 16].copy_from_slice(&src[off..][..stride * self.mb_h * 16]);
-                    self.shuf.add_golden_frame(new_golden);
-                },
-                _ => {},
-            };
+            validate!(!ahdr.is_intra || (usize::from(ahdr.mb_w) == self.mb_w && usize::from(ahdr.mb_h) == self.mb_h));
+            if ahdr.is_intra || !want_alpha {
+                self.shuf.clear_alpha();
+            } else if !self.shuf.has_alpha_refs() {
+                if self.alpha_dropped {
+                    self.err_info.stage = DecoderStage::Codec("skipped planes");
+                }
+                return Err(DecoderError::MissingReference);
+            }
+            self.alpha_dropped = !want_alpha || (self.alpha_dropped && !ahdr.is_intra);
+            self.last_ahdr = Some(ahdr);
+            if want_alpha {
+                std::mem::swap(&mut self.models, &mut self.amodels);
+                let ret = self.decode_planes(br, dframe, &mut bc, &ahdr, asrc, true);
+                std::mem::swap(&mut self.models, &mut self.amodels);
+                ret?;
+            }
+            self.err_info.stage = DecoderStage::Frame;
+            self.err_info.offset = None;
         }
 
-        if hdr.is_golden && !self.has_alpha {
-            self.shuf.add_golden_frame(buf.clone());
+        if !keep_ref {
+            self.shuf.clear_last();
+            return Ok((NABufferType::None, if hdr.is_intra { FrameType::I } else { FrameType::P }));
+        }
+        let mut buf = if let Some(buf) = pool_buf { buf } else { return Err(DecoderError::Bug); };
+        if direct {
+            if let (Some(dst), Some(vbuf)) = (out.as_ref(), buf.as_mut()) {
+                if !dst.copy_to_buf(vbuf) {
+                    return Err(DecoderError::AllocError);
+                }
+            }
+        }
+        if want_alpha {
+            if let Some(ahdr) = self.last_ahdr {
+                if ahdr.is_golden {
+                    self.shuf.add_golden_alpha_frame(buf.clone());
+                }
+                self.shuf.add_alpha_frame(buf.clone());
+            }
+        }
+        if want_colour {
+            if hdr.is_golden {
+                self.shuf.add_golden_frame(buf.clone());
+            }
+            self.shuf.add_frame(buf.clone());
         }
-        self.shuf.add_frame(buf.clone());
 
-        Ok((NABufferType::Video(buf), if hdr.is_intra { FrameType::I } else { FrameType::P }))
+        if self.skip_mode == FrameSkipMode::DecodeOnly {
+            return Ok((NABufferType::None, FrameType::Skip));
+        }
+        if hdr.interlaced && self.field_mode != FieldOutputMode::Woven {
+            let mut obuf = supp.pool_u8.get_free().ok_or(DecoderError::AllocError)?;
+            if let Some(dst) = obuf.as_mut() {
+                output_fields(&buf, dst, self.field_mode);
+            } else {
+                return Err(DecoderError::AllocError);
+            }
+            buf = obuf;
+        }
+        let ftype = if hdr.is_intra { FrameType::I } else { FrameType::P };
+        if direct {
+            return Ok((NABufferType::None, ftype));
+        }
+        if let Some(dst) = out {
+            if !dst.copy_from_buf(&buf) {
+                return Err(DecoderError::Bug);
+            }
+        }
+        Ok((NABufferType::Video(buf), ftype))
     }
     fn decode_planes(&mut self, br: &mut dyn VP56Parser, dframe: &mut NASimpleVideoFrame<u8>, bc: &mut BoolCoder, hdr: &VP56Header, src: &[u8], alpha: bool) -> DecoderResult<()> {
         let mut cr;
         if hdr.multistream {
             let off = hdr.offset as usize;
+            validate!(off < src.len());
             if !hdr.use_huffman {
                 let bc2 = BoolCoder::new(&src[off..])?;
                 cr = CoeffReader::Bool(bc2);
@@ -584,9 +1033,12 @@ impl VP56Decoder {
             br.reset_models(&mut self.models);
             self.reset_mbtype_models();
         } else {
+            self.set_err_stage(DecoderStage::Codec("mode models"), bc);
             self.decode_mode_prob_models(bc)?;
+            self.set_err_stage(DecoderStage::Codec("MV models"), bc);
             br.decode_mv_models(bc, &mut self.models.mv_models)?;
         }
+        self.set_err_stage(DecoderStage::Codec("coefficient models"), bc);
         br.decode_coeff_models(bc, &mut self.models, hdr.is_intra)?;
         if hdr.use_huffman {
             for i in 0..2 {
@@ -631,6 +1083,7 @@ impl VP56Decoder {
             self.fstate.last_idx = [24; 4];
             for mb_x in 0..self.mb_w {
                 self.fstate.mb_x = mb_x;
+                self.set_err_stage(DecoderStage::Macroblock { mb_x, mb_y }, bc);
                 self.decode_mb(dframe, bc, &mut cr, br, hdr, alpha)?;
                 self.dc_pred.next_mb();
             }
@@ -826,7 +1279,7 @@ impl VP56Decoder {
                 },
                 VPMBType::InterMV           => {
                     let diff_mv = self.decode_mv(bc, br);
-                    self.mb_info[mb_pos].mv = pred_mv + diff_mv;
+                    self.mb_info[mb_pos].mv = add_mv(pred_mv, diff_mv);
                 },
                 VPMBType::InterNearest      => {
                     self.mb_info[mb_pos].mv = nearest_mv;
@@ -843,7 +1296,7 @@ impl VP56Decoder {
                             VPMBType::InterNoMV => {},
                             VPMBType::InterMV   => {
                                 let diff_mv = self.decode_mv(bc, br);
-                                four_mv[i] = pred_mv + diff_mv;
+                                four_mv[i] = add_mv(pred_mv, diff_mv);
                             },
                             VPMBType::InterNearest => {
                                 four_mv[i] = nearest_mv;
@@ -866,7 +1319,7 @@ impl VP56Decoder {
                 },
                 VPMBType::GoldenMV          => {
                     let diff_mv = self.decode_mv(bc, br);
-                    self.mb_info[mb_pos].mv = pred_mv + diff_mv;
+                    self.mb_info[mb_pos].mv = add_mv(pred_mv, diff_mv);
                 },
                 VPMBType::GoldenNearest     => {
                     self.mb_info[mb_pos].mv = nearest_mv;
@@ -878,9 +1331,9 @@ impl VP56Decoder {
             };
         }
         if !mb_type.is_intra() && (mb_type != VPMBType::InterFourMV) {
-            self.do_mc(br, frm, mb_type, self.mb_info[mb_pos].mv, alpha);
+            self.do_mc(br, frm, mb_type, self.mb_info[mb_pos].mv, alpha)?;
         } else if mb_type == VPMBType::InterFourMV {
-            self.do_fourmv(br, frm, &four_mv, alpha);
+            self.do_fourmv(br, frm, &four_mv, alpha)?;
         }
 
         for blk_no in 0..4 {
@@ -904,7 +1357,13 @@ impl VP56Decoder {
             let bx = mb_x * 2 + (blk_no & 1);
             let by = mb_y * 2 + (blk_no >> 1);
             let has_ac = self.fstate.last_idx[self.fstate.ctx_idx] > 0;
-            if mb_type.is_intra() {
+            if let Some(ref idct) = self.lowres {
+                if mb_type.is_intra() {
+                    idct.put_block(&self.coeffs[blk_no], bx, by, self.fstate.plane, self.ilace_mb, frm);
+                } else {
+                    idct.add_block(&self.coeffs[blk_no], bx, by, self.fstate.plane, self.ilace_mb, frm);
+                }
+            } else if mb_type.is_intra() {
                 if !self.ilace_mb {
                     if has_ac {
                         vp_put_block(&mut self.coeffs[blk_no], bx, by, self.fstate.plane, frm);
@@ -943,7 +1402,13 @@ impl VP56Decoder {
             };
             self.top_ctx[self.fstate.plane][mb_x] = self.fstate.top_ctx;
             self.predict_dc(mb_type, mb_pos, blk_no, alpha);
-            if !alpha {
+            if let (false, Some(ref idct)) = (alpha, &self.lowres) {
+                if mb_type.is_intra() {
+                    idct.put_block(&self.coeffs[blk_no], mb_x, mb_y, self.fstate.plane, false, frm);
+                } else {
+                    idct.add_block(&self.coeffs[blk_no], mb_x, mb_y, self.fstate.plane, false, frm);
+                }
+            } else if !alpha {
                 let has_ac = self.fstate.last_idx[self.fstate.ctx_idx] > 0;
                 if mb_type.is_intra() {
                     if has_ac {
@@ -962,45 +1427,79 @@ impl VP56Decoder {
         }
         Ok(())
     }
-    fn do_mc(&mut self, br: &dyn VP56Parser, frm: &mut NASimpleVideoFrame<u8>, mb_type: VPMBType, mv: MV, alpha: bool) {
+    fn do_mc(&mut self, br: &dyn VP56Parser, frm: &mut NASimpleVideoFrame<u8>, mb_type: VPMBType, mv: MV, alpha: bool) -> DecoderResult<()> {
         let x = self.fstate.mb_x * 16;
         let y = self.fstate.mb_y * 16;
         let plane = if !alpha { 0 } else { 3 };
-        let src = if mb_type.get_ref_id() == VP_REF_INTER {
-                self.shuf.get_last().unwrap()
-            } else {
-                self.shuf.get_golden().unwrap()
-            };
+        let src = match (mb_type.get_ref_id() == VP_REF_INTER, alpha) {
+                (true,  false) => self.shuf.get_last(),
+                (false, false) => self.shuf.get_golden(),
+                (true,  true)  => self.shuf.get_last_alpha(),
+                (false, true)  => self.shuf.get_golden_alpha(),
+            }.ok_or(DecoderError::MissingReference)?;
+
+        if let Some(ref idct) = self.lowres {
+            let shift = idct.get_shift();
+            let bs = idct.get_block_size();
+            let (x, y) = (x >> shift, y >> shift);
+            idct.mc_block(frm, &src, plane, x,      y,      mv);
+            idct.mc_block(frm, &src, plane, x + bs, y,      mv);
+            idct.mc_block(frm, &src, plane, x,      y + bs, mv);
+            idct.mc_block(frm, &src, plane, x + bs, y + bs, mv);
+            if !alpha {
+                let x = x >> 1;
+                let y = y >> 1;
+                idct.mc_block(frm, &src, 1, x, y, mv);
+                idct.mc_block(frm, &src, 2, x, y, mv);
+            }
+            return Ok(());
+        }
 
-        br.mc_block(frm, self.mc_buf.clone(), src.clone(), plane, x + 0, y + 0, mv, self.loop_thr);
-        br.mc_block(frm, self.mc_buf.clone(), src.clone(), plane, x + 8, y + 0, mv, self.loop_thr);
-        br.mc_block(frm, self.mc_buf.clone(), src.clone(), plane, x + 0, y + 8, mv, self.loop_thr);
-        br.mc_block(frm, self.mc_buf.clone(), src.clone(), plane, x + 8, y + 8, mv, self.loop_thr);
+        br.mc_block(frm, &mut self.mc_buf, src.clone(), plane, x + 0, y + 0, mv, self.loop_thr);
+        br.mc_block(frm, &mut self.mc_buf, src.clone(), plane, x + 8, y + 0, mv, self.loop_thr);
+        br.mc_block(frm, &mut self.mc_buf, src.clone(), plane, x + 0, y + 8, mv, self.loop_thr);
+        br.mc_block(frm, &mut self.mc_buf, src.clone(), plane, x + 8, y + 8, mv, self.loop_thr);
         if !alpha {
             let x = self.fstate.mb_x * 8;
             let y = self.fstate.mb_y * 8;
-            br.mc_block(frm, self.mc_buf.clone(), src.clone(), 1, x, y, mv, self.loop_thr);
-            br.mc_block(frm, self.mc_buf.clone(), src,         2, x, y, mv, self.loop_thr);
+            br.mc_block(frm, &mut self.mc_buf, src.clone(), 1, x, y, mv, self.loop_thr);
+            br.mc_block(frm, &mut self.mc_buf, src,         2, x, y, mv, self.loop_thr);
         }
+        Ok(())
     }
-    fn do_fourmv(&mut self, br: &dyn VP56Parser, frm: &mut NASimpleVideoFrame<u8>, mvs: &[MV; 4], alpha: bool) {
+    fn do_fourmv(&mut self, br: &dyn VP56Parser, frm: &mut NASimpleVideoFrame<u8>, mvs: &[MV; 4], alpha: bool) -> DecoderResult<()> {
         let x = self.fstate.mb_x * 16;
         let y = self.fstate.mb_y * 16;
         let plane = if !alpha { 0 } else { 3 };
-        let src = self.shuf.get_last().unwrap();
-        for blk_no in 0..4 {
-            br.mc_block(frm, self.mc_buf.clone(), src.clone(),
-                        plane, x + (blk_no & 1) * 8, y + (blk_no & 2) * 4,
-                        mvs[blk_no], self.loop_thr);
+        let src = if !alpha { self.shuf.get_last() } else { self.shuf.get_last_alpha() }.ok_or(DecoderError::MissingReference)?;
+        if let Some(ref idct) = self.lowres {
+            let shift = idct.get_shift();
+            for blk_no in 0..4 {
+                idct.mc_block(frm, &src, plane, (x + (blk_no & 1) * 8) >> shift, (y + (blk_no & 2) * 4) >> shift, mvs[blk_no]);
+            }
+        } else {
+            for blk_no in 0..4 {
+                br.mc_block(frm, &mut self.mc_buf, src.clone(),
+                            plane, x + (blk_no & 1) * 8, y + (blk_no & 2) * 4,
+                            mvs[blk_no], self.loop_thr);
+            }
         }
         if !alpha {
             let x = self.fstate.mb_x * 8;
             let y = self.fstate.mb_y * 8;
-            let sum = mvs[0] + mvs[1] + mvs[2] + mvs[3];
-            let mv = MV { x: sum.x / 4, y: sum.y / 4 };
-            br.mc_block(frm, self.mc_buf.clone(), src.clone(), 1, x, y, mv, self.loop_thr);
-            br.mc_block(frm, self.mc_buf.clone(), src,         2, x, y, mv, self.loop_thr);
+            let sum_x: i32 = mvs.iter().map(|mv| i32::from(mv.x)).sum();
+            let sum_y: i32 = mvs.iter().map(|mv| i32::from(mv.y)).sum();
+            let mv = MV { x: (sum_x / 4) as i16, y: (sum_y / 4) as i16 };
+            if let Some(ref idct) = self.lowres {
+                let shift = idct.get_shift();
+                idct.mc_block(frm, &src, 1, x >> shift, y >> shift, mv);
+                idct.mc_block(frm, &src, 2, x >> shift, y >> shift, mv);
+                return Ok(());
+            }
+            br.mc_block(frm, &mut self.mc_buf, src.clone(), 1, x, y, mv, self.loop_thr);
+            br.mc_block(frm, &mut self.mc_buf, src,         2, x, y, mv, self.loop_thr);
         }
+        Ok(())
     }
     fn predict_dc(&mut self, mb_type: VPMBType, _mb_pos: usize, blk_no: usize, _alpha: bool) {
         let is_luma = blk_no < 4;
@@ -1015,38 +1514,38 @@ impl VP56Decoder {
                 (&mut self.dc_pred.ref_c, self.dc_pred.c_idx)
             };
         let ref_id = mb_type.get_ref_id();
-        let mut dc_pred = 0;
+        let mut dc_pred = 0i32;
         let mut count = 0;
         let has_left_blk = is_luma && ((blk_no & 1) == 1);
         if has_left_blk || self.dc_pred.ref_left == ref_id {
-            dc_pred += match blk_no {
+            dc_pred += i32::from(match blk_no {
                     0 | 1 => self.dc_pred.ldc_y[0],
                     2 | 3 => self.dc_pred.ldc_y[1],
                     4     => self.dc_pred.ldc_u,
                     _     => self.dc_pred.ldc_v,
-                };
+                });
             count += 1;
         }
         if dc_ref[dc_idx] == ref_id {
-            dc_pred += dcs[dc_idx];
+            dc_pred += i32::from(dcs[dc_idx]);
             count += 1;
         }
         if self.version == 5 {
             if (count < 2) && (dc_ref[dc_idx - 1] == ref_id) {
-                dc_pred += dcs[dc_idx - 1];
+                dc_pred += i32::from(dcs[dc_idx - 1]);
                 count += 1;
             }
             if (count < 2) && (dc_ref[dc_idx + 1] == ref_id) {
-                dc_pred += dcs[dc_idx + 1];
+                dc_pred += i32::from(dcs[dc_idx + 1]);
                 count += 1;
             }
         }
         if count == 0 {
-            dc_pred = self.last_dc[ref_id as usize][plane];
+            dc_pred = i32::from(self.last_dc[ref_id as usize][plane]);
         } else if count == 2 {
             dc_pred /= 2;
         }
-        self.coeffs[blk_no][0] += dc_pred;
+        self.coeffs[blk_no][0] = self.coeffs[blk_no][0].wrapping_add(dc_pred as i16);
 
         let dc = self.coeffs[blk_no][0];
         if blk_no != 4 { // update top block reference only for the second chroma component
@@ -1084,7 +1583,7 @@ const VP56_DC_QUANTS: [i16; 64] = [
     16, 16, 15, 11, 11, 11, 10, 10,
      9,  8,  7,  5,  3,  3,  2,  2
 ];
-const VP56_AC_QUANTS: [i16; 64] = [
+pub(crate) const VP56_AC_QUANTS: [i16; 64] = [
     94, 92, 90, 88, 86, 82, 78, 74,
     70, 66, 62, 58, 54, 53, 52, 51,
     50, 49, 48, 47, 46, 45, 44, 43,
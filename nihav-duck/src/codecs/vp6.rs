@@ -6,8 +6,9 @@ use super::vpcommon::*;
 pub use super::vp56::*;
 use super::vp6data::*;
 
-#[derive(Default)]
-pub struct VP6BR {
+// stream parameters persisting between frames, colour and alpha streams in VP6A have their own
+#[derive(Clone,Copy,Default)]
+struct VP6StreamParams {
     vpversion:      u8,
     profile:        u8,
     interlaced:     bool,
@@ -20,6 +21,13 @@ pub struct VP6BR {
     filter_alpha:   usize,
 }
 
+#[derive(Clone,Default)]
+pub struct VP6BR {
+    params:         VP6StreamParams,
+    aparams:        VP6StreamParams,
+    is_alpha:       bool,
+}
+
 impl VP6BR {
     pub fn new() -> Self {
         Self::default()
@@ -27,6 +35,12 @@ impl VP6BR {
 }
 
 impl VP56Parser for VP6BR {
+    fn select_stream(&mut self, alpha: bool) {
+        if self.is_alpha != alpha {
+            std::mem::swap(&mut self.params, &mut self.aparams);
+            self.is_alpha = alpha;
+        }
+    }
     fn parse_header(&mut self, bc: &mut BoolCoder) -> DecoderResult<VP56Header> {
         let mut hdr = VP56Header::default();
 // horrible hack to match VP6 header parsing
@@ -44,9 +58,9 @@ impl VP56Parser for VP6BR {
             validate!((hdr.profile == VP6_SIMPLE_PROFILE) || (hdr.profile == VP6_ADVANCED_PROFILE));
             hdr.interlaced                      = br.read_bool()?;
         } else {
-            hdr.version = self.vpversion;
-            hdr.profile = self.profile;
-            hdr.interlaced = self.interlaced;
+            hdr.version = self.params.vpversion;
+            hdr.profile = self.params.profile;
+            hdr.interlaced = self.params.interlaced;
         }
         if hdr.multistream || (hdr.profile == VP6_SIMPLE_PROFILE) {
             hdr.offset                          = br.read(16)? as u16;
@@ -55,7 +69,7 @@ impl VP56Parser for VP6BR {
         }
         let bytes = br.tell() >> 3;
         bc.skip_bytes(bytes);
-        self.loop_mode = 0;
+        self.params.loop_mode = 0;
         if hdr.is_intra {
             hdr.mb_h                            = bc.read_bits(8) as u8;
             hdr.mb_w                            = bc.read_bits(8) as u8;
@@ -68,40 +82,40 @@ impl VP56Parser for VP6BR {
         } else {
             hdr.is_golden                       = bc.read_bool();
             if hdr.profile == VP6_ADVANCED_PROFILE {
-                self.loop_mode                  = bc.read_bool() as u8;
-                if self.loop_mode != 0 {
-                    self.loop_mode             += bc.read_bool() as u8;
-                    validate!(self.loop_mode <= 1);
+                self.params.loop_mode           = bc.read_bool() as u8;
+                if self.params.loop_mode != 0 {
+                    self.params.loop_mode      += bc.read_bool() as u8;
+                    validate!(self.params.loop_mode <= 1);
                 }
                 if hdr.version == VERSION_VP62 {
-                    self.do_pm                  = bc.read_bool();
+                    self.params.do_pm           = bc.read_bool();
                 }
             }
         }
 
-        if (hdr.profile == VP6_ADVANCED_PROFILE) && (hdr.is_intra || self.do_pm) {
-            self.autosel_pm                     = bc.read_bool();
-            if self.autosel_pm {
-                self.var_thresh                 = bc.read_bits(5) as u16;
+        if (hdr.profile == VP6_ADVANCED_PROFILE) && (hdr.is_intra || self.params.do_pm) {
+            self.params.autosel_pm              = bc.read_bool();
+            if self.params.autosel_pm {
+                self.params.var_thresh          = bc.read_bits(5) as u16;
                 if hdr.version != VERSION_VP62 {
-                    self.var_thresh <<= 5;
+                    self.params.var_thresh <<= 5;
                 }
-                self.mv_thresh                  = bc.read_bits(3) as u8;
+                self.params.mv_thresh           = bc.read_bits(3) as u8;
             } else {
-                self.bicubic                    = bc.read_bool();
+                self.params.bicubic             = bc.read_bool();
             }
             if hdr.version == VERSION_VP62 {
-                self.filter_alpha               = bc.read_bits(4) as usize;
+                self.params.filter_alpha        = bc.read_bits(4) as usize;
             } else {
-                self.filter_alpha = 16;
+                self.params.filter_alpha = 16;
             }
         }
 
         hdr.use_huffman                         = bc.read_bool();
 
-        self.vpversion  = hdr.version;
-        self.profile    = hdr.profile;
-        self.interlaced = hdr.interlaced;
+        self.params.vpversion  = hdr.version;
+        self.params.profile    = hdr.profile;
+        self.params.interlaced = hdr.interlaced;
         Ok(hdr)
     }
     fn decode_mv(&self, bc: &mut BoolCoder, model: &VP56MVModel) -> i16 {
@@ -139,7 +153,7 @@ impl VP56Parser for VP6BR {
             mdl.tree_probs.copy_from_slice(&TREE_PROBS[i]);
         }
         models.vp6models.zero_run_probs.copy_from_slice(&ZERO_RUN_PROBS);
-        reset_scan(&mut models.vp6models, self.interlaced);
+        reset_scan(&mut models.vp6models, self.params.interlaced);
     }
     fn decode_mv_models(&self, bc: &mut BoolCoder, models: &mut [VP56MVModel; 2]) -> DecoderResult<()> {
         for comp in 0..2 {
@@ -187,7 +201,7 @@ impl VP56Parser for VP6BR {
             }
             update_scan(&mut models.vp6models);
         } else {
-            reset_scan(&mut models.vp6models, self.interlaced);
+            reset_scan(&mut models.vp6models, self.params.interlaced);
         }
 
         for comp in 0..2 {
@@ -302,7 +316,7 @@ impl VP56Parser for VP6BR {
 
         Ok(())
     }
-    fn mc_block(&self, dst: &mut NASimpleVideoFrame<u8>, mut mc_buf: NAVideoBufferRef<u8>, src: NAVideoBufferRef<u8>, plane: usize, x: usize, y: usize, mv: MV, loop_str: i16) {
+    fn mc_block(&self, dst: &mut NASimpleVideoFrame<u8>, mc_buf: &mut NAVideoBuffer<u8>, src: NAVideoBufferRef<u8>, plane: usize, x: usize, y: usize, mv: MV, loop_str: i16) {
         let is_luma = (plane != 1) && (plane != 2);
         let (sx, sy, mx, my, msx, msy) = if is_luma {
                 (mv.x >> 2, mv.y >> 2, (mv.x & 3) << 1, (mv.y & 3) << 1, mv.x / 4, mv.y / 4)
@@ -322,18 +336,18 @@ impl VP56Parser for VP6BR {
             vp31_loop_filter(tmp_blk, off, 16, 1, 12, loop_str);
         }
         let copy_mode = (mx == 0) && (my == 0);
-        let mut bicubic = !copy_mode && is_luma && self.bicubic;
-        if is_luma && !copy_mode && (self.profile == VP6_ADVANCED_PROFILE) {
-            if !self.autosel_pm {
+        let mut bicubic = !copy_mode && is_luma && self.params.bicubic;
+        if is_luma && !copy_mode && (self.params.profile == VP6_ADVANCED_PROFILE) {
+            if !self.params.autosel_pm {
                 bicubic = true;
             } else {
-                let mv_limit = 1 << (self.mv_thresh + 1);
-                if (mv.x.abs() <= mv_limit) && (mv.y.abs() <= mv_limit) {
+                let mv_limit = 1 << (self.params.mv_thresh + 1);
+                if (mv.x.unsigned_abs() <= mv_limit) && (mv.y.unsigned_abs() <= mv_limit) {
                     let mut var_off = 16 * 2 + 2;
                     if mv.x < 0 { var_off += 1; }
                     if mv.y < 0 { var_off += 16; }
                     let var = calc_variance(&tmp_blk[var_off..], 16);
-                    if var >= self.var_thresh {
+                    if var >= self.params.var_thresh {
                         bicubic = true;
                     }
                 }
@@ -347,8 +361,8 @@ impl VP56Parser for VP6BR {
                 dline[..8].copy_from_slice(&sline[..8]);
             }
         } else if bicubic {
-            let coeff_h = &VP6_BICUBIC_COEFFS[self.filter_alpha][mx as usize];
-            let coeff_v = &VP6_BICUBIC_COEFFS[self.filter_alpha][my as usize];
+            let coeff_h = &VP6_BICUBIC_COEFFS[self.params.filter_alpha][mx as usize];
+            let coeff_v = &VP6_BICUBIC_COEFFS[self.params.filter_alpha][my as usize];
             mc_bicubic(dbuf, dstride, tmp_blk, 16 * 2 + 2, 16, coeff_h, coeff_v);
         } else {
             mc_bilinear(dbuf, dstride, tmp_blk, 16 * 2 + 2, 16, mx as u16, my as u16);
@@ -424,14 +438,14 @@ fn decode_token_huff(br: &mut BitReader, huff: &VP6Huff) -> DecoderResult<(i16,
     let tok                                     = br.read_huff(huff)?;
     match tok {
         0   => Ok((0, false)),
-        1 | 2 | 3 | 4 => {
+        1..=4 => {
             if !br.read_bool()? {
                 Ok((i16::from(tok), false))
             } else {
                 Ok((-i16::from(tok), false))
             }
         },
-        5 | 6 | 7 | 8 | 9 | 10 => {
+        5..=10 => {
             let base = (tok - 5) as usize;
             let add_bits                        = br.read(VP6_COEF_ADD_BITS[base])? as i16;
             let val = VP56_COEF_BASE[base] + add_bits;
@@ -590,3 +604,984 @@ fn mc_bicubic(dst: &mut [u8], dstride: usize, src: &[u8], mut soff: usize, sstri
         }
     }
 }
+
+struct VP6Decoder {
+    dec:        VP56Decoder,
+    info:       NACodecInfoRef,
+    br:         VP6BR,
+    has_alpha:  bool,
+    flipped:    bool,
+}
+
+impl VP6Decoder {
+    fn new(flipped: bool, has_alpha: bool) -> Self {
+        Self {
+            dec:        VP56Decoder::new(6, has_alpha, flipped),
+            info:       NACodecInfoRef::default(),
+            br:         VP6BR::new(),
+            has_alpha,
+            flipped,
+        }
+    }
+}
+
+impl NADecoder for VP6Decoder {
+    fn init(&mut self, supp: &mut NADecoderSupport, info: NACodecInfoRef) -> DecoderResult<()> {
+        if let NACodecTypeInfo::Video(vinfo) = info.get_properties() {
+            let fmt = if !self.has_alpha { YUV420_FORMAT } else { VP_YUVA420_FORMAT };
+            let myvinfo = NAVideoInfo::new(vinfo.get_width(), vinfo.get_height(), self.flipped, fmt);
+            let myinfo = NACodecTypeInfo::Video(myvinfo);
+            self.info = NACodecInfo::new_ref(info.get_name(), myinfo, info.get_extradata()).into_ref();
+            self.dec.init(supp, myvinfo)?;
+            Ok(())
+        } else {
+            Err(DecoderError::InvalidData)
+        }
+    }
+    fn decode(&mut self, supp: &mut NADecoderSupport, pkt: &NAPacket) -> DecoderResult<NAFrameRef> {
+        let src = pkt.get_buffer();
+        let (bufinfo, ftype) = self.dec.decode_frame(supp, src.as_slice(), &mut self.br)?;
+        if let Some(vinfo) = bufinfo.get_video_info() {
+            if self.info.get_properties().get_video_info() != Some(vinfo) {
+                self.info = self.info.replace_info(NACodecTypeInfo::Video(vinfo));
+            }
+        }
+        let mut frm = NAFrame::new_from_pkt(pkt, self.info.clone(), bufinfo);
+        frm.set_keyframe(ftype == FrameType::I);
+        frm.set_frame_type(ftype);
+        frm.set_interlaced(self.dec.is_interlaced());
+        Ok(frm.into_ref())
+    }
+    fn flush(&mut self) {
+        self.dec.flush();
+    }
+    fn set_skip_mode(&mut self, mode: FrameSkipMode) {
+        self.dec.set_skip_mode(mode);
+    }
+    fn set_next_is_key(&mut self, next_is_key: bool) {
+        self.dec.set_next_is_key(next_is_key);
+    }
+    fn set_field_mode(&mut self, mode: FieldOutputMode) {
+        self.dec.set_field_mode(mode);
+    }
+}
+
+pub fn get_decoder_vp6() -> Box<dyn NADecoder + Send> {
+    Box::new(VP6Decoder::new(true, false))
+}
+
+pub fn get_decoder_vp6f() -> Box<dyn NADecoder + Send> {
+    Box::new(VP6Decoder::new(false, false))
+}
+
+pub fn get_decoder_vp6_alpha() -> Box<dyn NADecoder + Send> {
+    Box::new(VP6Decoder::new(false, true))
+}
+
+#[cfg(test)]
+mod test {
+    use nihav_core::codecs::*;
+    use nihav_codec_support::test::dec_video::*;
+    use super::*;
+
+    // The expected hashes are the output of this decoder on the synthetic streams from vp6synth.rs
+    // so these are regression tests, not conformance tests against a reference decoder.
+    fn test_vp6_regression(name: &str, has_alpha: bool, expected: &[Vec<[u32; 4]>]) {
+        let name = format!("{}/assets/{}", env!("CARGO_MANIFEST_DIR"), name);
+        let mut dec_reg = RegisteredDecoders::new();
+        crate::codecs::duck_register_all_decoders(&mut dec_reg);
+        let codec_name = if !has_alpha { "vp6" } else { "vp6a" };
+        let mut dec = (dec_reg.find_decoder(codec_name).unwrap())();
+        let mut supp = NADecoderSupport::new();
+        let info = NACodecInfo::new(codec_name, NACodecTypeInfo::Video(NAVideoInfo::new(64, 48, false, YUV420_FORMAT)), None).into_ref();
+        dec.init(&mut supp, info).unwrap();
+        let mut pts = 0;
+        test_decoding_md5(&name, |src| {
+                let pkt = NAPacket::new(0, NATimeInfo::new(Some(pts), None, Some(1), 1, 30), false, src.to_vec());
+                pts += 1;
+                let frm = dec.decode(&mut supp, &pkt)?;
+                assert_eq!(frm.get_pts(), pkt.get_pts());
+                Ok(frm.get_buffer())
+            }, expected);
+    }
+    #[test]
+    fn test_vp6_frame_info() {
+        let name = format!("{}/assets/vp6_golden.vp6", env!("CARGO_MANIFEST_DIR"));
+        let pkts = read_raw_packets(&name);
+        let mut dec_reg = RegisteredDecoders::new();
+        crate::codecs::duck_register_all_decoders(&mut dec_reg);
+        let mut dec = (dec_reg.find_decoder("vp6f").unwrap())();
+        let mut supp = NADecoderSupport::new();
+        let info = NACodecInfo::new("vp6f", NACodecTypeInfo::Video(NAVideoInfo::new(32, 32, false, YUV420_FORMAT)), None).into_ref();
+        dec.init(&mut supp, info).unwrap();
+        for (i, src) in pkts.iter().enumerate() {
+            let ts = NATimeInfo::new(Some(i as u64 * 40), Some(i as u64 * 40), Some(40), 1, 1000);
+            let pkt = NAPacket::new(3, ts, false, src.clone());
+            let frm = dec.decode(&mut supp, &pkt).unwrap();
+            assert_eq!(frm.get_time_information(), ts);
+            assert_eq!(frm.get_stream_id(), 3);
+            assert_eq!(frm.is_keyframe(), i == 0);
+            assert_eq!(frm.get_frame_type(), if i == 0 { FrameType::I } else { FrameType::P });
+            let vinfo = frm.get_info().get_properties().get_video_info().unwrap();
+            assert_eq!((vinfo.get_width(), vinfo.get_height()), (64, 48));
+        }
+    }
+
+    #[test]
+    fn test_vp6_limits() {
+        let name = format!("{}/assets/vp6_simple.vp6", env!("CARGO_MANIFEST_DIR"));
+        let pkts = read_raw_packets(&name);
+        let mut br = VP6BR::new();
+
+        let mut dec = VP56Decoder::new(6, false, true);
+        let mut supp = NADecoderSupport::new();
+        supp.set_limits(DecoderLimits { max_width: 32, ..DecoderLimits::new() });
+        assert_eq!(dec.init(&mut supp, NAVideoInfo::new(64, 48, true, YUV420_FORMAT)).err(), Some(DecoderError::LimitExceeded));
+        dec.init(&mut supp, NAVideoInfo::new(16, 16, true, YUV420_FORMAT)).unwrap();
+        assert_eq!(dec.decode_frame(&mut supp, &pkts[0], &mut br).err(), Some(DecoderError::LimitExceeded));
+
+        let mut dec = VP56Decoder::new(6, false, true);
+        let mut supp = NADecoderSupport::new();
+        supp.set_limits(DecoderLimits { max_pool_bufs: 2, ..DecoderLimits::new() });
+        assert_eq!(dec.init(&mut supp, NAVideoInfo::new(64, 48, true, YUV420_FORMAT)).err(), Some(DecoderError::LimitExceeded));
+
+        let mut dec = VP56Decoder::new(6, false, true);
+        let mut supp = NADecoderSupport::new();
+        supp.set_limits(DecoderLimits { max_packet_size: pkts[0].len() - 1, ..DecoderLimits::new() });
+        dec.init(&mut supp, NAVideoInfo::new(64, 48, true, YUV420_FORMAT)).unwrap();
+        assert_eq!(dec.decode_frame(&mut supp, &pkts[0], &mut br).err(), Some(DecoderError::LimitExceeded));
+        assert!(dec.decode_frame(&mut supp, &pkts[0][..pkts[0].len() - 1], &mut br).is_ok());
+    }
+    #[test]
+    fn test_vp6_error_info() {
+        let name = format!("{}/assets/vp6_simple.vp6", env!("CARGO_MANIFEST_DIR"));
+        let pkts = read_raw_packets(&name);
+        let mut br = VP6BR::new();
+        let mut dec = VP56Decoder::new(6, false, true);
+        let mut supp = NADecoderSupport::new();
+        dec.init(&mut supp, NAVideoInfo::new(64, 48, true, YUV420_FORMAT)).unwrap();
+        assert_eq!(dec.decode_frame(&mut supp, &pkts[1], &mut br).err(), Some(DecoderError::MissingReference));
+        let info = dec.get_last_error().unwrap();
+        assert_eq!(info, DecoderErrorInfo { error: DecoderError::MissingReference, stage: DecoderStage::Frame, alpha: false, offset: None });
+        assert!(dec.decode_frame(&mut supp, &pkts[0], &mut br).is_ok());
+        assert_eq!(dec.get_last_error(), None);
+
+        let name = format!("{}/assets/vp6a.vp6", env!("CARGO_MANIFEST_DIR"));
+        let mut pkt = read_raw_packets(&name)[0].clone();
+        let aoffset = ((pkt[0] as usize) << 16) | ((pkt[1] as usize) << 8) | (pkt[2] as usize);
+        for el in pkt[aoffset + 3..].iter_mut() {
+            *el = 0xFF;
+        }
+        let mut dec = VP56Decoder::new(6, true, true);
+        let mut supp = NADecoderSupport::new();
+        dec.init(&mut supp, NAVideoInfo::new(64, 48, true, VP_YUVA420_FORMAT)).unwrap();
+        assert!(dec.decode_frame(&mut supp, &pkt, &mut br).is_err());
+        let info = dec.get_last_error().unwrap();
+        assert!(info.alpha);
+        assert_eq!(info.stage, DecoderStage::Header);
+        assert_eq!(info.offset, Some(aoffset + 3));
+
+        let info = DecoderErrorInfo { error: DecoderError::InvalidData, stage: DecoderStage::Macroblock { mb_x: 2, mb_y: 1 }, alpha: true, offset: Some(42) };
+        assert_eq!(info.to_string(), "invalid data in macroblock (2, 1) of alpha plane at byte 42");
+        let info = DecoderErrorInfo { error: DecoderError::ShortData, stage: DecoderStage::Codec("MV models"), alpha: false, offset: None };
+        assert_eq!(info.to_string(), "short data in MV models");
+    }
+    #[test]
+    fn test_vp6_skip_modes() {
+        let mut br = VP6BR::new();
+        let mut dec = VP56Decoder::new(6, false, true);
+        let mut supp = NADecoderSupport::new();
+        dec.init(&mut supp, NAVideoInfo::new(64, 48, true, YUV420_FORMAT)).unwrap();
+        let mut decode_ref = |pkts: &[Vec<u8>]| {
+            let mut hashes = Vec::new();
+            let mut hdrs = Vec::new();
+            dec.flush();
+            for pkt in pkts.iter() {
+                let (buf, _) = dec.decode_frame(&mut supp, pkt, &mut br).unwrap();
+                hashes.push(frame_plane_md5(&buf));
+                hdrs.push(dec.get_last_header().unwrap());
+            }
+            (hashes, hdrs)
+        };
+        // the stream has a keyframe in the middle
+        let name = format!("{}/assets/vp6_advanced.vp6", env!("CARGO_MANIFEST_DIR"));
+        let kf_pkts = read_raw_packets(&name);
+        let (kf_hashes, kf_hdrs) = decode_ref(&kf_pkts);
+        // and this one has golden inter frames
+        let name = format!("{}/assets/vp6_golden.vp6", env!("CARGO_MANIFEST_DIR"));
+        let pkts = read_raw_packets(&name);
+        let (ref_hashes, hdrs) = decode_ref(&pkts);
+
+        for mode in [FrameSkipMode::NonReference, FrameSkipMode::DecodeOnly, FrameSkipMode::KeyframesOnly] {
+            dec.flush();
+            dec.set_skip_mode(mode);
+            for (i, pkt) in kf_pkts.iter().enumerate() {
+                let next_is_key = kf_hdrs.get(i + 1).is_some_and(|hdr| hdr.is_intra);
+                dec.set_next_is_key(next_is_key);
+                let (buf, ftype) = dec.decode_frame(&mut supp, pkt, &mut br).unwrap();
+                let skipped = match mode {
+                        FrameSkipMode::NonReference => !kf_hdrs[i].is_intra && !kf_hdrs[i].is_golden && next_is_key,
+                        FrameSkipMode::DecodeOnly => true,
+                        _ => !kf_hdrs[i].is_intra,
+                    };
+                if skipped {
+                    assert_eq!(ftype, FrameType::Skip);
+                    assert!(matches!(buf, NABufferType::None));
+                } else {
+                    assert_eq!(frame_plane_md5(&buf), kf_hashes[i], "frame {} in mode {:?}", i, mode);
+                }
+            }
+        }
+        assert!(kf_hdrs.iter().enumerate().any(|(i, hdr)| !hdr.is_intra && kf_hdrs.get(i + 1).is_some_and(|hdr| hdr.is_intra)));
+        // with alpha both parts have to be non-reference, a hint for a packet with golden alpha is ignored
+        let name = format!("{}/assets/vp6a.vp6", env!("CARGO_MANIFEST_DIR"));
+        let apkts = read_raw_packets(&name);
+        let mut abr = VP6BR::new();
+        let mut adec = VP56Decoder::new(6, true, true);
+        let mut asupp = NADecoderSupport::new();
+        adec.init(&mut asupp, NAVideoInfo::new(64, 48, true, VP_YUVA420_FORMAT)).unwrap();
+        let mut ahashes = Vec::new();
+        let mut non_ref = Vec::new();
+        for pkt in apkts.iter() {
+            let (buf, _) = adec.decode_frame(&mut asupp, pkt, &mut abr).unwrap();
+            ahashes.push(frame_plane_md5(&buf));
+            let (hdr, ahdr) = (adec.get_last_header().unwrap(), adec.get_last_alpha_header().unwrap());
+            non_ref.push((!hdr.is_intra && !hdr.is_golden, !ahdr.is_intra && !ahdr.is_golden));
+        }
+        let skip_idx = non_ref.iter().position(|&(cnr, anr)| cnr && anr).unwrap();
+        let agold_idx = non_ref.iter().position(|&(cnr, anr)| cnr && !anr).unwrap();
+        assert!(agold_idx + 1 < apkts.len() && skip_idx + 1 < apkts.len());
+        adec.flush();
+        adec.set_skip_mode(FrameSkipMode::NonReference);
+        for (i, pkt) in apkts[..skip_idx + 1].iter().enumerate() {
+            adec.set_next_is_key(i == skip_idx || i == agold_idx);
+            let (buf, ftype) = adec.decode_frame(&mut asupp, pkt, &mut abr).unwrap();
+            if i == skip_idx {
+                assert_eq!(ftype, FrameType::Skip);
+            } else {
+                assert_eq!(frame_plane_md5(&buf), ahashes[i], "frame {}", i);
+            }
+        }
+        assert_eq!(adec.decode_frame(&mut asupp, &apkts[skip_idx + 1], &mut abr).err(), Some(DecoderError::MissingReference));
+        adec.flush();
+        for (i, pkt) in apkts[..agold_idx + 2].iter().enumerate() {
+            adec.set_next_is_key(i == agold_idx);
+            let (buf, _) = adec.decode_frame(&mut asupp, pkt, &mut abr).unwrap();
+            assert_eq!(frame_plane_md5(&buf), ahashes[i], "frame {}", i);
+        }
+
+        // golden frames are never skipped, a wrong hint for a non-golden frame drops only the last frame reference
+        let gold_idx = hdrs.iter().position(|hdr| !hdr.is_intra && hdr.is_golden).unwrap();
+        let skip_idx = hdrs.iter().skip(gold_idx + 1).position(|hdr| !hdr.is_golden).unwrap() + gold_idx + 1;
+        assert!(skip_idx + 1 < pkts.len());
+        dec.flush();
+        dec.set_skip_mode(FrameSkipMode::NonReference);
+        for (i, pkt) in pkts[..skip_idx].iter().enumerate() {
+            dec.set_next_is_key(true);
+            let (buf, ftype) = dec.decode_frame(&mut supp, pkt, &mut br).unwrap();
+            if hdrs[i].is_intra || hdrs[i].is_golden {
+                assert_eq!(frame_plane_md5(&buf), ref_hashes[i]);
+            } else {
+                assert_eq!(ftype, FrameType::Skip);
+            }
+        }
+        dec.set_next_is_key(true);
+        let (_, ftype) = dec.decode_frame(&mut supp, &pkts[skip_idx], &mut br).unwrap();
+        assert_eq!(ftype, FrameType::Skip);
+        assert_eq!(dec.decode_frame(&mut supp, &pkts[skip_idx + 1], &mut br).err(), Some(DecoderError::MissingReference));
+
+        // only decoding keeps references valid
+        dec.flush();
+        dec.set_skip_mode(FrameSkipMode::DecodeOnly);
+        for pkt in pkts[..pkts.len() - 1].iter() {
+            dec.decode_frame(&mut supp, pkt, &mut br).unwrap();
+        }
+        dec.set_skip_mode(FrameSkipMode::None);
+        let (buf, _) = dec.decode_frame(&mut supp, &pkts[pkts.len() - 1], &mut br).unwrap();
+        assert_eq!(frame_plane_md5(&buf), ref_hashes[pkts.len() - 1]);
+
+        dec.set_skip_mode(FrameSkipMode::KeyframesOnly);
+        dec.decode_frame(&mut supp, &pkts[0], &mut br).unwrap();
+        dec.decode_frame(&mut supp, &pkts[1], &mut br).unwrap();
+        dec.set_skip_mode(FrameSkipMode::None);
+        assert_eq!(dec.decode_frame(&mut supp, &pkts[2], &mut br).err(), Some(DecoderError::MissingReference));
+    }
+    #[test]
+    fn test_vp6_decode_into() {
+        let name = format!("{}/assets/vp6a.vp6", env!("CARGO_MANIFEST_DIR"));
+        let pkts = read_raw_packets(&name);
+        let mut ref_br = VP6BR::new();
+        let mut ref_dec = VP56Decoder::new(6, true, true);
+        let mut ref_supp = NADecoderSupport::new();
+        ref_dec.init(&mut ref_supp, NAVideoInfo::new(64, 48, true, VP_YUVA420_FORMAT)).unwrap();
+        let mut ref_frames = Vec::new();
+        for pkt in pkts.iter() {
+            let (ref_buf, ref_ftype) = ref_dec.decode_frame(&mut ref_supp, pkt, &mut ref_br).unwrap();
+            // copied so the frame pool does not run out of buffers
+            ref_frames.push((ref_buf.get_vbuf().unwrap().copy_buffer(), ref_ftype));
+        }
+        let check_frame = |data: &[u8], strides: &[usize], offsets: &[usize], flip: bool, ref_buf: &NAVideoBuffer<u8>| {
+            let ref_data = ref_buf.get_data();
+            for comp in 0..4 {
+                let (w, h) = ref_buf.get_dimensions(comp);
+                for y in 0..h {
+                    let sy = if flip { y } else { h - 1 - y };
+                    let sline = &ref_data[ref_buf.get_offset(comp) + sy * ref_buf.get_stride(comp)..][..w];
+                    assert_eq!(&data[offsets[comp] + y * strides[comp]..][..w], sline);
+                }
+            }
+        };
+
+        // padded lines and planes in the reverse order, in the decoder orientation (reconstructed in place)
+        // and top-down (decoded into a pool buffer and copied)
+        let strides = [80, 40, 40, 72];
+        let offsets = [72 * 48 + 40 * 24 * 2, 72 * 48 + 40 * 24, 72 * 48, 0];
+        let mut data = vec![0u8; 80 * 48 + 72 * 48 + 40 * 24 * 2];
+        for &flip in [true, false].iter() {
+            let mut br = VP6BR::new();
+            let mut dec = VP56Decoder::new(6, true, true);
+            let mut supp = NADecoderSupport::new();
+            dec.init(&mut supp, NAVideoInfo::new(64, 48, true, VP_YUVA420_FORMAT)).unwrap();
+            let vinfo = NAVideoInfo::new(64, 48, flip, VP_YUVA420_FORMAT);
+            for (pkt, (ref_buf, ref_ftype)) in pkts.iter().zip(ref_frames.iter()) {
+                let mut frm = NASimpleVideoFrame::from_slice(&mut data, &vinfo, &strides, &offsets).unwrap();
+                let ftype = dec.decode_frame_into(&mut supp, pkt, &mut br, &mut frm).unwrap();
+                assert_eq!(ftype, *ref_ftype);
+                check_frame(&data, &strides, &offsets, flip, ref_buf);
+            }
+        }
+
+        let mut br = VP6BR::new();
+        let mut dec = VP56Decoder::new(6, true, true);
+        let mut supp = NADecoderSupport::new();
+        dec.init(&mut supp, NAVideoInfo::new(64, 48, true, VP_YUVA420_FORMAT)).unwrap();
+        // alpha plane can be omitted
+        let mut frm = NASimpleVideoFrame::from_slice(&mut data, &NAVideoInfo::new(64, 48, false, YUV420_FORMAT), &strides, &offsets).unwrap();
+        dec.decode_frame_into(&mut supp, &pkts[0], &mut br, &mut frm).unwrap();
+        // too small frame is rejected without decoding so the same packet can be decoded again
+        let vinfo_half = NAVideoInfo::new(32, 24, true, VP_YUVA420_FORMAT);
+        let mut frm = NASimpleVideoFrame::from_slice(&mut data, &vinfo_half, &strides, &offsets).unwrap();
+        assert_eq!(dec.decode_frame_into(&mut supp, &pkts[1], &mut br, &mut frm).err(), Some(DecoderError::InvalidData));
+        let vinfo = NAVideoInfo::new(64, 48, true, VP_YUVA420_FORMAT);
+        let mut frm = NASimpleVideoFrame::from_slice(&mut data, &vinfo, &strides, &offsets).unwrap();
+        dec.decode_frame_into(&mut supp, &pkts[1], &mut br, &mut frm).unwrap();
+        check_frame(&data, &strides, &offsets, true, &ref_frames[1].0);
+
+        assert!(NASimpleVideoFrame::from_slice(&mut data[..1000], &vinfo, &strides, &offsets).is_none());
+        assert!(NASimpleVideoFrame::from_slice(&mut data, &vinfo, &[32, 32, 32, 32], &offsets).is_none());
+    }
+    #[test]
+    fn test_vp6_decode_into_nonref() {
+        // with non-reference frame skipping an intra or golden frame followed by a keyframe
+        // is reconstructed in place without keeping a reference copy, otherwise the hint is ignored
+        let name = format!("{}/assets/vp6_golden.vp6", env!("CARGO_MANIFEST_DIR"));
+        let pkts = read_raw_packets(&name);
+        let mut br = VP6BR::new();
+        let mut dec = VP56Decoder::new(6, false, true);
+        let mut supp = NADecoderSupport::new();
+        dec.init(&mut supp, NAVideoInfo::new(64, 48, true, YUV420_FORMAT)).unwrap();
+        let mut ref_hashes = Vec::new();
+        let mut hdrs = Vec::new();
+        for pkt in pkts.iter() {
+            let (buf, _) = dec.decode_frame(&mut supp, pkt, &mut br).unwrap();
+            ref_hashes.push(frame_plane_md5(&buf));
+            hdrs.push(dec.get_last_header().unwrap());
+        }
+        let gold_idx = hdrs.iter().position(|hdr| !hdr.is_intra && hdr.is_golden).unwrap();
+        assert!(!hdrs[1].is_intra && !hdrs[gold_idx + 1].is_intra);
+
+        let vinfo = NAVideoInfo::new(64, 48, true, YUV420_FORMAT);
+        let mut data = vec![0u8; 64 * 48 * 3 / 2];
+        let strides = [64, 32, 32];
+        let offsets = [0, 64 * 48, 64 * 48 + 32 * 24];
+        let mut decode_into = |dec: &mut VP56Decoder, supp: &mut NADecoderSupport, br: &mut VP6BR, pkt: &[u8]| {
+            let mut frm = NASimpleVideoFrame::from_slice(&mut data, &vinfo, &strides, &offsets).unwrap();
+            dec.decode_frame_into(supp, pkt, br, &mut frm)?;
+            let mut vbuf = alloc_video_buffer(vinfo, 4).unwrap().get_vbuf().unwrap();
+            assert!(frm.copy_to_buf(vbuf.as_mut().unwrap()));
+            Ok(frame_plane_md5(&NABufferType::Video(vbuf)))
+        };
+        for mode in [FrameSkipMode::None, FrameSkipMode::NonReference] {
+            let keeps_ref = mode == FrameSkipMode::None;
+            for &idx in [0, gold_idx].iter() {
+                dec.flush();
+                dec.set_skip_mode(mode);
+                for (i, pkt) in pkts[..idx].iter().enumerate() {
+                    assert_eq!(decode_into(&mut dec, &mut supp, &mut br, pkt).unwrap(), ref_hashes[i]);
+                }
+                dec.set_next_is_key(true);
+                assert_eq!(decode_into(&mut dec, &mut supp, &mut br, &pkts[idx]).unwrap(), ref_hashes[idx], "frame {} in mode {:?}", idx, mode);
+                let ret = decode_into(&mut dec, &mut supp, &mut br, &pkts[idx + 1]);
+                if keeps_ref {
+                    assert_eq!(ret.unwrap(), ref_hashes[idx + 1]);
+                } else {
+                    assert_eq!(ret.err(), Some(DecoderError::MissingReference));
+                }
+            }
+        }
+    }
+    #[test]
+    fn test_vp6_threaded_output() {
+        let name = format!("{}/assets/vp6_golden.vp6", env!("CARGO_MANIFEST_DIR"));
+        let pkts = read_raw_packets(&name);
+        let mut br = VP6BR::new();
+        let mut dec = VP56Decoder::new(6, false, true);
+        let mut supp = NADecoderSupport::new();
+        dec.init(&mut supp, NAVideoInfo::new(64, 48, true, YUV420_FORMAT)).unwrap();
+        let ref_hashes: Vec<_> = pkts.iter().map(|pkt| frame_plane_md5(&dec.decode_frame(&mut supp, pkt, &mut br).unwrap().0)).collect();
+
+        // decode on a worker thread while the frames are checked by the receiver
+        let (sender, receiver) = std::sync::mpsc::sync_channel(0);
+        let worker = std::thread::spawn(move || {
+                let mut dec_reg = RegisteredDecoders::new();
+                crate::codecs::duck_register_all_decoders(&mut dec_reg);
+                let mut dec = (dec_reg.find_decoder("vp6").unwrap())();
+                let mut supp = NADecoderSupport::new();
+                let info = NACodecInfo::new("vp6", NACodecTypeInfo::Video(NAVideoInfo::new(64, 48, false, YUV420_FORMAT)), None).into_ref();
+                dec.init(&mut supp, info).unwrap();
+                for pkt in pkts.into_iter() {
+                    let pkt = NAPacket::new(0, NATimeInfo::new(None, None, None, 1, 30), false, pkt);
+                    let frm = dec.decode(&mut supp, &pkt).unwrap();
+                    sender.send(frm).unwrap();
+                }
+            });
+        for hashes in ref_hashes.iter() {
+            let frm = receiver.recv().unwrap();
+            assert_eq!(&frame_plane_md5(&frm.get_buffer()), hashes);
+        }
+        worker.join().unwrap();
+    }
+    #[test]
+    fn test_vp6_pool_growth() {
+        let name = format!("{}/assets/vp6_golden.vp6", env!("CARGO_MANIFEST_DIR"));
+        let pkts = read_raw_packets(&name);
+        let mut br = VP6BR::new();
+        let mut dec = VP56Decoder::new(6, false, true);
+        let mut supp = NADecoderSupport::new();
+        dec.init(&mut supp, NAVideoInfo::new(64, 48, true, YUV420_FORMAT)).unwrap();
+        let nbufs = supp.pool_u8.get_num_frames();
+
+        // holding on to all output frames exhausts the fixed pool
+        let mut held = Vec::new();
+        let mut result = Ok(());
+        for pkt in pkts.iter() {
+            match dec.decode_frame(&mut supp, pkt, &mut br) {
+                Ok((buf, _)) => held.push(buf),
+                Err(err) => { result = Err(err); break; },
+            }
+        }
+        assert_eq!(result, Err(DecoderError::AllocError));
+        assert_eq!(supp.pool_u8.get_stats().failures, 1);
+
+        held.clear();
+        supp.pool_u8.set_growth(NAPoolGrowth::UpTo(pkts.len() + 2));
+        for pkt in pkts.iter() {
+            held.push(dec.decode_frame(&mut supp, pkt, &mut br).unwrap().0);
+        }
+        assert!(supp.pool_u8.get_num_frames() > nbufs);
+        held.clear();
+        dec.flush();
+        supp.pool_u8.trim(nbufs);
+        assert_eq!(supp.pool_u8.get_num_frames(), nbufs);
+    }
+    #[test]
+    fn test_vp6_plane_selection() {
+        let name = format!("{}/assets/vp6a.vp6", env!("CARGO_MANIFEST_DIR"));
+        let pkts = read_raw_packets(&name);
+        let mut br = VP6BR::new();
+        let mut dec = VP56Decoder::new(6, true, true);
+        let mut supp = NADecoderSupport::new();
+        dec.init(&mut supp, NAVideoInfo::new(64, 48, true, VP_YUVA420_FORMAT)).unwrap();
+        let ref_hashes: Vec<_> = pkts.iter().map(|pkt| frame_plane_md5(&dec.decode_frame(&mut supp, pkt, &mut br).unwrap().0)).collect();
+
+        for (planes, range) in [(VP56Planes::Colour, 0..3), (VP56Planes::Alpha, 3..4)] {
+            dec.flush();
+            for (pkt, hashes) in pkts.iter().zip(ref_hashes.iter()) {
+                let (buf, _) = dec.decode_frame_planes(&mut supp, pkt, &mut br, planes).unwrap();
+                assert_eq!(frame_plane_md5(&buf)[range.clone()], hashes[range.clone()]);
+            }
+            // the skipped planes cannot be decoded until the next intra frame
+            dec.flush();
+            dec.decode_frame_planes(&mut supp, &pkts[0], &mut br, planes).unwrap();
+            dec.decode_frame_planes(&mut supp, &pkts[1], &mut br, planes).unwrap();
+            assert_eq!(dec.decode_frame(&mut supp, &pkts[2], &mut br).err(), Some(DecoderError::MissingReference));
+            // but the selected planes are still intact
+            let (buf, _) = dec.decode_frame_planes(&mut supp, &pkts[2], &mut br, planes).unwrap();
+            assert_eq!(frame_plane_md5(&buf)[range.clone()], ref_hashes[2][range.clone()]);
+            dec.decode_frame(&mut supp, &pkts[0], &mut br).unwrap();
+            let (buf, _) = dec.decode_frame(&mut supp, &pkts[1], &mut br).unwrap();
+            assert_eq!(frame_plane_md5(&buf), ref_hashes[1]);
+        }
+    }
+    #[test]
+    fn test_vp6_plane_selection_switch() {
+        let name = format!("{}/assets/vp6a.vp6", env!("CARGO_MANIFEST_DIR"));
+        let pkts = read_raw_packets(&name);
+        let mut br = VP6BR::new();
+        let mut dec = VP56Decoder::new(6, true, true);
+        let mut supp = NADecoderSupport::new();
+        dec.init(&mut supp, NAVideoInfo::new(64, 48, true, VP_YUVA420_FORMAT)).unwrap();
+        assert_eq!(dec.get_ref_planes(), None);
+        let ref_hashes: Vec<_> = pkts.iter().map(|pkt| frame_plane_md5(&dec.decode_frame(&mut supp, pkt, &mut br).unwrap().0)).collect();
+        let skipped = DecoderStage::Codec("skipped planes");
+
+        // switch to colour-only decoding in the middle of a GOP (keyframes are 0 and 5)
+        dec.flush();
+        dec.decode_frame(&mut supp, &pkts[0], &mut br).unwrap();
+        dec.decode_frame(&mut supp, &pkts[1], &mut br).unwrap();
+        assert_eq!(dec.get_ref_planes(), Some(VP56Planes::All));
+        dec.decode_frame_planes(&mut supp, &pkts[2], &mut br, VP56Planes::Colour).unwrap();
+        assert_eq!(dec.get_ref_planes(), Some(VP56Planes::Colour));
+        assert_eq!(dec.decode_frame(&mut supp, &pkts[3], &mut br).err(), Some(DecoderError::MissingReference));
+        let err = dec.get_last_error().unwrap();
+        assert_eq!((err.stage, err.alpha), (skipped, true));
+        // a failed request does not affect the colour chain
+        assert_eq!(dec.get_ref_planes(), Some(VP56Planes::Colour));
+        let hashes = frame_plane_md5(&dec.decode_frame_planes(&mut supp, &pkts[3], &mut br, VP56Planes::Colour).unwrap().0);
+        assert_eq!(hashes[..3], ref_hashes[3][..3]);
+        assert!(dec.decode_frame_planes(&mut supp, &pkts[4], &mut br, VP56Planes::Alpha).is_err());
+        assert_eq!(dec.get_last_error().unwrap().stage, skipped);
+        assert_eq!(dec.get_ref_planes(), Some(VP56Planes::Colour));
+        let hashes = frame_plane_md5(&dec.decode_frame_planes(&mut supp, &pkts[4], &mut br, VP56Planes::Colour).unwrap().0);
+        assert_eq!(hashes[..3], ref_hashes[4][..3]);
+        // the keyframe restores both chains
+        let hashes = frame_plane_md5(&dec.decode_frame(&mut supp, &pkts[5], &mut br).unwrap().0);
+        assert_eq!(hashes, ref_hashes[5]);
+        assert_eq!(dec.get_ref_planes(), Some(VP56Planes::All));
+
+        // and the same for alpha-only decoding
+        dec.decode_frame(&mut supp, &pkts[6], &mut br).unwrap();
+        dec.decode_frame_planes(&mut supp, &pkts[7], &mut br, VP56Planes::Alpha).unwrap();
+        assert_eq!(dec.get_ref_planes(), Some(VP56Planes::Alpha));
+        assert_eq!(dec.decode_frame(&mut supp, &pkts[8], &mut br).err(), Some(DecoderError::MissingReference));
+        let err = dec.get_last_error().unwrap();
+        assert_eq!((err.stage, err.alpha), (skipped, false));
+        let hashes = frame_plane_md5(&dec.decode_frame_planes(&mut supp, &pkts[8], &mut br, VP56Planes::Alpha).unwrap().0);
+        assert_eq!(hashes[3..], ref_hashes[8][3..]);
+
+        // references missing for other reasons are not reported as skipped planes
+        dec.flush();
+        assert_eq!(dec.get_ref_planes(), None);
+        assert_eq!(dec.decode_frame(&mut supp, &pkts[9], &mut br).err(), Some(DecoderError::MissingReference));
+        assert_ne!(dec.get_last_error().unwrap().stage, skipped);
+    }
+    // colour and alpha streams keep their own golden frames so decoding them together should match decoding them separately
+    fn test_vp6a_split(pkts: &[Vec<u8>]) -> Vec<(bool, bool)> {
+        let mut br = VP6BR::new();
+        let mut dec = VP56Decoder::new(6, true, true);
+        let mut supp = NADecoderSupport::new();
+        dec.init(&mut supp, NAVideoInfo::new(64, 48, true, VP_YUVA420_FORMAT)).unwrap();
+        let mut cbr = VP6BR::new();
+        let mut cdec = VP56Decoder::new(6, false, true);
+        let mut csupp = NADecoderSupport::new();
+        cdec.init(&mut csupp, NAVideoInfo::new(64, 48, true, YUV420_FORMAT)).unwrap();
+        let mut abr = VP6BR::new();
+        let mut adec = VP56Decoder::new(6, false, true);
+        let mut asupp = NADecoderSupport::new();
+        adec.init(&mut asupp, NAVideoInfo::new(64, 48, true, YUV420_FORMAT)).unwrap();
+        let mut golden = Vec::new();
+        for pkt in pkts.iter() {
+            let aoffset = ((pkt[0] as usize) << 16) | ((pkt[1] as usize) << 8) | (pkt[2] as usize);
+            let hashes = frame_plane_md5(&dec.decode_frame(&mut supp, pkt, &mut br).unwrap().0);
+            let chashes = frame_plane_md5(&cdec.decode_frame(&mut csupp, &pkt[3..][..aoffset], &mut cbr).unwrap().0);
+            let ahashes = frame_plane_md5(&adec.decode_frame(&mut asupp, &pkt[3 + aoffset..], &mut abr).unwrap().0);
+            assert_eq!(hashes[..3], chashes[..3]);
+            assert_eq!(hashes[3], ahashes[0]);
+            golden.push((dec.get_last_header().unwrap().is_golden, dec.get_last_alpha_header().unwrap().is_golden));
+        }
+        golden
+    }
+    #[test]
+    fn test_vp6a_golden() {
+        let name = format!("{}/assets/vp6a.vp6", env!("CARGO_MANIFEST_DIR"));
+        let golden = test_vp6a_split(&read_raw_packets(&name));
+        for combo in [(false, false), (false, true), (true, false), (true, true)] {
+            assert!(golden.contains(&combo), "no frames with golden flags {:?}", combo);
+        }
+
+        // keyframes in one stream only
+        use crate::codecs::vp6synth::*;
+        let cfg = TEST_STREAMS.iter().find(|(name, _)| *name == "vp6a.vp6").unwrap().1;
+        for (key_int, alpha_key_int) in [(3, 4), (4, 3)] {
+            let mut synth = VP6Synth::new(SynthConfig { key_int, alpha_key_int, nframes: 12, ..cfg });
+            let pkts: Vec<_> = (0..12).map(|_| synth.gen_frame()).collect();
+            test_vp6a_split(&pkts);
+        }
+    }
+    #[test]
+    fn test_vp6_snapshot() {
+        for &(name, has_alpha) in [("vp6_golden.vp6", false), ("vp6_interlaced.vp6", false), ("vp6a.vp6", true)].iter() {
+            let name = format!("{}/assets/{}", env!("CARGO_MANIFEST_DIR"), name);
+            let pkts = read_raw_packets(&name);
+            let fmt = if !has_alpha { YUV420_FORMAT } else { VP_YUVA420_FORMAT };
+            let mut br = VP6BR::new();
+            let mut dec = VP56Decoder::new(6, has_alpha, true);
+            let mut supp = NADecoderSupport::new();
+            dec.init(&mut supp, NAVideoInfo::new(64, 48, true, fmt)).unwrap();
+
+            // snapshots are kept for every frame and should not exhaust the frame pool
+            let mut ref_hashes = Vec::with_capacity(pkts.len());
+            let mut snapshots = Vec::with_capacity(pkts.len());
+            for pkt in pkts.iter() {
+                snapshots.push((dec.clone(), br.clone()));
+                let (buf, _) = dec.decode_frame(&mut supp, pkt, &mut br).unwrap();
+                ref_hashes.push(frame_plane_md5(&buf));
+            }
+
+            // restoring replaces the current decoder state, releasing its reference frames
+            for (start, (sdec, sbr)) in snapshots.iter().enumerate().rev() {
+                dec = sdec.clone();
+                br = sbr.clone();
+                for (pkt, hashes) in pkts[start..].iter().zip(ref_hashes[start..].iter()) {
+                    let (buf, _) = dec.decode_frame(&mut supp, pkt, &mut br).unwrap();
+                    assert_eq!(&frame_plane_md5(&buf), hashes);
+                }
+            }
+        }
+    }
+    #[test]
+    fn test_vp6_lowres_idct() {
+        let mut full = alloc_video_buffer(NAVideoInfo::new(16, 16, false, YUV420_FORMAT), 4).unwrap().get_vbuf().unwrap();
+        let mut low = alloc_video_buffer(NAVideoInfo::new(16, 16, false, YUV420_FORMAT), 4).unwrap().get_vbuf().unwrap();
+        let mut seed = 42u32;
+        for shift in 1..4 {
+            let idct = VPLowResIDCT::new(shift);
+            let bs = idct.get_block_size();
+            for _ in 0..16 {
+                // only the coefficients kept by the reduced transform, small enough to avoid clipping
+                let mut coeffs = [0i16; 64];
+                for y in 0..bs {
+                    for x in 0..bs {
+                        seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                        coeffs[x + y * 8] = ((seed >> 16) % 401) as i16 - 200;
+                    }
+                }
+                let mut ccopy = coeffs;
+                vp_put_block(&mut ccopy, 0, 0, 0, &mut NASimpleVideoFrame::from_video_buf(full.as_mut().unwrap()).unwrap());
+                idct.put_block(&coeffs, 0, 0, 0, false, &mut NASimpleVideoFrame::from_video_buf(low.as_mut().unwrap()).unwrap());
+                let fstride = full.get_stride(0);
+                let lstride = low.get_stride(0);
+                let fdata = full.get_data();
+                let ldata = low.get_data();
+                let size = 8 / bs;
+                for y in 0..bs {
+                    for x in 0..bs {
+                        let mut sum = 0;
+                        for line in fdata[y * size * fstride + x * size..].chunks(fstride).take(size) {
+                            sum += line[..size].iter().map(|&p| i32::from(p)).sum::<i32>();
+                        }
+                        let avg = (sum + (size * size / 2) as i32) / ((size * size) as i32);
+                        let diff = (avg - i32::from(ldata[x + y * lstride])).abs();
+                        assert!(diff <= 1, "scale {} pixel {},{}: {} vs {}", shift, x, y, avg, ldata[x + y * lstride]);
+                    }
+                }
+            }
+        }
+    }
+    #[test]
+    fn test_vp6_lowres() {
+        for &(name, has_alpha) in [("vp6_advanced.vp6", false), ("vp6_interlaced.vp6", false), ("vp6_huffman.vp6", false), ("vp6a.vp6", true)].iter() {
+            let name = format!("{}/assets/{}", env!("CARGO_MANIFEST_DIR"), name);
+            let pkts = read_raw_packets(&name);
+            let fmt = if !has_alpha { YUV420_FORMAT } else { VP_YUVA420_FORMAT };
+            let mut br = VP6BR::new();
+            let mut dec = VP56Decoder::new(6, has_alpha, true);
+            let mut supp = NADecoderSupport::new();
+            dec.init(&mut supp, NAVideoInfo::new(64, 48, true, fmt)).unwrap();
+            for (scale, shift) in [(VP56Scale::Half, 1), (VP56Scale::Quarter, 2), (VP56Scale::Eighth, 3), (VP56Scale::Full, 0)] {
+                dec.set_scale(scale);
+                assert_eq!(dec.get_scale(), scale);
+                for pkt in pkts.iter() {
+                    let (buf, _) = dec.decode_frame(&mut supp, pkt, &mut br).unwrap();
+                    let vinfo = buf.get_video_info().unwrap();
+                    assert_eq!((vinfo.get_width(), vinfo.get_height()), (64 >> shift, 48 >> shift));
+                }
+            }
+            // switching scale requires a keyframe
+            dec.set_scale(VP56Scale::Half);
+            assert_eq!(dec.decode_frame(&mut supp, &pkts[1], &mut br).err(), Some(DecoderError::MissingReference));
+        }
+    }
+    #[test]
+    fn test_vp6_simple_regression() {
+        test_vp6_regression("vp6_simple.vp6", false, &[
+            vec![[0x8a9ab0df, 0x5703e2bb, 0x66c9c2e9, 0x4e1069b9], [0x6ad3e63a, 0x0ab6c09d, 0xd11dbc86, 0x802d1494], [0xeff9598f, 0x9f61c1dc, 0x78d842f1, 0x798c2ab8]],
+            vec![[0xe6d65bf5, 0x0af8fcac, 0x838778fb, 0x2d94f8fc], [0x1408f7f9, 0xf06fc9dd, 0x207b4d6a, 0x22083d53], [0x59eb265c, 0xa2fb172f, 0x6c1d69c1, 0x7d537dce]],
+            vec![[0x6a958ea3, 0x53599889, 0xe1b7875f, 0xff932b2c], [0xd105f73b, 0x9501ec44, 0x49ffd6f4, 0x09feee00], [0x94b1dad5, 0x4eb5cf67, 0x384182f3, 0xcb8399e7]],
+            vec![[0xa9ebd6c7, 0xa6c4696b, 0x2ab92ec6, 0xafe4c2df], [0x11192dd3, 0x36bcf221, 0x077a1f55, 0x5baa5226], [0x62d03abf, 0x207766ba, 0x23bc633b, 0x62b9d86f]],
+            vec![[0x2cbe7bfb, 0x3f73195a, 0xd9055dfb, 0x49bf2e41], [0xcc3bee5a, 0x874e4320, 0x67163de0, 0x5f45c835], [0xb149d814, 0xbc5c2ee9, 0x566452ad, 0x9e6ea6be]],
+            vec![[0xf76b4ed5, 0x6b9c201c, 0xba58d682, 0xd1308985], [0x30004925, 0x06f07323, 0xcf58b017, 0xccdac396], [0xdb93f417, 0xe31b5247, 0x30e6aeda, 0x84f2cd67]],
+            vec![[0x40b066bb, 0xbacaea76, 0xc8774423, 0xd2a77396], [0xeaa78c16, 0x81d96829, 0x072d29be, 0xca12b483], [0xc0a12ced, 0xa0a5f172, 0xd854a42d, 0x892c8084]],
+            vec![[0x5d205b1e, 0x6eb3ff9a, 0x4ec739a4, 0x15115766], [0x9342edfd, 0xd624d7d5, 0x5d9b9296, 0x8d07c052], [0x299cfe8d, 0xe9da9771, 0x0b8cd30f, 0x6b44759f]],
+            vec![[0x64e0740e, 0x8e14019a, 0x0b8b2ec7, 0x050b40ac], [0x87955f03, 0xb21ccfad, 0x2e9eaf77, 0x5407ec20], [0x4214f37c, 0x64d0860f, 0x1993b71c, 0x8087c141]],
+            vec![[0x2d8fdff3, 0x748aafc1, 0x48d212e3, 0x5d1948ec], [0x56d3191f, 0x4108787f, 0xc2bc21bc, 0x54207e70], [0xf42db497, 0xabdad65b, 0x1cf2d8d4, 0x91663bf5]],
+        ]);
+    }
+    #[test]
+    fn test_vp6_advanced_regression() {
+        test_vp6_regression("vp6_advanced.vp6", false, &[
+            vec![[0x4b3d0fd7, 0x935a2354, 0x63cd61be, 0xee9065f5], [0xaabbc2a3, 0x3a378662, 0xe56f976b, 0x4e8567aa], [0xa3f2866c, 0x7986fc4d, 0x8a8ddcdb, 0xef752f22]],
+            vec![[0xd7d3fadc, 0xd6b72397, 0x6fb6f2cc, 0xb98b8572], [0xd48cf164, 0xd934cf4f, 0x5abb65d9, 0x450fec41], [0x7b3b75b7, 0x181c3141, 0xe7e9d236, 0xa677065f]],
+            vec![[0xf7d5ce0c, 0xc0aa205f, 0xcdf90211, 0x1f71586d], [0xe5376199, 0x1198ac83, 0xb3233271, 0xc4ed0f30], [0x394deeff, 0x7b522d99, 0xa9a07f82, 0x8ad21ebd]],
+            vec![[0x6ed0835e, 0xa2c31ece, 0x7a920018, 0x3a332ab8], [0x55921cc4, 0x66f98fd4, 0x8f712de8, 0x3967e561], [0x227a571c, 0xa56277e5, 0xf4555b96, 0x27a5434a]],
+            vec![[0x6b9da1b9, 0x1328a032, 0x06ccd7db, 0x55f1fb08], [0x7b28e896, 0x3d4e82ff, 0x0519e59d, 0x983a1f32], [0x0c434cb7, 0xcb19a0c1, 0x46fe8e43, 0x5d7d77ad]],
+            vec![[0x3d2237b3, 0x864e4729, 0xe3317785, 0x1d862bf5], [0x9da1ee1e, 0x36e32b49, 0x87fad398, 0x51f47704], [0xaadac8e8, 0x1157c705, 0x7e0f0049, 0xa31bac5d]],
+            vec![[0xb87c9382, 0x52ccb57f, 0xdc7439eb, 0x5578315e], [0xfd59e2c9, 0x2caf6563, 0x0721dc1a, 0x436b77c6], [0xb442400f, 0xf9478df2, 0x89fc6537, 0x04c30044]],
+            vec![[0x4703ad95, 0x3b72efac, 0x4a0bfe9a, 0xacae105b], [0x7e69f81d, 0x37947c16, 0x00da10ad, 0x6c30fc1e], [0x0aa0323b, 0x28f32b51, 0x0292e6ef, 0x38a16f16]],
+            vec![[0x7cc76e27, 0x3cc90bf2, 0xdbbfa923, 0x27463180], [0x5ed91893, 0x065fa14b, 0xd82ec9a4, 0x8429e7b8], [0xfbd0e2be, 0x14c9fc79, 0x3753c953, 0x62b3f8e3]],
+            vec![[0x5fd48c6e, 0x08592374, 0x7dc05c38, 0x1fe4b4fe], [0xffd25927, 0x8877955b, 0xe6c57dc9, 0x2e6106e6], [0x06d904a0, 0x483eecbe, 0x2814d5d0, 0x1aba3fef]],
+        ]);
+    }
+    #[test]
+    fn test_vp6_vp60_multistream_regression() {
+        test_vp6_regression("vp6_vp60_multistream.vp6", false, &[
+            vec![[0xa29e6ff5, 0x990da123, 0x91d367f6, 0xf48e2013], [0x740940d0, 0x654e6dd0, 0x20c65b35, 0xba1c20d0], [0x594ff802, 0x5e3e9023, 0xfdd68028, 0x2f4285de]],
+            vec![[0xc9f1ad41, 0x0534c7c4, 0x1881e818, 0x1caccfe3], [0xade389f6, 0x948df7f2, 0x845688e1, 0xaff0090c], [0x09270579, 0xc56ebaab, 0x48e9380d, 0x01763ad0]],
+            vec![[0x0fc47b2b, 0x34921c6e, 0xb4b41a5f, 0x05be85b2], [0x1d6f6edd, 0x2edcc88f, 0x499d4f88, 0x076055dd], [0xf3dea8a9, 0x75989dc1, 0x72e31e41, 0xfb7379cf]],
+            vec![[0x1bae9a7b, 0x062a2eac, 0xa4fdeabf, 0xf5b786a6], [0xb6fff713, 0x098d9975, 0xe2c3b583, 0xde80577a], [0x82169462, 0x4f5b65de, 0x2f5d2f35, 0x1fc13918]],
+            vec![[0xc0f211d9, 0x59fd5fed, 0x212ec2f9, 0x0af781cd], [0x14d5d790, 0xb833a867, 0xfb48807d, 0x194e3745], [0xf8dfb6fd, 0x1a0ac207, 0x65d3474b, 0xfe3d8ec9]],
+            vec![[0x5aa6ecc4, 0x78ad535e, 0x1a83e481, 0x103f7e6c], [0x36392847, 0xede37780, 0x6eda1038, 0x711a1def], [0x83499d09, 0x9d01f2b8, 0xc5b0c445, 0x89f5cf5a]],
+            vec![[0xb0915894, 0x3b30dab3, 0x589fba08, 0xa83d56ac], [0xdfbb4b47, 0x0ab63971, 0xbefb0df5, 0xb903843f], [0xc8383944, 0x89ef0a28, 0x5d803691, 0x7c1a4c04]],
+            vec![[0x657ea7c8, 0xd0d4ffe4, 0xd3f4abfc, 0xc53772db], [0xa5f7af89, 0x2408d659, 0xe3b374d3, 0x55edf6bc], [0x4959b865, 0x9238e323, 0x965d0e57, 0xb77759a6]],
+            vec![[0x351edfbc, 0x94cd8d7d, 0x2d925b04, 0xe8e35d41], [0x869d8b00, 0x7470c850, 0x74e9aa4c, 0xf8be8a89], [0xf732f3bf, 0xc7e2491a, 0x4747c319, 0x69deba00]],
+            vec![[0x645a0b85, 0xa5a172ab, 0xcbb1eb51, 0x16619ca0], [0x67628616, 0x6290a4c1, 0x72e30e5a, 0x2419fefb], [0x1313a92f, 0x957162e3, 0xd4c4be41, 0x0c783c45]],
+        ]);
+    }
+    #[test]
+    fn test_vp6_huffman_regression() {
+        test_vp6_regression("vp6_huffman.vp6", false, &[
+            vec![[0xcddc3c70, 0x9c602fe6, 0x38c0ea27, 0xd78de34f], [0xeff9598f, 0x9f61c1dc, 0x78d842f1, 0x798c2ab8], [0xc13d162e, 0x8ab7a930, 0xb64d48c1, 0xa490923a]],
+            vec![[0x52f1903a, 0x6441f77e, 0x539c2dd0, 0x8284529e], [0x7325d40d, 0x5db48679, 0x21cd9bc4, 0xd54bb034], [0x84a47e89, 0x8aae2118, 0x5f519dd1, 0xa0243535]],
+            vec![[0x164349ef, 0x94f5526b, 0x008a1df1, 0x858ad18e], [0xaef9e303, 0x44b1a4d1, 0x6888f059, 0x047281df], [0x8db783e1, 0x316c74a5, 0xfb9d9b60, 0x3538fca6]],
+            vec![[0x9f5aed38, 0x9cad0414, 0x389f9df9, 0x79e28d77], [0xddfaab53, 0xe081009d, 0xcd80d285, 0x96efe570], [0xc2fcd03c, 0xec1331fe, 0x0660dc3f, 0x6b63737c]],
+            vec![[0x9d17fdb3, 0x20100ea9, 0x78584929, 0xa1cdcfcd], [0x9f24d49a, 0x1a65f4b1, 0x0fa9ef01, 0xc9b7d873], [0x9b3879dd, 0xbdf9ee06, 0x9c1f3fca, 0xbd86946d]],
+            vec![[0x2b48497a, 0x9b71ca44, 0xe30aeec7, 0x911f5d3a], [0xb82a295b, 0xb7a85568, 0x7662d93f, 0x305039f5], [0x52f96668, 0x68a66dba, 0xcd6fbf3a, 0x1115af17]],
+            vec![[0xc65a7069, 0x1b537587, 0x8f33b572, 0xc55b27ae], [0x0869117c, 0xee5c3ccf, 0xfc238eb9, 0x7bc38721], [0x2cb538be, 0xed921e49, 0xf282bf57, 0x04e08c2d]],
+            vec![[0xb90c1b33, 0x4a8b9496, 0x611d438c, 0x9c250b75], [0xa13f4c55, 0xad23d391, 0x91488e00, 0x94b02981], [0x780d6211, 0x7a3b6d6b, 0x60736bc5, 0x14dae21d]],
+            vec![[0x3e46e46b, 0xd06993fd, 0x40bca26c, 0x65f1e8a6], [0x1ed5098b, 0x1234b022, 0xcf935a1c, 0xa5a07b48], [0xb47182cb, 0x1e8fca39, 0x7db6bc79, 0xe490b713]],
+            vec![[0xec8d619c, 0xa740194e, 0x28ec37b4, 0x8f107836], [0xa75f498c, 0x03f65d26, 0x7d7ff39c, 0xfe45432c], [0x6f375f45, 0xd6105bce, 0x1d2f9991, 0xd0dc1cb5]],
+        ]);
+    }
+    // reference implementation of the Huffman code lookup by trying all codes in turn
+    fn read_huff_linear(br: &mut BitReader, huff: &VP6Huff) -> DecoderResult<u8> {
+        let peekval = br.peek(16);
+        for (i, (code, bit)) in huff.codes.iter().zip(huff.bits.iter()).enumerate() {
+            if (peekval >> (16 - *bit)) == u32::from(*code) {
+                br.skip(u32::from(*bit))?;
+                return Ok(i as u8);
+            }
+        }
+        Err(DecoderError::InvalidData)
+    }
+    // code tables from random probabilities (skewed ones produce long codes) and a stream of symbols coded with them
+    fn gen_huff_stream(seed: u32, nsyms: usize) -> (Vec<VP6Huff>, Vec<(usize, u8)>, Vec<u8>) {
+        use nihav_core::io::bitwriter::*;
+        use super::super::testsynth::RNG;
+
+        let mut rng = RNG::new(seed);
+        let mut tables = Vec::new();
+        for i in 0..16 {
+            let mut huff = VP6Huff::default();
+            let mut probs = [0; 14];
+            for el in probs.iter_mut() {
+                *el = if rng.chance(1, 3) { 1 + rng.range(8) as u8 } else { 1 + rng.range(255) as u8 };
+            }
+            if (i & 1) == 0 {
+                let mut tprobs = [0; 11];
+                tprobs.copy_from_slice(&probs[..11]);
+                huff.build_codes(&tprobs);
+            } else {
+                huff.build_codes_zero_run(&probs);
+            }
+            tables.push(huff);
+        }
+        let mut syms = Vec::with_capacity(nsyms);
+        let mut bw = BitWriter::new(Vec::new(), BitWriterMode::BE);
+        for _ in 0..nsyms {
+            let tab = rng.range(tables.len() as u32) as usize;
+            let nelems = if (tab & 1) == 0 { 12 } else { 9 };
+            let sym = rng.range(nelems) as u8;
+            bw.write_huff(&tables[tab], sym);
+            syms.push((tab, sym));
+        }
+        (tables, syms, bw.end())
+    }
+    #[test]
+    fn test_vp6_huff_lut() {
+        let (tables, syms, data) = gen_huff_stream(42, 20000);
+        assert!(tables.iter().any(|huff| huff.bits.iter().any(|&len| len > 8)), "no long codes to test");
+        let mut br = BitReader::new(&data, BitReaderMode::BE);
+        let mut br_ref = BitReader::new(&data, BitReaderMode::BE);
+        for &(tab, sym) in syms.iter() {
+            let ref_sym = read_huff_linear(&mut br_ref, &tables[tab]).unwrap();
+            assert_eq!(br.read_huff(&tables[tab]).unwrap(), ref_sym);
+            assert_eq!(ref_sym, sym);
+            assert_eq!(br.tell(), br_ref.tell());
+        }
+    }
+    // run with `cargo test --release -- --ignored --nocapture bench_vp6_huff`
+    #[test]
+    #[ignore]
+    fn bench_vp6_huff() {
+        use std::time::Instant;
+
+        const ROUNDS: usize = 50;
+        let (tables, syms, data) = gen_huff_stream(1, 200000);
+        let mut times = [0.0; 2];
+        let mut sums = [0usize; 2];
+        for (mode, (time, sum)) in times.iter_mut().zip(sums.iter_mut()).enumerate() {
+            let start = Instant::now();
+            for _ in 0..ROUNDS {
+                let mut br = BitReader::new(&data, BitReaderMode::BE);
+                for &(tab, _) in syms.iter() {
+                    let sym = if mode == 0 { read_huff_linear(&mut br, &tables[tab]) } else { br.read_huff(&tables[tab]) };
+                    *sum += usize::from(sym.unwrap());
+                }
+            }
+            *time = start.elapsed().as_secs_f64();
+        }
+        assert_eq!(sums[0], sums[1]);
+        let nsyms = (syms.len() * ROUNDS) as f64;
+        println!("linear search: {:.2} ns/symbol", times[0] * 1e9 / nsyms);
+        println!("lookup table:  {:.2} ns/symbol", times[1] * 1e9 / nsyms);
+
+        let name = format!("{}/assets/vp6_huffman.vp6", env!("CARGO_MANIFEST_DIR"));
+        let pkts = read_raw_packets(&name);
+        let start = Instant::now();
+        for _ in 0..ROUNDS {
+            let mut dec = VP6Decoder::new(true, false);
+            let mut supp = NADecoderSupport::new();
+            let info = NACodecInfo::new("vp6", NACodecTypeInfo::Video(NAVideoInfo::new(64, 48, false, YUV420_FORMAT)), None).into_ref();
+            dec.init(&mut supp, info).unwrap();
+            for src in pkts.iter() {
+                let pkt = NAPacket::new(0, NATimeInfo::new(None, None, None, 1, 30), false, src.to_vec());
+                dec.decode(&mut supp, &pkt).unwrap();
+            }
+        }
+        println!("vp6_huffman.vp6: {:.3} ms per decoding", start.elapsed().as_secs_f64() * 1e3 / (ROUNDS as f64));
+    }
+    #[test]
+    fn test_vp6_interlaced_regression() {
+        test_vp6_regression("vp6_interlaced.vp6", false, &[
+            vec![[0xde60f8b6, 0xba4d45db, 0xac7e95c6, 0x2d14e07f], [0xeff9598f, 0x9f61c1dc, 0x78d842f1, 0x798c2ab8], [0x1104788f, 0x655e499c, 0x88091078, 0xc564c63f]],
+            vec![[0x9b0e3494, 0xece4b18f, 0x5f782f59, 0xb927297a], [0x92634b95, 0xa0af134c, 0x745a388f, 0x68ffe50c], [0x9534cab6, 0x838a3b40, 0x7883755b, 0x912b52fe]],
+            vec![[0xba8949fa, 0x14d460fc, 0x621cb2aa, 0x41d5382a], [0x90ea8fa3, 0x30279567, 0xb5fed719, 0x0e1f00b3], [0xaca8e243, 0x4674a5c0, 0x29b29450, 0x89fba1aa]],
+            vec![[0x77dad585, 0x18ce3b2b, 0x080c1680, 0x5bd218c0], [0x1af60322, 0x96f27043, 0x71521723, 0xa2ba87ee], [0x3c6161dd, 0xac414a03, 0x62c2799f, 0xe9748569]],
+            vec![[0x476569aa, 0x22467880, 0x0ddb87b7, 0x3d0f28a0], [0xac1700f3, 0x6a11b950, 0xeeaf49dd, 0xe410b9d0], [0x41302c7c, 0xcf3f4c28, 0x40726749, 0x9533dcb5]],
+            vec![[0xba8d3d75, 0xcb03a338, 0x5f929dea, 0x27aa5656], [0x5c2284ff, 0x91d860cc, 0xde4f516d, 0xf71f3cc5], [0x164023fd, 0x2322ac9c, 0x6f3e43e4, 0xcf2b0261]],
+            vec![[0x8c59d84f, 0x03ecc955, 0x74d4c721, 0x7cdda798], [0x06e84ee4, 0x9af4a4c0, 0xf56eef43, 0x5bbab3cf], [0xb20f0e7a, 0x1c6a9efc, 0xda58ea07, 0x47297633]],
+            vec![[0x5782f601, 0xe04ba1c5, 0xb77fc1b3, 0x03cdbb5e], [0x4a4e06ee, 0x8a03ef49, 0x8653273c, 0x8ddb591f], [0x4270d1d7, 0xfee63d61, 0xcf205721, 0xe8a54b3d]],
+            vec![[0x760fd152, 0x975cea2d, 0xdab0e81c, 0x86783d22], [0xbfa23581, 0x2a129181, 0x38415d95, 0x731631f7], [0x1d915188, 0xb8c55a45, 0xb47ffd4a, 0x8ff29a3c]],
+            vec![[0x7fc54d22, 0xb326a595, 0x2d4ede12, 0x6371738c], [0x26717dc1, 0xa500459c, 0x37cb463c, 0x7de5de96], [0x5e2a5e8c, 0xab96e3a9, 0xcfb6d66e, 0x39b2b0c3]],
+        ]);
+    }
+    fn decode_planes_with_mode(name: &str, mode: FieldOutputMode) -> Vec<(bool, Vec<Vec<u8>>)> {
+        let name = format!("{}/assets/{}", env!("CARGO_MANIFEST_DIR"), name);
+        let mut dec_reg = RegisteredDecoders::new();
+        crate::codecs::duck_register_all_decoders(&mut dec_reg);
+        let mut dec = (dec_reg.find_decoder("vp6").unwrap())();
+        let mut supp = NADecoderSupport::new();
+        dec.set_field_mode(mode);
+        let info = NACodecInfo::new("vp6", NACodecTypeInfo::Video(NAVideoInfo::new(64, 48, false, YUV420_FORMAT)), None).into_ref();
+        dec.init(&mut supp, info).unwrap();
+        let mut frames = Vec::new();
+        for src in read_raw_packets(&name).iter() {
+            let pkt = NAPacket::new(0, NATimeInfo::new(None, None, None, 1, 30), false, src.clone());
+            let frm = dec.decode(&mut supp, &pkt).unwrap();
+            let vbuf = frm.get_buffer().get_vbuf().unwrap();
+            let mut planes = Vec::new();
+            for comp in 0..vbuf.get_num_components() {
+                let (w, h) = vbuf.get_dimensions(comp);
+                let mut plane = Vec::with_capacity(w * h);
+                for line in vbuf.get_data()[vbuf.get_offset(comp)..].chunks(vbuf.get_stride(comp)).take(h) {
+                    plane.extend_from_slice(&line[..w]);
+                }
+                planes.push(plane);
+            }
+            frames.push((frm.is_interlaced(), planes));
+        }
+        frames
+    }
+    #[test]
+    fn test_vp6_field_modes() {
+        let woven = decode_planes_with_mode("vp6_interlaced.vp6", FieldOutputMode::Woven);
+        let fields = decode_planes_with_mode("vp6_interlaced.vp6", FieldOutputMode::Fields);
+        let bob = decode_planes_with_mode("vp6_interlaced.vp6", FieldOutputMode::Bob);
+        let blend = decode_planes_with_mode("vp6_interlaced.vp6", FieldOutputMode::Blend);
+        // references stay woven so every output frame is derived from the same decoded picture
+        let (w, h) = (64, 48);
+        for (i, (ilace, planes)) in woven.iter().enumerate() {
+            assert!(*ilace);
+            let src = &planes[0];
+            let line = |y: usize| &src[y * w..][..w];
+            for y in 0..h {
+                assert_eq!(&fields[i].1[0][y * w..][..w], line(if y < h / 2 { y * 2 } else { (y - h / 2) * 2 + 1 }));
+                let bline = &bob[i].1[0][y * w..][..w];
+                if (y & 1) == 0 || y == h - 1 {
+                    assert_eq!(bline, line(y & !1));
+                } else {
+                    for x in 0..w {
+                        assert_eq!(u16::from(bline[x]), (u16::from(line(y - 1)[x]) + u16::from(line(y + 1)[x]) + 1) >> 1);
+                    }
+                }
+                let bline = &blend[i].1[0][y * w..][..w];
+                let (top, bot) = (line(y.max(1) - 1), line((y + 1).min(h - 1)));
+                for x in 0..w {
+                    let sum = u16::from(top[x]) + 2 * u16::from(line(y)[x]) + u16::from(bot[x]);
+                    assert_eq!(u16::from(bline[x]), (sum + 2) >> 2);
+                }
+            }
+        }
+
+        // progressive frames are output as is
+        let woven = decode_planes_with_mode("vp6_simple.vp6", FieldOutputMode::Woven);
+        let blend = decode_planes_with_mode("vp6_simple.vp6", FieldOutputMode::Blend);
+        assert!(woven.iter().all(|(ilace, _)| !ilace));
+        assert!(woven == blend);
+    }
+    #[test]
+    fn test_vp6_fourmv_regression() {
+        test_vp6_regression("vp6_fourmv.vp6", false, &[
+            vec![[0x897ed0bd, 0x3545fd6a, 0x20120be2, 0x46932aa2], [0x39a9e3ea, 0xf4d7bf98, 0x34347b33, 0x38f27016], [0x701fe718, 0x9ba4af57, 0xb0cbac54, 0x16c0bbab]],
+            vec![[0x9f8761fe, 0x9eaa93a8, 0x43a05cab, 0x3e658dbc], [0xc51a7865, 0xb6fa7790, 0x9787b5d4, 0xd289d2e4], [0x2f3fb6ba, 0xadc22c3e, 0x72994bca, 0x9806f141]],
+            vec![[0xd47dad4d, 0xefa993ec, 0x9ed1c6fb, 0xbce52477], [0xed6a094c, 0x106a8c49, 0x7a51d376, 0xedd9ad5d], [0x20c022c3, 0x6c75d0c3, 0xa765f8af, 0x3affe85d]],
+            vec![[0xf634b9f2, 0xb001367c, 0x84425272, 0x47d3c338], [0xdcb9a4bf, 0x60a96387, 0x78cb4423, 0x0b69eb24], [0xb5f6669d, 0x1ee73804, 0xc4b76a36, 0x75309457]],
+            vec![[0x2361556f, 0x8076809e, 0xd188a187, 0xc6500f7d], [0x67970461, 0x5d006698, 0x7ad55d6b, 0xe4774a75], [0xbf3c16bc, 0xb1ba16e8, 0xbfa15ed3, 0xe8a4375b]],
+            vec![[0xe1737021, 0xa700c3c6, 0x1a2a0b4f, 0x8268d569], [0xd0c86325, 0xcfd09687, 0x8a26a8e6, 0x02eb33e0], [0xeb1c9580, 0xbdb2b661, 0x234af7b9, 0xce9f99f2]],
+            vec![[0xf837b5b3, 0xc9bc59b0, 0x7ec687aa, 0xd7d68c36], [0x28c9171b, 0x6a3e0695, 0xefb9ee0f, 0x64ea3e9a], [0xa2452e0f, 0x563bb0d0, 0x604a7949, 0x022a1eab]],
+            vec![[0xccb50920, 0x8fb29ca5, 0x7f537bb1, 0x468168eb], [0xcebdab9a, 0xb375fae7, 0x31c69c29, 0x3f6be77f], [0xd3860b0e, 0x402be9d6, 0x14dd650e, 0x6e1114e9]],
+            vec![[0x2460fe3b, 0xb36dbbca, 0x80f56b1f, 0x84bdcb5f], [0x86c93db8, 0x081739f4, 0x3ae3455f, 0x087cb19e], [0xd06d8335, 0x6f7c9ed1, 0x93675b14, 0xc7cd252d]],
+            vec![[0x47d077f1, 0x9c463f4b, 0xc5699dfc, 0xc528f1bc], [0xf37f97ae, 0xf8ae76e3, 0x174840f7, 0x0d68462c], [0xbb2fd60e, 0x67ad3b09, 0xefaee8b7, 0xf8b15980]],
+        ]);
+    }
+    #[test]
+    fn test_vp6_golden_regression() {
+        test_vp6_regression("vp6_golden.vp6", false, &[
+            vec![[0x5ddc04a8, 0xadd9073b, 0x0e19fa2d, 0x0a7ce8b6], [0x04b9f484, 0x74423240, 0x23d740aa, 0xef94b026], [0x0573bcbf, 0x25132808, 0xcf8d3e5e, 0xcba9b929]],
+            vec![[0xe2d47a08, 0x9e0ffc30, 0x06e2556e, 0xe5f75425], [0xd3bf5b6f, 0x27ddd39d, 0x223b5df1, 0xa3149262], [0x2ff99b33, 0x67c8a3b8, 0xc1c08a0c, 0xbe94d7f1]],
+            vec![[0x163ea1f3, 0xbbceb5e2, 0x521e9bec, 0x260ea933], [0xc881acfb, 0x1dcbb4ef, 0xc3181460, 0x1752ca42], [0x0ca373c4, 0x6376fca8, 0xf7105fd3, 0xbfcbac0f]],
+            vec![[0xd0f5961e, 0x9fc82bc3, 0x27f6a0cb, 0x1eacf8e5], [0x45b62349, 0xdcd87247, 0xbc620036, 0x38b2108b], [0xb9dab881, 0x49d28570, 0xacb7cf12, 0x08c44c6e]],
+            vec![[0xdf5df392, 0xe6cc6dff, 0xd1a412e6, 0x07a6fbf1], [0x28c57e35, 0x372b86cd, 0xa3db679f, 0x2199f74e], [0xb28064fb, 0x48b2f900, 0x33dd2fdb, 0x26458a02]],
+            vec![[0x3d1faed1, 0x15b6c525, 0x9987be49, 0xdad41994], [0xb468afb8, 0x42c914d7, 0xf170dd50, 0xce582022], [0xb8258417, 0xa2eb6612, 0xa2cb51f5, 0xb34a5106]],
+            vec![[0x5d31913a, 0x7baa8dd9, 0xaafce39b, 0x80dc0eee], [0x9497df5f, 0x3c7ef32a, 0x4e6e16c9, 0xd94ccb19], [0xc38db94c, 0x860aee70, 0x8dc1d670, 0x0fca1688]],
+            vec![[0x3e055dd1, 0x056353d4, 0x05431c0f, 0x936fd4c5], [0xac47b40b, 0x6cd7ba7f, 0xc297ac63, 0x15d6efd3], [0x927e4cbc, 0x7bfda142, 0xb74c60a1, 0xb1313416]],
+            vec![[0x7ca911b1, 0x357a2345, 0x4bc8be87, 0x725208ba], [0x42116db1, 0x3c220513, 0x88acd141, 0x23f66644], [0xeb9ff76e, 0xe11f31f2, 0xa8559f97, 0xe5fd9992]],
+            vec![[0xaebfb3d2, 0x794ff47e, 0xa394d430, 0x4c952c68], [0x8b7afb8b, 0x4f5b9c40, 0xa8001cca, 0x9799582f], [0x65a8d07a, 0xa2d19748, 0x8168d6e7, 0x26d370a9]],
+        ]);
+    }
+    #[test]
+    fn test_vp6a_regression() {
+        test_vp6_regression("vp6a.vp6", true, &[
+            vec![[0xefd50d73, 0x98370801, 0x5aeb570f, 0xfb716208], [0xeff9598f, 0x9f61c1dc, 0x78d842f1, 0x798c2ab8], [0x0f3b71ba, 0x34f55605, 0x2788c14e, 0x72a1a486], [0xa4d90a6e, 0x82933de9, 0xe3cb3c66, 0xd0e80f91]],
+            vec![[0xdae961a2, 0x28f30fa3, 0x0a88e3d4, 0x477465f9], [0x8e87becf, 0x20f9d995, 0x34703f0d, 0x66d02e2e], [0xd1399f44, 0x0c045868, 0xac8d2ae0, 0x118a449d], [0xf5d7e9dd, 0x616512cd, 0x5ea892a4, 0x126b3edf]],
+            vec![[0xf779883a, 0xe7295017, 0xb8405fb0, 0x8b4ab152], [0xd91c9f43, 0xc76fa1f1, 0x7959b8a1, 0xefb7b906], [0x62d4d0cb, 0x131e18bc, 0x006c77af, 0x6038867e], [0x8f49f8a0, 0x0ad18785, 0x6925b2e7, 0x7e69e3f6]],
+            vec![[0x25d95e8e, 0xabc50113, 0x8b96de1f, 0xf9f5120e], [0xa53504f8, 0xda92667e, 0xad063fa6, 0x5b67f6c0], [0xd447bf8b, 0x19598ad2, 0xc49a7838, 0x4e03c06c], [0xf4ea9ff9, 0x01c27cde, 0x0a10020e, 0xf1df143c]],
+            vec![[0x404dfa3b, 0x200a78dc, 0x409dd727, 0x1e687b0e], [0x8fff1a6a, 0xeb386d99, 0x2e066de6, 0xab0ccfc8], [0xcc1b99b1, 0x6f203f7b, 0xcd0c24f7, 0x26ceed33], [0x6ba599f2, 0x47a74b05, 0xed794c1d, 0xc9e50b45]],
+            vec![[0xd36da0c3, 0x32cd84c9, 0x8c4c4784, 0xab02beb9], [0xeff9598f, 0x9f61c1dc, 0x78d842f1, 0x798c2ab8], [0xeff9598f, 0x9f61c1dc, 0x78d842f1, 0x798c2ab8], [0x9be90cb3, 0x7c4252bb, 0xf15662d3, 0xd9ec7789]],
+            vec![[0xad332943, 0xa078a36d, 0xd58809bc, 0x595b973f], [0x22a352c4, 0x0d34f70c, 0xe6ebafe3, 0x1e199403], [0x40b1b209, 0x05f2e017, 0x0d6dd4e5, 0x211aac7d], [0xe0a9772a, 0x1e69381d, 0xf4c06af3, 0x930bfba9]],
+            vec![[0x8f358b8c, 0x40d46a2c, 0xba192a8b, 0x81567afe], [0xf60e3d9a, 0xec112e0a, 0xa00c396f, 0x8f526fbf], [0xed018a0d, 0x3b267012, 0x45006c0a, 0xf162c918], [0xc37bfa89, 0x4b440469, 0x78fe5d95, 0xe598a830]],
+            vec![[0x90af8de8, 0x89a26abd, 0x2a7099db, 0xe6404eb7], [0xa8de8627, 0xd7d4ef91, 0xb328a0e2, 0x1d469592], [0xd2fc3866, 0x02d12e51, 0x7488b69a, 0x8feac854], [0x5e1c0ea6, 0x2cf67d83, 0x1d6ae40f, 0x4da500bb]],
+            vec![[0xced454af, 0x7ef9269f, 0x349a8160, 0xac2374ed], [0xd4b803ce, 0x18ef9092, 0x055a0fa3, 0xbcf39978], [0x71f5a630, 0xba8fd0b7, 0xdeeafb08, 0x20e85796], [0x5b6692bd, 0x3a105196, 0xe7536072, 0xa62325bc]],
+        ]);
+    }
+}
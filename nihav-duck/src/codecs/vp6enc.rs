@@ -0,0 +1,279 @@
+//! Encoder-side functionality for VP6 bitstream coding.
+//!
+//! Currently it provides writing of the Huffman-coded coefficient partition, the counterpart of the decoder
+//! Huffman mode. Code tables are derived from the same probabilities with [`VP6Huff`] methods.
+//!
+//! [`VP6Huff`]: ../vp6/struct.VP6Huff.html
+use nihav_core::io::bitwriter::*;
+use super::vpcommon::*;
+use super::vp56::TOKEN_LARGE;
+pub use super::vp56::{VP6Huff, VP6HuffModels, WriteHuff};
+use super::vp6data::*;
+
+const HUFF_EOB: u8 = 11;
+const ZERO_RUN_ESCAPE: u8 = 8;
+/// The longest EOB run that can be coded.
+pub const MAX_EOB_RUN: usize = 73;
+/// The largest absolute coefficient value that can be coded.
+pub const MAX_COEF_VALUE: i16 = 67 + 2047;
+
+fn coef_token(aval: u16) -> (u8, u16) {
+    if aval <= 4 {
+        return (aval as u8, 0);
+    }
+    for cat in (0..6).rev() {
+        let base = VP56_COEF_BASE[cat] as u16;
+        if aval >= base {
+            return (TOKEN_LARGE + cat as u8, aval - base);
+        }
+    }
+    unreachable!();
+}
+
+/// Writes a coefficient token (with its sign and extra bits) using the provided code table.
+///
+/// The absolute value must not exceed [`MAX_COEF_VALUE`] since larger values cannot be coded.
+pub fn write_token_huff(bw: &mut BitWriter, huff: &VP6Huff, val: i16) {
+    debug_assert!(val.unsigned_abs() <= MAX_COEF_VALUE as u16, "coefficient {} is out of range", val);
+    let (token, add) = coef_token(val.unsigned_abs());
+    bw.write_huff(huff, token);
+    if token >= TOKEN_LARGE {
+        bw.write(u32::from(add), VP6_COEF_ADD_BITS[usize::from(token - TOKEN_LARGE)]);
+    }
+    if token != 0 {
+        bw.write_bit(val < 0);
+    }
+}
+
+/// Writes the end-of-block token.
+pub fn write_eob_huff(bw: &mut BitWriter, huff: &VP6Huff) {
+    bw.write_huff(huff, HUFF_EOB);
+}
+
+/// Writes the number of the following blocks having their DC (or all AC) coefficients equal to zero.
+pub fn write_eob_run_huff(bw: &mut BitWriter, run: usize) {
+    debug_assert!(run <= MAX_EOB_RUN);
+    match run {
+        0 | 1   => bw.write(run as u32, 2),
+        2..=5   => {
+            bw.write(2, 2);
+            bw.write((run - 2) as u32, 2);
+        },
+        6..=9   => {
+            bw.write(0b110, 3);
+            bw.write((run - 6) as u32, 2);
+        },
+        _       => {
+            bw.write(0b111, 3);
+            bw.write((run - 10) as u32, 6);
+        },
+    };
+}
+
+/// Writes the number of zero coefficients following a zero token.
+pub fn write_zero_run_huff(bw: &mut BitWriter, huff: &VP6Huff, run: usize) {
+    if run < usize::from(ZERO_RUN_ESCAPE) {
+        bw.write_huff(huff, run as u8);
+    } else {
+        bw.write_huff(huff, ZERO_RUN_ESCAPE);
+        bw.write((run - usize::from(ZERO_RUN_ESCAPE)) as u32, 6);
+    }
+}
+
+#[derive(Clone)]
+struct CoeffBlock {
+    plane:  usize,
+    coeffs: [i16; 64],
+    end:    usize,
+}
+
+/// Huffman-coded coefficient partition writer.
+///
+/// Blocks are collected for the whole partition first since runs of blocks without DC or AC coefficients
+/// are signalled in the first block of the run.
+#[derive(Clone,Default)]
+pub struct VP6HuffCoeffWriter {
+    blocks: Vec<CoeffBlock>,
+}
+
+impl VP6HuffCoeffWriter {
+    /// Constructs a new instance of `VP6HuffCoeffWriter`.
+    pub fn new() -> Self { Self::default() }
+    /// Removes all collected blocks.
+    pub fn clear(&mut self) { self.blocks.clear(); }
+    /// Returns the number of collected blocks.
+    pub fn get_num_blocks(&self) -> usize { self.blocks.len() }
+    /// Adds a block in coding order.
+    ///
+    /// Coefficients are quantised and stored in scan order with the first one being DC prediction residual.
+    /// Plane numbers are the same as in the decoder (0 for luma, 1-2 for chroma and 3 for alpha).
+    /// Coefficient values must be within the `-MAX_COEF_VALUE..=MAX_COEF_VALUE` range.
+    pub fn add_block(&mut self, plane: usize, coeffs: &[i16; 64]) {
+        let end = coeffs[1..].iter().rposition(|&x| x != 0).map_or(1, |pos| pos + 2);
+        self.blocks.push(CoeffBlock { plane: if plane == 0 || plane == 3 { 0 } else { 1 }, coeffs: *coeffs, end });
+    }
+    // the number of following blocks of the same plane type satisfying the condition
+    fn count_run<F: Fn(&CoeffBlock) -> bool>(&self, start: usize, plane: usize, cond: F) -> usize {
+        self.blocks[start + 1..].iter().filter(|blk| blk.plane == plane)
+            .take_while(|blk| cond(blk)).take(MAX_EOB_RUN).count()
+    }
+    /// Writes all collected blocks using the provided code tables.
+    pub fn write(&self, bw: &mut BitWriter, model: &VP6HuffModels) {
+        let mut dc_zero_run = [0; 2];
+        let mut ac_zero_run = [0; 2];
+        for (blk_no, blk) in self.blocks.iter().enumerate() {
+            let plane = blk.plane;
+            let coeffs = &blk.coeffs;
+
+            if dc_zero_run[plane] == 0 {
+                write_token_huff(bw, &model.dc_token_tree[plane], coeffs[0]);
+                if coeffs[0] == 0 {
+                    dc_zero_run[plane] = self.count_run(blk_no, plane, |b| b.coeffs[0] == 0);
+                    write_eob_run_huff(bw, dc_zero_run[plane]);
+                }
+            } else {
+                dc_zero_run[plane] -= 1;
+            }
+
+            if ac_zero_run[plane] > 0 {
+                ac_zero_run[plane] -= 1;
+                continue;
+            }
+
+            let mut last_val = coeffs[0];
+            let mut idx = 1;
+            while idx < 64 {
+                let ac_band = VP6_IDX_TO_AC_BAND[idx].min(3);
+                let ac_mode = last_val.unsigned_abs().min(2) as usize;
+                let huff = &model.ac_token_tree[plane][ac_mode][ac_band];
+                if idx >= blk.end {
+                    write_eob_huff(bw, huff);
+                    if idx == 1 {
+                        ac_zero_run[plane] = self.count_run(blk_no, plane, |b| b.end == 1);
+                        write_eob_run_huff(bw, ac_zero_run[plane]);
+                    }
+                    break;
+                }
+                let val = coeffs[idx];
+                write_token_huff(bw, huff, val);
+                idx += 1;
+                last_val = val;
+                if val == 0 {
+                    let run = coeffs[idx..blk.end].iter().position(|&x| x != 0).unwrap_or(0);
+                    write_zero_run_huff(bw, &model.zero_run_tree[if idx >= 7 { 1 } else { 0 }], run);
+                    idx += run;
+                }
+            }
+        }
+    }
+    /// Writes all collected blocks into a new coefficient partition.
+    pub fn encode(&self, model: &VP6HuffModels) -> Vec<u8> {
+        let mut bw = BitWriter::new(Vec::new(), BitWriterMode::BE);
+        self.write(&mut bw, model);
+        bw.end()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use nihav_core::io::bitreader::*;
+    use super::*;
+    use super::super::vp6::*;
+    use super::super::testsynth::RNG;
+
+    fn random_models(rng: &mut RNG) -> VP6HuffModels {
+        let mut model = VP6HuffModels::default();
+        let mut gen_probs = |probs: &mut [u8]| for el in probs.iter_mut() { *el = (rng.range(255) + 1) as u8; };
+        for plane in 0..2 {
+            let mut probs = [0; 11];
+            gen_probs(&mut probs);
+            model.dc_token_tree[plane].build_codes(&probs);
+            for mode in 0..3 {
+                for band in 0..6 {
+                    gen_probs(&mut probs);
+                    model.ac_token_tree[plane][mode][band].build_codes(&probs);
+                }
+            }
+            let mut zprobs = [0; 14];
+            gen_probs(&mut zprobs);
+            model.zero_run_tree[plane].build_codes_zero_run(&zprobs);
+        }
+        model
+    }
+    fn random_block(rng: &mut RNG, has_dc: bool, has_ac: bool) -> [i16; 64] {
+        let mut blk = [0; 64];
+        if has_dc {
+            blk[0] = rng.value(MAX_COEF_VALUE);
+        }
+        if has_ac {
+            let end = 2 + rng.range(62) as usize;
+            for (idx, el) in blk.iter_mut().enumerate().take(end).skip(1) {
+                if idx + 1 == end || rng.chance(1, 3) {
+                    *el = rng.value(MAX_COEF_VALUE);
+                }
+            }
+        }
+        blk
+    }
+
+    #[test]
+    fn test_huff_coeff_writer() {
+        let mut rng = RNG::new(7);
+        let model = random_models(&mut rng);
+        let mut writer = VP6HuffCoeffWriter::new();
+        let mut blocks = Vec::new();
+        // runs of blocks without DC or AC of varying lengths, including ones longer than the longest codable run
+        let mut dc_run = 0;
+        let mut ac_run = 0;
+        for blk_no in 0..2000 {
+            if dc_run == 0 && rng.chance(1, 8) {
+                dc_run = rng.range(120) as usize;
+            }
+            if ac_run == 0 && rng.chance(1, 8) {
+                ac_run = rng.range(120) as usize;
+            }
+            let plane = [0, 0, 0, 0, 1, 2][blk_no % 6];
+            let blk = random_block(&mut rng, dc_run == 0, ac_run == 0);
+            dc_run = dc_run.saturating_sub(1);
+            ac_run = ac_run.saturating_sub(1);
+            writer.add_block(plane, &blk);
+            blocks.push((plane, blk));
+        }
+        assert_eq!(writer.get_num_blocks(), blocks.len());
+        let data = writer.encode(&model);
+
+        let dec = VP6BR::new();
+        let mut vp6models = VP6Models::new();
+        for (i, el) in vp6models.zigzag.iter_mut().enumerate() {
+            *el = i;
+        }
+        let mut fstate = FrameState::new();
+        fstate.ac_quant = 1;
+        let mut br = BitReader::new(&data, BitReaderMode::BE);
+        for (blk_no, (plane, blk)) in blocks.iter().enumerate() {
+            fstate.plane = *plane;
+            let mut coeffs = [0; 64];
+            dec.decode_block_huff(&mut br, &mut coeffs, &vp6models, &model, &mut fstate).unwrap();
+            assert_eq!(&coeffs, blk, "block {} differs", blk_no);
+        }
+        assert!(br.left() < 8);
+    }
+    #[test]
+    fn test_huff_eob_runs() {
+        let model = random_models(&mut RNG::new(1));
+        let mut writer = VP6HuffCoeffWriter::new();
+        for _ in 0..(MAX_EOB_RUN + 1) * 2 {
+            writer.add_block(0, &[0; 64]);
+        }
+        let mut bw = BitWriter::new(Vec::new(), BitWriterMode::BE);
+        writer.write(&mut bw, &model);
+        let run_bits = bw.tell();
+        let mut writer = VP6HuffCoeffWriter::new();
+        writer.add_block(0, &[0; 64]);
+        let mut bw = BitWriter::new(Vec::new(), BitWriterMode::BE);
+        writer.write(&mut bw, &model);
+        let single_bits = bw.tell();
+        // only two blocks are coded, each starting the longest possible DC and AC runs (9 bits instead of 2 for each)
+        assert_eq!(run_bits, (single_bits + 7 * 2) * 2);
+    }
+}
@@ -0,0 +1,810 @@
+//! Synthetic VP6 stream generator for the decoder tests.
+//!
+//! This is not an encoder: macroblock types, motion vectors and coefficients are picked at random
+//! and written with exactly the same syntax and probability models the decoder uses.
+//! The produced streams cover most of the bitstream features and can be freely redistributed
+//! unlike the real-world samples.
+use nihav_core::io::bitwriter::*;
+use nihav_codec_support::codecs::{MV, ZERO_MV};
+use super::vpcommon::*;
+use super::vp56::*;
+use super::vp6data::*;
+use super::testsynth::*;
+
+/// Stream parameters for the generator.
+#[derive(Clone,Copy)]
+pub struct SynthConfig {
+    pub version:        u8,
+    pub profile:        u8,
+    pub interlaced:     bool,
+    pub multistream:    bool,
+    pub huffman:        bool,
+    pub alpha:          bool,
+    pub fourmv:         bool,
+    pub golden:         bool,
+    pub mb_w:           usize,
+    pub mb_h:           usize,
+    pub nframes:        usize,
+    pub key_int:        usize,
+    /// Keyframe interval for the alpha stream, zero means the same as for the colour one.
+    pub alpha_key_int:  usize,
+    pub seed:           u32,
+}
+
+#[derive(Clone,Copy,Default)]
+struct CoeffModels {
+    dc_value_probs: [u8; 11],
+    dc_token_probs: [[u8; 5]; 3],
+    ac_val_probs:   [[[u8; 11]; 6]; 3],
+}
+
+#[derive(Default)]
+struct StreamModels {
+    mv_models:      [VP56MVModel; 2],
+    coeff_models:   [CoeffModels; 2],
+    zero_run_probs: [[u8; 14]; 2],
+    huff:           VP6HuffModels,
+}
+
+#[derive(Clone,Copy)]
+struct MBInfo {
+    mb_type:    VPMBType,
+    mv:         MV,
+}
+
+enum CoeffWriter {
+    None,
+    Bool(BoolEncoder),
+    Huff(BitWriter),
+}
+
+const FOURMV_SUB_TYPE: [VPMBType; 4] = [ VPMBType::InterNoMV, VPMBType::InterMV, VPMBType::InterNearest, VPMBType::InterNear ];
+
+/// Synthetic VP6 stream generator.
+pub struct VP6Synth {
+    cfg:            SynthConfig,
+    rng:            RNG,
+    frameno:        usize,
+    models:         [StreamModels; 2],
+    mbtype_probs:   [[[u8; 10]; 10]; 3],
+    mb_info:        Vec<MBInfo>,
+
+    last_mbt:       VPMBType,
+    ilace_prob:     u8,
+    ilace_mb:       bool,
+    top_ctx:        [Vec<u8>; 3],
+    left_ctx:       [u8; 4],
+    dc_max:         i16,
+    ac_max:         i16,
+}
+
+fn rescale_mb_mode_prob(prob: u32, total: u32) -> u8 {
+    (255 * prob / (1 + total)) as u8
+}
+
+fn map_mb_type(mbtype: VPMBType) -> usize {
+    match mbtype {
+        VPMBType::InterNoMV     => 0,
+        VPMBType::Intra         => 1,
+        VPMBType::InterMV       => 2,
+        VPMBType::InterNearest  => 3,
+        VPMBType::InterNear     => 4,
+        VPMBType::GoldenNoMV    => 5,
+        VPMBType::GoldenMV      => 6,
+        VPMBType::InterFourMV   => 7,
+        VPMBType::GoldenNearest => 8,
+        VPMBType::GoldenNear    => 9,
+    }
+}
+
+// the decoder derives macroblock type probabilities from the transmitted statistics,
+// the generator never updates those so they are calculated only once from the defaults
+fn calc_mbtype_probs() -> [[[u8; 10]; 10]; 3] {
+    const DEFAULT_XMITTED_PROBS: [[u8; 20]; 3] = [
+        [ 42,  69, 2, 1, 7, 1, 42, 44, 22, 6, 3, 1, 2, 0, 5, 1, 1, 0, 0, 0 ],
+        [  8, 229, 1, 1, 8, 0,  0,  0,  0, 0, 2, 1, 1, 0, 0, 0, 1, 1, 0, 0 ],
+        [ 35, 122, 1, 1, 6, 1, 34, 46,  0, 0, 2, 1, 1, 0, 1, 0, 1, 1, 0, 0 ]
+    ];
+    let mut probs = [[[0; 10]; 10]; 3];
+    for (ctx, prob_xmitted) in DEFAULT_XMITTED_PROBS.iter().enumerate() {
+        for mode in 0..10 {
+            let mdl = &mut probs[ctx][mode];
+            let mut cnt = [0u32; 10];
+            let mut total = 0;
+            for i in 0..10 {
+                if i == mode { continue; }
+                cnt[i] = 100 * u32::from(prob_xmitted[i * 2]);
+                total += cnt[i];
+            }
+            let sum = u32::from(prob_xmitted[mode * 2]) + u32::from(prob_xmitted[mode * 2 + 1]);
+            mdl[9] = 255 - rescale_mb_mode_prob(u32::from(prob_xmitted[mode * 2 + 1]), sum);
+
+            let inter_mv0_weight = cnt[0] + cnt[2];
+            let inter_mv1_weight = cnt[3] + cnt[4];
+            let gold_mv0_weight = cnt[5] + cnt[6];
+            let gold_mv1_weight = cnt[8] + cnt[9];
+            let mix_weight = cnt[1] + cnt[7];
+            mdl[0] = 1 + rescale_mb_mode_prob(inter_mv0_weight + inter_mv1_weight, total);
+            mdl[1] = 1 + rescale_mb_mode_prob(inter_mv0_weight, inter_mv0_weight + inter_mv1_weight);
+            mdl[2] = 1 + rescale_mb_mode_prob(mix_weight, mix_weight + gold_mv0_weight + gold_mv1_weight);
+            mdl[3] = 1 + rescale_mb_mode_prob(cnt[0], inter_mv0_weight);
+            mdl[4] = 1 + rescale_mb_mode_prob(cnt[3], inter_mv1_weight);
+            mdl[5] = 1 + rescale_mb_mode_prob(cnt[1], mix_weight);
+            mdl[6] = 1 + rescale_mb_mode_prob(gold_mv0_weight, gold_mv0_weight + gold_mv1_weight);
+            mdl[7] = 1 + rescale_mb_mode_prob(cnt[5], gold_mv0_weight);
+            mdl[8] = 1 + rescale_mb_mode_prob(cnt[8], gold_mv1_weight);
+        }
+    }
+    probs
+}
+
+fn write_mv_comp(bc: &mut BoolEncoder, model: &VP56MVModel, val: i16) {
+    let aval = val.unsigned_abs();
+    assert!(aval < 256);
+    if aval < 8 {
+        bc.put_prob(false, model.nz_prob);
+        bc.put_prob(aval >= 4, model.tree_probs[0]);
+        if aval < 4 {
+            bc.put_prob(aval >= 2, model.tree_probs[1]);
+            bc.put_prob((aval & 1) != 0, model.tree_probs[if aval < 2 { 2 } else { 3 }]);
+        } else {
+            bc.put_prob(aval >= 6, model.tree_probs[4]);
+            bc.put_prob((aval & 1) != 0, model.tree_probs[if aval < 6 { 5 } else { 6 }]);
+        }
+    } else {
+        bc.put_prob(true, model.nz_prob);
+        for ord in LONG_VECTOR_ORDER.iter() {
+            bc.put_prob(((aval >> *ord) & 1) != 0, model.raw_probs[*ord]);
+        }
+        if (aval & 0xF0) != 0 {
+            bc.put_prob((aval & 8) != 0, model.raw_probs[3]);
+        }
+    }
+    if aval != 0 {
+        bc.put_prob(val < 0, model.sign_prob);
+    }
+}
+
+fn coef_token(aval: u16) -> (usize, u16) {
+    if aval < 5 {
+        return (aval as usize, 0);
+    }
+    for cat in (0..6).rev() {
+        let base = VP56_COEF_BASE[cat] as u16;
+        if aval >= base {
+            return (TOKEN_LARGE as usize + cat, aval - base);
+        }
+    }
+    unreachable!();
+}
+
+fn write_coef_bc(bc: &mut BoolEncoder, probs: &[u8], prob34: u8, val_probs: &[u8; 11], is_dc: bool, has_nnz: bool, val: Option<i16>) {
+    let val = if let Some(val) = val {
+            val
+        } else {
+            assert!(!is_dc && has_nnz);
+            bc.put_prob(false, probs[0]);
+            bc.put_prob(false, probs[1]);
+            return;
+        };
+    if val == 0 {
+        assert!(has_nnz);
+        bc.put_prob(false, probs[0]);
+        if !is_dc {
+            bc.put_prob(true, probs[1]);
+        }
+        return;
+    }
+    if has_nnz {
+        bc.put_prob(true, probs[0]);
+    }
+    let (token, add) = coef_token(val.unsigned_abs());
+    bc.put_prob(token != 1, probs[2]);
+    if token != 1 {
+        bc.put_prob(token >= TOKEN_LARGE as usize, probs[3]);
+        if token < TOKEN_LARGE as usize {
+            bc.put_prob(token != 2, probs[4]);
+            if token != 2 {
+                bc.put_prob(token == 4, prob34);
+            }
+        } else {
+            let cat = token - (TOKEN_LARGE as usize);
+            bc.put_prob(cat >= 2, val_probs[6]);
+            if cat < 2 {
+                bc.put_prob(cat == 1, val_probs[7]);
+            } else {
+                bc.put_prob(cat >= 4, val_probs[8]);
+                if cat < 4 {
+                    bc.put_prob(cat == 3, val_probs[9]);
+                } else {
+                    bc.put_prob(cat == 5, val_probs[10]);
+                }
+            }
+            let nbits = VP6_COEF_ADD_BITS[cat];
+            for (i, prob) in VP56_COEF_ADD_PROBS[cat].iter().take(nbits as usize).enumerate() {
+                bc.put_prob(((add >> (nbits - 1 - (i as u8))) & 1) != 0, *prob);
+            }
+        }
+    }
+    bc.put_bool(val < 0);
+}
+
+fn write_zero_run_bc(bc: &mut BoolEncoder, probs: &[u8; 14], run: usize) {
+    bc.put_prob(run >= 4, probs[0]);
+    if run < 4 {
+        bc.put_prob(run >= 2, probs[1]);
+        bc.put_prob((run & 1) != 0, probs[if run < 2 { 2 } else { 3 }]);
+    } else {
+        bc.put_prob(run >= 8, probs[4]);
+        if run < 8 {
+            bc.put_prob(run >= 6, probs[5]);
+            bc.put_prob((run & 1) != 0, probs[if run < 6 { 6 } else { 7 }]);
+        } else {
+            let nval = run - 8;
+            for i in 0..6 {
+                bc.put_prob(((nval >> i) & 1) != 0, probs[i + 8]);
+            }
+        }
+    }
+}
+
+fn write_coef_huff(bw: &mut BitWriter, huff: &VP6Huff, val: Option<i16>) {
+    let val = if let Some(val) = val {
+            val
+        } else {
+            bw.write_huff(huff, 11);
+            return;
+        };
+    let (token, add) = coef_token(val.unsigned_abs());
+    bw.write_huff(huff, token as u8);
+    if token >= TOKEN_LARGE as usize {
+        bw.write(u32::from(add), VP6_COEF_ADD_BITS[token - (TOKEN_LARGE as usize)]);
+    }
+    if token != 0 {
+        bw.write((val < 0) as u32, 1);
+    }
+}
+
+fn write_zero_run_huff(bw: &mut BitWriter, huff: &VP6Huff, run: usize) {
+    if run < 8 {
+        bw.write_huff(huff, run as u8);
+    } else {
+        bw.write_huff(huff, 8);
+        bw.write((run - 8) as u32, 6);
+    }
+}
+
+impl VP6Synth {
+    pub fn new(cfg: SynthConfig) -> Self {
+        Self {
+            cfg,
+            rng:            RNG::new(cfg.seed),
+            frameno:        0,
+            models:         [StreamModels::default(), StreamModels::default()],
+            mbtype_probs:   calc_mbtype_probs(),
+            mb_info:        vec![MBInfo { mb_type: VPMBType::Intra, mv: ZERO_MV }; cfg.mb_w * cfg.mb_h],
+
+            last_mbt:       VPMBType::InterNoMV,
+            ilace_prob:     0,
+            ilace_mb:       false,
+            top_ctx:        [vec![0; cfg.mb_w * 2], vec![0; cfg.mb_w], vec![0; cfg.mb_w]],
+            left_ctx:       [0; 4],
+            dc_max:         0,
+            ac_max:         0,
+        }
+    }
+    /// Generates the next frame packet.
+    pub fn gen_frame(&mut self) -> Vec<u8> {
+        let is_intra = self.frameno.is_multiple_of(self.cfg.key_int);
+        let alpha_key_int = if self.cfg.alpha_key_int != 0 { self.cfg.alpha_key_int } else { self.cfg.key_int };
+        let alpha_intra = self.frameno.is_multiple_of(alpha_key_int);
+        self.frameno += 1;
+        let mut colour = self.gen_stream(0, is_intra);
+        if !self.cfg.alpha {
+            return colour;
+        }
+        let alpha = self.gen_stream(1, alpha_intra);
+        let size = colour.len();
+        let mut pkt = vec![(size >> 16) as u8, (size >> 8) as u8, size as u8];
+        pkt.append(&mut colour);
+        pkt.extend_from_slice(&alpha);
+        pkt
+    }
+    fn gen_stream(&mut self, sidx: usize, is_intra: bool) -> Vec<u8> {
+        let cfg = self.cfg;
+        let is_advanced = cfg.profile == VP6_ADVANCED_PROFILE;
+        // keep the reconstructed coefficients well inside 16-bit range
+        let quant = if self.rng.chance(1, 4) { 56 + self.rng.range(8) } else { 20 + self.rng.range(44) } as usize;
+        self.dc_max = 8;
+        self.ac_max = (2000 / (VP56_AC_QUANTS[quant] * 4)).max(2);
+        let is_golden = is_intra || (cfg.golden && self.rng.chance(1, 3));
+        let has_offset = cfg.multistream || (cfg.profile == VP6_SIMPLE_PROFILE);
+
+        let mut bc = BoolEncoder::new();
+        if is_intra {
+            bc.put_bits(cfg.mb_h as u32, 8);
+            bc.put_bits(cfg.mb_w as u32, 8);
+            bc.put_bits(cfg.mb_h as u32, 8);
+            bc.put_bits(cfg.mb_w as u32, 8);
+            bc.put_bits(0, 2);
+        } else {
+            bc.put_bool(is_golden);
+        }
+        let mut do_pm = false;
+        if !is_intra && is_advanced {
+            bc.put_bool(false);
+            if cfg.version == VERSION_VP62 {
+                do_pm = self.rng.chance(1, 2);
+                bc.put_bool(do_pm);
+            }
+        }
+        if is_advanced && (is_intra || do_pm) {
+            let autosel_pm = self.rng.chance(1, 2);
+            bc.put_bool(autosel_pm);
+            if autosel_pm {
+                bc.put_bits(self.rng.range(32), 5);
+                bc.put_bits(self.rng.range(8), 3);
+            } else {
+                bc.put_bool(self.rng.chance(1, 2));
+            }
+            if cfg.version == VERSION_VP62 {
+                bc.put_bits(self.rng.range(16), 4);
+            }
+        }
+        bc.put_bool(cfg.huffman);
+
+        if is_intra {
+            self.reset_models(sidx);
+        } else {
+            for _ in 0..3 {
+                bc.put_prob(false, 174);
+                bc.put_prob(false, 254);
+            }
+            self.write_mv_models(&mut bc, sidx);
+        }
+        self.write_coeff_models(&mut bc, sidx, is_intra);
+        if cfg.huffman {
+            let models = &mut self.models[sidx];
+            for i in 0..2 {
+                models.huff.dc_token_tree[i].build_codes(&models.coeff_models[i].dc_value_probs);
+                for mode in 0..3 {
+                    for band in 0..6 {
+                        models.huff.ac_token_tree[i][mode][band].build_codes(&models.coeff_models[i].ac_val_probs[mode][band]);
+                    }
+                }
+                models.huff.zero_run_tree[i].build_codes_zero_run(&models.zero_run_probs[i]);
+            }
+        }
+        if cfg.interlaced {
+            self.ilace_prob = 1 + (self.rng.range(255) as u8);
+            bc.put_bits(u32::from(self.ilace_prob), 8);
+        }
+
+        let mut cw = match (has_offset, cfg.huffman) {
+                (false, _)    => CoeffWriter::None,
+                (true, false) => CoeffWriter::Bool(BoolEncoder::new()),
+                (true, true)  => CoeffWriter::Huff(BitWriter::new(Vec::new(), BitWriterMode::BE)),
+            };
+        self.last_mbt = VPMBType::InterNoMV;
+        self.ilace_mb = false;
+        for ctx in self.top_ctx.iter_mut() {
+            for el in ctx.iter_mut() {
+                *el = 0;
+            }
+        }
+        for mb_y in 0..cfg.mb_h {
+            self.left_ctx = [0; 4];
+            for mb_x in 0..cfg.mb_w {
+                self.gen_mb(&mut bc, &mut cw, sidx, is_intra, mb_x, mb_y);
+            }
+        }
+
+        let part1 = bc.finish();
+        let part2 = match cw {
+                CoeffWriter::None       => Vec::new(),
+                CoeffWriter::Bool(bc2)  => bc2.finish(),
+                CoeffWriter::Huff(bw)   => bw.end(),
+            };
+
+        let mut hdr = BitWriter::new(Vec::new(), BitWriterMode::BE);
+        hdr.write(!is_intra as u32, 1);
+        hdr.write(quant as u32, 6);
+        hdr.write(cfg.multistream as u32, 1);
+        if is_intra {
+            hdr.write(u32::from(cfg.version), 5);
+            hdr.write(u32::from(cfg.profile), 2);
+            hdr.write(cfg.interlaced as u32, 1);
+        }
+        if has_offset {
+            let hdr_size = if is_intra { 4 } else { 3 };
+            hdr.write((hdr_size + part1.len()) as u32, 16);
+        }
+        let mut frame = hdr.end();
+        frame.extend_from_slice(&part1);
+        frame.extend_from_slice(&part2);
+        // padding makes the stream decodable even if the decoder cuts partitions a bit short
+        frame.extend_from_slice(&[0; 3]);
+        frame
+    }
+    fn reset_models(&mut self, sidx: usize) {
+        let models = &mut self.models[sidx];
+        for (i, mdl) in models.mv_models.iter_mut().enumerate() {
+            mdl.nz_prob         = NZ_PROBS[i];
+            mdl.sign_prob       = 128;
+            mdl.raw_probs.copy_from_slice(&RAW_PROBS[i]);
+            mdl.tree_probs.copy_from_slice(&TREE_PROBS[i]);
+        }
+        models.zero_run_probs.copy_from_slice(&ZERO_RUN_PROBS);
+    }
+    fn gen_probability(&mut self) -> u8 {
+        let val = self.rng.range(128) as u8;
+        if val == 0 { 1 } else { val << 1 }
+    }
+    fn maybe_update(&mut self, bc: &mut BoolEncoder, upd_prob: u8) -> Option<u8> {
+        let update = self.rng.chance(1, 5);
+        bc.put_prob(update, upd_prob);
+        if update {
+            let prob = self.gen_probability();
+            bc.put_probability(prob);
+            Some(prob)
+        } else {
+            None
+        }
+    }
+    fn write_mv_models(&mut self, bc: &mut BoolEncoder, sidx: usize) {
+        for comp in 0..2 {
+            if let Some(prob) = self.maybe_update(bc, HAS_NZ_PROB[comp]) {
+                self.models[sidx].mv_models[comp].nz_prob = prob;
+            }
+            if let Some(prob) = self.maybe_update(bc, HAS_SIGN_PROB[comp]) {
+                self.models[sidx].mv_models[comp].sign_prob = prob;
+            }
+        }
+        for comp in 0..2 {
+            for (i, upd_prob) in HAS_TREE_PROB[comp].iter().enumerate() {
+                if let Some(prob) = self.maybe_update(bc, *upd_prob) {
+                    self.models[sidx].mv_models[comp].tree_probs[i] = prob;
+                }
+            }
+        }
+        for comp in 0..2 {
+            for (i, upd_prob) in HAS_RAW_PROB[comp].iter().enumerate() {
+                if let Some(prob) = self.maybe_update(bc, *upd_prob) {
+                    self.models[sidx].mv_models[comp].raw_probs[i] = prob;
+                }
+            }
+        }
+    }
+    fn write_coeff_models(&mut self, bc: &mut BoolEncoder, sidx: usize, is_intra: bool) {
+        let mut def_prob = [128u8; 11];
+        for plane in 0..2 {
+            for i in 0..11 {
+                if let Some(prob) = self.maybe_update(bc, HAS_COEF_PROBS[plane][i]) {
+                    def_prob[i] = prob;
+                    self.models[sidx].coeff_models[plane].dc_value_probs[i] = prob;
+                } else if is_intra {
+                    self.models[sidx].coeff_models[plane].dc_value_probs[i] = def_prob[i];
+                }
+            }
+        }
+
+        // scan order is transmitted in coded order so the generator does not need to track it
+        let upd_scan = self.rng.chance(1, 3);
+        bc.put_bool(upd_scan);
+        if upd_scan {
+            for upd_prob in HAS_SCAN_UPD_PROBS.iter().skip(1) {
+                let update = self.rng.chance(1, 4);
+                bc.put_prob(update, *upd_prob);
+                if update {
+                    bc.put_bits(self.rng.range(16), 4);
+                }
+            }
+        }
+
+        for comp in 0..2 {
+            for i in 0..14 {
+                if let Some(prob) = self.maybe_update(bc, HAS_ZERO_RUN_PROBS[comp][i]) {
+                    self.models[sidx].zero_run_probs[comp][i] = prob;
+                }
+            }
+        }
+
+        for ctype in 0..3 {
+            for plane in 0..2 {
+                for group in 0..6 {
+                    for i in 0..11 {
+                        if let Some(prob) = self.maybe_update(bc, VP6_AC_PROBS[ctype][plane][group][i]) {
+                            def_prob[i] = prob;
+                            self.models[sidx].coeff_models[plane].ac_val_probs[ctype][group][i] = prob;
+                        } else if is_intra {
+                            self.models[sidx].coeff_models[plane].ac_val_probs[ctype][group][i] = def_prob[i];
+                        }
+                    }
+                }
+            }
+        }
+        for mdl in self.models[sidx].coeff_models.iter_mut() {
+            for i in 0..3 {
+                for k in 0..5 {
+                    mdl.dc_token_probs[i][k] = rescale_prob(mdl.dc_value_probs[k], &VP6_DC_WEIGHTS[k][i], 255);
+                }
+            }
+        }
+    }
+    fn find_mv_pred(&self, mb_x: usize, mb_y: usize, ref_id: u8) -> (usize, MV, MV, MV) {
+        const CAND_POS: [(i8, i8); 12] = [
+            (-1,  0), ( 0, -1),
+            (-1, -1), (-1,  1),
+            (-2,  0), ( 0, -2),
+            (-1, -2), (-2, -1),
+            (-2,  1), (-1,  2),
+            (-2, -2), (-2,  2)
+        ];
+
+        let mut nearest_mv = ZERO_MV;
+        let mut near_mv = ZERO_MV;
+        let mut pred_mv = ZERO_MV;
+        let mut num_mv: usize = 0;
+
+        for (i, (yoff, xoff)) in CAND_POS.iter().enumerate() {
+            let cx = (mb_x as isize) + (*xoff as isize);
+            let cy = (mb_y as isize) + (*yoff as isize);
+            if (cx < 0) || (cy < 0) || (cx as usize >= self.cfg.mb_w) || (cy as usize >= self.cfg.mb_h) {
+                continue;
+            }
+            let info = self.mb_info[(cx as usize) + (cy as usize) * self.cfg.mb_w];
+            if (info.mb_type.get_ref_id() != ref_id) || (info.mv == ZERO_MV) {
+                continue;
+            }
+            if num_mv == 0 {
+                nearest_mv = info.mv;
+                num_mv += 1;
+                if i < 2 {
+                    pred_mv = info.mv;
+                }
+            } else if info.mv != nearest_mv {
+                near_mv = info.mv;
+                num_mv += 1;
+                break;
+            }
+        }
+
+        (num_mv, nearest_mv, near_mv, pred_mv)
+    }
+    fn gen_mb_type(&mut self) -> VPMBType {
+        let mut cand = vec![VPMBType::Intra, VPMBType::InterNoMV, VPMBType::InterNoMV,
+                            VPMBType::InterMV, VPMBType::InterMV, VPMBType::InterMV,
+                            VPMBType::InterNearest, VPMBType::InterNear];
+        if self.cfg.fourmv {
+            cand.push(VPMBType::InterFourMV);
+            cand.push(VPMBType::InterFourMV);
+            cand.push(VPMBType::InterFourMV);
+        }
+        if self.cfg.golden {
+            cand.push(VPMBType::GoldenNoMV);
+            cand.push(VPMBType::GoldenMV);
+            cand.push(VPMBType::GoldenNearest);
+            cand.push(VPMBType::GoldenNear);
+        }
+        cand[self.rng.range(cand.len() as u32) as usize]
+    }
+    fn write_mb_type(&mut self, bc: &mut BoolEncoder, ctx: usize, mb_type: VPMBType) {
+        let probs = &self.mbtype_probs[ctx][map_mb_type(self.last_mbt)];
+        bc.put_prob(mb_type == self.last_mbt, probs[9]);
+        if mb_type == self.last_mbt {
+            return;
+        }
+        let path: &[(bool, usize)] = match mb_type {
+                VPMBType::InterNoMV     => &[(false, 0), (false, 1), (false, 3)],
+                VPMBType::InterMV       => &[(false, 0), (false, 1), (true,  3)],
+                VPMBType::InterNearest  => &[(false, 0), (true,  1), (false, 4)],
+                VPMBType::InterNear     => &[(false, 0), (true,  1), (true,  4)],
+                VPMBType::Intra         => &[(true,  0), (false, 2), (false, 5)],
+                VPMBType::InterFourMV   => &[(true,  0), (false, 2), (true,  5)],
+                VPMBType::GoldenNoMV    => &[(true,  0), (true,  2), (false, 6), (false, 7)],
+                VPMBType::GoldenMV      => &[(true,  0), (true,  2), (false, 6), (true,  7)],
+                VPMBType::GoldenNearest => &[(true,  0), (true,  2), (true,  6), (false, 8)],
+                VPMBType::GoldenNear    => &[(true,  0), (true,  2), (true,  6), (true,  8)],
+            };
+        for &(bit, idx) in path.iter() {
+            bc.put_prob(bit, probs[idx]);
+        }
+        self.last_mbt = mb_type;
+    }
+    fn gen_mv(&mut self, bc: &mut BoolEncoder, sidx: usize, pred_mv: MV) -> MV {
+        let mv = MV { x: self.rng.range(81) as i16 - 40, y: self.rng.range(81) as i16 - 40 };
+        let diff = mv - pred_mv;
+        write_mv_comp(bc, &self.models[sidx].mv_models[0], diff.x);
+        write_mv_comp(bc, &self.models[sidx].mv_models[1], diff.y);
+        mv
+    }
+    fn gen_mb(&mut self, bc: &mut BoolEncoder, cw: &mut CoeffWriter, sidx: usize, is_intra: bool, mb_x: usize, mb_y: usize) {
+        if self.cfg.interlaced {
+            let iprob = self.ilace_prob;
+            let prob = if mb_x == 0 {
+                    iprob
+                } else if !self.ilace_mb {
+                    iprob + (((256 - u16::from(iprob)) >> 1) as u8)
+                } else {
+                    iprob - (iprob >> 1)
+                };
+            self.ilace_mb = self.rng.chance(1, 2);
+            bc.put_prob(self.ilace_mb, prob);
+        }
+
+        let mut mb_type = VPMBType::Intra;
+        let mut mv = ZERO_MV;
+        if !is_intra {
+            let (num_mv, nearest_mv, near_mv, pred_mv) = self.find_mv_pred(mb_x, mb_y, VP_REF_INTER);
+            mb_type = self.gen_mb_type();
+            self.write_mb_type(bc, (num_mv + 1) % 3, mb_type);
+            match mb_type {
+                VPMBType::InterMV       => { mv = self.gen_mv(bc, sidx, pred_mv); },
+                VPMBType::InterNearest  => { mv = nearest_mv; },
+                VPMBType::InterNear     => { mv = near_mv; },
+                VPMBType::InterFourMV   => {
+                    let mut sub_types = [0; 4];
+                    for el in sub_types.iter_mut() {
+                        *el = self.rng.range(4);
+                        bc.put_bits(*el, 2);
+                    }
+                    for &sub_type in sub_types.iter() {
+                        mv = match FOURMV_SUB_TYPE[sub_type as usize] {
+                                VPMBType::InterMV       => self.gen_mv(bc, sidx, pred_mv),
+                                VPMBType::InterNearest  => nearest_mv,
+                                VPMBType::InterNear     => near_mv,
+                                _                       => ZERO_MV,
+                            };
+                    }
+                },
+                VPMBType::GoldenMV | VPMBType::GoldenNearest | VPMBType::GoldenNear => {
+                    let (_, nearest_mv, near_mv, pred_mv) = self.find_mv_pred(mb_x, mb_y, VP_REF_GOLDEN);
+                    mv = match mb_type {
+                            VPMBType::GoldenMV      => self.gen_mv(bc, sidx, pred_mv),
+                            VPMBType::GoldenNearest => nearest_mv,
+                            _                       => near_mv,
+                        };
+                },
+                _ => {},
+            };
+        }
+        self.mb_info[mb_x + mb_y * self.cfg.mb_w] = MBInfo { mb_type, mv };
+
+        for blk_no in 0..6 {
+            let (plane, ctx_idx, top_idx) = if blk_no < 4 {
+                    (0, blk_no >> 1, mb_x * 2 + (blk_no & 1))
+                } else {
+                    (blk_no - 3, blk_no - 2, mb_x)
+                };
+            let blk = self.gen_block();
+            let dc_mode = usize::from(self.top_ctx[plane][top_idx] + self.left_ctx[ctx_idx]);
+            let mplane = if plane == 0 { 0 } else { 1 };
+            match cw {
+                CoeffWriter::None       => self.write_block_bc(bc, &blk, sidx, mplane, dc_mode),
+                CoeffWriter::Bool(bc2)  => self.write_block_bc(bc2, &blk, sidx, mplane, dc_mode),
+                CoeffWriter::Huff(bw)   => self.write_block_huff(bw, &blk, sidx, mplane),
+            };
+            let cat = if blk[0] != 0 { 1 } else { 0 };
+            self.top_ctx[plane][top_idx] = cat;
+            self.left_ctx[ctx_idx] = cat;
+        }
+    }
+    // coefficients are generated in the coded order
+    fn gen_block(&mut self) -> [i16; 64] {
+        let mut blk = [0; 64];
+        if !self.rng.chance(1, 3) {
+            blk[0] = self.rng.value(self.dc_max);
+        }
+        let end = match self.rng.range(8) {
+                0..=2 => 1,
+                3..=5 => 2 + self.rng.range(10),
+                6     => 2 + self.rng.range(40),
+                _     => 64,
+            } as usize;
+        for el in blk[1..end].iter_mut() {
+            if self.rng.chance(1, 2) {
+                *el = self.rng.value(self.ac_max);
+            }
+        }
+        blk
+    }
+    fn write_block_bc(&self, bc: &mut BoolEncoder, blk: &[i16; 64], sidx: usize, plane: usize, dc_mode: usize) {
+        let model = &self.models[sidx].coeff_models[plane];
+        let zero_run_probs = &self.models[sidx].zero_run_probs;
+        let end = blk[1..].iter().rposition(|&x| x != 0).map_or(1, |pos| pos + 2);
+
+        write_coef_bc(bc, &model.dc_token_probs[dc_mode], model.dc_value_probs[5], &model.dc_value_probs, true, true, Some(blk[0]));
+        let mut idx = 1;
+        let mut last_val = blk[0];
+        while idx < 64 {
+            let ac_band = VP6_IDX_TO_AC_BAND[idx];
+            let ac_mode = last_val.unsigned_abs().min(2) as usize;
+            let has_nnz = (idx == 1) || (last_val != 0);
+            let probs = &model.ac_val_probs[ac_mode][ac_band];
+            if idx >= end {
+                write_coef_bc(bc, probs, probs[5], probs, false, has_nnz, None);
+                break;
+            }
+            let val = blk[idx];
+            write_coef_bc(bc, probs, probs[5], probs, false, has_nnz, Some(val));
+            idx += 1;
+            last_val = val;
+            if val == 0 {
+                let run = blk[idx..].iter().position(|&x| x != 0).unwrap();
+                write_zero_run_bc(bc, &zero_run_probs[if idx >= 7 { 1 } else { 0 }], run);
+                idx += run;
+            }
+        }
+    }
+    fn write_block_huff(&self, bw: &mut BitWriter, blk: &[i16; 64], sidx: usize, plane: usize) {
+        let huff = &self.models[sidx].huff;
+        let end = blk[1..].iter().rposition(|&x| x != 0).map_or(1, |pos| pos + 2);
+
+        write_coef_huff(bw, &huff.dc_token_tree[plane], Some(blk[0]));
+        if blk[0] == 0 {
+            bw.write(0, 2); // no EOB run
+        }
+        let mut idx = 1;
+        let mut last_val = blk[0];
+        while idx < 64 {
+            let ac_band = VP6_IDX_TO_AC_BAND[idx].min(3);
+            let ac_mode = last_val.unsigned_abs().min(2) as usize;
+            let tree = &huff.ac_token_tree[plane][ac_mode][ac_band];
+            if idx >= end {
+                write_coef_huff(bw, tree, None);
+                if idx == 1 {
+                    bw.write(0, 2); // no EOB run
+                }
+                break;
+            }
+            let val = blk[idx];
+            write_coef_huff(bw, tree, Some(val));
+            idx += 1;
+            last_val = val;
+            if val == 0 {
+                let run = blk[idx..].iter().position(|&x| x != 0).unwrap();
+                write_zero_run_huff(bw, &huff.zero_run_tree[if idx >= 7 { 1 } else { 0 }], run);
+                idx += run;
+            }
+        }
+    }
+}
+
+/// Generates the whole stream in raw packet format (32-bit big-endian size followed by packet data).
+pub fn gen_stream(cfg: SynthConfig) -> Vec<u8> {
+    let mut synth = VP6Synth::new(cfg);
+    let mut data = Vec::new();
+    for _ in 0..cfg.nframes {
+        put_packet(&mut data, &synth.gen_frame());
+    }
+    data
+}
+
+const fn synth_cfg(version: u8, profile: u8, seed: u32) -> SynthConfig {
+    SynthConfig {
+        version, profile, seed,
+        interlaced:     false,
+        multistream:    false,
+        huffman:        false,
+        alpha:          false,
+        fourmv:         false,
+        golden:         false,
+        mb_w:           4,
+        mb_h:           3,
+        nframes:        10,
+        key_int:        5,
+        alpha_key_int:  0,
+    }
+}
+
+/// Test fixtures stored in `assets/` and the parameters they are generated from.
+pub const TEST_STREAMS: [(&str, SynthConfig); 8] = [
+    ("vp6_simple.vp6",      synth_cfg(VERSION_VP60, VP6_SIMPLE_PROFILE, 1)),
+    ("vp6_advanced.vp6",    SynthConfig { fourmv: true, golden: true, ..synth_cfg(VERSION_VP62, VP6_ADVANCED_PROFILE, 2) }),
+    ("vp6_vp60_multistream.vp6", SynthConfig { multistream: true, fourmv: true, ..synth_cfg(VERSION_VP60, VP6_ADVANCED_PROFILE, 3) }),
+    ("vp6_huffman.vp6",     SynthConfig { multistream: true, huffman: true, fourmv: true, golden: true, ..synth_cfg(VERSION_VP62, VP6_ADVANCED_PROFILE, 4) }),
+    ("vp6_interlaced.vp6",  SynthConfig { interlaced: true, multistream: true, ..synth_cfg(VERSION_VP62, VP6_ADVANCED_PROFILE, 5) }),
+    ("vp6_fourmv.vp6",      SynthConfig { huffman: true, fourmv: true, ..synth_cfg(VERSION_VP60, VP6_SIMPLE_PROFILE, 6) }),
+    ("vp6_golden.vp6",      SynthConfig { golden: true, key_int: 10, ..synth_cfg(VERSION_VP62, VP6_ADVANCED_PROFILE, 7) }),
+    ("vp6a.vp6",            SynthConfig { alpha: true, multistream: true, fourmv: true, golden: true, ..synth_cfg(VERSION_VP62, VP6_ADVANCED_PROFILE, 14) }),
+];
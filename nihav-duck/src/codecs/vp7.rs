@@ -0,0 +1,844 @@
+//! VP7 decoder.
+//!
+//! VP7 is the direct predecessor of VP8 and shares most of the design with it: boolean coder,
+//! intra prediction modes, coefficient token coding and 6-tap motion compensation.
+//! The differences are in the frame header, macroblock features used instead of segmentation,
+//! motion vector prediction from a wider neighbourhood, transform and quantisers, loop filter
+//! details and optional fading of the previous frame.
+use nihav_core::codecs::*;
+use nihav_codec_support::codecs::{MV, ZERO_MV};
+use super::vpcommon::*;
+use super::vp78::*;
+use super::vp78data::*;
+use super::vp78dsp::*;
+use super::vp7data::*;
+use super::vp7dsp::*;
+
+// VP7 uses the same value for both missing top and left edges
+const EDGE_PIXELS: [u8; 2] = [128, 128];
+
+const FEATURE_QUANT: usize      = 0;
+const FEATURE_LF_LEVEL: usize   = 1;
+const FEATURE_GOLDEN: usize     = 2;
+// the last feature (blit pitch) is a hint for the player and does not affect decoding
+const NUM_FEATURES: usize       = 4;
+
+#[derive(Clone,Copy,Default)]
+struct MBFeature {
+    present_prob:   u8,
+    tree_probs:     [u8; 3],
+    values:         [u8; 4],
+}
+
+#[derive(Clone,Copy)]
+struct ProbModels {
+    coef_probs:     CoeffProbs,
+    y_mode_probs:   [u8; 4],
+    uv_mode_probs:  [u8; 3],
+    mv_probs:       [[u8; 17]; 2],
+    scan:           [usize; 16],
+}
+
+impl Default for ProbModels {
+    fn default() -> Self {
+        Self {
+            coef_probs:     DEFAULT_COEF_PROBS,
+            y_mode_probs:   Y_MODE_PROBS,
+            uv_mode_probs:  UV_MODE_PROBS,
+            mv_probs:       DEFAULT_MV_PROBS,
+            scan:           DEFAULT_SCAN_ORDER,
+        }
+    }
+}
+
+#[derive(Clone,Copy,Default)]
+struct MBInfo {
+    lf_level:   u8,
+    upd_golden: bool,
+}
+
+/// Finds the nearest and near motion vector candidates and the scores for the mode contexts.
+///
+/// Candidates are searched in twelve positions up to two macroblocks away. Positions are
+/// calculated as if the frame had an additional macroblock column so the left candidates for the
+/// first macroblock in a row may come from the end of the row above. Scores are returned in zero,
+/// nearest and near order.
+pub(super) fn find_mv_pred(mvs: &[[MV; 16]], mb_w: usize, mb_x: usize, mb_y: usize, profile: u8) -> ([MV; 2], [usize; 3]) {
+    let mut near_mvs = [ZERO_MV; 2];
+    let mut cnt = [0; 3];
+    let vwidth = (mb_w + 1) as isize;
+    let boundary = if profile == 0 { 1 } else { 0 };
+    for pos in MV_PRED_POS.iter() {
+        let idx = (mb_y as isize + pos.yoff) * vwidth + (mb_x as isize) + pos.xoff;
+        let mv = if idx >= boundary && idx % vwidth != vwidth - 1 {
+                let (x, y) = ((idx % vwidth) as usize, (idx / vwidth) as usize);
+                mvs[x + y * mb_w][pos.subblk]
+            } else {
+                ZERO_MV
+            };
+        let slot = if mv == ZERO_MV {
+                0
+            } else if near_mvs[0] == ZERO_MV || near_mvs[0] == mv {
+                near_mvs[0] = mv;
+                1
+            } else if near_mvs[1] == ZERO_MV || near_mvs[1] == mv {
+                near_mvs[1] = mv;
+                2
+            } else {
+                continue;
+            };
+        cnt[slot] += pos.score;
+    }
+    (near_mvs, cnt)
+}
+
+/// Updates inter frame Y2 DC prediction and returns the predicted DC value.
+///
+/// The prediction kicks in only after the same DC value has been repeated several times.
+pub(super) fn predict_inter_dc(pred: &mut [i16; 2], dc: i16) -> i16 {
+    let dc = if pred[1] > 3 { dc.wrapping_add(pred[0]) } else { dc };
+    if pred[0] == 0 || dc == 0 || (pred[0] ^ dc) < 0 {
+        pred[1] = 0;
+    } else if pred[0] == dc {
+        pred[1] = pred[1].wrapping_add(1);
+    }
+    pred[0] = dc;
+    dc
+}
+
+fn add_residual(dst: &mut [u8], off: usize, stride: usize, coeffs: &mut [i16; 16]) {
+    if coeffs.iter().any(|&c| c != 0) {
+        vp7_idct(coeffs);
+        add_coeffs4x4(dst, off, stride, coeffs);
+    }
+}
+
+struct VP7Decoder {
+    info:           NACodecInfoRef,
+    width:          usize,
+    height:         usize,
+    mb_w:           usize,
+    mb_h:           usize,
+    profile:        u8,
+    shuf:           VPShuffler,
+
+    models:         ProbModels,
+    features:       [Option<MBFeature>; NUM_FEATURES],
+    // Y AC quantiser index followed by optional explicit Y DC, Y2 DC, Y2 AC, chroma DC and chroma AC ones
+    qidx:           [Option<u8>; 6],
+    lf_simple:      bool,
+    lf_level:       u8,
+    lf_sharpness:   u8,
+    prob_intra:     u8,
+    prob_last:      u8,
+    fade_present:   bool,
+    dc_pred:        [[i16; 2]; 2],
+
+    mb_info:        Vec<MBInfo>,
+    mvs:            Vec<[MV; 16]>,
+    top_bmodes:     Vec<PredMode>,
+    left_bmodes:    [PredMode; 4],
+    top_nz:         Vec<[bool; 9]>,
+    left_nz:        [bool; 9],
+    coeffs:         [[i16; 16]; 25],
+    mc_buf:         [u8; MC_BUF_SIZE],
+}
+
+impl VP7Decoder {
+    fn new() -> Self {
+        Self {
+            info:           NACodecInfoRef::default(),
+            width:          0,
+            height:         0,
+            mb_w:           0,
+            mb_h:           0,
+            profile:        0,
+            shuf:           VPShuffler::new(),
+
+            models:         ProbModels::default(),
+            features:       [None; NUM_FEATURES],
+            qidx:           [None; 6],
+            lf_simple:      false,
+            lf_level:       0,
+            lf_sharpness:   0,
+            prob_intra:     0,
+            prob_last:      0,
+            fade_present:   true,
+            dc_pred:        [[0; 2]; 2],
+
+            mb_info:        Vec::new(),
+            mvs:            Vec::new(),
+            top_bmodes:     Vec::new(),
+            left_bmodes:    [PredMode::DCPred; 4],
+            top_nz:         Vec::new(),
+            left_nz:        [false; 9],
+            coeffs:         [[0; 16]; 25],
+            mc_buf:         [0; MC_BUF_SIZE],
+        }
+    }
+    fn set_dimensions(&mut self, supp: &mut NADecoderSupport, width: usize, height: usize) -> DecoderResult<()> {
+        if !supp.limits.check_dimensions(width, height) {
+            return Err(DecoderError::LimitExceeded);
+        }
+        if width == self.width && height == self.height {
+            return Ok(());
+        }
+        self.width  = width;
+        self.height = height;
+        self.mb_w   = width.div_ceil(16);
+        self.mb_h   = height.div_ceil(16);
+        let nmbs = self.mb_w * self.mb_h;
+        self.mb_info.clear();
+        self.mb_info.resize(nmbs, MBInfo::default());
+        self.mvs.clear();
+        self.mvs.resize(nmbs, [ZERO_MV; 16]);
+        self.top_bmodes.clear();
+        self.top_bmodes.resize(self.mb_w * 4, PredMode::DCPred);
+        self.top_nz.clear();
+        self.top_nz.resize(self.mb_w, [false; 9]);
+        self.shuf.clear();
+
+        let vinfo = self.get_vinfo();
+        self.info = self.info.replace_info(NACodecTypeInfo::Video(vinfo));
+        Ok(())
+    }
+    fn get_vinfo(&self) -> NAVideoInfo {
+        NAVideoInfo::new(self.width, self.height, false, YUV420_FORMAT)
+    }
+    fn read_features(&mut self, bc: &mut BoolCoder) {
+        let feature_bits = &FEATURE_BITS[usize::from(self.profile)];
+        for (feat, &bits) in self.features.iter_mut().zip(feature_bits.iter()) {
+            *feat = if bc.read_bool() {
+                    let mut feature = MBFeature { present_prob: bc.read_byte(), ..MBFeature::default() };
+                    for prob in feature.tree_probs.iter_mut() {
+                        *prob = if bc.read_bool() { bc.read_byte() } else { 255 };
+                    }
+                    if bits > 0 {
+                        for val in feature.values.iter_mut() {
+                            *val = if bc.read_bool() { bc.read_bits(bits) as u8 } else { 0 };
+                        }
+                    }
+                    Some(feature)
+                } else {
+                    None
+                };
+        }
+    }
+    fn read_quants(&mut self, bc: &mut BoolCoder) {
+        self.qidx[0] = Some(bc.read_bits(7) as u8);
+        for qidx in self.qidx[1..].iter_mut() {
+            *qidx = if bc.read_bool() { Some(bc.read_bits(7) as u8) } else { None };
+        }
+    }
+    // macroblock quantiser feature replaces only the base index, explicitly coded ones stay the same
+    fn get_quants(&self, base: Option<u8>) -> QuantSet {
+        let base = base.or(self.qidx[0]).map_or(0, usize::from);
+        let idx = |i: usize| self.qidx[i].map_or(base, usize::from);
+        QuantSet {
+            y:  [Y_DC_QUANTS[idx(1)], Y_AC_QUANTS[base]],
+            y2: [Y2_DC_QUANTS[idx(2)], Y2_AC_QUANTS[idx(3)]],
+            uv: [Y_DC_QUANTS[idx(4)].min(132), Y_AC_QUANTS[idx(5)]],
+        }
+    }
+    fn read_inter_probs(&mut self, bc: &mut BoolCoder) {
+        self.prob_intra = bc.read_byte();
+        self.prob_last  = bc.read_byte();
+        if bc.read_bool() {
+            for prob in self.models.y_mode_probs.iter_mut() {
+                *prob = bc.read_byte();
+            }
+        }
+        if bc.read_bool() {
+            for prob in self.models.uv_mode_probs.iter_mut() {
+                *prob = bc.read_byte();
+            }
+        }
+        for (probs, upd_probs) in self.models.mv_probs.iter_mut().zip(MV_UPDATE_PROBS.iter()) {
+            for (prob, &upd_prob) in probs.iter_mut().zip(upd_probs.iter()) {
+                if bc.read_prob(upd_prob) {
+                    *prob = bc.read_probability();
+                }
+            }
+        }
+    }
+    fn read_mv(bc: &mut BoolCoder, probs: &[[u8; 17]; 2]) -> MV {
+        let y = read_mv_component(bc, &probs[0], 8);
+        let x = read_mv_component(bc, &probs[1], 8);
+        MV { x, y }
+    }
+    fn read_split_mvs(&self, bc: &mut BoolCoder, mb_x: usize, mb_y: usize) -> [MV; 16] {
+        let mb_idx = mb_x + mb_y * self.mb_w;
+        let left_mvs  = if mb_x > 0 { self.mvs[mb_idx - 1] } else { [ZERO_MV; 16] };
+        let above_mvs = if mb_y > 0 { self.mvs[mb_idx - self.mb_w] } else { [ZERO_MV; 16] };
+        let split = read_mv_split_mode(bc) as usize;
+        let mut mvs = [ZERO_MV; 16];
+        for (part, &blk) in MV_PARTITION_FIRST_BLOCK[split].iter().enumerate() {
+            let left  = if (blk & 3) != 0 { mvs[blk - 1] } else { left_mvs[blk + 3] };
+            let above = if blk >= 4 { mvs[blk - 4] } else { above_mvs[blk + 12] };
+            let mv = match read_sub_mv_ref(bc, &SUB_MV_REF_PROBS) {
+                    SubMVRef::Left  => left,
+                    SubMVRef::Above => above,
+                    SubMVRef::Zero  => ZERO_MV,
+                    SubMVRef::New   => Self::read_mv(bc, &self.models.mv_probs),
+                };
+            for (dst, &bpart) in mvs.iter_mut().zip(MV_PARTITIONS[split].iter()) {
+                if usize::from(bpart) == part {
+                    *dst = mv;
+                }
+            }
+        }
+        mvs
+    }
+    fn read_mb_mvs(&mut self, bc: &mut BoolCoder, mb_x: usize, mb_y: usize) -> [MV; 16] {
+        let mb_idx = mb_x + mb_y * self.mb_w;
+        // current macroblock may be referenced by the prediction in single macroblock wide frames
+        self.mvs[mb_idx] = [ZERO_MV; 16];
+        let (near_mvs, cnt) = find_mv_pred(&self.mvs, self.mb_w, mb_x, mb_y, self.profile);
+        let mv = if !bc.read_prob(MODE_CONTEXTS[cnt[0]][0]) {
+                ZERO_MV
+            } else if !bc.read_prob(MODE_CONTEXTS[cnt[1]][1]) {
+                near_mvs[0]
+            } else if !bc.read_prob(MODE_CONTEXTS[cnt[2]][2]) {
+                near_mvs[1]
+            } else if !bc.read_prob(MODE_CONTEXTS[cnt[2]][3]) {
+                Self::read_mv(bc, &self.models.mv_probs)
+            } else {
+                return self.read_split_mvs(bc, mb_x, mb_y);
+            };
+        [mv; 16]
+    }
+    #[allow(clippy::too_many_arguments)]
+    fn decode_mb(&mut self, bc: &mut BoolCoder, bc2: &mut BoolCoder, frm: &mut NASimpleVideoFrame<u8>, refs: &[Option<NAVideoBufferRef<u8>>; 2], mb_x: usize, mb_y: usize, is_intra: bool) -> DecoderResult<()> {
+        let mb_idx = mb_x + mb_y * self.mb_w;
+
+        let mut feat_vals = [None; NUM_FEATURES];
+        for (val, feat) in feat_vals.iter_mut().zip(self.features.iter()) {
+            if let Some(feat) = feat {
+                if bc.read_prob(feat.present_prob) {
+                    let probs = &feat.tree_probs;
+                    let idx = vp_tree!(bc, probs[0], vp_tree!(bc, probs[1], 0, 1), vp_tree!(bc, probs[2], 2, 3));
+                    *val = Some(feat.values[idx]);
+                }
+            }
+        }
+        self.mb_info[mb_idx] = MBInfo {
+                lf_level:   feat_vals[FEATURE_LF_LEVEL].unwrap_or(self.lf_level),
+                upd_golden: feat_vals[FEATURE_GOLDEN].is_some(),
+            };
+
+        let mut bmodes = [PredMode::DCPred; 16];
+        let mut mvs = [ZERO_MV; 16];
+        let mut ymode = PredMode::Inter;
+        let mut uvmode = PredMode::Inter;
+        let mut rframe = VP8Ref::Intra;
+        if is_intra || !bc.read_prob(self.prob_intra) {
+            ymode = if is_intra { read_kf_y_mode(bc) } else { read_y_mode(bc, &self.models.y_mode_probs) };
+            if ymode == PredMode::BPred {
+                for i in 0..16 {
+                    bmodes[i] = if is_intra {
+                            let top  = if i >= 4 { bmodes[i - 4] } else { self.top_bmodes[mb_x * 4 + i] };
+                            let left = if (i & 3) != 0 { bmodes[i - 1] } else { self.left_bmodes[i >> 2] };
+                            read_b_mode(bc, &KF_B_MODE_PROBS[top.to_b_index()][left.to_b_index()])
+                        } else {
+                            read_b_mode(bc, &B_MODE_PROBS)
+                        };
+                }
+            } else {
+                // implied sub-block modes for the context of the following macroblocks
+                let bmode = if ymode == PredMode::DCPred { PredMode::DCPred } else { PredMode::TMPred };
+                bmodes = [bmode; 16];
+            }
+            uvmode = read_uv_mode(bc, if is_intra { &KF_UV_MODE_PROBS } else { &self.models.uv_mode_probs });
+            self.mvs[mb_idx] = mvs;
+        } else {
+            rframe = if bc.read_prob(self.prob_last) { VP8Ref::Golden } else { VP8Ref::Last };
+            mvs = self.read_mb_mvs(bc, mb_x, mb_y);
+            self.mvs[mb_idx] = mvs;
+        }
+        if is_intra {
+            self.top_bmodes[mb_x * 4..][..4].copy_from_slice(&bmodes[12..]);
+            for (left, row) in self.left_bmodes.iter_mut().zip(bmodes.chunks_exact(4)) {
+                *left = row[3];
+            }
+        }
+
+        let has_y2 = ymode != PredMode::BPred;
+        let quants = self.get_quants(feat_vals[FEATURE_QUANT]);
+        decode_mb_coeffs(bc2, &mut self.coeffs, &self.models.coef_probs, &mut self.top_nz[mb_x], &mut self.left_nz, has_y2, &quants, &self.models.scan, true);
+        if rframe != VP8Ref::Intra {
+            let pred = &mut self.dc_pred[if rframe == VP8Ref::Last { 0 } else { 1 }];
+            self.coeffs[Y2_BLK][0] = predict_inter_dc(pred, self.coeffs[Y2_BLK][0]);
+        }
+        if has_y2 && self.coeffs[Y2_BLK].iter().any(|&c| c != 0) {
+            vp7_iwht(&mut self.coeffs);
+        }
+
+        let ystride = frm.stride[0];
+        let yoff = frm.offset[0] + mb_x * 16 + mb_y * 16 * ystride;
+        let (has_top, has_left) = (mb_y > 0, mb_x > 0);
+        match ymode {
+            PredMode::BPred => {
+                let mut mbctx = IPredContext::default();
+                mbctx.fill(frm.data, yoff, ystride, 16, has_top, has_left, EDGE_PIXELS);
+                if !has_top {
+                    mbctx.top[16..].copy_from_slice(&[EDGE_PIXELS[0]; 4]);
+                } else if mb_x + 1 < self.mb_w {
+                    mbctx.top[16..].copy_from_slice(&frm.data[yoff - ystride + 16..][..4]);
+                } else {
+                    let last = frm.data[yoff - ystride + 15];
+                    mbctx.top[16..].copy_from_slice(&[last; 4]);
+                }
+                for (i, (&bmode, blk)) in bmodes.iter().zip(self.coeffs.iter_mut()).enumerate() {
+                    let (x, y) = (i & 3, i >> 2);
+                    let off = yoff + x * 4 + y * 4 * ystride;
+                    let mut ctx = IPredContext { has_top: true, has_left: true, ..IPredContext::default() };
+                    if y == 0 {
+                        ctx.top[..8].copy_from_slice(&mbctx.top[x * 4..][..8]);
+                    } else {
+                        ctx.top[..4].copy_from_slice(&frm.data[off - ystride..][..4]);
+                        if x < 3 {
+                            ctx.top[4..8].copy_from_slice(&frm.data[off - ystride + 4..][..4]);
+                        } else {
+                            ctx.top[4..8].copy_from_slice(&mbctx.top[16..]);
+                        }
+                    }
+                    if x == 0 {
+                        ctx.left[..4].copy_from_slice(&mbctx.left[y * 4..][..4]);
+                    } else {
+                        for (el, line) in ctx.left[..4].iter_mut().zip(frm.data[off - 1..].chunks(ystride)) {
+                            *el = line[0];
+                        }
+                    }
+                    ctx.tl = match (x, y) {
+                            (0, 0) => mbctx.tl,
+                            (_, 0) => mbctx.top[x * 4 - 1],
+                            (0, _) => mbctx.left[y * 4 - 1],
+                            _      => frm.data[off - ystride - 1],
+                        };
+                    ipred_4x4(frm.data, off, ystride, bmode, &ctx);
+                    add_residual(frm.data, off, ystride, blk);
+                }
+            },
+            PredMode::Inter => {
+                let refbuf = refs[if rframe == VP8Ref::Last { 0 } else { 1 }].as_ref().ok_or(DecoderError::MissingReference)?;
+                for (i, (mv, blk)) in mvs.iter().zip(self.coeffs.iter_mut()).enumerate() {
+                    let (x, y) = (i & 3, i >> 2);
+                    let off = yoff + x * 4 + y * 4 * ystride;
+                    mc_block(frm.data, off, ystride, mb_x * 16 + x * 4, mb_y * 16 + y * 4, mv.x * 2, mv.y * 2, refbuf, 0, 4, 4, &mut self.mc_buf);
+                    add_residual(frm.data, off, ystride, blk);
+                }
+            },
+            _ => {
+                let mut ctx = IPredContext::default();
+                ctx.fill(frm.data, yoff, ystride, 16, has_top, has_left, EDGE_PIXELS);
+                ipred_mb(frm.data, yoff, ystride, 16, ymode, &ctx);
+                for (i, blk) in self.coeffs[..16].iter_mut().enumerate() {
+                    let (x, y) = (i & 3, i >> 2);
+                    add_residual(frm.data, yoff + x * 4 + y * 4 * ystride, ystride, blk);
+                }
+            },
+        }
+
+        for plane in 1..3 {
+            let cstride = frm.stride[plane];
+            let coff = frm.offset[plane] + mb_x * 8 + mb_y * 8 * cstride;
+            if rframe != VP8Ref::Intra {
+                let refbuf = refs[if rframe == VP8Ref::Last { 0 } else { 1 }].as_ref().ok_or(DecoderError::MissingReference)?;
+                for y in 0..2 {
+                    for x in 0..2 {
+                        let mut sum_x = 0;
+                        let mut sum_y = 0;
+                        for &blk in [0, 1, 4, 5].iter() {
+                            let mv = mvs[blk + x * 2 + y * 8];
+                            sum_x += mv.x;
+                            sum_y += mv.y;
+                        }
+                        let cmv_x = (sum_x + 2 - i16::from(sum_x < 0)) >> 2;
+                        let cmv_y = (sum_y + 2 - i16::from(sum_y < 0)) >> 2;
+                        mc_block(frm.data, coff + x * 4 + y * 4 * cstride, cstride, mb_x * 8 + x * 4, mb_y * 8 + y * 4, cmv_x, cmv_y, refbuf, plane, 4, 4, &mut self.mc_buf);
+                    }
+                }
+            } else {
+                let mut ctx = IPredContext::default();
+                ctx.fill(frm.data, coff, cstride, 8, has_top, has_left, EDGE_PIXELS);
+                ipred_mb(frm.data, coff, cstride, 8, uvmode, &ctx);
+            }
+            for (i, blk) in self.coeffs[12 + plane * 4..][..4].iter_mut().enumerate() {
+                let (x, y) = (i & 1, i >> 1);
+                add_residual(frm.data, coff + x * 4 + y * 4 * cstride, cstride, blk);
+            }
+        }
+        Ok(())
+    }
+    fn loop_filter_frame(&self, frm: &mut NASimpleVideoFrame<u8>, is_intra: bool) {
+        for mb_y in 0..self.mb_h {
+            for mb_x in 0..self.mb_w {
+                let level = self.mb_info[mb_x + mb_y * self.mb_w].lf_level;
+                if level == 0 {
+                    continue;
+                }
+                let (inner_limit, hev_thr) = loop_filter_params(level, self.lf_sharpness, is_intra);
+                let (inner_limit, hev_thr) = (i16::from(inner_limit), i16::from(hev_thr));
+                let level = i16::from(level);
+                let mbedge_limit = level + 2;
+                let bedge_limit_y = level;
+                let bedge_limit_uv = level * 2;
+
+                let ystride = frm.stride[0];
+                let yoff = frm.offset[0] + mb_x * 16 + mb_y * 16 * ystride;
+                if self.lf_simple {
+                    if mb_x > 0 {
+                        loop_filter_simple(frm.data, yoff, 1, ystride, 16, mbedge_limit, LFVariant::VP7);
+                    }
+                    for x in (4..16).step_by(4) {
+                        loop_filter_simple(frm.data, yoff + x, 1, ystride, 16, bedge_limit_y, LFVariant::VP7);
+                    }
+                    if mb_y > 0 {
+                        loop_filter_simple(frm.data, yoff, ystride, 1, 16, mbedge_limit, LFVariant::VP7);
+                    }
+                    for y in (4..16).step_by(4) {
+                        loop_filter_simple(frm.data, yoff + y * ystride, ystride, 1, 16, bedge_limit_y, LFVariant::VP7);
+                    }
+                    continue;
+                }
+                // VP7 filters macroblock edges first and then horizontal and vertical inner edges
+                for plane in 0..3 {
+                    let stride = frm.stride[plane];
+                    let (size, bedge_limit) = if plane == 0 { (16, bedge_limit_y) } else { (8, bedge_limit_uv) };
+                    let off = frm.offset[plane] + mb_x * size + mb_y * size * stride;
+                    if mb_x > 0 {
+                        loop_filter_mb_edge(frm.data, off, 1, stride, size, mbedge_limit, inner_limit, hev_thr, LFVariant::VP7);
+                    }
+                    if mb_y > 0 {
+                        loop_filter_mb_edge(frm.data, off, stride, 1, size, mbedge_limit, inner_limit, hev_thr, LFVariant::VP7);
+                    }
+                    for y in (4..size).step_by(4) {
+                        loop_filter_inner_edge(frm.data, off + y * stride, stride, 1, size, bedge_limit, inner_limit, hev_thr, LFVariant::VP7);
+                    }
+                    for x in (4..size).step_by(4) {
+                        loop_filter_inner_edge(frm.data, off + x, 1, stride, size, bedge_limit, inner_limit, hev_thr, LFVariant::VP7);
+                    }
+                }
+            }
+        }
+    }
+    // copies macroblocks marked with the golden frame update feature into a new golden frame
+    fn update_golden_partially(&self, frm: &NAVideoBuffer<u8>, golden: &NAVideoBuffer<u8>) -> NAVideoBufferRef<u8> {
+        let mut dst = golden.copy_buffer();
+        let strides = [dst.get_stride(0), dst.get_stride(1), dst.get_stride(2)];
+        let offsets = [dst.get_offset(0), dst.get_offset(1), dst.get_offset(2)];
+        let src = frm.get_data();
+        let ddata = dst.get_data_mut().unwrap();
+        for (mb_idx, mb) in self.mb_info.iter().enumerate() {
+            if !mb.upd_golden {
+                continue;
+            }
+            let (mb_x, mb_y) = (mb_idx % self.mb_w, mb_idx / self.mb_w);
+            for plane in 0..3 {
+                let size = if plane == 0 { 16 } else { 8 };
+                let stride = strides[plane];
+                let off = offsets[plane] + mb_x * size + mb_y * size * stride;
+                for (dline, sline) in ddata[off..].chunks_mut(stride).zip(src[off..].chunks(stride)).take(size) {
+                    dline[..size].copy_from_slice(&sline[..size]);
+                }
+            }
+        }
+        dst.into_ref()
+    }
+    fn decode_frame(&mut self, supp: &mut NADecoderSupport, src: &[u8]) -> DecoderResult<(NABufferType, FrameType)> {
+        if !supp.limits.check_packet_size(src.len()) {
+            return Err(DecoderError::LimitExceeded);
+        }
+        if src.is_empty() {
+            if !self.shuf.has_refs() {
+                return Err(DecoderError::MissingReference);
+            }
+            return Ok((NABufferType::None, FrameType::Skip));
+        }
+        validate!(src.len() > 4);
+        let is_intra = (src[0] & 1) == 0;
+        let profile = (src[0] >> 1) & 7;
+        if profile > 1 {
+            return Err(DecoderError::NotImplemented);
+        }
+        let part1_size = ((u32::from(src[0]) | (u32::from(src[1]) << 8) | (u32::from(src[2]) << 16)) >> 4) as usize;
+        // the frame tag is shortened in the advanced profile
+        let hdr_size = 4 - usize::from(profile);
+        validate!(hdr_size + part1_size < src.len());
+        let mut bc = BoolCoder::new(&src[hdr_size..][..part1_size])?;
+
+        if is_intra {
+            let width   = bc.read_bits(12) as usize;
+            let height  = bc.read_bits(12) as usize;
+            let _hscale = bc.read_bits(2);
+            let _vscale = bc.read_bits(2);
+            validate!(width > 0 && height > 0);
+            self.set_dimensions(supp, width, height)?;
+            self.models = ProbModels::default();
+        } else if !self.shuf.has_refs() {
+            return Err(DecoderError::MissingReference);
+        }
+        self.profile = profile;
+        if is_intra || profile > 0 {
+            self.dc_pred = [[0; 2]; 2];
+        }
+        self.read_features(&mut bc);
+        self.read_quants(&mut bc);
+        let update_golden = is_intra || bc.read_bool();
+        let mut saved_models = None;
+        if profile > 0 {
+            if !bc.read_bool() {
+                saved_models = Some(self.models);
+            }
+            if !is_intra {
+                self.fade_present = bc.read_bool();
+            }
+        }
+        let (alpha, beta) = if self.fade_present && bc.read_bool() {
+                (bc.read_byte() as i8, bc.read_byte() as i8)
+            } else {
+                (0, 0)
+            };
+        if profile == 0 {
+            self.lf_simple = bc.read_bool();
+        }
+        if bc.read_bool() {
+            for el in self.models.scan[1..].iter_mut() {
+                *el = DEFAULT_SCAN_ORDER[bc.read_bits(4) as usize];
+            }
+        }
+        if profile > 0 {
+            self.lf_simple = bc.read_bool();
+        }
+        self.lf_level       = bc.read_bits(6) as u8;
+        self.lf_sharpness   = bc.read_bits(3) as u8;
+        update_coef_probs(&mut bc, &mut self.models.coef_probs);
+        if !is_intra {
+            self.read_inter_probs(&mut bc);
+        }
+
+        let vinfo = self.get_vinfo();
+        let mut buf = supp.pool_u8.get_free();
+        if buf.as_ref().map(|buf| buf.get_info()) != Some(vinfo) {
+            self.shuf.clear();
+            if !is_intra {
+                return Err(DecoderError::MissingReference);
+            }
+            supp.pool_u8.reset();
+            supp.pool_u8.set_limits(supp.limits);
+            supp.pool_u8.prealloc_video(vinfo, 4)?;
+            buf = supp.pool_u8.get_free();
+        }
+        let mut buf = buf.ok_or(DecoderError::AllocError)?;
+
+        let mut refs = [None, None];
+        if !is_intra {
+            let mut last = self.shuf.get_last().ok_or(DecoderError::MissingReference)?;
+            if alpha != 0 || beta != 0 {
+                // the faded frame serves only as a reference for the current frame
+                let mut faded = last.copy_buffer();
+                let (off, stride) = (faded.get_offset(0), faded.get_stride(0));
+                vp7_fade(faded.get_data_mut().unwrap(), off, stride, self.mb_w * 16, self.mb_h * 16, alpha, beta);
+                last = faded.into_ref();
+            }
+            refs = [Some(last), self.shuf.get_golden()];
+        }
+
+        let mut bc2 = BoolCoder::new(&src[hdr_size + part1_size..])?;
+        let mut frm = buf.as_mut().and_then(NASimpleVideoFrame::from_video_buf).ok_or(DecoderError::AllocError)?;
+        if is_intra {
+            for el in self.top_bmodes.iter_mut() {
+                *el = PredMode::DCPred;
+            }
+        }
+        for el in self.top_nz.iter_mut() {
+            *el = [false; 9];
+        }
+        for mb_y in 0..self.mb_h {
+            self.left_bmodes = [PredMode::DCPred; 4];
+            self.left_nz = [false; 9];
+            for mb_x in 0..self.mb_w {
+                self.decode_mb(&mut bc, &mut bc2, &mut frm, &refs, mb_x, mb_y, is_intra)?;
+            }
+        }
+        self.loop_filter_frame(&mut frm, is_intra);
+
+        if update_golden {
+            self.shuf.add_golden_frame(buf.clone());
+        } else if self.mb_info.iter().any(|mb| mb.upd_golden) {
+            let golden = self.shuf.get_golden().ok_or(DecoderError::MissingReference)?;
+            let new_golden = self.update_golden_partially(&buf, &golden);
+            self.shuf.add_golden_frame(new_golden);
+        }
+        self.shuf.add_frame(buf.clone());
+        if let Some(models) = saved_models {
+            self.models = models;
+        }
+        Ok((NABufferType::Video(buf), if is_intra { FrameType::I } else { FrameType::P }))
+    }
+}
+
+impl NADecoder for VP7Decoder {
+    fn init(&mut self, supp: &mut NADecoderSupport, info: NACodecInfoRef) -> DecoderResult<()> {
+        if let NACodecTypeInfo::Video(vinfo) = info.get_properties() {
+            let myvinfo = NAVideoInfo::new(vinfo.get_width(), vinfo.get_height(), false, YUV420_FORMAT);
+            self.info = NACodecInfo::new_ref(info.get_name(), NACodecTypeInfo::Video(myvinfo), info.get_extradata()).into_ref();
+            supp.pool_u8.set_limits(supp.limits);
+            supp.pool_u8.set_dec_bufs(3);
+            Ok(())
+        } else {
+            Err(DecoderError::InvalidData)
+        }
+    }
+    fn decode(&mut self, supp: &mut NADecoderSupport, pkt: &NAPacket) -> DecoderResult<NAFrameRef> {
+        let src = pkt.get_buffer();
+        let (bufinfo, ftype) = self.decode_frame(supp, &src)?;
+        let mut frm = NAFrame::new_from_pkt(pkt, self.info.clone(), bufinfo);
+        frm.set_keyframe(ftype == FrameType::I);
+        frm.set_frame_type(ftype);
+        Ok(frm.into_ref())
+    }
+    fn flush(&mut self) {
+        self.shuf.clear();
+    }
+}
+
+pub fn get_decoder_vp7() -> Box<dyn NADecoder + Send> {
+    Box::new(VP7Decoder::new())
+}
+
+#[cfg(test)]
+mod test {
+    use nihav_core::codecs::*;
+    use nihav_codec_support::test::dec_video::*;
+    use super::*;
+
+    fn asset_name(name: &str) -> String {
+        format!("{}/assets/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+    // Both fixtures come from vp7synth and the hashes match its own reconstruction,
+    // no real VP7 sample or reference decoder output has been available to check against.
+    fn test_vp7(name: &str, expected: &[Vec<[u32; 4]>]) {
+        let mut dec_reg = RegisteredDecoders::new();
+        crate::codecs::duck_register_all_decoders(&mut dec_reg);
+        let mut dec = (dec_reg.find_decoder("vp7").unwrap())();
+        let mut supp = NADecoderSupport::new();
+        let info = NACodecInfo::new("vp7", NACodecTypeInfo::Video(NAVideoInfo::new(0, 0, false, YUV420_FORMAT)), None).into_ref();
+        dec.init(&mut supp, info).unwrap();
+        let mut pts = 0;
+        test_decoding_md5(&asset_name(name), |src| {
+                let pkt = NAPacket::new(0, NATimeInfo::new(Some(pts), None, Some(1), 1, 30), false, src.to_vec());
+                pts += 1;
+                let frm = dec.decode(&mut supp, &pkt)?;
+                let vinfo = frm.get_info().get_properties().get_video_info().unwrap();
+                assert_eq!((vinfo.get_width(), vinfo.get_height()), (56, 40));
+                Ok(frm.get_buffer())
+            }, expected);
+    }
+    #[test]
+    fn test_vp7_mv_pred() {
+        let mb_w = 3;
+        let mut mvs = vec![[ZERO_MV; 16]; mb_w * 3];
+        let mv1 = MV { x: 4, y: -2 };
+        let mv2 = MV { x: -8, y: 6 };
+        // above macroblock bottom-left sub-block and left macroblock top-right one
+        mvs[1][12] = mv1;
+        mvs[3][3] = mv2;
+        let (near_mvs, cnt) = find_mv_pred(&mvs, mb_w, 1, 1, 1);
+        assert_eq!(near_mvs, [mv1, mv2]);
+        assert_eq!(cnt, [11, 2, 2]);
+        // the last macroblock of the previous row is a left candidate for the first one in the next row
+        let mut mvs = vec![[ZERO_MV; 16]; mb_w * 3];
+        mvs[2][3] = mv1;
+        let (near_mvs, cnt) = find_mv_pred(&mvs, mb_w, 0, 1, 1);
+        assert_eq!(near_mvs, [mv1, ZERO_MV]);
+        assert_eq!(cnt, [14, 1, 0]);
+    }
+    #[test]
+    fn test_vp7_dc_pred() {
+        let mut pred = [0; 2];
+        for _ in 0..5 {
+            assert_eq!(predict_inter_dc(&mut pred, 10), 10);
+        }
+        assert_eq!(pred, [10, 4]);
+        // the prediction is added once the same value was repeated enough times
+        assert_eq!(predict_inter_dc(&mut pred, 0), 10);
+        assert_eq!(predict_inter_dc(&mut pred, 3), 13);
+        assert_eq!(pred, [13, 5]);
+        // and it is reset only when the sign changes or the value becomes zero
+        assert_eq!(predict_inter_dc(&mut pred, -20), -7);
+        assert_eq!(pred, [-7, 0]);
+    }
+    #[test]
+    fn test_vp7_skip() {
+        let pkts = read_raw_packets(&asset_name("vp7.vp7"));
+        let mut supp = NADecoderSupport::new();
+        let mut dec = VP7Decoder::new();
+        let info = NACodecInfo::new("vp7", NACodecTypeInfo::Video(NAVideoInfo::new(0, 0, false, YUV420_FORMAT)), None).into_ref();
+        dec.init(&mut supp, info).unwrap();
+        assert_eq!(dec.decode_frame(&mut supp, &[]).err(), Some(DecoderError::MissingReference));
+        assert_eq!(dec.decode_frame(&mut supp, &pkts[1]).err(), Some(DecoderError::MissingReference));
+        let (_, ftype) = dec.decode_frame(&mut supp, &pkts[0]).unwrap();
+        assert_eq!(ftype, FrameType::I);
+        let (buf, ftype) = dec.decode_frame(&mut supp, &[]).unwrap();
+        assert!(matches!(buf, NABufferType::None));
+        assert_eq!(ftype, FrameType::Skip);
+        let (_, ftype) = dec.decode_frame(&mut supp, &pkts[1]).unwrap();
+        assert_eq!(ftype, FrameType::P);
+        dec.flush();
+        assert_eq!(dec.decode_frame(&mut supp, &pkts[2]).err(), Some(DecoderError::MissingReference));
+
+        let mut hdr = pkts[0].clone();
+        hdr[0] |= 2 << 1;
+        assert_eq!(dec.decode_frame(&mut supp, &hdr).err(), Some(DecoderError::NotImplemented));
+        supp.limits.max_width = 32;
+        assert_eq!(dec.decode_frame(&mut supp, &pkts[0]).err(), Some(DecoderError::LimitExceeded));
+    }
+    #[test]
+    fn test_vp7_corrupted() {
+        let pkts = read_raw_packets(&asset_name("vp7_advanced.vp7"));
+        let mut supp = NADecoderSupport::new();
+        let mut dec = VP7Decoder::new();
+        for (i, pkt) in pkts.iter().enumerate() {
+            let mut pkt = pkt.clone();
+            for (j, el) in pkt.iter_mut().enumerate().skip(4) {
+                if (j + i) % 7 == 0 {
+                    *el = el.wrapping_mul(37).wrapping_add(11);
+                }
+            }
+            let _ = dec.decode_frame(&mut supp, &pkt);
+            pkt.truncate(pkt.len() / 2);
+            let _ = dec.decode_frame(&mut supp, &pkt);
+        }
+    }
+    #[test]
+    fn test_vp7_simple() {
+        test_vp7("vp7.vp7", &[
+            vec![[0x5bd9d1f6, 0x0c1b9885, 0x40e01756, 0x2d181fed], [0x90eaf60c, 0x8a18fc1e, 0x5c7475ef, 0xcf50a709], [0x46a63a49, 0xb6f62e31, 0x71386abb, 0x634b9ea7]],
+            vec![[0x9bd276f8, 0xe1ce3268, 0xc76468a9, 0xf67d026c], [0x1e43dd7d, 0x75c03cc7, 0x9a75ecab, 0xac77b1ab], [0x75276d8b, 0xd88f3ce8, 0xd04f13c6, 0x23caf14f]],
+            vec![[0x77948950, 0xb34e7f74, 0xfb8c1a1d, 0x79d0d42f], [0xc3ed7968, 0xe1a8c4cd, 0x0166e3e2, 0x3c756ab1], [0x50ae186e, 0xeced470f, 0x52a099b6, 0xca33b1f0]],
+            vec![[0xe8c1c1b2, 0x49baf211, 0xc8b012cf, 0x0ea05b02], [0x55102354, 0x55f181cd, 0xd65697cf, 0xff4e1160], [0x54ef1a4b, 0xcc54c770, 0x1600b7fd, 0x4cdb250e]],
+            vec![[0x4ecad09e, 0xa69532fc, 0x179551d1, 0x6ad9b822], [0xbcfa7e90, 0x643ec59e, 0x6662a69c, 0x4011c49a], [0xfaeafa4d, 0x70ff1020, 0x4fd3ba2f, 0x8c33fbe9]],
+            vec![[0xe694a542, 0xa178c6ee, 0xe25d82e2, 0xf10c2c84], [0xe56ddf58, 0x3e9d1412, 0xb17eb793, 0xd333da5a], [0x5259d5bb, 0x5ed91aef, 0x72ae1c12, 0x35c56331]],
+            vec![[0x33922df9, 0x29ca9fdb, 0xd678b805, 0x56d1e2ec], [0xd9918718, 0x0678d900, 0x715ec444, 0x0e7351d0], [0x38ca7623, 0x81e3a732, 0x0f18bd1d, 0x30afee2e]],
+            vec![[0x94cadb14, 0xaba1001f, 0x1f541732, 0x0f654a34], [0xdfb2e0de, 0x1e5eb103, 0xfa61c678, 0x4cb0634c], [0x16c35929, 0xbf7eb68d, 0x30187fe9, 0x7f84caad]],
+            vec![[0x67340ab3, 0x5c6b04f6, 0x1a4a4d38, 0x9c97e9ec], [0x08b8996c, 0x38141394, 0xc989acde, 0x9de813ce], [0xc3b6c46a, 0xe4ce1736, 0x166149f4, 0xe0c85d60]],
+            vec![[0x575ef85f, 0x0be06b6f, 0x87d96a00, 0xb2d9febb], [0xd456923c, 0x2c39cd99, 0x76e428e9, 0x26ca532d], [0x6da1d51f, 0x3261222d, 0x3dd92250, 0xdccb097f]],
+        ]);
+    }
+    #[test]
+    fn test_vp7_advanced() {
+        test_vp7("vp7_advanced.vp7", &[
+            vec![[0x1f7b6db0, 0x63eb7275, 0x312fd075, 0x28541047], [0xd552596f, 0x76701b87, 0xf60d24c9, 0x609cdb44], [0x0f2d7b28, 0xc4802356, 0x7afa26ea, 0x675a09c6]],
+            vec![[0x631faac4, 0x270f084b, 0x8e0900d5, 0x9a8e7197], [0x22cb3d27, 0x9aeebf31, 0x60ca9bd8, 0xdd90219a], [0x651edf1a, 0x6d0123b5, 0x6e502fb4, 0x1f87839d]],
+            vec![[0x8fe0949a, 0x26881a70, 0xf2f2c401, 0x62d947df], [0x0bdac38e, 0x2910934e, 0x5c4d71f1, 0x57bd6eb3], [0xc1744929, 0x62a00c30, 0xff4d68ad, 0xe258b191]],
+            vec![[0x6dc20463, 0x391458f9, 0x949a9940, 0x7c545675], [0xfa84a065, 0xee496b80, 0xb9f255ec, 0x7f917e7a], [0x3957f3f8, 0xef14eaa4, 0xc81ee4f9, 0x706e4534]],
+            vec![[0xc1b26f51, 0x06a3064b, 0x69478805, 0x476c36f0], [0xc6f3556a, 0x236a85e8, 0x9d890f21, 0x33ee8c62], [0x5cc40c02, 0xeed92bc4, 0x61c44d3a, 0x84c8f49b]],
+            vec![[0x927eb6c2, 0x67a8541c, 0x3701cecb, 0x0e80e2b7], [0xb5eb03c0, 0x0aca0ff3, 0x93f9ecfd, 0x198edb49], [0xe65db604, 0x0eb023d8, 0xad4b3a8f, 0xbe185199]],
+            vec![[0xc4243749, 0x29f52736, 0xd059c571, 0xe66e38de], [0xc94eafe2, 0x395ae842, 0x0c3c2797, 0x1cc42a2f], [0x75b65033, 0x09a99804, 0x729be46e, 0xe486b391]],
+            vec![[0xb185e6d3, 0xf4a54780, 0x512b1cb7, 0x964a053d], [0xdebffdc9, 0x3f817ac2, 0xc78b429b, 0x3fb01774], [0x7912dda8, 0x6f815f44, 0x724fcbc8, 0x49674ccb]],
+            vec![[0x2b3b5fa5, 0xbb73120f, 0x25db05b5, 0xddf56874], [0x5683e0ca, 0x2d2f1bb4, 0x5bb6ec43, 0xdb597cff], [0x200010c4, 0x92a5ca74, 0x6ec8f8ca, 0x1f4ba8b1]],
+            vec![[0x4c524a05, 0xac0658ca, 0x3d8db405, 0xbec8fc39], [0x1da10c70, 0xf4477507, 0xf0172002, 0x2e4b8ab1], [0x6e1a9964, 0xf9b356c3, 0xd3f118fb, 0x1d50cda5]],
+        ]);
+    }
+}
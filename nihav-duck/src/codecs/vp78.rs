@@ -0,0 +1,287 @@
+//! Definitions and bitstream parsing functions shared by VP7 and VP8 decoders.
+use super::vpcommon::*;
+use super::vp78data::*;
+
+#[derive(Clone,Copy,Debug,PartialEq,Default)]
+pub enum PredMode {
+    #[default]
+    DCPred,
+    HPred,
+    VPred,
+    TMPred,
+    BPred,
+
+    // sub-block only modes
+    LDPred,
+    RDPred,
+    VRPred,
+    VLPred,
+    HDPred,
+    HUPred,
+
+    Inter,
+}
+
+impl PredMode {
+    // index in the sub-block mode probability tables
+    pub fn to_b_index(self) -> usize {
+        match self {
+            PredMode::DCPred => 0,
+            PredMode::TMPred => 1,
+            PredMode::VPred  => 2,
+            PredMode::HPred  => 3,
+            PredMode::LDPred => 4,
+            PredMode::RDPred => 5,
+            PredMode::VRPred => 6,
+            PredMode::VLPred => 7,
+            PredMode::HDPred => 8,
+            PredMode::HUPred => 9,
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Default)]
+pub enum VP8Ref {
+    #[default]
+    Intra,
+    Last,
+    Golden,
+    AltRef,
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Default)]
+pub enum MVSplitMode {
+    TopBottom,
+    LeftRight,
+    Quarters,
+    Sixteenths,
+    #[default]
+    None,
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum SubMVRef {
+    Left,
+    Above,
+    New,
+    Zero,
+}
+
+pub type CoeffProbs = [[[[u8; 11]; 3]; 8]; 4];
+
+pub const BLK_TYPE_Y_NO_DC: usize = 0;
+pub const BLK_TYPE_Y2: usize      = 1;
+pub const BLK_TYPE_CHROMA: usize  = 2;
+pub const BLK_TYPE_Y: usize       = 3;
+
+// coefficient blocks are stored as 16 luma blocks, 4+4 chroma blocks and Y2 block
+pub const Y2_BLK: usize = 24;
+// non-zero context entries are 4 luma, 2+2 chroma and Y2
+pub const Y2_NZ: usize = 8;
+
+/// Dequantisers (DC and AC) for each block type.
+#[derive(Clone,Copy,Debug,Default,PartialEq)]
+pub struct QuantSet {
+    pub y:  [i16; 2],
+    pub y2: [i16; 2],
+    pub uv: [i16; 2],
+}
+
+pub fn read_kf_y_mode(bc: &mut BoolCoder) -> PredMode {
+    let probs = &KF_Y_MODE_PROBS;
+    vp_tree!(bc, probs[0], PredMode::BPred,
+             vp_tree!(bc, probs[1],
+                      vp_tree!(bc, probs[2], PredMode::DCPred, PredMode::VPred),
+                      vp_tree!(bc, probs[3], PredMode::HPred, PredMode::TMPred)))
+}
+
+pub fn read_y_mode(bc: &mut BoolCoder, probs: &[u8; 4]) -> PredMode {
+    vp_tree!(bc, probs[0], PredMode::DCPred,
+             vp_tree!(bc, probs[1],
+                      vp_tree!(bc, probs[2], PredMode::VPred, PredMode::HPred),
+                      vp_tree!(bc, probs[3], PredMode::TMPred, PredMode::BPred)))
+}
+
+pub fn read_uv_mode(bc: &mut BoolCoder, probs: &[u8; 3]) -> PredMode {
+    vp_tree!(bc, probs[0], PredMode::DCPred,
+             vp_tree!(bc, probs[1], PredMode::VPred,
+                      vp_tree!(bc, probs[2], PredMode::HPred, PredMode::TMPred)))
+}
+
+pub fn read_b_mode(bc: &mut BoolCoder, probs: &[u8; 9]) -> PredMode {
+    vp_tree!(bc, probs[0], PredMode::DCPred,
+             vp_tree!(bc, probs[1], PredMode::TMPred,
+                      vp_tree!(bc, probs[2], PredMode::VPred,
+                               vp_tree!(bc, probs[3],
+                                        vp_tree!(bc, probs[4], PredMode::HPred,
+                                                 vp_tree!(bc, probs[5], PredMode::RDPred, PredMode::VRPred)),
+                                        vp_tree!(bc, probs[6], PredMode::LDPred,
+                                                 vp_tree!(bc, probs[7], PredMode::VLPred,
+                                                          vp_tree!(bc, probs[8], PredMode::HDPred, PredMode::HUPred)))))))
+}
+
+pub fn read_mv_split_mode(bc: &mut BoolCoder) -> MVSplitMode {
+    let probs = &MV_SPLIT_MODE_PROBS;
+    vp_tree!(bc, probs[0], MVSplitMode::Sixteenths,
+             vp_tree!(bc, probs[1], MVSplitMode::Quarters,
+                      vp_tree!(bc, probs[2], MVSplitMode::TopBottom, MVSplitMode::LeftRight)))
+}
+
+pub fn read_sub_mv_ref(bc: &mut BoolCoder, probs: &[u8; 3]) -> SubMVRef {
+    vp_tree!(bc, probs[0], SubMVRef::Left,
+             vp_tree!(bc, probs[1], SubMVRef::Above,
+                      vp_tree!(bc, probs[2], SubMVRef::Zero, SubMVRef::New)))
+}
+
+/// Reads motion vector component using short tree probabilities followed by long form bit probabilities.
+///
+/// VP7 codes 8 bits in the long form and VP8 codes 10 bits.
+pub fn read_mv_component(bc: &mut BoolCoder, probs: &[u8], long_bits: usize) -> i16 {
+    let val = if bc.read_prob(probs[0]) {
+            let mut val = 0;
+            for i in 0..3 {
+                val |= (bc.read_prob(probs[9 + i]) as i16) << i;
+            }
+            for i in (4..long_bits).rev() {
+                val |= (bc.read_prob(probs[9 + i]) as i16) << i;
+            }
+            // bit 3 is implicit for the values that could have been coded with the short tree
+            if (val & !0xF) == 0 || bc.read_prob(probs[9 + 3]) {
+                val += 8;
+            }
+            val
+        } else {
+            let sprobs = &probs[2..9];
+            vp_tree!(bc, sprobs[0],
+                     vp_tree!(bc, sprobs[1],
+                              vp_tree!(bc, sprobs[2], 0, 1),
+                              vp_tree!(bc, sprobs[3], 2, 3)),
+                     vp_tree!(bc, sprobs[4],
+                              vp_tree!(bc, sprobs[5], 4, 5),
+                              vp_tree!(bc, sprobs[6], 6, 7)))
+        };
+    if val != 0 && bc.read_prob(probs[1]) {
+        -val
+    } else {
+        val
+    }
+}
+
+pub fn update_coef_probs(bc: &mut BoolCoder, probs: &mut CoeffProbs) {
+    for (upd_type, dst_type) in COEF_UPDATE_PROBS.iter().zip(probs.iter_mut()) {
+        for (upd_band, dst_band) in upd_type.iter().zip(dst_type.iter_mut()) {
+            for (upd_ctx, dst_ctx) in upd_band.iter().zip(dst_band.iter_mut()) {
+                for (&upd_prob, prob) in upd_ctx.iter().zip(dst_ctx.iter_mut()) {
+                    if bc.read_prob(upd_prob) {
+                        *prob = bc.read_byte();
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Decodes coefficients for a single block and returns the position where decoding stopped
+/// (zero means that the block has no coded coefficients).
+///
+/// VP7 may code the end of block right after a zero coefficient while VP8 does not allow that.
+#[allow(clippy::too_many_arguments)]
+pub fn decode_coeffs(bc: &mut BoolCoder, coeffs: &mut [i16; 16], probs: &[[[u8; 11]; 3]; 8], start: usize, mut ctx: usize, quant: [i16; 2], scan: &[usize; 16], eob_after_zero: bool) -> usize {
+    let mut idx = start;
+    let mut after_zero = false;
+    while idx < 16 {
+        let cprobs = &probs[COEF_BANDS[idx]][ctx];
+        if (!after_zero || eob_after_zero) && !bc.read_prob(cprobs[0]) {
+            return idx;
+        }
+        idx += 1;
+        if !bc.read_prob(cprobs[1]) {
+            after_zero = true;
+            ctx = 0;
+            continue;
+        }
+        after_zero = false;
+        let val = if !bc.read_prob(cprobs[2]) {
+                ctx = 1;
+                1
+            } else {
+                ctx = 2;
+                vp_tree!(bc, cprobs[3],
+                         vp_tree!(bc, cprobs[4], 2, 3 + (bc.read_prob(cprobs[5]) as i16)),
+                         vp_tree!(bc, cprobs[6],
+                                  vp_tree!(bc, cprobs[7], read_coef_cat(bc, 0), read_coef_cat(bc, 1)),
+                                  vp_tree!(bc, cprobs[8],
+                                           vp_tree!(bc, cprobs[9], read_coef_cat(bc, 2), read_coef_cat(bc, 3)),
+                                           vp_tree!(bc, cprobs[10], read_coef_cat(bc, 4), read_coef_cat(bc, 5)))))
+            };
+        let val = if bc.read_bool() { -val } else { val };
+        let q = if idx > 1 { quant[1] } else { quant[0] };
+        coeffs[scan[idx - 1]] = val.wrapping_mul(q);
+    }
+    16
+}
+
+fn read_coef_cat(bc: &mut BoolCoder, cat: usize) -> i16 {
+    let mut add = 0;
+    for &prob in VP56_COEF_ADD_PROBS[cat].iter().take_while(|&&prob| prob != 128) {
+        add = (add << 1) | (bc.read_prob(prob) as i16);
+    }
+    VP56_COEF_BASE[cat] + add
+}
+
+/// Decodes all coefficient blocks of a macroblock updating non-zero contexts.
+/// Returns false if the macroblock turns out to have no coded coefficients.
+#[allow(clippy::too_many_arguments)]
+pub fn decode_mb_coeffs(bc: &mut BoolCoder, coeffs: &mut [[i16; 16]; 25], probs: &CoeffProbs, top_nz: &mut [bool; 9], left_nz: &mut [bool; 9], has_y2: bool, quants: &QuantSet, scan: &[usize; 16], eob_after_zero: bool) -> bool {
+    for blk in coeffs.iter_mut() {
+        *blk = [0; 16];
+    }
+    let mut coded = false;
+    let (ytype, ystart) = if has_y2 {
+            let ctx = (top_nz[Y2_NZ] as usize) + (left_nz[Y2_NZ] as usize);
+            let nz = decode_coeffs(bc, &mut coeffs[Y2_BLK], &probs[BLK_TYPE_Y2], 0, ctx, quants.y2, &DEFAULT_SCAN_ORDER, eob_after_zero) > 0;
+            top_nz[Y2_NZ] = nz;
+            left_nz[Y2_NZ] = nz;
+            coded |= nz;
+            (BLK_TYPE_Y_NO_DC, 1)
+        } else {
+            (BLK_TYPE_Y, 0)
+        };
+    for (i, blk) in coeffs[..16].iter_mut().enumerate() {
+        let (x, y) = (i & 3, i >> 2);
+        let ctx = (top_nz[x] as usize) + (left_nz[y] as usize);
+        let nz = decode_coeffs(bc, blk, &probs[ytype], ystart, ctx, quants.y, scan, eob_after_zero) > ystart;
+        top_nz[x] = nz;
+        left_nz[y] = nz;
+        coded |= nz;
+    }
+    for (i, blk) in coeffs[16..24].iter_mut().enumerate() {
+        let (x, y) = (4 + (i & 1) + (i >> 2) * 2, 4 + ((i >> 1) & 1) + (i >> 2) * 2);
+        let ctx = (top_nz[x] as usize) + (left_nz[y] as usize);
+        let nz = decode_coeffs(bc, blk, &probs[BLK_TYPE_CHROMA], 0, ctx, quants.uv, scan, eob_after_zero) > 0;
+        top_nz[x] = nz;
+        left_nz[y] = nz;
+        coded |= nz;
+    }
+    coded
+}
+
+/// Calculates inner edge limit and high edge variance threshold for the loop filter level.
+pub fn loop_filter_params(level: u8, sharpness: u8, is_intra: bool) -> (u8, u8) {
+    let mut inner_limit = level;
+    if sharpness > 0 {
+        inner_limit >>= if sharpness > 4 { 2 } else { 1 };
+        inner_limit = inner_limit.min(9 - sharpness);
+    }
+    let inner_limit = inner_limit.max(1);
+    let hev_thr = match (is_intra, level) {
+            (true,  40..) => 2,
+            (true,  15..) => 1,
+            (false, 40..) => 3,
+            (false, 20..) => 2,
+            (false, 15..) => 1,
+            _ => 0,
+        };
+    (inner_limit, hev_thr)
+}
@@ -0,0 +1,429 @@
+//! DSP functions shared by VP7 and VP8 decoders.
+use nihav_core::frame::*;
+use nihav_codec_support::codecs::blockdsp::edge_emu;
+use super::vp78::PredMode;
+use super::vp78data::SUBPEL_FILTERS;
+
+fn clip_u8(val: i16) -> u8 {
+    val.clamp(0, 255) as u8
+}
+
+/// Neighbouring pixels used for intra prediction.
+#[derive(Clone,Copy)]
+pub struct IPredContext {
+    pub left:       [u8; 16],
+    pub has_left:   bool,
+    // the last four pixels are above-right ones and are needed only for sub-block prediction
+    pub top:        [u8; 20],
+    pub has_top:    bool,
+    pub tl:         u8,
+}
+
+impl Default for IPredContext {
+    fn default() -> Self {
+        Self { left: [0x80; 16], has_left: false, top: [0x80; 20], has_top: false, tl: 0x80 }
+    }
+}
+
+impl IPredContext {
+    /// Fills context with the pixels around the block at the provided offset.
+    ///
+    /// Missing edges are replaced with `edge[0]` for the top and `edge[1]` for the left one,
+    /// top-left pixel uses top edge value if there is no top edge.
+    #[allow(clippy::too_many_arguments)]
+    pub fn fill(&mut self, src: &[u8], off: usize, stride: usize, size: usize, has_top: bool, has_left: bool, edge: [u8; 2]) {
+        self.has_top  = has_top;
+        self.has_left = has_left;
+        if has_top {
+            self.top[..size].copy_from_slice(&src[off - stride..][..size]);
+        } else {
+            for el in self.top[..size].iter_mut() {
+                *el = edge[0];
+            }
+        }
+        if has_left {
+            for (el, line) in self.left[..size].iter_mut().zip(src[off - 1..].chunks(stride)) {
+                *el = line[0];
+            }
+        } else {
+            for el in self.left[..size].iter_mut() {
+                *el = edge[1];
+            }
+        }
+        self.tl = match (has_top, has_left) {
+                (true, true)  => src[off - stride - 1],
+                (true, false) => edge[1],
+                _             => edge[0],
+            };
+    }
+}
+
+/// Predicts 16x16 luma or 8x8 chroma block.
+pub fn ipred_mb(dst: &mut [u8], off: usize, stride: usize, size: usize, mode: PredMode, ctx: &IPredContext) {
+    let dst = &mut dst[off..];
+    match mode {
+        PredMode::DCPred => {
+            let shift = if size == 16 { 4 } else { 3 };
+            let tsum: u16 = ctx.top[..size].iter().map(|&el| u16::from(el)).sum();
+            let lsum: u16 = ctx.left[..size].iter().map(|&el| u16::from(el)).sum();
+            let dc = match (ctx.has_top, ctx.has_left) {
+                    (true,  true)  => (tsum + lsum + (1 << shift)) >> (shift + 1),
+                    (true,  false) => (tsum + (1 << (shift - 1))) >> shift,
+                    (false, true)  => (lsum + (1 << (shift - 1))) >> shift,
+                    (false, false) => 0x80,
+                } as u8;
+            for line in dst.chunks_mut(stride).take(size) {
+                for el in line[..size].iter_mut() {
+                    *el = dc;
+                }
+            }
+        },
+        PredMode::VPred => {
+            for line in dst.chunks_mut(stride).take(size) {
+                line[..size].copy_from_slice(&ctx.top[..size]);
+            }
+        },
+        PredMode::HPred => {
+            for (line, &left) in dst.chunks_mut(stride).zip(ctx.left[..size].iter()) {
+                for el in line[..size].iter_mut() {
+                    *el = left;
+                }
+            }
+        },
+        PredMode::TMPred => {
+            let tl = i16::from(ctx.tl);
+            for (line, &left) in dst.chunks_mut(stride).zip(ctx.left[..size].iter()) {
+                let diff = i16::from(left) - tl;
+                for (el, &top) in line[..size].iter_mut().zip(ctx.top.iter()) {
+                    *el = clip_u8(i16::from(top) + diff);
+                }
+            }
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn avg2(a: u8, b: u8) -> u8 {
+    ((u16::from(a) + u16::from(b) + 1) >> 1) as u8
+}
+fn avg3(a: u8, b: u8, c: u8) -> u8 {
+    ((u16::from(a) + 2 * u16::from(b) + u16::from(c) + 2) >> 2) as u8
+}
+
+/// Predicts 4x4 luma sub-block.
+pub fn ipred_4x4(dst: &mut [u8], off: usize, stride: usize, mode: PredMode, ctx: &IPredContext) {
+    let mut blk = [[0u8; 4]; 4];
+    let top = &ctx.top;
+    let left = &ctx.left;
+    // left column bottom to top, top-left pixel and top row
+    let edge = [left[3], left[2], left[1], left[0], ctx.tl, top[0], top[1], top[2], top[3]];
+    match mode {
+        PredMode::DCPred => {
+            let sum: u16 = top[..4].iter().chain(left[..4].iter()).map(|&el| u16::from(el)).sum();
+            blk = [[((sum + 4) >> 3) as u8; 4]; 4];
+        },
+        PredMode::TMPred => {
+            let tl = i16::from(ctx.tl);
+            for (line, &left) in blk.iter_mut().zip(left.iter()) {
+                for (el, &top) in line.iter_mut().zip(top.iter()) {
+                    *el = clip_u8(i16::from(left) + i16::from(top) - tl);
+                }
+            }
+        },
+        PredMode::VPred => {
+            for line in blk.iter_mut() {
+                for (x, el) in line.iter_mut().enumerate() {
+                    *el = avg3(edge[4 + x], edge[5 + x], top[x + 1]);
+                }
+            }
+        },
+        PredMode::HPred => {
+            let l = [ctx.tl, left[0], left[1], left[2], left[3], left[3]];
+            for (y, line) in blk.iter_mut().enumerate() {
+                *line = [avg3(l[y], l[y + 1], l[y + 2]); 4];
+            }
+        },
+        PredMode::LDPred => {
+            for (y, line) in blk.iter_mut().enumerate() {
+                for (x, el) in line.iter_mut().enumerate() {
+                    let i = x + y;
+                    *el = avg3(top[i], top[i + 1], top[(i + 2).min(7)]);
+                }
+            }
+        },
+        PredMode::RDPred => {
+            for (y, line) in blk.iter_mut().enumerate() {
+                for (x, el) in line.iter_mut().enumerate() {
+                    let i = 3 - y + x;
+                    *el = avg3(edge[i], edge[i + 1], edge[i + 2]);
+                }
+            }
+        },
+        PredMode::VRPred => {
+            blk[3][0] = avg3(edge[1], edge[2], edge[3]);
+            blk[2][0] = avg3(edge[2], edge[3], edge[4]);
+            blk[3][1] = avg3(edge[3], edge[4], edge[5]);
+            blk[1][0] = blk[3][1];
+            blk[2][1] = avg2(edge[4], edge[5]);
+            blk[0][0] = blk[2][1];
+            blk[3][2] = avg3(edge[4], edge[5], edge[6]);
+            blk[1][1] = blk[3][2];
+            blk[2][2] = avg2(edge[5], edge[6]);
+            blk[0][1] = blk[2][2];
+            blk[3][3] = avg3(edge[5], edge[6], edge[7]);
+            blk[1][2] = blk[3][3];
+            blk[2][3] = avg2(edge[6], edge[7]);
+            blk[0][2] = blk[2][3];
+            blk[1][3] = avg3(edge[6], edge[7], edge[8]);
+            blk[0][3] = avg2(edge[7], edge[8]);
+        },
+        PredMode::VLPred => {
+            blk[0][0] = avg2(top[0], top[1]);
+            blk[1][0] = avg3(top[0], top[1], top[2]);
+            blk[2][0] = avg2(top[1], top[2]);
+            blk[0][1] = blk[2][0];
+            blk[1][1] = avg3(top[1], top[2], top[3]);
+            blk[3][0] = blk[1][1];
+            blk[2][1] = avg2(top[2], top[3]);
+            blk[0][2] = blk[2][1];
+            blk[3][1] = avg3(top[2], top[3], top[4]);
+            blk[1][2] = blk[3][1];
+            blk[2][2] = avg2(top[3], top[4]);
+            blk[0][3] = blk[2][2];
+            blk[3][2] = avg3(top[3], top[4], top[5]);
+            blk[1][3] = blk[3][2];
+            blk[2][3] = avg3(top[4], top[5], top[6]);
+            blk[3][3] = avg3(top[5], top[6], top[7]);
+        },
+        PredMode::HDPred => {
+            blk[3][0] = avg2(edge[0], edge[1]);
+            blk[3][1] = avg3(edge[0], edge[1], edge[2]);
+            blk[2][0] = avg2(edge[1], edge[2]);
+            blk[3][2] = blk[2][0];
+            blk[2][1] = avg3(edge[1], edge[2], edge[3]);
+            blk[3][3] = blk[2][1];
+            blk[2][2] = avg2(edge[2], edge[3]);
+            blk[1][0] = blk[2][2];
+            blk[2][3] = avg3(edge[2], edge[3], edge[4]);
+            blk[1][1] = blk[2][3];
+            blk[1][2] = avg2(edge[3], edge[4]);
+            blk[0][0] = blk[1][2];
+            blk[1][3] = avg3(edge[3], edge[4], edge[5]);
+            blk[0][1] = blk[1][3];
+            blk[0][2] = avg3(edge[4], edge[5], edge[6]);
+            blk[0][3] = avg3(edge[5], edge[6], edge[7]);
+        },
+        PredMode::HUPred => {
+            blk[0][0] = avg2(left[0], left[1]);
+            blk[0][1] = avg3(left[0], left[1], left[2]);
+            blk[0][2] = avg2(left[1], left[2]);
+            blk[1][0] = blk[0][2];
+            blk[0][3] = avg3(left[1], left[2], left[3]);
+            blk[1][1] = blk[0][3];
+            blk[1][2] = avg2(left[2], left[3]);
+            blk[2][0] = blk[1][2];
+            blk[1][3] = avg3(left[2], left[3], left[3]);
+            blk[2][1] = blk[1][3];
+            blk[2][2] = left[3];
+            blk[2][3] = left[3];
+            blk[3] = [left[3]; 4];
+        },
+        _ => unreachable!(),
+    }
+    for (dline, sline) in dst[off..].chunks_mut(stride).zip(blk.iter()) {
+        dline[..4].copy_from_slice(sline);
+    }
+}
+
+/// Adds inverse transformed coefficients to the prediction.
+pub fn add_coeffs4x4(dst: &mut [u8], off: usize, stride: usize, coeffs: &[i16; 16]) {
+    for (line, row) in dst[off..].chunks_mut(stride).zip(coeffs.chunks(4)) {
+        for (el, &diff) in line[..4].iter_mut().zip(row.iter()) {
+            *el = clip_u8(i16::from(*el) + diff);
+        }
+    }
+}
+
+const MC_EDGE_STRIDE: usize = 32;
+/// Size of the scratch buffer required by `mc_block()`.
+pub const MC_BUF_SIZE: usize = MC_EDGE_STRIDE * (16 + 5);
+
+fn filter6(src: &[u8], off: usize, step: usize, filt: &[i16; 6]) -> u8 {
+    let mut sum = 64;
+    for (i, &coef) in filt.iter().enumerate() {
+        sum += i32::from(src[off + i * step]) * i32::from(coef);
+    }
+    (sum >> 7).clamp(0, 255) as u8
+}
+
+/// Performs motion compensation of a block with 6-tap subpixel filters.
+///
+/// Motion vector is in 1/8th pel units of the plane and the reference frame is considered
+/// to be extended beyond the edges of its coded area.
+#[allow(clippy::too_many_arguments)]
+pub fn mc_block(dst: &mut [u8], doff: usize, dstride: usize, xpos: usize, ypos: usize, mvx: i16, mvy: i16, reffrm: &NAVideoBuffer<u8>, plane: usize, bw: usize, bh: usize, mc_buf: &mut [u8]) {
+    let (fx, fy) = ((mvx & 7) as usize, (mvy & 7) as usize);
+    let sx = (xpos as isize) + isize::from(mvx >> 3) - 2;
+    let sy = (ypos as isize) + isize::from(mvy >> 3) - 2;
+    let (w, h) = reffrm.get_dimensions(plane);
+    let (w, h) = if plane == 0 { ((w + 15) & !15, (h + 15) & !15) } else { ((w + 7) & !7, (h + 7) & !7) };
+
+    let (src, soff, sstride) = if sx >= 0 && sy >= 0 && (sx as usize) + bw + 5 <= w && (sy as usize) + bh + 5 <= h {
+            let sstride = reffrm.get_stride(plane);
+            (reffrm.get_data().as_slice(), reffrm.get_offset(plane) + (sx as usize) + (sy as usize) * sstride, sstride)
+        } else {
+            edge_emu(reffrm, sx, sy, bw + 5, bh + 5, mc_buf, MC_EDGE_STRIDE, plane, 4);
+            (&mc_buf[..], 0, MC_EDGE_STRIDE)
+        };
+    let src_off = soff + 2 + 2 * sstride;
+
+    let dst = &mut dst[doff..];
+    match (fx, fy) {
+        (0, 0) => {
+            for (dline, sline) in dst.chunks_mut(dstride).zip(src[src_off..].chunks(sstride)).take(bh) {
+                dline[..bw].copy_from_slice(&sline[..bw]);
+            }
+        },
+        (_, 0) => {
+            let filt = &SUBPEL_FILTERS[fx];
+            for (y, dline) in dst.chunks_mut(dstride).take(bh).enumerate() {
+                for (x, el) in dline[..bw].iter_mut().enumerate() {
+                    *el = filter6(src, src_off + x + y * sstride - 2, 1, filt);
+                }
+            }
+        },
+        (0, _) => {
+            let filt = &SUBPEL_FILTERS[fy];
+            for (y, dline) in dst.chunks_mut(dstride).take(bh).enumerate() {
+                for (x, el) in dline[..bw].iter_mut().enumerate() {
+                    *el = filter6(src, src_off + x + y * sstride - 2 * sstride, sstride, filt);
+                }
+            }
+        },
+        _ => {
+            let mut tmp = [0u8; 16 * (16 + 5)];
+            let hfilt = &SUBPEL_FILTERS[fx];
+            for (y, tline) in tmp.chunks_mut(16).take(bh + 5).enumerate() {
+                for (x, el) in tline[..bw].iter_mut().enumerate() {
+                    *el = filter6(src, soff + x + y * sstride, 1, hfilt);
+                }
+            }
+            let vfilt = &SUBPEL_FILTERS[fy];
+            for (y, dline) in dst.chunks_mut(dstride).take(bh).enumerate() {
+                for (x, el) in dline[..bw].iter_mut().enumerate() {
+                    *el = filter6(&tmp, x + y * 16, 16, vfilt);
+                }
+            }
+        },
+    }
+}
+
+fn clip_s8(val: i16) -> i16 {
+    val.clamp(-128, 127)
+}
+
+/// Loop filter variants.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum LFVariant {
+    VP7,
+    VP8,
+}
+
+// pixels around the edge are p3 p2 p1 p0 | q0 q1 q2 q3
+fn load_pixels(buf: &[u8], off: usize, step: usize) -> [i16; 8] {
+    let mut pix = [0i16; 8];
+    for (i, el) in pix.iter_mut().enumerate() {
+        *el = i16::from(buf[off + i * step - 4 * step]);
+    }
+    pix
+}
+
+fn simple_limit(pix: &[i16; 8], limit: i16, variant: LFVariant) -> bool {
+    match variant {
+        LFVariant::VP7 => (pix[3] - pix[4]).abs() <= limit,
+        LFVariant::VP8 => (pix[3] - pix[4]).abs() * 2 + ((pix[2] - pix[5]).abs() >> 1) <= limit,
+    }
+}
+
+fn normal_limit(pix: &[i16; 8], edge_limit: i16, inner_limit: i16, variant: LFVariant) -> bool {
+    simple_limit(pix, edge_limit, variant) &&
+        (pix[0] - pix[1]).abs() <= inner_limit && (pix[1] - pix[2]).abs() <= inner_limit &&
+        (pix[2] - pix[3]).abs() <= inner_limit && (pix[7] - pix[6]).abs() <= inner_limit &&
+        (pix[6] - pix[5]).abs() <= inner_limit && (pix[5] - pix[4]).abs() <= inner_limit
+}
+
+fn high_edge_variance(pix: &[i16; 8], hev_thr: i16) -> bool {
+    (pix[2] - pix[3]).abs() > hev_thr || (pix[5] - pix[4]).abs() > hev_thr
+}
+
+// adjusts p0 and q0 (and p1 and q1 unless outer taps are used)
+fn filter_common(buf: &mut [u8], off: usize, step: usize, pix: &[i16; 8], use_outer_taps: bool, variant: LFVariant) {
+    let mut a = 3 * (pix[4] - pix[3]);
+    if use_outer_taps {
+        a += clip_s8(pix[2] - pix[5]);
+    }
+    let a = clip_s8(a);
+    let f1 = (a + 4).min(127) >> 3;
+    let f2 = match variant {
+            LFVariant::VP7 => f1 - (((a & 7) == 4) as i16),
+            LFVariant::VP8 => (a + 3).min(127) >> 3,
+        };
+    buf[off - step] = clip_u8(pix[3] + f2);
+    buf[off]        = clip_u8(pix[4] - f1);
+    if !use_outer_taps {
+        let a = (f1 + 1) >> 1;
+        buf[off - 2 * step] = clip_u8(pix[2] + a);
+        buf[off + step]     = clip_u8(pix[5] - a);
+    }
+}
+
+/// Filters an edge between macroblocks with the normal loop filter.
+///
+/// `step` is the distance between pixels across the edge and `stride` is the distance between pixels along the edge.
+#[allow(clippy::too_many_arguments)]
+pub fn loop_filter_mb_edge(buf: &mut [u8], mut off: usize, step: usize, stride: usize, len: usize, edge_limit: i16, inner_limit: i16, hev_thr: i16, variant: LFVariant) {
+    for _ in 0..len {
+        let pix = load_pixels(buf, off, step);
+        if normal_limit(&pix, edge_limit, inner_limit, variant) {
+            if high_edge_variance(&pix, hev_thr) {
+                filter_common(buf, off, step, &pix, true, variant);
+            } else {
+                let w = clip_s8(clip_s8(pix[2] - pix[5]) + 3 * (pix[4] - pix[3]));
+                let a0 = (27 * w + 63) >> 7;
+                let a1 = (18 * w + 63) >> 7;
+                let a2 = (9 * w + 63) >> 7;
+                buf[off - 3 * step] = clip_u8(pix[1] + a2);
+                buf[off - 2 * step] = clip_u8(pix[2] + a1);
+                buf[off - step]     = clip_u8(pix[3] + a0);
+                buf[off]            = clip_u8(pix[4] - a0);
+                buf[off + step]     = clip_u8(pix[5] - a1);
+                buf[off + 2 * step] = clip_u8(pix[6] - a2);
+            }
+        }
+        off += stride;
+    }
+}
+
+/// Filters an edge between sub-blocks inside a macroblock with the normal loop filter.
+#[allow(clippy::too_many_arguments)]
+pub fn loop_filter_inner_edge(buf: &mut [u8], mut off: usize, step: usize, stride: usize, len: usize, edge_limit: i16, inner_limit: i16, hev_thr: i16, variant: LFVariant) {
+    for _ in 0..len {
+        let pix = load_pixels(buf, off, step);
+        if normal_limit(&pix, edge_limit, inner_limit, variant) {
+            let hev = high_edge_variance(&pix, hev_thr);
+            filter_common(buf, off, step, &pix, hev, variant);
+        }
+        off += stride;
+    }
+}
+
+/// Filters an edge with the simple loop filter.
+pub fn loop_filter_simple(buf: &mut [u8], mut off: usize, step: usize, stride: usize, len: usize, edge_limit: i16, variant: LFVariant) {
+    for _ in 0..len {
+        let pix = load_pixels(buf, off, step);
+        if simple_limit(&pix, edge_limit, variant) {
+            filter_common(buf, off, step, &pix, true, variant);
+        }
+        off += stride;
+    }
+}
@@ -0,0 +1,115 @@
+pub const DEFAULT_MV_PROBS: [[u8; 17]; 2] = [
+    [ 162, 128, 225, 146, 172, 147, 214,  39, 156, 247, 210, 135,  68, 138, 220, 239, 246 ],
+    [ 164, 128, 204, 170, 119, 235, 140, 230, 228, 244, 184, 201,  44, 173, 221, 239, 253 ]
+];
+
+// bits for the feature values (quantiser index, loop filter level, golden update, blit pitch) in each profile
+pub const FEATURE_BITS: [[u8; 4]; 2] = [
+    [ 7, 6, 0, 8 ],
+    [ 7, 6, 0, 5 ]
+];
+
+pub struct MVPredPos {
+    pub yoff:   isize,
+    pub xoff:   isize,
+    pub subblk: usize,
+    pub score:  usize,
+}
+
+macro_rules! mv_pred_pos {
+    ($y: expr, $x: expr, $blk: expr, $score: expr) => {
+        MVPredPos { yoff: $y, xoff: $x, subblk: $blk, score: $score }
+    }
+}
+
+pub const MV_PRED_POS: [MVPredPos; 12] = [
+    mv_pred_pos!(-1,  0, 12, 2),
+    mv_pred_pos!( 0, -1,  3, 2),
+    mv_pred_pos!(-1, -1, 15, 2),
+    mv_pred_pos!(-1,  1, 12, 1),
+    mv_pred_pos!(-2,  0, 12, 1),
+    mv_pred_pos!( 0, -2,  3, 1),
+    mv_pred_pos!(-1, -2, 15, 1),
+    mv_pred_pos!(-2, -1, 15, 1),
+    mv_pred_pos!(-2,  1, 12, 1),
+    mv_pred_pos!(-1,  2, 12, 1),
+    mv_pred_pos!(-2, -2, 15, 1),
+    mv_pred_pos!(-2,  2, 12, 1),
+];
+
+pub const MODE_CONTEXTS: [[u8; 4]; 31] = [
+    [   3,   3,   1, 246 ],
+    [   7,  89,  66, 239 ],
+    [  10,  90,  78, 238 ],
+    [  14, 118,  95, 241 ],
+    [  14, 123, 106, 238 ],
+    [  20, 140, 109, 240 ],
+    [  13, 155, 103, 238 ],
+    [  21, 158,  99, 240 ],
+    [  27,  82, 108, 232 ],
+    [  19,  99, 123, 217 ],
+    [  45, 139, 148, 236 ],
+    [  50, 117, 144, 235 ],
+    [  57, 128, 164, 238 ],
+    [  69, 139, 171, 239 ],
+    [  74, 154, 179, 238 ],
+    [ 112, 165, 186, 242 ],
+    [  98, 143, 185, 245 ],
+    [ 105, 153, 190, 250 ],
+    [ 124, 167, 192, 245 ],
+    [ 131, 186, 203, 246 ],
+    [  59, 184, 222, 224 ],
+    [ 148, 215, 214, 213 ],
+    [ 137, 211, 210, 219 ],
+    [ 190, 227, 128, 228 ],
+    [ 183, 228, 128, 228 ],
+    [ 194, 234, 128, 228 ],
+    [ 202, 236, 128, 228 ],
+    [ 205, 240, 128, 228 ],
+    [ 205, 244, 128, 228 ],
+    [ 225, 246, 128, 228 ],
+    [ 233, 251, 128, 228 ]
+];
+
+pub const SUB_MV_REF_PROBS: [u8; 3] = [ 180, 255, 255 ];
+
+pub const Y_DC_QUANTS: [i16; 128] = [
+      4,   4,   5,   6,   6,   7,   8,   8,   9,  10,  11,  12,  13,  14,  15,  16,
+     17,  18,  19,  20,  21,  22,  23,  24,  25,  26,  27,  28,  29,  30,  31,  32,
+     33,  33,  34,  35,  36,  36,  37,  38,  39,  39,  40,  41,  41,  42,  43,  43,
+     44,  45,  45,  46,  47,  48,  48,  49,  50,  51,  52,  53,  53,  54,  56,  57,
+     58,  59,  60,  62,  63,  65,  66,  68,  70,  72,  74,  76,  79,  81,  84,  87,
+     90,  93,  96, 100, 104, 108, 112, 116, 121, 126, 131, 136, 142, 148, 154, 160,
+    167, 174, 182, 189, 198, 206, 215, 224, 234, 244, 254, 265, 277, 288, 301, 313,
+    327, 340, 355, 370, 385, 401, 418, 436, 454, 473, 492, 513, 534, 556, 579, 603
+];
+pub const Y_AC_QUANTS: [i16; 128] = [
+      4,   4,   5,   5,   6,   6,   7,   8,   9,  10,  11,  12,  13,  15,  16,  17,
+     19,  20,  22,  23,  25,  26,  28,  29,  31,  32,  34,  35,  37,  38,  40,  41,
+     42,  44,  45,  46,  48,  49,  50,  51,  53,  54,  55,  56,  57,  58,  59,  61,
+     62,  63,  64,  65,  67,  68,  69,  70,  72,  73,  75,  76,  78,  80,  82,  84,
+     86,  88,  91,  93,  96,  99, 102, 105, 109, 112, 116, 121, 125, 130, 135, 140,
+    146, 152, 158, 165, 172, 180, 188, 196, 205, 214, 224, 234, 245, 256, 268, 281,
+    294, 308, 322, 337, 353, 369, 386, 404, 423, 443, 463, 484, 506, 529, 553, 578,
+    604, 631, 659, 688, 718, 749, 781, 814, 849, 885, 922, 960, 1000, 1041, 1083, 1127
+];
+pub const Y2_DC_QUANTS: [i16; 128] = [
+      7,   9,  11,  13,  15,  17,  19,  21,  23,  26,  28,  30,  33,  35,  37,  39,
+     42,  44,  46,  48,  51,  53,  55,  57,  59,  61,  63,  65,  67,  69,  70,  72,
+     74,  75,  77,  78,  80,  81,  83,  84,  85,  87,  88,  89,  90,  92,  93,  94,
+     95,  96,  97,  99, 100, 101, 102, 104, 105, 106, 108, 109, 111, 113, 114, 116,
+    118, 120, 123, 125, 128, 131, 134, 137, 140, 144, 148, 152, 156, 161, 166, 171,
+    176, 182, 188, 195, 202, 209, 217, 225, 234, 243, 253, 263, 274, 285, 297, 309,
+    322, 336, 350, 365, 381, 397, 414, 432, 450, 470, 490, 511, 533, 556, 579, 604,
+    630, 656, 684, 713, 742, 773, 805, 838, 873, 908, 945, 983, 1022, 1063, 1105, 1148
+];
+pub const Y2_AC_QUANTS: [i16; 128] = [
+      7,   9,  11,  13,  16,  18,  21,  24,  26,  29,  32,  35,  38,  41,  43,  46,
+     49,  52,  55,  58,  61,  64,  66,  69,  72,  74,  77,  79,  82,  84,  86,  88,
+     91,  93,  95,  97,  98, 100, 102, 104, 105, 107, 109, 110, 112, 113, 115, 116,
+    117, 119, 120, 122, 123, 125, 127, 128, 130, 132, 134, 136, 138, 141, 143, 146,
+    149, 152, 155, 158, 162, 166, 171, 175, 180, 185, 191, 197, 204, 210, 218, 226,
+    234, 243, 252, 262, 273, 284, 295, 308, 321, 335, 350, 365, 381, 398, 416, 435,
+    455, 476, 497, 520, 544, 569, 595, 622, 650, 680, 711, 743, 776, 811, 848, 885,
+    925, 965, 1008, 1052, 1097, 1144, 1193, 1244, 1297, 1351, 1407, 1466, 1526, 1588, 1652, 1719
+];
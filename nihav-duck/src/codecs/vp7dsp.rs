@@ -0,0 +1,59 @@
+const C1: i32 = 23170;
+const C2: i32 = 12540;
+const C3: i32 = 30274;
+
+// both inverse DCT and inverse WHT in VP7 use the same transform
+// (intermediate values may overflow on invalid input and are allowed to wrap around)
+fn idct4(src: [i32; 4], round: i32, shift: u8) -> [i32; 4] {
+    let a1 = (src[0] + src[2]).wrapping_mul(C1);
+    let b1 = (src[0] - src[2]).wrapping_mul(C1);
+    let c1 = (src[1] * C2).wrapping_sub(src[3] * C3);
+    let d1 = (src[1] * C3).wrapping_add(src[3] * C2);
+    [a1.wrapping_add(d1).wrapping_add(round) >> shift, b1.wrapping_add(c1).wrapping_add(round) >> shift,
+     b1.wrapping_sub(c1).wrapping_add(round) >> shift, a1.wrapping_sub(d1).wrapping_add(round) >> shift]
+}
+
+fn transform(coeffs: &[i16; 16]) -> [i32; 16] {
+    let mut tmp = [0i32; 16];
+    for (dst, src) in tmp.chunks_exact_mut(4).zip(coeffs.chunks_exact(4)) {
+        let row = idct4([i32::from(src[0]), i32::from(src[1]), i32::from(src[2]), i32::from(src[3])], 0, 14);
+        // intermediate values are stored as 16-bit ones
+        for (dst, &src) in dst.iter_mut().zip(row.iter()) {
+            *dst = i32::from(src as i16);
+        }
+    }
+    let mut dst = [0i32; 16];
+    for i in 0..4 {
+        let col = idct4([tmp[i], tmp[i + 4], tmp[i + 8], tmp[i + 12]], 0x20000, 18);
+        for (j, &val) in col.iter().enumerate() {
+            dst[i + j * 4] = val;
+        }
+    }
+    dst
+}
+
+pub fn vp7_idct(coeffs: &mut [i16; 16]) {
+    let res = transform(coeffs);
+    for (dst, &src) in coeffs.iter_mut().zip(res.iter()) {
+        *dst = src as i16;
+    }
+}
+
+/// Performs inverse WHT on Y2 block and puts the results as DCs of the luma blocks.
+pub fn vp7_iwht(coeffs: &mut [[i16; 16]; 25]) {
+    let res = transform(&coeffs[24]);
+    for (blk, &dc) in coeffs[..16].iter_mut().zip(res.iter()) {
+        blk[0] = dc as i16;
+    }
+}
+
+/// Applies fading to the plane: `Y' = Y + Y * beta / 256 + alpha`.
+pub fn vp7_fade(data: &mut [u8], off: usize, stride: usize, w: usize, h: usize, alpha: i8, beta: i8) {
+    let (alpha, beta) = (i16::from(alpha), i16::from(beta));
+    for line in data[off..].chunks_mut(stride).take(h) {
+        for el in line[..w].iter_mut() {
+            let y = i16::from(*el);
+            *el = (y + ((y * beta) >> 8) + alpha).clamp(0, 255) as u8;
+        }
+    }
+}
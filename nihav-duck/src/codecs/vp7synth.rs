@@ -0,0 +1,673 @@
+//! Synthetic VP7 stream generator for the decoder tests.
+//!
+//! Like the VP6 one, it picks modes, motion vectors and coefficients at random and writes them
+//! with the same syntax and contexts the decoder uses. Syntax element writers for the parts
+//! shared with VP8 are exported for reuse.
+use nihav_codec_support::codecs::{MV, ZERO_MV};
+use super::vpcommon::*;
+use super::testsynth::*;
+use super::vp78::*;
+use super::vp78data::*;
+use super::vp7::find_mv_pred;
+use super::vp7data::*;
+
+pub(super) fn write_kf_y_mode(bc: &mut BoolEncoder, mode: PredMode) {
+    let probs = &KF_Y_MODE_PROBS;
+    bc.put_prob(mode != PredMode::BPred, probs[0]);
+    match mode {
+        PredMode::DCPred | PredMode::VPred => {
+            bc.put_prob(false, probs[1]);
+            bc.put_prob(mode == PredMode::VPred, probs[2]);
+        },
+        PredMode::HPred | PredMode::TMPred => {
+            bc.put_prob(true, probs[1]);
+            bc.put_prob(mode == PredMode::TMPred, probs[3]);
+        },
+        _ => {},
+    }
+}
+
+pub(super) fn write_y_mode(bc: &mut BoolEncoder, probs: &[u8; 4], mode: PredMode) {
+    bc.put_prob(mode != PredMode::DCPred, probs[0]);
+    match mode {
+        PredMode::VPred | PredMode::HPred => {
+            bc.put_prob(false, probs[1]);
+            bc.put_prob(mode == PredMode::HPred, probs[2]);
+        },
+        PredMode::TMPred | PredMode::BPred => {
+            bc.put_prob(true, probs[1]);
+            bc.put_prob(mode == PredMode::BPred, probs[3]);
+        },
+        _ => {},
+    }
+}
+
+pub(super) fn write_uv_mode(bc: &mut BoolEncoder, probs: &[u8; 3], mode: PredMode) {
+    bc.put_prob(mode != PredMode::DCPred, probs[0]);
+    if mode != PredMode::DCPred {
+        bc.put_prob(mode != PredMode::VPred, probs[1]);
+        if mode != PredMode::VPred {
+            bc.put_prob(mode == PredMode::TMPred, probs[2]);
+        }
+    }
+}
+
+pub(super) fn write_b_mode(bc: &mut BoolEncoder, probs: &[u8; 9], mode: PredMode) {
+    bc.put_prob(mode != PredMode::DCPred, probs[0]);
+    if mode == PredMode::DCPred {
+        return;
+    }
+    bc.put_prob(mode != PredMode::TMPred, probs[1]);
+    if mode == PredMode::TMPred {
+        return;
+    }
+    bc.put_prob(mode != PredMode::VPred, probs[2]);
+    match mode {
+        PredMode::VPred => {},
+        PredMode::HPred | PredMode::RDPred | PredMode::VRPred => {
+            bc.put_prob(false, probs[3]);
+            bc.put_prob(mode != PredMode::HPred, probs[4]);
+            if mode != PredMode::HPred {
+                bc.put_prob(mode == PredMode::VRPred, probs[5]);
+            }
+        },
+        _ => {
+            bc.put_prob(true, probs[3]);
+            bc.put_prob(mode != PredMode::LDPred, probs[6]);
+            if mode != PredMode::LDPred {
+                bc.put_prob(mode != PredMode::VLPred, probs[7]);
+                if mode != PredMode::VLPred {
+                    bc.put_prob(mode == PredMode::HUPred, probs[8]);
+                }
+            }
+        },
+    }
+}
+
+pub(super) fn write_mv_split_mode(bc: &mut BoolEncoder, mode: MVSplitMode) {
+    let probs = &MV_SPLIT_MODE_PROBS;
+    bc.put_prob(mode != MVSplitMode::Sixteenths, probs[0]);
+    if mode != MVSplitMode::Sixteenths {
+        bc.put_prob(mode != MVSplitMode::Quarters, probs[1]);
+        if mode != MVSplitMode::Quarters {
+            bc.put_prob(mode == MVSplitMode::LeftRight, probs[2]);
+        }
+    }
+}
+
+pub(super) fn write_sub_mv_ref(bc: &mut BoolEncoder, probs: &[u8; 3], mode: SubMVRef) {
+    bc.put_prob(mode != SubMVRef::Left, probs[0]);
+    if mode != SubMVRef::Left {
+        bc.put_prob(mode != SubMVRef::Above, probs[1]);
+        if mode != SubMVRef::Above {
+            bc.put_prob(mode == SubMVRef::New, probs[2]);
+        }
+    }
+}
+
+pub(super) fn write_mv_component(bc: &mut BoolEncoder, probs: &[u8], long_bits: usize, val: i16) {
+    let aval = val.unsigned_abs() as usize;
+    assert!(aval < (1 << long_bits));
+    bc.put_prob(aval >= 8, probs[0]);
+    if aval >= 8 {
+        for i in 0..3 {
+            bc.put_prob(((aval >> i) & 1) != 0, probs[9 + i]);
+        }
+        for i in (4..long_bits).rev() {
+            bc.put_prob(((aval >> i) & 1) != 0, probs[9 + i]);
+        }
+        if (aval & !0xF) != 0 {
+            bc.put_prob((aval & 8) != 0, probs[9 + 3]);
+        }
+    } else {
+        let sprobs = &probs[2..9];
+        bc.put_prob(aval >= 4, sprobs[0]);
+        if aval < 4 {
+            bc.put_prob(aval >= 2, sprobs[1]);
+            bc.put_prob((aval & 1) != 0, sprobs[2 + aval / 2]);
+        } else {
+            bc.put_prob(aval >= 6, sprobs[4]);
+            bc.put_prob((aval & 1) != 0, sprobs[5 + (aval - 4) / 2]);
+        }
+    }
+    if aval != 0 {
+        bc.put_prob(val < 0, probs[1]);
+    }
+}
+
+/// Writes random coefficient probability updates and applies them to the provided models.
+pub(super) fn write_coef_prob_updates(bc: &mut BoolEncoder, rng: &mut RNG, probs: &mut CoeffProbs) {
+    for (upd_type, dst_type) in COEF_UPDATE_PROBS.iter().zip(probs.iter_mut()) {
+        for (upd_band, dst_band) in upd_type.iter().zip(dst_type.iter_mut()) {
+            for (upd_ctx, dst_ctx) in upd_band.iter().zip(dst_band.iter_mut()) {
+                for (&upd_prob, prob) in upd_ctx.iter().zip(dst_ctx.iter_mut()) {
+                    let update = rng.chance(1, 40);
+                    bc.put_prob(update, upd_prob);
+                    if update {
+                        *prob = (1 + rng.range(255)) as u8;
+                        bc.put_bits(u32::from(*prob), 8);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn write_coef_cat(bc: &mut BoolEncoder, cat: usize, add: u16) {
+    let probs = &VP56_COEF_ADD_PROBS[cat];
+    let nbits = probs.iter().position(|&prob| prob == 128).unwrap_or(probs.len());
+    for (i, &prob) in probs[..nbits].iter().enumerate() {
+        bc.put_prob(((add >> (nbits - 1 - i)) & 1) != 0, prob);
+    }
+}
+
+/// Writes block coefficient tokens (in the coding order) up to the end of block position.
+pub(super) fn write_coeffs(bc: &mut BoolEncoder, coeffs: &[i16; 16], eob: usize, probs: &[[[u8; 11]; 3]; 8], start: usize, mut ctx: usize, eob_after_zero: bool) {
+    let mut after_zero = false;
+    for (idx, &val) in coeffs.iter().enumerate().skip(start) {
+        let cprobs = &probs[COEF_BANDS[idx]][ctx];
+        if !after_zero || eob_after_zero {
+            bc.put_prob(idx < eob, cprobs[0]);
+        }
+        if idx >= eob {
+            assert!(!after_zero || eob_after_zero);
+            return;
+        }
+        bc.put_prob(val != 0, cprobs[1]);
+        if val == 0 {
+            after_zero = true;
+            ctx = 0;
+            continue;
+        }
+        after_zero = false;
+        let aval = val.unsigned_abs();
+        bc.put_prob(aval > 1, cprobs[2]);
+        if aval == 1 {
+            ctx = 1;
+        } else {
+            ctx = 2;
+            if aval <= 4 {
+                bc.put_prob(false, cprobs[3]);
+                bc.put_prob(aval > 2, cprobs[4]);
+                if aval > 2 {
+                    bc.put_prob(aval == 4, cprobs[5]);
+                }
+            } else {
+                bc.put_prob(true, cprobs[3]);
+                let cat = VP56_COEF_BASE.iter().rposition(|&base| aval >= base as u16).unwrap();
+                if cat < 2 {
+                    bc.put_prob(false, cprobs[6]);
+                    bc.put_prob(cat == 1, cprobs[7]);
+                } else {
+                    bc.put_prob(true, cprobs[6]);
+                    if cat < 4 {
+                        bc.put_prob(false, cprobs[8]);
+                        bc.put_prob(cat == 3, cprobs[9]);
+                    } else {
+                        bc.put_prob(true, cprobs[8]);
+                        bc.put_prob(cat == 5, cprobs[10]);
+                    }
+                }
+                write_coef_cat(bc, cat, aval - VP56_COEF_BASE[cat] as u16);
+            }
+        }
+        bc.put_bool(val < 0);
+    }
+}
+
+/// Generates random coefficient tokens for a block and returns the end of block position.
+pub(super) fn gen_coeffs(rng: &mut RNG, coeffs: &mut [i16; 16], start: usize, max: i16, eob_after_zero: bool) -> usize {
+    *coeffs = [0; 16];
+    if rng.chance(1, 2) {
+        return start;
+    }
+    let eob = (start + 1 + rng.range(6) as usize).min(16);
+    for el in coeffs[start..eob].iter_mut() {
+        if rng.chance(1, 2) {
+            *el = rng.value(max);
+        }
+    }
+    if !eob_after_zero && coeffs[eob - 1] == 0 {
+        coeffs[eob - 1] = 1;
+    }
+    eob
+}
+
+/// Generates and writes coefficients for all blocks of a macroblock updating non-zero contexts.
+pub(super) fn write_mb_coeffs(bc: &mut BoolEncoder, rng: &mut RNG, probs: &CoeffProbs, top_nz: &mut [bool; 9], left_nz: &mut [bool; 9], has_y2: bool, eob_after_zero: bool) {
+    let mut coeffs = [0; 16];
+    let (ytype, ystart) = if has_y2 {
+            let ctx = (top_nz[Y2_NZ] as usize) + (left_nz[Y2_NZ] as usize);
+            let eob = gen_coeffs(rng, &mut coeffs, 0, 40, eob_after_zero);
+            write_coeffs(bc, &coeffs, eob, &probs[BLK_TYPE_Y2], 0, ctx, eob_after_zero);
+            top_nz[Y2_NZ] = eob > 0;
+            left_nz[Y2_NZ] = eob > 0;
+            (BLK_TYPE_Y_NO_DC, 1)
+        } else {
+            (BLK_TYPE_Y, 0)
+        };
+    for i in 0..16 {
+        let (x, y) = (i & 3, i >> 2);
+        let ctx = (top_nz[x] as usize) + (left_nz[y] as usize);
+        let eob = gen_coeffs(rng, &mut coeffs, ystart, 20, eob_after_zero);
+        write_coeffs(bc, &coeffs, eob, &probs[ytype], ystart, ctx, eob_after_zero);
+        top_nz[x] = eob > ystart;
+        left_nz[y] = eob > ystart;
+    }
+    for i in 0..8 {
+        let (x, y) = (4 + (i & 1) + (i >> 2) * 2, 4 + ((i >> 1) & 1) + (i >> 2) * 2);
+        let ctx = (top_nz[x] as usize) + (left_nz[y] as usize);
+        let eob = gen_coeffs(rng, &mut coeffs, 0, 20, eob_after_zero);
+        write_coeffs(bc, &coeffs, eob, &probs[BLK_TYPE_CHROMA], 0, ctx, eob_after_zero);
+        top_nz[x] = eob > 0;
+        left_nz[y] = eob > 0;
+    }
+}
+
+pub(super) const Y_MODES: [PredMode; 5] = [PredMode::DCPred, PredMode::VPred, PredMode::HPred, PredMode::TMPred, PredMode::BPred];
+pub(super) const UV_MODES: [PredMode; 4] = [PredMode::DCPred, PredMode::VPred, PredMode::HPred, PredMode::TMPred];
+pub(super) const B_MODES: [PredMode; 10] = [
+    PredMode::DCPred, PredMode::TMPred, PredMode::VPred, PredMode::HPred, PredMode::LDPred,
+    PredMode::RDPred, PredMode::VRPred, PredMode::VLPred, PredMode::HDPred, PredMode::HUPred
+];
+const SPLIT_MODES: [MVSplitMode; 4] = [MVSplitMode::TopBottom, MVSplitMode::LeftRight, MVSplitMode::Quarters, MVSplitMode::Sixteenths];
+const SUB_MV_REFS: [SubMVRef; 4] = [SubMVRef::Left, SubMVRef::Above, SubMVRef::Zero, SubMVRef::New];
+
+/// Stream parameters for the generator.
+#[derive(Clone,Copy)]
+pub struct VP7SynthConfig {
+    pub profile:    u8,
+    pub width:      usize,
+    pub height:     usize,
+    pub nframes:    usize,
+    pub key_int:    usize,
+    /// Use macroblock features (quantiser, loop filter level, partial golden frame update and pitch).
+    pub features:   bool,
+    pub fade:       bool,
+    pub scan:       bool,
+    pub split:      bool,
+    pub golden:     bool,
+    pub seed:       u32,
+}
+
+#[derive(Clone,Copy)]
+struct Models {
+    coef_probs:     CoeffProbs,
+    y_mode_probs:   [u8; 4],
+    uv_mode_probs:  [u8; 3],
+    mv_probs:       [[u8; 17]; 2],
+}
+
+impl Default for Models {
+    fn default() -> Self {
+        Self {
+            coef_probs:     DEFAULT_COEF_PROBS,
+            y_mode_probs:   Y_MODE_PROBS,
+            uv_mode_probs:  UV_MODE_PROBS,
+            mv_probs:       DEFAULT_MV_PROBS,
+        }
+    }
+}
+
+#[derive(Clone,Copy,Default)]
+struct Feature {
+    present_prob:   u8,
+    tree_probs:     [u8; 3],
+}
+
+/// Synthetic VP7 stream generator.
+pub struct VP7Synth {
+    cfg:            VP7SynthConfig,
+    rng:            RNG,
+    frameno:        usize,
+    mb_w:           usize,
+    mb_h:           usize,
+    models:         Models,
+    fade_present:   bool,
+    features:       [Option<Feature>; 4],
+    prob_intra:     u8,
+    prob_last:      u8,
+
+    mvs:            Vec<[MV; 16]>,
+    top_bmodes:     Vec<PredMode>,
+    left_bmodes:    [PredMode; 4],
+    top_nz:         Vec<[bool; 9]>,
+    left_nz:        [bool; 9],
+}
+
+impl VP7Synth {
+    pub fn new(cfg: VP7SynthConfig) -> Self {
+        let mb_w = cfg.width.div_ceil(16);
+        let mb_h = cfg.height.div_ceil(16);
+        Self {
+            cfg,
+            rng:            RNG::new(cfg.seed),
+            frameno:        0,
+            mb_w, mb_h,
+            models:         Models::default(),
+            fade_present:   true,
+            features:       [None; 4],
+            prob_intra:     0,
+            prob_last:      0,
+
+            mvs:            vec![[ZERO_MV; 16]; mb_w * mb_h],
+            top_bmodes:     vec![PredMode::DCPred; mb_w * 4],
+            left_bmodes:    [PredMode::DCPred; 4],
+            top_nz:         vec![[false; 9]; mb_w],
+            left_nz:        [false; 9],
+        }
+    }
+    /// Generates the next frame packet.
+    pub fn gen_frame(&mut self) -> Vec<u8> {
+        let is_intra = self.frameno.is_multiple_of(self.cfg.key_int);
+        self.frameno += 1;
+        let profile = self.cfg.profile;
+
+        let mut bc = BoolEncoder::new();
+        if is_intra {
+            bc.put_bits(self.cfg.width as u32, 12);
+            bc.put_bits(self.cfg.height as u32, 12);
+            bc.put_bits(0, 2);
+            bc.put_bits(0, 2);
+            self.models = Models::default();
+        }
+        self.write_features(&mut bc);
+        // quantisers
+        bc.put_bits(self.rng.range(64), 7);
+        for _ in 0..5 {
+            let explicit = self.cfg.features && self.rng.chance(1, 4);
+            bc.put_bool(explicit);
+            if explicit {
+                bc.put_bits(self.rng.range(64), 7);
+            }
+        }
+        if !is_intra {
+            bc.put_bool(self.cfg.golden && self.rng.chance(1, 4));
+        }
+        let mut saved_models = None;
+        if profile > 0 {
+            let update_probs = self.rng.chance(2, 3);
+            bc.put_bool(update_probs);
+            if !update_probs {
+                saved_models = Some(self.models);
+            }
+            if !is_intra {
+                self.fade_present = self.cfg.fade && self.rng.chance(1, 2);
+                bc.put_bool(self.fade_present);
+            }
+        }
+        if self.fade_present {
+            let fade = self.cfg.fade && !is_intra && self.rng.chance(1, 2);
+            bc.put_bool(fade);
+            if fade {
+                bc.put_bits((self.rng.range(21) as i8 - 10) as u8 as u32, 8);
+                bc.put_bits((self.rng.range(41) as i8 - 20) as u8 as u32, 8);
+            }
+        }
+        let lf_simple = self.rng.chance(1, 3);
+        if profile == 0 {
+            bc.put_bool(lf_simple);
+        }
+        let scan = self.cfg.scan && self.rng.chance(1, 2);
+        bc.put_bool(scan);
+        if scan {
+            let mut order: Vec<u32> = (1..16).collect();
+            for i in (1..order.len()).rev() {
+                order.swap(i, self.rng.range(i as u32 + 1) as usize);
+            }
+            for &idx in order.iter() {
+                bc.put_bits(idx, 4);
+            }
+        }
+        if profile > 0 {
+            bc.put_bool(lf_simple);
+        }
+        bc.put_bits(self.rng.range(40), 6);
+        bc.put_bits(self.rng.range(8), 3);
+        write_coef_prob_updates(&mut bc, &mut self.rng, &mut self.models.coef_probs);
+        if !is_intra {
+            self.write_inter_probs(&mut bc);
+        }
+
+        let mut bc2 = BoolEncoder::new();
+        for el in self.top_bmodes.iter_mut() {
+            *el = PredMode::DCPred;
+        }
+        for el in self.top_nz.iter_mut() {
+            *el = [false; 9];
+        }
+        for mb_y in 0..self.mb_h {
+            self.left_bmodes = [PredMode::DCPred; 4];
+            self.left_nz = [false; 9];
+            for mb_x in 0..self.mb_w {
+                self.gen_mb(&mut bc, &mut bc2, mb_x, mb_y, is_intra);
+            }
+        }
+        if let Some(models) = saved_models {
+            self.models = models;
+        }
+
+        let part1 = bc.finish();
+        let part2 = bc2.finish();
+        let tag = ((part1.len() as u32) << 4) | (u32::from(profile) << 1) | (!is_intra as u32);
+        let mut pkt = vec![tag as u8, (tag >> 8) as u8, (tag >> 16) as u8];
+        if profile == 0 {
+            pkt.push(0);
+        }
+        pkt.extend_from_slice(&part1);
+        pkt.extend_from_slice(&part2);
+        pkt
+    }
+    fn write_features(&mut self, bc: &mut BoolEncoder) {
+        let feature_bits = &FEATURE_BITS[usize::from(self.cfg.profile)];
+        for (feat, &bits) in self.features.iter_mut().zip(feature_bits.iter()) {
+            let enabled = self.cfg.features && self.rng.chance(1, 2);
+            bc.put_bool(enabled);
+            *feat = if enabled {
+                    let mut feature = Feature { present_prob: (1 + self.rng.range(255)) as u8, ..Feature::default() };
+                    bc.put_bits(u32::from(feature.present_prob), 8);
+                    for prob in feature.tree_probs.iter_mut() {
+                        let explicit = self.rng.chance(3, 4);
+                        bc.put_bool(explicit);
+                        *prob = if explicit { (1 + self.rng.range(254)) as u8 } else { 255 };
+                        if explicit {
+                            bc.put_bits(u32::from(*prob), 8);
+                        }
+                    }
+                    if bits > 0 {
+                        for _ in 0..4 {
+                            let coded = self.rng.chance(3, 4);
+                            bc.put_bool(coded);
+                            if coded {
+                                // keep quantisers and loop filter levels in the same range as the frame ones
+                                bc.put_bits(self.rng.range(1 << bits.min(6)), bits);
+                            }
+                        }
+                    }
+                    Some(feature)
+                } else {
+                    None
+                };
+        }
+    }
+    fn write_inter_probs(&mut self, bc: &mut BoolEncoder) {
+        self.prob_intra = (20 + self.rng.range(200)) as u8;
+        self.prob_last  = (20 + self.rng.range(200)) as u8;
+        bc.put_bits(u32::from(self.prob_intra), 8);
+        bc.put_bits(u32::from(self.prob_last), 8);
+        let update = self.rng.chance(1, 3);
+        bc.put_bool(update);
+        if update {
+            for prob in self.models.y_mode_probs.iter_mut() {
+                *prob = (1 + self.rng.range(255)) as u8;
+                bc.put_bits(u32::from(*prob), 8);
+            }
+        }
+        let update = self.rng.chance(1, 3);
+        bc.put_bool(update);
+        if update {
+            for prob in self.models.uv_mode_probs.iter_mut() {
+                *prob = (1 + self.rng.range(255)) as u8;
+                bc.put_bits(u32::from(*prob), 8);
+            }
+        }
+        for (probs, upd_probs) in self.models.mv_probs.iter_mut().zip(MV_UPDATE_PROBS.iter()) {
+            for (prob, &upd_prob) in probs.iter_mut().zip(upd_probs.iter()) {
+                let update = self.rng.chance(1, 8);
+                bc.put_prob(update, upd_prob);
+                if update {
+                    *prob = ((1 + self.rng.range(127)) << 1) as u8;
+                    bc.put_probability(*prob);
+                }
+            }
+        }
+    }
+    fn gen_mv(&mut self) -> MV {
+        let range = if self.rng.chance(1, 8) { 255 } else { 40 };
+        let x = self.rng.range(range * 2 + 1) as i16 - range as i16;
+        let y = self.rng.range(range * 2 + 1) as i16 - range as i16;
+        MV { x, y }
+    }
+    fn write_mv(&self, bc: &mut BoolEncoder, mv: MV) {
+        write_mv_component(bc, &self.models.mv_probs[0], 8, mv.y);
+        write_mv_component(bc, &self.models.mv_probs[1], 8, mv.x);
+    }
+    fn gen_split_mvs(&mut self, bc: &mut BoolEncoder, mb_x: usize, mb_y: usize) -> [MV; 16] {
+        let mb_idx = mb_x + mb_y * self.mb_w;
+        let left_mvs  = if mb_x > 0 { self.mvs[mb_idx - 1] } else { [ZERO_MV; 16] };
+        let above_mvs = if mb_y > 0 { self.mvs[mb_idx - self.mb_w] } else { [ZERO_MV; 16] };
+        let split = SPLIT_MODES[self.rng.range(4) as usize];
+        write_mv_split_mode(bc, split);
+        let split = split as usize;
+        let mut mvs = [ZERO_MV; 16];
+        for (part, &blk) in MV_PARTITION_FIRST_BLOCK[split].iter().enumerate() {
+            let left  = if (blk & 3) != 0 { mvs[blk - 1] } else { left_mvs[blk + 3] };
+            let above = if blk >= 4 { mvs[blk - 4] } else { above_mvs[blk + 12] };
+            let mode = SUB_MV_REFS[self.rng.range(4) as usize];
+            write_sub_mv_ref(bc, &SUB_MV_REF_PROBS, mode);
+            let mv = match mode {
+                    SubMVRef::Left  => left,
+                    SubMVRef::Above => above,
+                    SubMVRef::Zero  => ZERO_MV,
+                    SubMVRef::New   => {
+                        let mv = self.gen_mv();
+                        self.write_mv(bc, mv);
+                        mv
+                    },
+                };
+            for (dst, &bpart) in mvs.iter_mut().zip(MV_PARTITIONS[split].iter()) {
+                if usize::from(bpart) == part {
+                    *dst = mv;
+                }
+            }
+        }
+        mvs
+    }
+    fn gen_mb(&mut self, bc: &mut BoolEncoder, bc2: &mut BoolEncoder, mb_x: usize, mb_y: usize, is_intra: bool) {
+        let mb_idx = mb_x + mb_y * self.mb_w;
+        for feat in self.features.iter().flatten() {
+            let present = self.rng.chance(1, 3);
+            bc.put_prob(present, feat.present_prob);
+            if present {
+                let idx = self.rng.range(4) as usize;
+                let probs = &feat.tree_probs;
+                bc.put_prob(idx >= 2, probs[0]);
+                bc.put_prob((idx & 1) != 0, probs[1 + idx / 2]);
+            }
+        }
+
+        let mut ymode = PredMode::Inter;
+        self.mvs[mb_idx] = [ZERO_MV; 16];
+        let intra_mb = is_intra || self.rng.chance(1, 8);
+        if !is_intra {
+            bc.put_prob(!intra_mb, self.prob_intra);
+        }
+        if intra_mb {
+            ymode = Y_MODES[self.rng.range(5) as usize];
+            if is_intra {
+                write_kf_y_mode(bc, ymode);
+            } else {
+                write_y_mode(bc, &self.models.y_mode_probs, ymode);
+            }
+            let mut bmodes = [if ymode == PredMode::DCPred { PredMode::DCPred } else { PredMode::TMPred }; 16];
+            if ymode == PredMode::BPred {
+                for i in 0..16 {
+                    bmodes[i] = B_MODES[self.rng.range(10) as usize];
+                    if is_intra {
+                        let top  = if i >= 4 { bmodes[i - 4] } else { self.top_bmodes[mb_x * 4 + i] };
+                        let left = if (i & 3) != 0 { bmodes[i - 1] } else { self.left_bmodes[i >> 2] };
+                        write_b_mode(bc, &KF_B_MODE_PROBS[top.to_b_index()][left.to_b_index()], bmodes[i]);
+                    } else {
+                        write_b_mode(bc, &B_MODE_PROBS, bmodes[i]);
+                    }
+                }
+            }
+            let uvmode = UV_MODES[self.rng.range(4) as usize];
+            write_uv_mode(bc, if is_intra { &KF_UV_MODE_PROBS } else { &self.models.uv_mode_probs }, uvmode);
+            if is_intra {
+                self.top_bmodes[mb_x * 4..][..4].copy_from_slice(&bmodes[12..]);
+                for (left, row) in self.left_bmodes.iter_mut().zip(bmodes.chunks_exact(4)) {
+                    *left = row[3];
+                }
+            }
+        } else {
+            bc.put_prob(self.cfg.golden && self.rng.chance(1, 4), self.prob_last);
+            let (near_mvs, cnt) = find_mv_pred(&self.mvs, self.mb_w, mb_x, mb_y, self.cfg.profile);
+            let mode = self.rng.range(if self.cfg.split { 5 } else { 4 });
+            bc.put_prob(mode > 0, MODE_CONTEXTS[cnt[0]][0]);
+            if mode > 0 {
+                bc.put_prob(mode > 1, MODE_CONTEXTS[cnt[1]][1]);
+            }
+            if mode > 1 {
+                bc.put_prob(mode > 2, MODE_CONTEXTS[cnt[2]][2]);
+            }
+            if mode > 2 {
+                bc.put_prob(mode > 3, MODE_CONTEXTS[cnt[2]][3]);
+            }
+            self.mvs[mb_idx] = match mode {
+                    0 => [ZERO_MV; 16],
+                    1 => [near_mvs[0]; 16],
+                    2 => [near_mvs[1]; 16],
+                    3 => {
+                        let mv = self.gen_mv();
+                        self.write_mv(bc, mv);
+                        [mv; 16]
+                    },
+                    _ => self.gen_split_mvs(bc, mb_x, mb_y),
+                };
+        }
+
+        write_mb_coeffs(bc2, &mut self.rng, &self.models.coef_probs, &mut self.top_nz[mb_x], &mut self.left_nz, ymode != PredMode::BPred, true);
+    }
+}
+
+/// Generates the whole stream in raw packet format (32-bit big-endian size followed by packet data).
+pub fn gen_stream(cfg: VP7SynthConfig) -> Vec<u8> {
+    let mut synth = VP7Synth::new(cfg);
+    let mut data = Vec::new();
+    for _ in 0..cfg.nframes {
+        put_packet(&mut data, &synth.gen_frame());
+    }
+    data
+}
+
+const fn synth_cfg(profile: u8, seed: u32) -> VP7SynthConfig {
+    VP7SynthConfig {
+        profile, seed,
+        width:      56,
+        height:     40,
+        nframes:    10,
+        key_int:    5,
+        features:   false,
+        fade:       false,
+        scan:       false,
+        split:      false,
+        golden:     false,
+    }
+}
+
+/// Test fixtures stored in `assets/` and the parameters they are generated from.
+pub const TEST_STREAMS: [(&str, VP7SynthConfig); 2] = [
+    ("vp7.vp7",         VP7SynthConfig { split: true, golden: true, ..synth_cfg(0, 1) }),
+    ("vp7_advanced.vp7", VP7SynthConfig { features: true, fade: true, scan: true, split: true, golden: true, key_int: 10, ..synth_cfg(1, 2) }),
+];
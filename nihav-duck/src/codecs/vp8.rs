@@ -0,0 +1,1099 @@
+//! VP8 decoder.
+//!
+//! VP8 builds on VP7 and shares most of the decoding process with it. The differences are
+//! segmentation instead of macroblock features, several coefficient partitions decoded
+//! row-interleaved, the alternative reference frame, simpler motion vector prediction from the
+//! three neighbouring macroblocks with context-dependent split motion vector coding, different
+//! quantisers and transforms, and the bilinear motion compensation used in the later versions.
+use nihav_core::codecs::*;
+use nihav_codec_support::codecs::{MV, ZERO_MV};
+use super::vpcommon::*;
+use super::vp78::*;
+use super::vp78data::*;
+use super::vp78dsp::*;
+use super::vp8data::*;
+use super::vp8dsp::*;
+
+// missing top edge is replaced with 127 and missing left edge with 129
+const EDGE_PIXELS: [u8; 2] = [127, 129];
+
+const MAX_PARTITIONS: usize = 8;
+
+#[derive(Clone,Copy)]
+struct ProbModels {
+    coef_probs:     CoeffProbs,
+    y_mode_probs:   [u8; 4],
+    uv_mode_probs:  [u8; 3],
+    mv_probs:       [[u8; 19]; 2],
+}
+
+impl Default for ProbModels {
+    fn default() -> Self {
+        Self {
+            coef_probs:     DEFAULT_COEF_PROBS,
+            y_mode_probs:   Y_MODE_PROBS,
+            uv_mode_probs:  UV_MODE_PROBS,
+            mv_probs:       DEFAULT_MV_PROBS,
+        }
+    }
+}
+
+#[derive(Clone,Copy,Default)]
+struct Segmentation {
+    enabled:    bool,
+    update_map: bool,
+    // segment values replace the frame ones instead of being added to them
+    absolute:   bool,
+    quant:      [i8; 4],
+    lf_level:   [i8; 4],
+    tree_probs: [u8; 3],
+}
+
+#[derive(Clone,Copy,Default)]
+struct LFDeltas {
+    enabled:    bool,
+    // intra, last, golden and altref frame
+    ref_deltas: [i8; 4],
+    // B_PRED, zero, other whole macroblock and split motion vector modes
+    mode_deltas:[i8; 4],
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+enum MVMode {
+    Zero,
+    Nearest,
+    Near,
+    New,
+    Split,
+}
+
+/// Macroblock information used for motion vector prediction.
+#[derive(Clone,Copy,Default)]
+pub(super) struct MVInfo {
+    pub rframe: VP8Ref,
+    pub mv:     MV,
+    pub split:  bool,
+}
+
+#[derive(Clone,Copy,Default)]
+struct MBInfo {
+    lf_level:       u8,
+    filter_inner:   bool,
+}
+
+/// Finds the best, nearest and near motion vector candidates and the weights for the mode contexts.
+///
+/// Candidates come from the above, left and above-left macroblocks, their motion vectors are
+/// inverted if they refer to a frame with a different sign bias. Weights are returned in zero,
+/// nearest, near and split order.
+pub(super) fn find_near_mvs(mv_info: &[MVInfo], mb_w: usize, mb_x: usize, mb_y: usize, rframe: VP8Ref, sign_bias: &[bool; 4]) -> ([MV; 3], [usize; 4]) {
+    let mb_idx = mb_x + mb_y * mb_w;
+    let mut near_mvs = [ZERO_MV; 4];
+    let mut cnt = [0; 4];
+    let mut idx = 0;
+    let mut split_cnt = 0;
+    let cands = [
+            (mb_y > 0,             mb_idx.wrapping_sub(mb_w),     2),
+            (mb_x > 0,             mb_idx.wrapping_sub(1),        2),
+            (mb_x > 0 && mb_y > 0, mb_idx.wrapping_sub(mb_w + 1), 1),
+        ];
+    for (i, &(avail, nidx, weight)) in cands.iter().enumerate() {
+        if !avail || mv_info[nidx].rframe == VP8Ref::Intra {
+            continue;
+        }
+        let cand = &mv_info[nidx];
+        if cand.split {
+            split_cnt += weight;
+        }
+        if cand.mv != ZERO_MV {
+            let mv = if sign_bias[cand.rframe as usize] != sign_bias[rframe as usize] { -cand.mv } else { cand.mv };
+            if i == 0 || mv != near_mvs[idx] {
+                idx += 1;
+                near_mvs[idx] = mv;
+            }
+            cnt[idx] += weight;
+        } else {
+            cnt[0] += weight;
+        }
+    }
+    // three distinct candidates with the last one equal to the first
+    if cnt[3] > 0 && near_mvs[3] == near_mvs[1] {
+        cnt[1] += 1;
+    }
+    cnt[3] = split_cnt;
+    if cnt[2] > cnt[1] {
+        cnt.swap(1, 2);
+        near_mvs.swap(1, 2);
+    }
+    if cnt[1] >= cnt[0] {
+        near_mvs[0] = near_mvs[1];
+    }
+    ([near_mvs[0], near_mvs[1], near_mvs[2]], cnt)
+}
+
+/// Clamps predicted motion vector so it points no further than one macroblock outside the frame.
+pub(super) fn clamp_mv(mv: MV, mb_x: usize, mb_y: usize, mb_w: usize, mb_h: usize) -> MV {
+    let clamp = |val: i16, pos: usize, size: usize| {
+            let min = -((pos as i32) * 64) - 64;
+            let max = ((size - 1 - pos) as i32) * 64 + 64;
+            i32::from(val).clamp(min, max) as i16
+        };
+    MV { x: clamp(mv.x, mb_x, mb_w), y: clamp(mv.y, mb_y, mb_h) }
+}
+
+/// Returns sub-block motion vector probabilities depending on the left and above motion vectors.
+pub(super) fn sub_mv_ref_probs(left: MV, above: MV) -> &'static [u8; 3] {
+    let ctx = match (left == above, left == ZERO_MV, above == ZERO_MV) {
+            (true, true, _)   => 4,
+            (true, _, _)      => 3,
+            (_, _, true)      => 2,
+            (_, true, _)      => 1,
+            _                 => 0,
+        };
+    &SUB_MV_REF_PROBS[ctx]
+}
+
+fn add_residual(dst: &mut [u8], off: usize, stride: usize, coeffs: &mut [i16; 16]) {
+    if coeffs.iter().any(|&c| c != 0) {
+        vp8_idct(coeffs);
+        add_coeffs4x4(dst, off, stride, coeffs);
+    }
+}
+
+type MCFunc = fn(&mut [u8], usize, usize, usize, usize, i16, i16, &NAVideoBuffer<u8>, usize, usize, usize, &mut [u8]);
+
+// motion vectors are stored in quarter-pel units and may accumulate beyond the block reach
+fn mc_mv(val: i32) -> i16 {
+    val.clamp(i32::from(i16::MIN), i32::from(i16::MAX)) as i16
+}
+
+pub(super) struct VP8Decoder {
+    info:           NACodecInfoRef,
+    width:          usize,
+    height:         usize,
+    mb_w:           usize,
+    mb_h:           usize,
+    version:        u8,
+    shuf:           VPShuffler,
+
+    models:         ProbModels,
+    segmentation:   Segmentation,
+    lf_deltas:      LFDeltas,
+    // Y AC quantiser index followed by Y DC, Y2 DC, Y2 AC, chroma DC and chroma AC deltas
+    qidx:           u8,
+    qdeltas:        [i8; 5],
+    quants:         [QuantSet; 4],
+    lf_simple:      bool,
+    lf_level:       u8,
+    seg_lf_levels:  [u8; 4],
+    lf_sharpness:   u8,
+    sign_bias:      [bool; 4],
+    prob_skip:      Option<u8>,
+    prob_intra:     u8,
+    prob_last:      u8,
+    prob_golden:    u8,
+
+    mb_info:        Vec<MBInfo>,
+    mv_info:        Vec<MVInfo>,
+    segment_map:    Vec<u8>,
+    mvs:            Vec<[MV; 16]>,
+    top_bmodes:     Vec<PredMode>,
+    left_bmodes:    [PredMode; 4],
+    top_nz:         Vec<[bool; 9]>,
+    left_nz:        [bool; 9],
+    coeffs:         [[i16; 16]; 25],
+    mc_buf:         [u8; MC_BUF_SIZE],
+}
+
+impl VP8Decoder {
+    pub(super) fn new() -> Self {
+        Self {
+            info:           NACodecInfoRef::default(),
+            width:          0,
+            height:         0,
+            mb_w:           0,
+            mb_h:           0,
+            version:        0,
+            shuf:           VPShuffler::new(),
+
+            models:         ProbModels::default(),
+            segmentation:   Segmentation::default(),
+            lf_deltas:      LFDeltas::default(),
+            qidx:           0,
+            qdeltas:        [0; 5],
+            quants:         [QuantSet::default(); 4],
+            lf_simple:      false,
+            lf_level:       0,
+            seg_lf_levels:  [0; 4],
+            lf_sharpness:   0,
+            sign_bias:      [false; 4],
+            prob_skip:      None,
+            prob_intra:     0,
+            prob_last:      0,
+            prob_golden:    0,
+
+            mb_info:        Vec::new(),
+            mv_info:        Vec::new(),
+            segment_map:    Vec::new(),
+            mvs:            Vec::new(),
+            top_bmodes:     Vec::new(),
+            left_bmodes:    [PredMode::DCPred; 4],
+            top_nz:         Vec::new(),
+            left_nz:        [false; 9],
+            coeffs:         [[0; 16]; 25],
+            mc_buf:         [0; MC_BUF_SIZE],
+        }
+    }
+    fn set_dimensions(&mut self, supp: &mut NADecoderSupport, width: usize, height: usize) -> DecoderResult<()> {
+        if !supp.limits.check_dimensions(width, height) {
+            return Err(DecoderError::LimitExceeded);
+        }
+        if width == self.width && height == self.height {
+            return Ok(());
+        }
+        self.width  = width;
+        self.height = height;
+        self.mb_w   = width.div_ceil(16);
+        self.mb_h   = height.div_ceil(16);
+        let nmbs = self.mb_w * self.mb_h;
+        self.mb_info.clear();
+        self.mb_info.resize(nmbs, MBInfo::default());
+        self.mv_info.clear();
+        self.mv_info.resize(nmbs, MVInfo::default());
+        self.segment_map.clear();
+        self.segment_map.resize(nmbs, 0);
+        self.mvs.clear();
+        self.mvs.resize(nmbs, [ZERO_MV; 16]);
+        self.top_bmodes.clear();
+        self.top_bmodes.resize(self.mb_w * 4, PredMode::DCPred);
+        self.top_nz.clear();
+        self.top_nz.resize(self.mb_w, [false; 9]);
+        self.shuf.clear();
+
+        let vinfo = self.get_vinfo();
+        self.info = self.info.replace_info(NACodecTypeInfo::Video(vinfo));
+        Ok(())
+    }
+    fn get_vinfo(&self) -> NAVideoInfo {
+        NAVideoInfo::new(self.width, self.height, false, YUV420_FORMAT)
+    }
+    fn read_delta(bc: &mut BoolCoder, bits: u8) -> Option<i8> {
+        if bc.read_bool() {
+            let val = bc.read_bits(bits) as i8;
+            Some(if bc.read_bool() { -val } else { val })
+        } else {
+            None
+        }
+    }
+    fn read_segmentation(&mut self, bc: &mut BoolCoder) {
+        let seg = &mut self.segmentation;
+        seg.enabled = bc.read_bool();
+        if !seg.enabled {
+            seg.update_map = false;
+            return;
+        }
+        seg.update_map = bc.read_bool();
+        let update_data = bc.read_bool();
+        if update_data {
+            seg.absolute = bc.read_bool();
+            for val in seg.quant.iter_mut() {
+                *val = Self::read_delta(bc, 7).unwrap_or(0);
+            }
+            for val in seg.lf_level.iter_mut() {
+                *val = Self::read_delta(bc, 6).unwrap_or(0);
+            }
+        }
+        if seg.update_map {
+            for prob in seg.tree_probs.iter_mut() {
+                *prob = if bc.read_bool() { bc.read_byte() } else { 255 };
+            }
+        }
+    }
+    fn read_lf_deltas(&mut self, bc: &mut BoolCoder) {
+        self.lf_deltas.enabled = bc.read_bool();
+        if self.lf_deltas.enabled && bc.read_bool() {
+            let deltas = &mut self.lf_deltas;
+            // deltas that are not coded keep their previous values
+            for val in deltas.ref_deltas.iter_mut().chain(deltas.mode_deltas.iter_mut()) {
+                if let Some(delta) = Self::read_delta(bc, 6) {
+                    *val = delta;
+                }
+            }
+        }
+    }
+    fn read_quants(&mut self, bc: &mut BoolCoder) {
+        self.qidx = bc.read_bits(7) as u8;
+        for delta in self.qdeltas.iter_mut() {
+            *delta = Self::read_delta(bc, 4).unwrap_or(0);
+        }
+    }
+    fn get_quants(&self, base: i16) -> QuantSet {
+        let idx = |delta: i8| (base + i16::from(delta)).clamp(0, 127) as usize;
+        QuantSet {
+            y:  [DC_QUANTS[idx(self.qdeltas[0])], AC_QUANTS[idx(0)]],
+            y2: [DC_QUANTS[idx(self.qdeltas[1])] * 2, (i32::from(AC_QUANTS[idx(self.qdeltas[2])]) * 155 / 100).max(8) as i16],
+            uv: [DC_QUANTS[idx(self.qdeltas[3])].min(132), AC_QUANTS[idx(self.qdeltas[4])]],
+        }
+    }
+    fn calc_segment_params(&mut self) {
+        let seg = self.segmentation;
+        for i in 0..4 {
+            let (qidx, lf_level) = match (seg.enabled, seg.absolute) {
+                    (false, _)    => (i16::from(self.qidx), i16::from(self.lf_level)),
+                    (true, true)  => (i16::from(seg.quant[i]), i16::from(seg.lf_level[i])),
+                    (true, false) => (i16::from(self.qidx) + i16::from(seg.quant[i]), i16::from(self.lf_level) + i16::from(seg.lf_level[i])),
+                };
+            // segment quantiser is clamped before adding the per-type deltas
+            self.quants[i] = self.get_quants(qidx.clamp(0, 127));
+            self.seg_lf_levels[i] = lf_level.clamp(0, 63) as u8;
+        }
+    }
+    fn read_inter_probs(&mut self, bc: &mut BoolCoder) {
+        self.prob_intra  = bc.read_byte();
+        self.prob_last   = bc.read_byte();
+        self.prob_golden = bc.read_byte();
+        if bc.read_bool() {
+            for prob in self.models.y_mode_probs.iter_mut() {
+                *prob = bc.read_byte();
+            }
+        }
+        if bc.read_bool() {
+            for prob in self.models.uv_mode_probs.iter_mut() {
+                *prob = bc.read_byte();
+            }
+        }
+        for (probs, upd_probs) in self.models.mv_probs.iter_mut().zip(MV_UPDATE_PROBS.iter()) {
+            for (prob, &upd_prob) in probs.iter_mut().zip(upd_probs.iter()) {
+                if bc.read_prob(upd_prob) {
+                    *prob = bc.read_probability();
+                }
+            }
+        }
+    }
+    fn read_mv(bc: &mut BoolCoder, probs: &[[u8; 19]; 2], pred: MV) -> MV {
+        let y = read_mv_component(bc, &probs[0], 10);
+        let x = read_mv_component(bc, &probs[1], 10);
+        MV { x: pred.x.wrapping_add(x), y: pred.y.wrapping_add(y) }
+    }
+    fn read_split_mvs(&self, bc: &mut BoolCoder, mb_x: usize, mb_y: usize, best_mv: MV) -> [MV; 16] {
+        let mb_idx = mb_x + mb_y * self.mb_w;
+        // intra macroblocks have zero motion vectors
+        let left_mvs  = if mb_x > 0 { self.mvs[mb_idx - 1] } else { [ZERO_MV; 16] };
+        let above_mvs = if mb_y > 0 { self.mvs[mb_idx - self.mb_w] } else { [ZERO_MV; 16] };
+        let split = read_mv_split_mode(bc) as usize;
+        let mut mvs = [ZERO_MV; 16];
+        for (part, &blk) in MV_PARTITION_FIRST_BLOCK[split].iter().enumerate() {
+            let left  = if (blk & 3) != 0 { mvs[blk - 1] } else { left_mvs[blk + 3] };
+            let above = if blk >= 4 { mvs[blk - 4] } else { above_mvs[blk + 12] };
+            let mv = match read_sub_mv_ref(bc, sub_mv_ref_probs(left, above)) {
+                    SubMVRef::Left  => left,
+                    SubMVRef::Above => above,
+                    SubMVRef::Zero  => ZERO_MV,
+                    SubMVRef::New   => Self::read_mv(bc, &self.models.mv_probs, best_mv),
+                };
+            for (dst, &bpart) in mvs.iter_mut().zip(MV_PARTITIONS[split].iter()) {
+                if usize::from(bpart) == part {
+                    *dst = mv;
+                }
+            }
+        }
+        mvs
+    }
+    fn read_mb_mvs(&self, bc: &mut BoolCoder, mb_x: usize, mb_y: usize, rframe: VP8Ref) -> ([MV; 16], MVMode) {
+        let (near_mvs, cnt) = find_near_mvs(&self.mv_info, self.mb_w, mb_x, mb_y, rframe, &self.sign_bias);
+        let [best_mv, nearest_mv, near_mv] = near_mvs.map(|mv| clamp_mv(mv, mb_x, mb_y, self.mb_w, self.mb_h));
+        let (mv, mode) = if !bc.read_prob(MODE_CONTEXTS[cnt[0]][0]) {
+                (ZERO_MV, MVMode::Zero)
+            } else if !bc.read_prob(MODE_CONTEXTS[cnt[1]][1]) {
+                (nearest_mv, MVMode::Nearest)
+            } else if !bc.read_prob(MODE_CONTEXTS[cnt[2]][2]) {
+                (near_mv, MVMode::Near)
+            } else if !bc.read_prob(MODE_CONTEXTS[cnt[3]][3]) {
+                (Self::read_mv(bc, &self.models.mv_probs, best_mv), MVMode::New)
+            } else {
+                return (self.read_split_mvs(bc, mb_x, mb_y, best_mv), MVMode::Split);
+            };
+        ([mv; 16], mode)
+    }
+    fn get_lf_level(&self, segment: usize, rframe: VP8Ref, ymode: PredMode, mv_mode: MVMode) -> u8 {
+        let level = self.seg_lf_levels[segment];
+        if !self.lf_deltas.enabled {
+            return level;
+        }
+        let mut level = i16::from(level) + i16::from(self.lf_deltas.ref_deltas[rframe as usize]);
+        let mode_idx = match (rframe, mv_mode) {
+                (VP8Ref::Intra, _) if ymode == PredMode::BPred => Some(0),
+                (VP8Ref::Intra, _) => None,
+                (_, MVMode::Zero)  => Some(1),
+                (_, MVMode::Split) => Some(3),
+                _                  => Some(2),
+            };
+        if let Some(idx) = mode_idx {
+            level += i16::from(self.lf_deltas.mode_deltas[idx]);
+        }
+        level.clamp(0, 63) as u8
+    }
+    #[allow(clippy::too_many_arguments)]
+    fn decode_mb(&mut self, bc: &mut BoolCoder, bc2: &mut BoolCoder, frm: &mut NASimpleVideoFrame<u8>, refs: &[Option<NAVideoBufferRef<u8>>; 3], mb_x: usize, mb_y: usize, is_intra: bool) -> DecoderResult<()> {
+        let mb_idx = mb_x + mb_y * self.mb_w;
+
+        if self.segmentation.update_map {
+            let probs = &self.segmentation.tree_probs;
+            self.segment_map[mb_idx] = vp_tree!(bc, probs[0], vp_tree!(bc, probs[1], 0, 1), vp_tree!(bc, probs[2], 2, 3));
+        } else if is_intra {
+            self.segment_map[mb_idx] = 0;
+        }
+        let segment = usize::from(self.segment_map[mb_idx]);
+        let skip = if let Some(prob) = self.prob_skip { bc.read_prob(prob) } else { false };
+
+        let mut bmodes = [PredMode::DCPred; 16];
+        let mut mvs = [ZERO_MV; 16];
+        let mut ymode = PredMode::Inter;
+        let mut uvmode = PredMode::Inter;
+        let mut rframe = VP8Ref::Intra;
+        let mut mv_mode = MVMode::Zero;
+        if is_intra || !bc.read_prob(self.prob_intra) {
+            ymode = if is_intra { read_kf_y_mode(bc) } else { read_y_mode(bc, &self.models.y_mode_probs) };
+            if ymode == PredMode::BPred {
+                for i in 0..16 {
+                    bmodes[i] = if is_intra {
+                            let top  = if i >= 4 { bmodes[i - 4] } else { self.top_bmodes[mb_x * 4 + i] };
+                            let left = if (i & 3) != 0 { bmodes[i - 1] } else { self.left_bmodes[i >> 2] };
+                            read_b_mode(bc, &KF_B_MODE_PROBS[top.to_b_index()][left.to_b_index()])
+                        } else {
+                            read_b_mode(bc, &B_MODE_PROBS)
+                        };
+                }
+            } else {
+                // implied sub-block modes for the context of the following macroblocks
+                bmodes = [ymode; 16];
+            }
+            uvmode = read_uv_mode(bc, if is_intra { &KF_UV_MODE_PROBS } else { &self.models.uv_mode_probs });
+        } else {
+            rframe = if !bc.read_prob(self.prob_last) {
+                    VP8Ref::Last
+                } else if !bc.read_prob(self.prob_golden) {
+                    VP8Ref::Golden
+                } else {
+                    VP8Ref::AltRef
+                };
+            (mvs, mv_mode) = self.read_mb_mvs(bc, mb_x, mb_y, rframe);
+        }
+        if is_intra {
+            self.top_bmodes[mb_x * 4..][..4].copy_from_slice(&bmodes[12..]);
+            for (left, row) in self.left_bmodes.iter_mut().zip(bmodes.chunks_exact(4)) {
+                *left = row[3];
+            }
+        }
+        let split = mv_mode == MVMode::Split;
+        self.mvs[mb_idx] = mvs;
+        self.mv_info[mb_idx] = MVInfo { rframe, mv: mvs[15], split };
+
+        let has_y2 = ymode != PredMode::BPred && !split;
+        let coded = if !skip {
+                decode_mb_coeffs(bc2, &mut self.coeffs, &self.models.coef_probs, &mut self.top_nz[mb_x], &mut self.left_nz, has_y2, &self.quants[segment], &DEFAULT_SCAN_ORDER, false)
+            } else {
+                for blk in self.coeffs.iter_mut() {
+                    *blk = [0; 16];
+                }
+                let top_nz = &mut self.top_nz[mb_x];
+                let nz_len = if has_y2 { 9 } else { 8 };
+                for (top, left) in top_nz[..nz_len].iter_mut().zip(self.left_nz[..nz_len].iter_mut()) {
+                    *top = false;
+                    *left = false;
+                }
+                false
+            };
+        if has_y2 && self.coeffs[Y2_BLK].iter().any(|&c| c != 0) {
+            vp8_iwht(&mut self.coeffs);
+        }
+        self.mb_info[mb_idx] = MBInfo {
+                lf_level:       self.get_lf_level(segment, rframe, ymode, mv_mode),
+                filter_inner:   ymode == PredMode::BPred || split || coded,
+            };
+
+        let ystride = frm.stride[0];
+        let yoff = frm.offset[0] + mb_x * 16 + mb_y * 16 * ystride;
+        let (has_top, has_left) = (mb_y > 0, mb_x > 0);
+        let mc_func: MCFunc = if self.version == 0 { mc_block } else { mc_block_bilinear };
+        match ymode {
+            PredMode::BPred => {
+                let mut mbctx = IPredContext::default();
+                mbctx.fill(frm.data, yoff, ystride, 16, has_top, has_left, EDGE_PIXELS);
+                if !has_top {
+                    mbctx.top[16..].copy_from_slice(&[EDGE_PIXELS[0]; 4]);
+                } else if mb_x + 1 < self.mb_w {
+                    mbctx.top[16..].copy_from_slice(&frm.data[yoff - ystride + 16..][..4]);
+                } else {
+                    let last = frm.data[yoff - ystride + 15];
+                    mbctx.top[16..].copy_from_slice(&[last; 4]);
+                }
+                for (i, (&bmode, blk)) in bmodes.iter().zip(self.coeffs.iter_mut()).enumerate() {
+                    let (x, y) = (i & 3, i >> 2);
+                    let off = yoff + x * 4 + y * 4 * ystride;
+                    let mut ctx = IPredContext { has_top: true, has_left: true, ..IPredContext::default() };
+                    if y == 0 {
+                        ctx.top[..8].copy_from_slice(&mbctx.top[x * 4..][..8]);
+                    } else {
+                        ctx.top[..4].copy_from_slice(&frm.data[off - ystride..][..4]);
+                        if x < 3 {
+                            ctx.top[4..8].copy_from_slice(&frm.data[off - ystride + 4..][..4]);
+                        } else {
+                            ctx.top[4..8].copy_from_slice(&mbctx.top[16..]);
+                        }
+                    }
+                    if x == 0 {
+                        ctx.left[..4].copy_from_slice(&mbctx.left[y * 4..][..4]);
+                    } else {
+                        for (el, line) in ctx.left[..4].iter_mut().zip(frm.data[off - 1..].chunks(ystride)) {
+                            *el = line[0];
+                        }
+                    }
+                    ctx.tl = match (x, y) {
+                            (0, 0) => mbctx.tl,
+                            (_, 0) => mbctx.top[x * 4 - 1],
+                            (0, _) => mbctx.left[y * 4 - 1],
+                            _      => frm.data[off - ystride - 1],
+                        };
+                    ipred_4x4(frm.data, off, ystride, bmode, &ctx);
+                    add_residual(frm.data, off, ystride, blk);
+                }
+            },
+            PredMode::Inter => {
+                let refbuf = refs[rframe as usize - 1].as_ref().ok_or(DecoderError::MissingReference)?;
+                if split {
+                    for (i, (mv, blk)) in mvs.iter().zip(self.coeffs.iter_mut()).enumerate() {
+                        let (x, y) = (i & 3, i >> 2);
+                        let off = yoff + x * 4 + y * 4 * ystride;
+                        mc_func(frm.data, off, ystride, mb_x * 16 + x * 4, mb_y * 16 + y * 4, mc_mv(i32::from(mv.x) * 2), mc_mv(i32::from(mv.y) * 2), refbuf, 0, 4, 4, &mut self.mc_buf);
+                        add_residual(frm.data, off, ystride, blk);
+                    }
+                } else {
+                    let mv = mvs[0];
+                    mc_func(frm.data, yoff, ystride, mb_x * 16, mb_y * 16, mc_mv(i32::from(mv.x) * 2), mc_mv(i32::from(mv.y) * 2), refbuf, 0, 16, 16, &mut self.mc_buf);
+                    for (i, blk) in self.coeffs[..16].iter_mut().enumerate() {
+                        let (x, y) = (i & 3, i >> 2);
+                        add_residual(frm.data, yoff + x * 4 + y * 4 * ystride, ystride, blk);
+                    }
+                }
+            },
+            _ => {
+                let mut ctx = IPredContext::default();
+                ctx.fill(frm.data, yoff, ystride, 16, has_top, has_left, EDGE_PIXELS);
+                ipred_mb(frm.data, yoff, ystride, 16, ymode, &ctx);
+                for (i, blk) in self.coeffs[..16].iter_mut().enumerate() {
+                    let (x, y) = (i & 3, i >> 2);
+                    add_residual(frm.data, yoff + x * 4 + y * 4 * ystride, ystride, blk);
+                }
+            },
+        }
+
+        // the last version uses only full-pel motion vectors for chroma
+        let cmv_mask = if self.version == 3 { !7 } else { !0 };
+        for plane in 1..3 {
+            let cstride = frm.stride[plane];
+            let coff = frm.offset[plane] + mb_x * 8 + mb_y * 8 * cstride;
+            if rframe != VP8Ref::Intra {
+                let refbuf = refs[rframe as usize - 1].as_ref().ok_or(DecoderError::MissingReference)?;
+                if split {
+                    for y in 0..2 {
+                        for x in 0..2 {
+                            let mut sum_x = 0;
+                            let mut sum_y = 0;
+                            for &blk in [0, 1, 4, 5].iter() {
+                                let mv = mvs[blk + x * 2 + y * 8];
+                                sum_x += i32::from(mv.x);
+                                sum_y += i32::from(mv.y);
+                            }
+                            let cmv_x = mc_mv((sum_x + 2 - i32::from(sum_x < 0)) >> 2) & cmv_mask;
+                            let cmv_y = mc_mv((sum_y + 2 - i32::from(sum_y < 0)) >> 2) & cmv_mask;
+                            mc_func(frm.data, coff + x * 4 + y * 4 * cstride, cstride, mb_x * 8 + x * 4, mb_y * 8 + y * 4, cmv_x, cmv_y, refbuf, plane, 4, 4, &mut self.mc_buf);
+                        }
+                    }
+                } else {
+                    let mv = mvs[0];
+                    mc_func(frm.data, coff, cstride, mb_x * 8, mb_y * 8, mv.x & cmv_mask, mv.y & cmv_mask, refbuf, plane, 8, 8, &mut self.mc_buf);
+                }
+            } else {
+                let mut ctx = IPredContext::default();
+                ctx.fill(frm.data, coff, cstride, 8, has_top, has_left, EDGE_PIXELS);
+                ipred_mb(frm.data, coff, cstride, 8, uvmode, &ctx);
+            }
+            for (i, blk) in self.coeffs[12 + plane * 4..][..4].iter_mut().enumerate() {
+                let (x, y) = (i & 1, i >> 1);
+                add_residual(frm.data, coff + x * 4 + y * 4 * cstride, cstride, blk);
+            }
+        }
+        Ok(())
+    }
+    fn loop_filter_frame(&self, frm: &mut NASimpleVideoFrame<u8>, is_intra: bool) {
+        for mb_y in 0..self.mb_h {
+            for mb_x in 0..self.mb_w {
+                let mb = self.mb_info[mb_x + mb_y * self.mb_w];
+                if mb.lf_level == 0 {
+                    continue;
+                }
+                let (inner_limit, hev_thr) = loop_filter_params(mb.lf_level, self.lf_sharpness, is_intra);
+                let (inner_limit, hev_thr) = (i16::from(inner_limit), i16::from(hev_thr));
+                let level = i16::from(mb.lf_level);
+                let mbedge_limit = (level + 2) * 2 + inner_limit;
+                let bedge_limit = level * 2 + inner_limit;
+
+                let ystride = frm.stride[0];
+                let yoff = frm.offset[0] + mb_x * 16 + mb_y * 16 * ystride;
+                if self.lf_simple {
+                    if mb_x > 0 {
+                        loop_filter_simple(frm.data, yoff, 1, ystride, 16, mbedge_limit, LFVariant::VP8);
+                    }
+                    if mb.filter_inner {
+                        for x in (4..16).step_by(4) {
+                            loop_filter_simple(frm.data, yoff + x, 1, ystride, 16, bedge_limit, LFVariant::VP8);
+                        }
+                    }
+                    if mb_y > 0 {
+                        loop_filter_simple(frm.data, yoff, ystride, 1, 16, mbedge_limit, LFVariant::VP8);
+                    }
+                    if mb.filter_inner {
+                        for y in (4..16).step_by(4) {
+                            loop_filter_simple(frm.data, yoff + y * ystride, ystride, 1, 16, bedge_limit, LFVariant::VP8);
+                        }
+                    }
+                    continue;
+                }
+                // VP8 filters vertical edges first and then the horizontal ones
+                for plane in 0..3 {
+                    let stride = frm.stride[plane];
+                    let size = if plane == 0 { 16 } else { 8 };
+                    let off = frm.offset[plane] + mb_x * size + mb_y * size * stride;
+                    if mb_x > 0 {
+                        loop_filter_mb_edge(frm.data, off, 1, stride, size, mbedge_limit, inner_limit, hev_thr, LFVariant::VP8);
+                    }
+                    if mb.filter_inner {
+                        for x in (4..size).step_by(4) {
+                            loop_filter_inner_edge(frm.data, off + x, 1, stride, size, bedge_limit, inner_limit, hev_thr, LFVariant::VP8);
+                        }
+                    }
+                }
+                for plane in 0..3 {
+                    let stride = frm.stride[plane];
+                    let size = if plane == 0 { 16 } else { 8 };
+                    let off = frm.offset[plane] + mb_x * size + mb_y * size * stride;
+                    if mb_y > 0 {
+                        loop_filter_mb_edge(frm.data, off, stride, 1, size, mbedge_limit, inner_limit, hev_thr, LFVariant::VP8);
+                    }
+                    if mb.filter_inner {
+                        for y in (4..size).step_by(4) {
+                            loop_filter_inner_edge(frm.data, off + y * stride, stride, 1, size, bedge_limit, inner_limit, hev_thr, LFVariant::VP8);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    pub(super) fn decode_frame(&mut self, supp: &mut NADecoderSupport, src: &[u8]) -> DecoderResult<(NABufferType, FrameType)> {
+        if !supp.limits.check_packet_size(src.len()) {
+            return Err(DecoderError::LimitExceeded);
+        }
+        if src.is_empty() {
+            if !self.shuf.has_refs() {
+                return Err(DecoderError::MissingReference);
+            }
+            return Ok((NABufferType::None, FrameType::Skip));
+        }
+        validate!(src.len() > 3);
+        let tag = u32::from(src[0]) | (u32::from(src[1]) << 8) | (u32::from(src[2]) << 16);
+        let is_intra = (tag & 1) == 0;
+        let version = ((tag >> 1) & 7) as u8;
+        let show = ((tag >> 4) & 1) != 0;
+        let part1_size = (tag >> 5) as usize;
+        if version > 3 {
+            return Err(DecoderError::NotImplemented);
+        }
+        let mut hdr_size = 3;
+        if is_intra {
+            validate!(src.len() > 10);
+            validate!(src[3..6] == [0x9D, 0x01, 0x2A]);
+            // the upper two bits are the scaling mode which is a hint for the player
+            let width   = (usize::from(src[6]) | (usize::from(src[7]) << 8)) & 0x3FFF;
+            let height  = (usize::from(src[8]) | (usize::from(src[9]) << 8)) & 0x3FFF;
+            validate!(width > 0 && height > 0);
+            self.set_dimensions(supp, width, height)?;
+            hdr_size = 10;
+        } else if !self.shuf.has_refs() {
+            return Err(DecoderError::MissingReference);
+        }
+        validate!(hdr_size + part1_size <= src.len());
+        let mut bc = BoolCoder::new(&src[hdr_size..][..part1_size])?;
+
+        if is_intra {
+            let _colour_space   = bc.read_bool();
+            let _clamping_type  = bc.read_bool();
+            self.models = ProbModels::default();
+            self.segmentation = Segmentation::default();
+            self.lf_deltas = LFDeltas::default();
+            self.sign_bias = [false; 4];
+        }
+        self.version = version;
+        self.read_segmentation(&mut bc);
+        self.lf_simple      = bc.read_bool();
+        self.lf_level       = bc.read_bits(6) as u8;
+        self.lf_sharpness   = bc.read_bits(3) as u8;
+        self.read_lf_deltas(&mut bc);
+        let num_partitions = 1 << bc.read_bits(2);
+        self.read_quants(&mut bc);
+        self.calc_segment_params();
+
+        let mut saved_models = None;
+        // golden and altref frames may be updated with the last frame or each other
+        let (mut refresh_golden, mut refresh_alt, mut copy_golden, mut copy_alt, mut refresh_last) = (true, true, 0, 0, true);
+        if !is_intra {
+            refresh_golden = bc.read_bool();
+            refresh_alt    = bc.read_bool();
+            if !refresh_golden {
+                copy_golden = bc.read_bits(2);
+            }
+            if !refresh_alt {
+                copy_alt = bc.read_bits(2);
+            }
+            self.sign_bias[VP8Ref::Golden as usize] = bc.read_bool();
+            self.sign_bias[VP8Ref::AltRef as usize] = bc.read_bool();
+        }
+        if !bc.read_bool() {
+            saved_models = Some(self.models);
+        }
+        if !is_intra {
+            refresh_last = bc.read_bool();
+        }
+        update_coef_probs(&mut bc, &mut self.models.coef_probs);
+        self.prob_skip = if bc.read_bool() { Some(bc.read_byte()) } else { None };
+        if !is_intra {
+            self.read_inter_probs(&mut bc);
+        }
+
+        // coefficient partition sizes are stored right after the first partition
+        let mut part_start = hdr_size + part1_size + (num_partitions - 1) * 3;
+        validate!(part_start <= src.len());
+        let mut parts: [&[u8]; MAX_PARTITIONS] = [&[]; MAX_PARTITIONS];
+        for (i, part) in parts[..num_partitions].iter_mut().enumerate() {
+            let part_size = if i + 1 < num_partitions {
+                    let sz = &src[hdr_size + part1_size + i * 3..];
+                    usize::from(sz[0]) | (usize::from(sz[1]) << 8) | (usize::from(sz[2]) << 16)
+                } else {
+                    src.len() - part_start
+                };
+            validate!(part_start + part_size <= src.len());
+            *part = &src[part_start..][..part_size];
+            part_start += part_size;
+        }
+        // the decoder needs at least four bytes to start while the data is implicitly padded with zeroes
+        let mut padded = [[0u8; 4]; MAX_PARTITIONS];
+        for (part, pad) in parts[..num_partitions].iter_mut().zip(padded.iter_mut()) {
+            if part.len() < 4 {
+                pad[..part.len()].copy_from_slice(part);
+                *part = pad;
+            }
+        }
+        let mut bc_parts = Vec::with_capacity(num_partitions);
+        for part in parts[..num_partitions].iter() {
+            bc_parts.push(BoolCoder::new(part)?);
+        }
+
+        let vinfo = self.get_vinfo();
+        let mut buf = supp.pool_u8.get_free();
+        if buf.as_ref().map(|buf| buf.get_info()) != Some(vinfo) {
+            self.shuf.clear();
+            if !is_intra {
+                return Err(DecoderError::MissingReference);
+            }
+            supp.pool_u8.reset();
+            supp.pool_u8.set_limits(supp.limits);
+            supp.pool_u8.prealloc_video(vinfo, 4)?;
+            buf = supp.pool_u8.get_free();
+        }
+        let mut buf = buf.ok_or(DecoderError::AllocError)?;
+
+        let refs = if !is_intra {
+                let last = self.shuf.get_last().ok_or(DecoderError::MissingReference)?;
+                [Some(last), self.shuf.get_golden(), self.shuf.get_altref()]
+            } else {
+                [None, None, None]
+            };
+
+        let mut frm = buf.as_mut().and_then(NASimpleVideoFrame::from_video_buf).ok_or(DecoderError::AllocError)?;
+        if is_intra {
+            for el in self.top_bmodes.iter_mut() {
+                *el = PredMode::DCPred;
+            }
+        }
+        for el in self.top_nz.iter_mut() {
+            *el = [false; 9];
+        }
+        for mb_y in 0..self.mb_h {
+            self.left_bmodes = [PredMode::DCPred; 4];
+            self.left_nz = [false; 9];
+            let bc2 = &mut bc_parts[mb_y & (num_partitions - 1)];
+            for mb_x in 0..self.mb_w {
+                self.decode_mb(&mut bc, bc2, &mut frm, &refs, mb_x, mb_y, is_intra)?;
+            }
+        }
+        if self.lf_level > 0 {
+            self.loop_filter_frame(&mut frm, is_intra);
+        }
+
+        let [last, golden, altref] = refs;
+        let new_golden = match (refresh_golden, copy_golden) {
+                (true, _) => Some(buf.clone()),
+                (_, 1)    => last.clone(),
+                (_, 2)    => altref.clone(),
+                _         => None,
+            };
+        let new_altref = match (refresh_alt, copy_alt) {
+                (true, _) => Some(buf.clone()),
+                (_, 1)    => last,
+                (_, 2)    => golden,
+                _         => None,
+            };
+        if let Some(frame) = new_golden {
+            self.shuf.add_golden_frame(frame);
+        }
+        if let Some(frame) = new_altref {
+            self.shuf.add_altref_frame(frame);
+        }
+        if refresh_last {
+            self.shuf.add_frame(buf.clone());
+        }
+        if let Some(models) = saved_models {
+            self.models = models;
+        }
+        if !show {
+            return Ok((NABufferType::None, FrameType::Skip));
+        }
+        Ok((NABufferType::Video(buf), if is_intra { FrameType::I } else { FrameType::P }))
+    }
+}
+
+impl NADecoder for VP8Decoder {
+    fn init(&mut self, supp: &mut NADecoderSupport, info: NACodecInfoRef) -> DecoderResult<()> {
+        if let NACodecTypeInfo::Video(vinfo) = info.get_properties() {
+            let myvinfo = NAVideoInfo::new(vinfo.get_width(), vinfo.get_height(), false, YUV420_FORMAT);
+            self.info = NACodecInfo::new_ref(info.get_name(), NACodecTypeInfo::Video(myvinfo), info.get_extradata()).into_ref();
+            supp.pool_u8.set_limits(supp.limits);
+            supp.pool_u8.set_dec_bufs(4);
+            Ok(())
+        } else {
+            Err(DecoderError::InvalidData)
+        }
+    }
+    fn decode(&mut self, supp: &mut NADecoderSupport, pkt: &NAPacket) -> DecoderResult<NAFrameRef> {
+        let src = pkt.get_buffer();
+        let (bufinfo, ftype) = self.decode_frame(supp, &src)?;
+        let mut frm = NAFrame::new_from_pkt(pkt, self.info.clone(), bufinfo);
+        frm.set_keyframe(ftype == FrameType::I);
+        frm.set_frame_type(ftype);
+        Ok(frm.into_ref())
+    }
+    fn flush(&mut self) {
+        self.shuf.clear();
+    }
+}
+
+pub fn get_decoder_vp8() -> Box<dyn NADecoder + Send> {
+    Box::new(VP8Decoder::new())
+}
+
+#[cfg(test)]
+mod test {
+    use nihav_core::codecs::*;
+    use nihav_codec_support::test::dec_video::*;
+    use super::*;
+
+    fn asset_name(name: &str) -> String {
+        format!("{}/assets/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+    fn test_vp8(name: &str, expected: &[Vec<[u32; 4]>]) {
+        test_vp8_size(name, (56, 40), expected);
+    }
+    fn test_vp8_size(name: &str, dims: (usize, usize), expected: &[Vec<[u32; 4]>]) {
+        let mut dec_reg = RegisteredDecoders::new();
+        crate::codecs::duck_register_all_decoders(&mut dec_reg);
+        let mut dec = (dec_reg.find_decoder("vp8").unwrap())();
+        let mut supp = NADecoderSupport::new();
+        let info = NACodecInfo::new("vp8", NACodecTypeInfo::Video(NAVideoInfo::new(0, 0, false, YUV420_FORMAT)), None).into_ref();
+        dec.init(&mut supp, info).unwrap();
+        let mut pts = 0;
+        test_decoding_md5(&asset_name(name), |src| {
+                let pkt = NAPacket::new(0, NATimeInfo::new(Some(pts), None, Some(1), 1, 30), false, src.to_vec());
+                pts += 1;
+                let frm = dec.decode(&mut supp, &pkt)?;
+                let vinfo = frm.get_info().get_properties().get_video_info().unwrap();
+                assert_eq!((vinfo.get_width(), vinfo.get_height()), dims);
+                Ok(frm.get_buffer())
+            }, expected);
+    }
+    #[test]
+    fn test_vp8_mv_pred() {
+        let mb_w = 3;
+        let mut mv_info = vec![MVInfo::default(); mb_w * 3];
+        let mv1 = MV { x: 4, y: -2 };
+        let mv2 = MV { x: -8, y: 6 };
+        let sign_bias = [false, false, true, false];
+        mv_info[0] = MVInfo { rframe: VP8Ref::Last, mv: mv1, split: true };
+        mv_info[1] = MVInfo { rframe: VP8Ref::Last, mv: mv1, split: false };
+        mv_info[3] = MVInfo { rframe: VP8Ref::Golden, mv: mv2, split: false };
+        // left motion vector refers to the frame with the different sign bias
+        let (near_mvs, cnt) = find_near_mvs(&mv_info, mb_w, 1, 1, VP8Ref::Last, &sign_bias);
+        assert_eq!(near_mvs, [mv1, mv1, -mv2]);
+        assert_eq!(cnt, [0, 3, 2, 1]);
+        let (near_mvs, cnt) = find_near_mvs(&mv_info, mb_w, 1, 1, VP8Ref::Golden, &sign_bias);
+        assert_eq!(near_mvs, [-mv1, -mv1, mv2]);
+        assert_eq!(cnt, [0, 3, 2, 1]);
+        // intra macroblocks are not used as candidates
+        mv_info[0].mv = ZERO_MV;
+        mv_info[1].rframe = VP8Ref::Intra;
+        let (near_mvs, cnt) = find_near_mvs(&mv_info, mb_w, 1, 1, VP8Ref::Golden, &sign_bias);
+        assert_eq!(near_mvs, [mv2, mv2, ZERO_MV]);
+        assert_eq!(cnt, [1, 2, 0, 1]);
+
+        assert_eq!(clamp_mv(MV { x: -200, y: 500 }, 0, 0, 3, 2), MV { x: -64, y: 128 });
+        assert_eq!(clamp_mv(MV { x: 200, y: -500 }, 2, 1, 3, 2), MV { x: 64, y: -128 });
+    }
+    #[test]
+    fn test_vp8_skip() {
+        let pkts = read_raw_packets(&asset_name("vp8.vp8"));
+        let mut supp = NADecoderSupport::new();
+        let mut dec = VP8Decoder::new();
+        let info = NACodecInfo::new("vp8", NACodecTypeInfo::Video(NAVideoInfo::new(0, 0, false, YUV420_FORMAT)), None).into_ref();
+        dec.init(&mut supp, info).unwrap();
+        assert_eq!(dec.decode_frame(&mut supp, &[]).err(), Some(DecoderError::MissingReference));
+        assert_eq!(dec.decode_frame(&mut supp, &pkts[1]).err(), Some(DecoderError::MissingReference));
+        let (_, ftype) = dec.decode_frame(&mut supp, &pkts[0]).unwrap();
+        assert_eq!(ftype, FrameType::I);
+        let (buf, ftype) = dec.decode_frame(&mut supp, &[]).unwrap();
+        assert!(matches!(buf, NABufferType::None));
+        assert_eq!(ftype, FrameType::Skip);
+        let (_, ftype) = dec.decode_frame(&mut supp, &pkts[1]).unwrap();
+        assert_eq!(ftype, FrameType::P);
+        dec.flush();
+        assert_eq!(dec.decode_frame(&mut supp, &pkts[2]).err(), Some(DecoderError::MissingReference));
+
+        let mut hdr = pkts[0].clone();
+        hdr[0] |= 4 << 1;
+        assert_eq!(dec.decode_frame(&mut supp, &hdr).err(), Some(DecoderError::NotImplemented));
+        let mut hdr = pkts[0].clone();
+        hdr[4] = 0;
+        assert_eq!(dec.decode_frame(&mut supp, &hdr).err(), Some(DecoderError::InvalidData));
+        supp.limits.max_width = 32;
+        assert_eq!(dec.decode_frame(&mut supp, &pkts[0]).err(), Some(DecoderError::LimitExceeded));
+    }
+    #[test]
+    fn test_vp8_corrupted() {
+        let pkts = read_raw_packets(&asset_name("vp8_advanced.vp8"));
+        let mut supp = NADecoderSupport::new();
+        let mut dec = VP8Decoder::new();
+        for (i, pkt) in pkts.iter().enumerate() {
+            let mut pkt = pkt.clone();
+            for (j, el) in pkt.iter_mut().enumerate().skip(3) {
+                if (j + i) % 7 == 0 {
+                    *el = el.wrapping_mul(37).wrapping_add(11);
+                }
+            }
+            let _ = dec.decode_frame(&mut supp, &pkt);
+            pkt.truncate(pkt.len() / 2);
+            let _ = dec.decode_frame(&mut supp, &pkt);
+        }
+    }
+    #[test]
+    fn test_vp8_simple() {
+        test_vp8("vp8.vp8", &[
+            vec![[0xc5c22f0b, 0x127c5edf, 0x910fd19d, 0x31248367], [0xdb0fe149, 0xfa7ce1d4, 0x837eefae, 0x20f5ecf0], [0x3da08303, 0x8a9053cf, 0x4b04c11a, 0x99417f47]],
+            vec![[0xf3875b15, 0xa46b464c, 0x08a72261, 0x6d942edb], [0xda6e7e37, 0xb702e0e7, 0x3cd095a7, 0x68979481], [0x616d6dc5, 0x70fca7ce, 0xa981a1cd, 0x803a07f7]],
+            vec![[0x6be0a929, 0xa12f7d3e, 0xf3aedfc0, 0x5a1c6ce3], [0x0c0a2b1b, 0xe916bca8, 0xdbce71bb, 0x1f49f885], [0x1a72adc7, 0xaaa13544, 0xb825968c, 0x162f6d43]],
+            vec![[0x11cc4dee, 0xf5d9972e, 0xbba55900, 0x14a4b123], [0xa2873efb, 0x0f0bcf2b, 0x4f07b2d7, 0x0e06c8ba], [0x46dfb5db, 0x6602aaf8, 0x1ffd8648, 0x556d498e]],
+            vec![[0x504baa4a, 0xbb225dec, 0x0b604fa3, 0x82ba6226], [0x6f385638, 0xa1eddfcf, 0x11e6b998, 0xc151ef0c], [0xf03f70f8, 0x38556198, 0x75d06564, 0x9aa5355a]],
+            vec![[0x78242815, 0x51c400f0, 0x6bca7863, 0xb52763b0], [0x0a343109, 0x4d600d52, 0x7ccee952, 0x968b855e], [0xa1d9a0ae, 0x9e3b440f, 0x5cc06be9, 0x9793f36a]],
+            vec![[0x446c95f5, 0xe579a4f2, 0xa5fa9c1d, 0x3e6e790f], [0x602ba80c, 0xd02c6b4f, 0x7cadf915, 0xf854e7b6], [0xff0b9f0f, 0x923a9eef, 0xc136de84, 0x02806deb]],
+            vec![[0x658d7e29, 0x8e8d66cd, 0x95365dad, 0x90d3584c], [0xd1d1b126, 0x464f43e0, 0x9d3fd15f, 0xbe541b29], [0x8496b6b3, 0x2814ba0f, 0x136431b0, 0x6923f261]],
+            vec![[0x8ea8ce49, 0x01c261db, 0x08eb0789, 0x1bc289e2], [0x1e65138f, 0x412aaa00, 0x06bdd800, 0x49b64581], [0x41c4c6b3, 0x693d9859, 0x51f56598, 0x307f3d57]],
+            vec![[0x24cd5ec4, 0x8a332e30, 0xdd0513e3, 0x0f925fe3], [0xbffd820e, 0xc059c077, 0xff26e1c3, 0x10a508dc], [0xd54d5fa5, 0xcd3441ec, 0x52021f13, 0x9087fe9b]],
+        ]);
+    }
+    #[test]
+    fn test_vp8_advanced() {
+        test_vp8("vp8_advanced.vp8", &[
+            vec![[0xb99153d9, 0x95ee0e3d, 0x55c74876, 0xea98736c], [0x20510828, 0xa8ff8ee5, 0xa4bedee5, 0x040e0bcc], [0x31128ac7, 0x6cb5146e, 0xab8716e0, 0x63a76c28]],
+            vec![[0xdc138637, 0x5411e2be, 0x8671713a, 0x126682d6], [0x2c1b80e4, 0x2cba7194, 0x47b9c09f, 0x5c53267d], [0x34d92ee3, 0xa35fd46a, 0x30f1d6aa, 0x8b5ca1d9]],
+            vec![],
+            vec![[0xf1bfb699, 0x30f2f64e, 0xf8fdd2e6, 0x5239d3a2], [0xd03a319b, 0xd4d54304, 0x82293567, 0xf5b138e2], [0x0af38bd7, 0x7ed47c56, 0x26b9bc4e, 0xca745ed1]],
+            vec![[0x37bcbe6b, 0x7bed59fa, 0x91198363, 0x200a32f8], [0x0559ea00, 0x9e6e3824, 0xad324f7b, 0x332c86fd], [0x472a5a36, 0xe87a06a3, 0x63131707, 0xc30bcc85]],
+            vec![[0x18055e3d, 0xd1d88cc7, 0x971f40ed, 0x4596a34e], [0x6c3add5f, 0xd528b522, 0xabf61ef6, 0xc62e897f], [0x2d1439b4, 0x941db5df, 0x3c50c2d1, 0xb7069d33]],
+            vec![[0x500cc43e, 0xdad47518, 0xf75d7287, 0x6087b081], [0x2a1a7097, 0x0a933f94, 0x8e926d21, 0x45beba36], [0x825aa00f, 0x077a459b, 0xd4428a34, 0x3c9e33a8]],
+            vec![[0x420445c8, 0x3858716d, 0x79071b31, 0x6b8c2d6e], [0x5c53a2a0, 0x573a6f80, 0x85f3c3af, 0xab2442a5], [0x0d22d8d6, 0xb56060df, 0xc0f1a69d, 0x4b0b8c70]],
+            vec![[0xe984366e, 0x3754f97d, 0x22f04a53, 0x180f1503], [0xae405d48, 0x4d467ef1, 0xb9834b5c, 0x599ea014], [0x7738e897, 0xf89fea7a, 0xf1015867, 0x56f7354a]],
+            vec![[0x357a04ce, 0x4b4ad96d, 0x54c34c97, 0x0f3cacd0], [0xbac23fcb, 0x07ed1504, 0x4056311a, 0xa42a2f2d], [0x0df6cfdb, 0x8a66c58c, 0x06f3fcf4, 0x0c1e69b4]],
+        ]);
+    }
+    // Keyframes produced by libwebp encoder with different segmentation, loop filter, partition
+    // and quantiser settings; the hashes come from libwebp decoder output.
+    // There are no inter frames here since libvpx reference decoder was not available to verify them.
+    #[test]
+    fn test_vp8_libwebp() {
+        test_vp8("libwebp_vp8.vp8", &[
+            vec![[0xb900f876, 0xaa6089d3, 0x93bb4733, 0x3a6d6b92], [0xc1434a17, 0x457563d0, 0x5f461c16, 0x2b868c6b], [0x15aaa3c1, 0x385fa338, 0xcb42f7b3, 0x232c69c0]],
+            vec![[0x659bd9b7, 0xcd31168b, 0x3936c73d, 0x2da338be], [0x0b34c496, 0x6b5acca3, 0x3757f24a, 0xcc55ddfb], [0xeaed073a, 0x52480cad, 0xdc559135, 0x1094c343]],
+            vec![[0xf4f954c3, 0x8b9e22f8, 0x178083c0, 0x84824f2e], [0x7b5cc6c6, 0x2bd77a88, 0x7d335c5d, 0x69bfddc2], [0x60de721e, 0xdaa88dd2, 0xaff8c86f, 0xece5ff3f]],
+            vec![[0xa0eb525f, 0xf0cacd7d, 0x19629297, 0x1167d0d0], [0xbde592f3, 0x3660167d, 0x631f1725, 0xfae3e1f4], [0x8cd839e0, 0xf214686b, 0xacb89d96, 0x40be4aad]],
+            vec![[0xd73f380f, 0x360ecaa8, 0x6afa13be, 0x9213c9f8], [0xda7dca5f, 0x3851f13f, 0x9fb6f4f2, 0x2c19894a], [0xf94cbc0a, 0xee2d96bd, 0x84c7bc4c, 0x4dbcd2e8]],
+            vec![[0xf8954699, 0xf394fe31, 0x5b00afdb, 0xfd45246d], [0x208a82b4, 0xc3f6f9c1, 0x90cac1d6, 0x89e4dbe8], [0xfd37cf80, 0x56b5b1d0, 0x4c48514f, 0x2dc4657f]],
+            vec![[0x105c96a4, 0x95365c12, 0x413d5e6b, 0xf5569677], [0x61d2726f, 0xa466d193, 0xe2819ffc, 0x2d205565], [0x8405071f, 0x7377c1f7, 0x2e8a25b9, 0x1a452fb5]],
+            vec![[0x18789b3d, 0x883fd1bb, 0x57283735, 0xfba3a75d], [0x11403899, 0xee098785, 0x6cfede82, 0xf4297d65], [0x7fc00cb0, 0xf25cc3a2, 0x0c2923d2, 0x8b8105e9]],
+        ]);
+    }
+    // The first frames of a libvpx-encoded WebM file (testdata/sample.webm from the infer crate).
+    // The keyframe matches libwebp output, the rest of the hashes come from this decoder since
+    // no reference inter frame decoder was available (the whole 166-frame stream decodes without visible drift).
+    #[test]
+    fn test_vp8_libvpx() {
+        test_vp8_size("libvpx_vp8.vp8", (560, 320), &[
+            vec![[0x3dcca880, 0x83f2709b, 0x885dbb99, 0xba3bf2bc], [0x572e4a18, 0x1c63dff5, 0x8b2f218c, 0xcb895b39], [0xc8da66ad, 0x5e821e09, 0x925ea6c8, 0x9521d77f]],
+            vec![[0x427050e4, 0x044debfd, 0xb4f668ef, 0xd5117025], [0x33a0c447, 0xad195c6e, 0xc2d5168e, 0x11daac35], [0x2a2f49e0, 0x03bdc473, 0x3ee4e40c, 0xcd78cf23]],
+            vec![[0x44b10bbe, 0x773588af, 0xb84ac45e, 0xc014117a], [0x71ed610c, 0xe07b71ce, 0xb9aed7c9, 0x267297c7], [0x7dee62da, 0x9524a71e, 0xc3ef9a07, 0xf3e66831]],
+            vec![[0xa62accd5, 0x0203790c, 0x3d490afe, 0x0d9ad2dc], [0x79f82abe, 0xce786de6, 0x7e4351ec, 0xbec7cda3], [0xb168aaf2, 0x265432ef, 0x4d2efc83, 0x7b6847c6]],
+            vec![[0xec48ab2b, 0xacd314a1, 0x82ca5681, 0x091e0380], [0x614e5e84, 0x0e618812, 0xfa72fc4a, 0x15429e94], [0x5943b65d, 0x5852e441, 0x31b1a5e3, 0x98119e5a]],
+            vec![[0x49a42dfd, 0x94face1e, 0x0ce08c94, 0xb72a63ad], [0x2f611ea2, 0xe592755f, 0x5bad83ff, 0x23ee8331], [0xc8696b0a, 0x1f7bd85f, 0x646b5ed8, 0x2120ad43]],
+            vec![[0x37d248dc, 0x6e9b1132, 0xab457c37, 0x8c656687], [0x9922940d, 0x8c9fccc7, 0x3f358695, 0x8d158681], [0x1d21059e, 0xaf297b26, 0x1fd794e2, 0x8b24f773]],
+            vec![[0x4de2bbd8, 0x63d933af, 0xfc4bda09, 0xda3f7d82], [0x01ebeb4f, 0x7f6a108f, 0xac1c10b0, 0x7e2077f2], [0x238279de, 0x0d7cac18, 0xd211ad8b, 0xd2d5e092]],
+            vec![[0x6e694b95, 0x54bc8a9e, 0x7f38422e, 0x4cef620a], [0x643b7f32, 0xfb700a20, 0xafa46be4, 0x9c7a89a5], [0x157b44ff, 0x1a6c83b6, 0x97d33460, 0xc538b4df]],
+            vec![[0xebc8e783, 0xbddfd178, 0x59a02d75, 0x7ec6cf78], [0xab842bc0, 0x48680f65, 0x56c27557, 0x3e1e0a77], [0x9dabf22f, 0x96d1d2c3, 0xe34e6a1e, 0x5b2dd9ac]],
+            vec![[0x9a891761, 0x873c9686, 0xdc6f0a0e, 0xa7552ecb], [0x0e5790e2, 0x5dedf55e, 0x32eb0201, 0x2126311b], [0x1d92df69, 0x81c7e821, 0xdb78f941, 0x366aa7ff]],
+            vec![[0xd1f838f8, 0x6d17ed0f, 0x7410fd4e, 0x7525d8f5], [0xcdeb9fa2, 0x9862b761, 0xb8a6d79a, 0x1117b973], [0xfeb19055, 0x0b96109d, 0x5052851a, 0x7411ff6a]],
+            vec![[0xf6441b8c, 0x701c5efb, 0xd1f0dda9, 0x62b75b18], [0xb0eb2ff6, 0x3b1acc97, 0x6b9f3b15, 0xbd19ad92], [0xbc68b557, 0x0dd93707, 0xa45daecf, 0x27f1fc79]],
+            vec![[0xa24fc6d7, 0x866c89b2, 0x09c11755, 0x575e438f], [0xfe02aad4, 0x559cf414, 0xa7446327, 0xde21a2ec], [0xa93da74a, 0x0d08ca69, 0x43bc6e3f, 0x268c0d1b]],
+            vec![[0x2593d153, 0x07219515, 0x00626382, 0xd0d429cc], [0x5ce77255, 0x305095fb, 0xabfb6591, 0x5fbd6939], [0x1ff6ee3d, 0x988b7db5, 0xc5e25b94, 0x9398d709]],
+            vec![[0xc318538f, 0xe7bd6292, 0xca87b1f2, 0x4a1060b5], [0xa6b1d56f, 0xa9d976b2, 0xc99071d2, 0xa4e91c84], [0x0ffca5f8, 0x5a19c2ee, 0x2a702ac8, 0xb437fba8]],
+            vec![[0x30a8ef7c, 0x3635f7cb, 0x18e9031f, 0xca67722d], [0xe47ceaad, 0xb2079d0c, 0x2b6fa01e, 0x1633dabb], [0xd89a9461, 0x2682c4c0, 0xfff0c799, 0xca830966]],
+            vec![[0x589799bc, 0xef2751b5, 0xdf83f65f, 0x8eb5c158], [0x3ae289fe, 0xc7e92664, 0xa8f114a2, 0x1c4b2f17], [0xfcd250b4, 0xb27816b2, 0x71360b8a, 0xea8fdab8]],
+            vec![[0x21fa16be, 0x8319f4ba, 0xc85c5b23, 0x96533ffd], [0x8a082299, 0xef6d4d23, 0x3ff074bd, 0xd25b1d4c], [0xbe21e767, 0xff0f65e4, 0x2c9bb736, 0x067f5728]],
+            vec![[0x4d3f3638, 0x75841ea8, 0x6b4d181d, 0x278de724], [0x40481827, 0x9a9779da, 0x524756d1, 0xb6878bd4], [0xc18f65e4, 0xc980610b, 0xf161c893, 0x2ed79b58]],
+            vec![[0x6d80afe1, 0x8ac1cb8c, 0x40259b8e, 0xbdbfbdcf], [0x70b6323b, 0x9c8e5114, 0xf997b01b, 0x12a75e38], [0x74479a1f, 0xa4859aa0, 0x1143c9c0, 0xdf46a4a7]],
+            vec![[0xb31085f1, 0x4387d3bd, 0xcb7c4cca, 0x7b6d3878], [0xcbdfb861, 0xce6ac7c8, 0x28937f54, 0x9cd813ad], [0xa11a08c3, 0xc9150ab6, 0x9b5d8d5e, 0xc1ac2ead]],
+            vec![[0x00c4951b, 0xd2a40dc9, 0x26336763, 0x2bc833ab], [0x77dc6d94, 0x56f7d202, 0x0247fa42, 0x9c149d56], [0x921d3b18, 0xad19ce62, 0x46df29d8, 0x0ae03048]],
+            vec![[0x1005056c, 0x1b89bdd5, 0x444b79e2, 0x07894d54], [0xd1eb527d, 0x65d744d8, 0xe79617fd, 0xdf2dd3db], [0x4be4eca7, 0xc87cd3ac, 0xe79850e7, 0xaae6de8b]],
+            vec![[0xc311ca14, 0xa25869da, 0x3923e983, 0x12424d09], [0x4860cf1c, 0xdd15dd38, 0x5666e958, 0x4414cadb], [0xe3930781, 0x86e3c87c, 0x7cc2d258, 0x02911a8d]],
+            vec![[0x07c902ad, 0x33ceaabf, 0xe79dfd15, 0xcdc26c05], [0x10b1b714, 0x98e1f457, 0x7bffca58, 0x4b6623e3], [0x3fbe0abe, 0x6a22df24, 0x934c4509, 0xf7854a0e]],
+            vec![[0xfdb74100, 0x5990f957, 0xb8dc4d3f, 0x432ce5ea], [0xc01a1b77, 0xfaacff0c, 0xb331b36e, 0x9863f9a3], [0x601430e9, 0xfacd8d9e, 0xf45495ab, 0x72a3d431]],
+            vec![[0x5a39ab47, 0x7694a4e0, 0x4a452409, 0x88b48a77], [0xbb909c03, 0xd374a560, 0x80c5786e, 0x8b8300ba], [0x8280d3a8, 0xca217f6e, 0xaea9be56, 0xad48d96b]],
+            vec![[0x98f71ef7, 0xe2af69c0, 0x21260fb2, 0x8d1b63f7], [0x074b3f6a, 0x2de3d0e1, 0x31a8fabf, 0xa73851b4], [0xb3d86bf6, 0x31ad9dbf, 0x739b5264, 0x1f1ad242]],
+            vec![[0x8af55432, 0xac3ab6b5, 0xe46c280f, 0x9514a93a], [0x58024f2b, 0x994e61ef, 0x021f4e6a, 0xb2a3b0f4], [0x702db3ba, 0x37d97e73, 0x357345df, 0xcba10926]],
+            vec![[0x3849f5f7, 0x75cd2af4, 0xe34efeab, 0x9cc62f97], [0x390914a6, 0x5d32ad56, 0x3c514962, 0x0ef0da35], [0x8a93fadb, 0xe5274a8f, 0x5aed1a2d, 0xc8cde8e7]],
+            vec![[0xacd5a76e, 0x9b06bdc9, 0x18687e20, 0x2d0ffcd2], [0x9d409b26, 0xfc42f244, 0xb58409c7, 0xba5f0bba], [0xa4a75bd2, 0xc523e225, 0xcda24a1f, 0x1dd5de7f]],
+            vec![[0x5b8eac6e, 0xb2d3bb26, 0xabff8e2c, 0x7abb1f93], [0x274893ba, 0x9a988506, 0xf2cda9f3, 0xf3942589], [0x8e9994b5, 0x580278a4, 0xa9a8adad, 0xb08390ab]],
+            vec![[0x64720198, 0xf8f8234e, 0x34e1b877, 0x572ffefe], [0x03a728ae, 0xc4ffa1db, 0xfb28619e, 0x1299f2a2], [0x855b9170, 0x8923229f, 0x6b6fd1d9, 0xcf63e0a6]],
+            vec![[0x94e967fb, 0xdb709446, 0x89eb06ae, 0x03dba26a], [0x0f428f54, 0x57f333d6, 0x89c1e5cd, 0xcb74caf7], [0x4cf92919, 0x1a39f101, 0x8ece9a11, 0xa33104b0]],
+            vec![[0x8ef60310, 0xd0ed2396, 0xe5484e83, 0x52102b23], [0xf3523d5a, 0xc800f447, 0xe3f56ed8, 0x1de37b06], [0x8c2e49d9, 0x36f3bdc7, 0xcd9ae315, 0x57f1db1d]],
+            vec![[0x2f69f86c, 0xf02c0817, 0xf92ecbd4, 0x8ef86f73], [0xa008bb84, 0x5f6dbc98, 0x3e728b96, 0x1ddcef7e], [0xf08fad42, 0x2b4df2ce, 0x1b517ff9, 0x026b557c]],
+            vec![[0x7a9da63f, 0xeeb9e258, 0x8122131d, 0xed3918d5], [0xb49daa9b, 0xd4f50857, 0xd63690b8, 0x30f56270], [0x81f7711b, 0x944f22b9, 0x6f16b023, 0x9d1e6c0f]],
+            vec![[0xb46a4440, 0x7f66e88a, 0x8b5c5fb3, 0x9c4c9297], [0xe8bc68f6, 0xc392bf57, 0xe55e9f1d, 0x2a4ee71c], [0xdc97a33a, 0xe382b5e7, 0xe8ba28e2, 0xcf50e8cb]],
+            vec![[0x2195346e, 0x8c7e26d6, 0x5caed949, 0x256ef5d6], [0x3f098170, 0xa9c94a84, 0x866c3594, 0x111445ef], [0xa0129de5, 0xb973265b, 0xab5b19cb, 0xc7ecfa8f]],
+        ]);
+    }
+}
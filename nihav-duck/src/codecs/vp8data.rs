@@ -0,0 +1,44 @@
+pub const DEFAULT_MV_PROBS: [[u8; 19]; 2] = [
+    [ 162, 128, 225, 146, 172, 147, 214,  39, 156, 128, 129, 132,  75, 145, 178, 206, 239, 254, 254 ],
+    [ 164, 128, 204, 170, 119, 235, 140, 230, 228, 128, 130, 130,  74, 148, 180, 203, 236, 254, 254 ]
+];
+
+// indexed by the weight of the candidates for the zero, nearest, near and split modes
+pub const MODE_CONTEXTS: [[u8; 4]; 6] = [
+    [   7,   1,   1, 143 ],
+    [  14,  18,  14, 107 ],
+    [ 135,  64,  57,  68 ],
+    [  60,  56, 128,  65 ],
+    [ 159, 134, 128,  34 ],
+    [ 234, 188, 128,  28 ]
+];
+
+// indexed by the relation between the left and above sub-block motion vectors
+pub const SUB_MV_REF_PROBS: [[u8; 3]; 5] = [
+    [ 147, 136,  18 ],
+    [ 106, 145,   1 ],
+    [ 179, 121,   1 ],
+    [ 223,   1,  34 ],
+    [ 208,   1,   1 ]
+];
+
+pub const DC_QUANTS: [i16; 128] = [
+      4,   5,   6,   7,   8,   9,  10,  10,  11,  12,  13,  14,  15,  16,  17,  17,
+     18,  19,  20,  20,  21,  21,  22,  22,  23,  23,  24,  25,  25,  26,  27,  28,
+     29,  30,  31,  32,  33,  34,  35,  36,  37,  37,  38,  39,  40,  41,  42,  43,
+     44,  45,  46,  46,  47,  48,  49,  50,  51,  52,  53,  54,  55,  56,  57,  58,
+     59,  60,  61,  62,  63,  64,  65,  66,  67,  68,  69,  70,  71,  72,  73,  74,
+     75,  76,  76,  77,  78,  79,  80,  81,  82,  83,  84,  85,  86,  87,  88,  89,
+     91,  93,  95,  96,  98, 100, 101, 102, 104, 106, 108, 110, 112, 114, 116, 118,
+    122, 124, 126, 128, 130, 132, 134, 136, 138, 140, 143, 145, 148, 151, 154, 157
+];
+pub const AC_QUANTS: [i16; 128] = [
+      4,   5,   6,   7,   8,   9,  10,  11,  12,  13,  14,  15,  16,  17,  18,  19,
+     20,  21,  22,  23,  24,  25,  26,  27,  28,  29,  30,  31,  32,  33,  34,  35,
+     36,  37,  38,  39,  40,  41,  42,  43,  44,  45,  46,  47,  48,  49,  50,  51,
+     52,  53,  54,  55,  56,  57,  58,  60,  62,  64,  66,  68,  70,  72,  74,  76,
+     78,  80,  82,  84,  86,  88,  90,  92,  94,  96,  98, 100, 102, 104, 106, 108,
+    110, 112, 114, 116, 119, 122, 125, 128, 131, 134, 137, 140, 143, 146, 149, 152,
+    155, 158, 161, 164, 167, 170, 173, 177, 181, 185, 189, 193, 197, 201, 205, 209,
+    213, 217, 221, 225, 229, 234, 239, 245, 249, 254, 259, 264, 269, 274, 279, 284
+];
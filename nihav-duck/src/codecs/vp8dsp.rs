@@ -0,0 +1,101 @@
+use nihav_core::frame::*;
+use nihav_codec_support::codecs::blockdsp::edge_emu;
+
+const C1: i32 = 20091; // cos(pi/8) * sqrt(2) - 1
+const C2: i32 = 35468; // sin(pi/8) * sqrt(2)
+
+fn idct4(src: [i32; 4]) -> [i32; 4] {
+    let a1 = src[0] + src[2];
+    let b1 = src[0] - src[2];
+    let c1 = ((src[1] * C2) >> 16) - (src[3] + ((src[3] * C1) >> 16));
+    let d1 = (src[1] + ((src[1] * C1) >> 16)) + ((src[3] * C2) >> 16);
+    [a1 + d1, b1 + c1, b1 - c1, a1 - d1]
+}
+
+pub fn vp8_idct(coeffs: &mut [i16; 16]) {
+    // columns are transformed first and intermediate values are stored as 16-bit ones
+    let mut tmp = [0i32; 16];
+    for i in 0..4 {
+        let col = idct4([i32::from(coeffs[i]), i32::from(coeffs[i + 4]), i32::from(coeffs[i + 8]), i32::from(coeffs[i + 12])]);
+        for (j, &val) in col.iter().enumerate() {
+            tmp[i + j * 4] = i32::from(val as i16);
+        }
+    }
+    for (dst, src) in coeffs.chunks_exact_mut(4).zip(tmp.chunks_exact(4)) {
+        let row = idct4([src[0], src[1], src[2], src[3]]);
+        for (dst, &val) in dst.iter_mut().zip(row.iter()) {
+            *dst = ((val + 4) >> 3) as i16;
+        }
+    }
+}
+
+/// Performs inverse WHT on Y2 block and puts the results as DCs of the luma blocks.
+pub fn vp8_iwht(coeffs: &mut [[i16; 16]; 25]) {
+    let src = &coeffs[24];
+    let mut tmp = [0i32; 16];
+    for i in 0..4 {
+        let a1 = i32::from(src[i])     + i32::from(src[i + 12]);
+        let b1 = i32::from(src[i + 4]) + i32::from(src[i + 8]);
+        let c1 = i32::from(src[i + 4]) - i32::from(src[i + 8]);
+        let d1 = i32::from(src[i])     - i32::from(src[i + 12]);
+        tmp[i]      = i32::from((a1 + b1) as i16);
+        tmp[i + 4]  = i32::from((c1 + d1) as i16);
+        tmp[i + 8]  = i32::from((a1 - b1) as i16);
+        tmp[i + 12] = i32::from((d1 - c1) as i16);
+    }
+    for (i, row) in tmp.chunks_exact(4).enumerate() {
+        let a1 = row[0] + row[3];
+        let b1 = row[1] + row[2];
+        let c1 = row[1] - row[2];
+        let d1 = row[0] - row[3];
+        let out = [a1 + b1, c1 + d1, a1 - b1, d1 - c1];
+        for (j, &val) in out.iter().enumerate() {
+            coeffs[i * 4 + j][0] = ((val + 3) >> 3) as i16;
+        }
+    }
+}
+
+const BILINEAR_FILTERS: [[u16; 2]; 8] = [
+    [ 128,   0 ], [ 112,  16 ], [  96,  32 ], [  80,  48 ],
+    [  64,  64 ], [  48,  80 ], [  32,  96 ], [  16, 112 ]
+];
+
+const EDGE_STRIDE: usize = 32;
+
+fn filter2(a: u8, b: u8, filt: &[u16; 2]) -> u8 {
+    ((u16::from(a) * filt[0] + u16::from(b) * filt[1] + 64) >> 7) as u8
+}
+
+/// Performs motion compensation of a block with bilinear subpixel filters.
+///
+/// Arguments are the same as for `mc_block()` and `mc_buf` should be at least `MC_BUF_SIZE` bytes long.
+#[allow(clippy::too_many_arguments)]
+pub fn mc_block_bilinear(dst: &mut [u8], doff: usize, dstride: usize, xpos: usize, ypos: usize, mvx: i16, mvy: i16, reffrm: &NAVideoBuffer<u8>, plane: usize, bw: usize, bh: usize, mc_buf: &mut [u8]) {
+    let (fx, fy) = ((mvx & 7) as usize, (mvy & 7) as usize);
+    let sx = (xpos as isize) + isize::from(mvx >> 3);
+    let sy = (ypos as isize) + isize::from(mvy >> 3);
+    let (w, h) = reffrm.get_dimensions(plane);
+    let (w, h) = if plane == 0 { ((w + 15) & !15, (h + 15) & !15) } else { ((w + 7) & !7, (h + 7) & !7) };
+
+    let (src, soff, sstride) = if sx >= 0 && sy >= 0 && (sx as usize) + bw < w && (sy as usize) + bh < h {
+            let sstride = reffrm.get_stride(plane);
+            (reffrm.get_data().as_slice(), reffrm.get_offset(plane) + (sx as usize) + (sy as usize) * sstride, sstride)
+        } else {
+            edge_emu(reffrm, sx, sy, bw + 1, bh + 1, mc_buf, EDGE_STRIDE, plane, 4);
+            (&mc_buf[..], 0, EDGE_STRIDE)
+        };
+
+    let mut tmp = [0u8; 16 * 17];
+    let hfilt = &BILINEAR_FILTERS[fx];
+    for (tline, sline) in tmp.chunks_mut(16).zip(src[soff..].chunks(sstride)).take(bh + 1) {
+        for (x, el) in tline[..bw].iter_mut().enumerate() {
+            *el = filter2(sline[x], sline[x + 1], hfilt);
+        }
+    }
+    let vfilt = &BILINEAR_FILTERS[fy];
+    for (y, dline) in dst[doff..].chunks_mut(dstride).take(bh).enumerate() {
+        for (x, el) in dline[..bw].iter_mut().enumerate() {
+            *el = filter2(tmp[x + y * 16], tmp[x + (y + 1) * 16], vfilt);
+        }
+    }
+}
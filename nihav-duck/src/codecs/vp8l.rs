@@ -0,0 +1,487 @@
+//! VP8L lossless image stream decoder.
+//!
+//! WebP uses it for the compressed alpha planes, those are stored as an image stream without
+//! VP8L header and keep alpha values in the green channel.
+use nihav_core::codecs::*;
+use nihav_core::io::bitreader::*;
+
+const NUM_LITERALS: usize = 256;
+const NUM_LENGTH_CODES: usize = 24;
+const NUM_DIST_CODES: usize = 40;
+const NUM_CODE_LEN_CODES: usize = 19;
+const MAX_CODE_LEN: usize = 15;
+const MAX_CACHE_BITS: u8 = 11;
+// code length repeated by the repeat code before any non-zero length is seen
+const DEFAULT_CODE_LEN: u8 = 8;
+
+const CODE_LEN_ORDER: [usize; NUM_CODE_LEN_CODES] = [
+    17, 18, 0, 1, 2, 3, 4, 5, 16, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15
+];
+
+/// Short distance codes in form of `(dy << 4) | (8 - dx)`.
+pub(super) const CODE_TO_PLANE: [u8; 120] = [
+    0x18, 0x07, 0x17, 0x19, 0x28, 0x06, 0x27, 0x29, 0x16, 0x1a,
+    0x26, 0x2a, 0x38, 0x05, 0x37, 0x39, 0x15, 0x1b, 0x36, 0x3a,
+    0x25, 0x2b, 0x48, 0x04, 0x47, 0x49, 0x14, 0x1c, 0x35, 0x3b,
+    0x46, 0x4a, 0x24, 0x2c, 0x58, 0x45, 0x4b, 0x34, 0x3c, 0x03,
+    0x57, 0x59, 0x13, 0x1d, 0x56, 0x5a, 0x23, 0x2d, 0x44, 0x4c,
+    0x55, 0x5b, 0x33, 0x3d, 0x68, 0x02, 0x67, 0x69, 0x12, 0x1e,
+    0x66, 0x6a, 0x22, 0x2e, 0x54, 0x5c, 0x43, 0x4d, 0x65, 0x6b,
+    0x32, 0x3e, 0x78, 0x01, 0x77, 0x79, 0x53, 0x5d, 0x11, 0x1f,
+    0x64, 0x6c, 0x42, 0x4e, 0x76, 0x7a, 0x21, 0x2f, 0x75, 0x7b,
+    0x31, 0x3f, 0x63, 0x6d, 0x52, 0x5e, 0x00, 0x74, 0x7c, 0x41,
+    0x4f, 0x10, 0x20, 0x62, 0x6e, 0x30, 0x73, 0x7d, 0x51, 0x5f,
+    0x40, 0x72, 0x7e, 0x61, 0x6f, 0x50, 0x71, 0x7f, 0x60, 0x70
+];
+
+const GREEN: usize = 0;
+const RED:   usize = 1;
+const BLUE:  usize = 2;
+const ALPHA: usize = 3;
+const DIST:  usize = 4;
+
+pub(super) fn subsample_size(size: usize, bits: u8) -> usize {
+    (size + (1 << bits) - 1) >> bits
+}
+
+pub(super) fn color_cache_hash(pix: u32, bits: u8) -> usize {
+    (pix.wrapping_mul(0x1E35A7BD) >> (32 - bits)) as usize
+}
+
+/// Canonical prefix code decoded bit by bit.
+struct PrefixCode {
+    // symbols sorted by their code lengths
+    syms:   Vec<u16>,
+    counts: [u16; MAX_CODE_LEN + 1],
+}
+
+impl PrefixCode {
+    fn new(lens: &[u8]) -> DecoderResult<Self> {
+        let mut counts = [0u16; MAX_CODE_LEN + 1];
+        for &len in lens.iter() {
+            counts[usize::from(len)] += 1;
+        }
+        let mut offsets = [0usize; MAX_CODE_LEN + 1];
+        for len in 1..MAX_CODE_LEN {
+            offsets[len + 1] = offsets[len] + usize::from(counts[len]);
+        }
+        let mut syms = vec![0; lens.len() - usize::from(counts[0])];
+        validate!(!syms.is_empty());
+        for (sym, &len) in lens.iter().enumerate() {
+            if len > 0 {
+                syms[offsets[usize::from(len)]] = sym as u16;
+                offsets[usize::from(len)] += 1;
+            }
+        }
+        // a single symbol is coded with zero bits whatever its length is
+        if syms.len() > 1 {
+            let mut left = 1i32;
+            for &cnt in counts[1..].iter() {
+                left = left * 2 - i32::from(cnt);
+                validate!(left >= 0);
+            }
+            validate!(left == 0);
+        }
+        Ok(Self { syms, counts })
+    }
+    fn read(&self, br: &mut BitReader) -> DecoderResult<usize> {
+        if self.syms.len() == 1 {
+            return Ok(usize::from(self.syms[0]));
+        }
+        let mut code = 0;
+        let mut first = 0;
+        let mut idx = 0;
+        for &cnt in self.counts[1..].iter() {
+            code |= br.read(1)?;
+            let cnt = u32::from(cnt);
+            if code < first + cnt {
+                return Ok(usize::from(self.syms[(idx + code - first) as usize]));
+            }
+            idx += cnt;
+            first = (first + cnt) << 1;
+            code <<= 1;
+        }
+        Err(DecoderError::InvalidData)
+    }
+}
+
+fn read_code_lengths(br: &mut BitReader, lens: &mut [u8]) -> DecoderResult<()> {
+    let mut cl_lens = [0u8; NUM_CODE_LEN_CODES];
+    let num_codes = br.read(4)? as usize + 4;
+    for &idx in CODE_LEN_ORDER[..num_codes].iter() {
+        cl_lens[idx] = br.read(3)? as u8;
+    }
+    let cl_code = PrefixCode::new(&cl_lens)?;
+
+    let mut max_tokens = if br.read_bool()? {
+            let nbits = 2 + 2 * br.read(3)? as u8;
+            let max_sym = 2 + br.read(nbits)? as usize;
+            validate!(max_sym <= lens.len());
+            max_sym
+        } else {
+            lens.len()
+        };
+    let mut prev_len = DEFAULT_CODE_LEN;
+    let mut pos = 0;
+    while pos < lens.len() && max_tokens > 0 {
+        max_tokens -= 1;
+        let tok = cl_code.read(br)?;
+        if tok < 16 {
+            lens[pos] = tok as u8;
+            pos += 1;
+            if tok != 0 {
+                prev_len = tok as u8;
+            }
+        } else {
+            let (len, count) = match tok {
+                    16 => (prev_len, 3 + br.read(2)? as usize),
+                    17 => (0, 3 + br.read(3)? as usize),
+                    _  => (0, 11 + br.read(7)? as usize),
+                };
+            validate!(pos + count <= lens.len());
+            lens[pos..][..count].fill(len);
+            pos += count;
+        }
+    }
+    Ok(())
+}
+
+fn read_prefix_code(br: &mut BitReader, alphabet_size: usize) -> DecoderResult<PrefixCode> {
+    let mut lens = vec![0u8; alphabet_size];
+    if br.read_bool()? {
+        // simple code with one or two symbols
+        let two_syms = br.read_bool()?;
+        let sym_bits = if br.read_bool()? { 8 } else { 1 };
+        let sym0 = br.read(sym_bits)? as usize;
+        validate!(sym0 < alphabet_size);
+        lens[sym0] = 1;
+        if two_syms {
+            let sym1 = br.read(8)? as usize;
+            validate!(sym1 < alphabet_size);
+            lens[sym1] = 1;
+        }
+    } else {
+        read_code_lengths(br, &mut lens)?;
+    }
+    PrefixCode::new(&lens)
+}
+
+fn read_lz_value(br: &mut BitReader, sym: usize) -> DecoderResult<usize> {
+    if sym < 4 {
+        return Ok(sym + 1);
+    }
+    let extra_bits = (sym - 2) >> 1;
+    let offset = (2 + (sym & 1)) << extra_bits;
+    Ok(offset + br.read(extra_bits as u8)? as usize + 1)
+}
+
+fn plane_code_to_distance(width: usize, code: usize) -> usize {
+    if code > CODE_TO_PLANE.len() {
+        code - CODE_TO_PLANE.len()
+    } else {
+        let dist_code = usize::from(CODE_TO_PLANE[code - 1]);
+        let yoff = dist_code >> 4;
+        let xoff = 8 - (dist_code & 0xF) as isize;
+        ((yoff * width) as isize + xoff).max(1) as usize
+    }
+}
+
+/// Decodes entropy-coded image, only the main image may use several groups of prefix codes.
+fn read_entropy_image(br: &mut BitReader, width: usize, height: usize, is_main: bool) -> DecoderResult<Vec<u32>> {
+    let cache_bits = if br.read_bool()? {
+            let bits = br.read(4)? as u8;
+            validate!(bits > 0 && bits <= MAX_CACHE_BITS);
+            bits
+        } else {
+            0
+        };
+    let (meta_bits, meta_w, meta_image) = if is_main && br.read_bool()? {
+            let bits = br.read(3)? as u8 + 2;
+            let meta_w = subsample_size(width, bits);
+            let image = read_entropy_image(br, meta_w, subsample_size(height, bits), false)?;
+            (bits, meta_w, image.iter().map(|&pix| ((pix >> 8) & 0xFFFF) as usize).collect())
+        } else {
+            (0, 1, vec![0])
+        };
+    let num_groups = meta_image.iter().fold(0, |acc, &grp| acc.max(grp)) + 1;
+    let cache_size = if cache_bits > 0 { 1 << cache_bits } else { 0 };
+    let mut groups = Vec::with_capacity(num_groups);
+    for _ in 0..num_groups {
+        groups.push([
+            read_prefix_code(br, NUM_LITERALS + NUM_LENGTH_CODES + cache_size)?,
+            read_prefix_code(br, NUM_LITERALS)?,
+            read_prefix_code(br, NUM_LITERALS)?,
+            read_prefix_code(br, NUM_LITERALS)?,
+            read_prefix_code(br, NUM_DIST_CODES)?,
+        ]);
+    }
+
+    let mut cache = vec![0u32; cache_size];
+    let npixels = width * height;
+    let mut pixels = Vec::with_capacity(npixels);
+    let (mut x, mut y) = (0, 0);
+    while pixels.len() < npixels {
+        let group = if meta_bits > 0 { &groups[meta_image[(x >> meta_bits) + (y >> meta_bits) * meta_w]] } else { &groups[0] };
+        let code = group[GREEN].read(br)?;
+        let start = pixels.len();
+        if code < NUM_LITERALS {
+            let red   = group[RED].read(br)? as u32;
+            let blue  = group[BLUE].read(br)? as u32;
+            let alpha = group[ALPHA].read(br)? as u32;
+            pixels.push((alpha << 24) | (red << 16) | ((code as u32) << 8) | blue);
+        } else if code < NUM_LITERALS + NUM_LENGTH_CODES {
+            let len = read_lz_value(br, code - NUM_LITERALS)?;
+            let dist_sym = group[DIST].read(br)?;
+            let dist = plane_code_to_distance(width, read_lz_value(br, dist_sym)?);
+            validate!(dist <= start && len <= npixels - start);
+            for i in start..start + len {
+                let pix = pixels[i - dist];
+                pixels.push(pix);
+            }
+        } else {
+            pixels.push(cache[code - NUM_LITERALS - NUM_LENGTH_CODES]);
+        }
+        if cache_bits > 0 {
+            for &pix in pixels[start..].iter() {
+                cache[color_cache_hash(pix, cache_bits)] = pix;
+            }
+        }
+        x += pixels.len() - start;
+        while x >= width {
+            x -= width;
+            y += 1;
+        }
+    }
+    Ok(pixels)
+}
+
+enum Transform {
+    Predictor(u8, Vec<u32>),
+    CrossColor(u8, Vec<u32>),
+    SubtractGreen,
+    ColorIndexing(u8, Vec<u32>),
+}
+
+fn add_pixels(a: u32, b: u32) -> u32 {
+    ((a & 0xFF00FF00).wrapping_add(b & 0xFF00FF00) & 0xFF00FF00) | ((a & 0x00FF00FF).wrapping_add(b & 0x00FF00FF) & 0x00FF00FF)
+}
+
+pub(super) fn avg2(a: u32, b: u32) -> u32 {
+    (((a ^ b) & 0xFEFEFEFE) >> 1) + (a & b)
+}
+
+fn channel(pix: u32, shift: u8) -> i32 {
+    ((pix >> shift) & 0xFF) as i32
+}
+
+fn map_channels<F: Fn(u8) -> i32>(func: F) -> u32 {
+    [0, 8, 16, 24].iter().fold(0, |acc, &shift| acc | ((func(shift).clamp(0, 255) as u32) << shift))
+}
+
+/// Predicts the pixel from its left, top, top-left and top-right neighbours with the provided mode.
+pub(super) fn predict(mode: u32, l: u32, t: u32, tl: u32, tr: u32) -> u32 {
+    match mode {
+        1  => l,
+        2  => t,
+        3  => tr,
+        4  => tl,
+        5  => avg2(avg2(l, tr), t),
+        6  => avg2(l, tl),
+        7  => avg2(l, t),
+        8  => avg2(tl, t),
+        9  => avg2(t, tr),
+        10 => avg2(avg2(l, tl), avg2(t, tr)),
+        11 => {
+            let diff: i32 = [0, 8, 16, 24].iter().map(|&shift|
+                    (channel(l, shift) - channel(tl, shift)).abs() - (channel(t, shift) - channel(tl, shift)).abs()
+                ).sum();
+            if diff <= 0 { t } else { l }
+        },
+        12 => map_channels(|shift| channel(l, shift) + channel(t, shift) - channel(tl, shift)),
+        13 => {
+            let avg = avg2(l, t);
+            map_channels(|shift| channel(avg, shift) + (channel(avg, shift) - channel(tl, shift)) / 2)
+        },
+        _  => 0xFF000000,
+    }
+}
+
+/// Calculates the colour component correction for the cross-colour transform.
+pub(super) fn color_delta(mult: u32, val: u32) -> u32 {
+    ((i32::from(mult as u8 as i8) * i32::from(val as u8 as i8)) >> 5) as u32
+}
+
+impl Transform {
+    fn read(br: &mut BitReader, width: &mut usize, height: usize, seen: &mut [bool; 4]) -> DecoderResult<Self> {
+        let ttype = br.read(2)? as usize;
+        validate!(!seen[ttype]);
+        seen[ttype] = true;
+        Ok(match ttype {
+            0 | 1 => {
+                let bits = br.read(3)? as u8 + 2;
+                let data = read_entropy_image(br, subsample_size(*width, bits), subsample_size(height, bits), false)?;
+                if ttype == 0 { Transform::Predictor(bits, data) } else { Transform::CrossColor(bits, data) }
+            },
+            2 => Transform::SubtractGreen,
+            _ => {
+                let num_colors = br.read(8)? as usize + 1;
+                let bits = match num_colors {
+                        0..=2  => 3,
+                        3..=4  => 2,
+                        5..=16 => 1,
+                        _      => 0,
+                    };
+                let mut pal = read_entropy_image(br, num_colors, 1, false)?;
+                for i in 1..pal.len() {
+                    pal[i] = add_pixels(pal[i], pal[i - 1]);
+                }
+                // indices outside the palette produce transparent black
+                pal.resize(1 << (8 >> bits), 0);
+                *width = subsample_size(*width, bits);
+                Transform::ColorIndexing(bits, pal)
+            },
+        })
+    }
+    fn apply(&self, pixels: &mut Vec<u32>, width: usize, height: usize) {
+        match *self {
+            Transform::Predictor(bits, ref modes) => {
+                let tiles_w = subsample_size(width, bits);
+                // the first line is predicted from the left with black as the initial value
+                pixels[0] = add_pixels(pixels[0], 0xFF000000);
+                for x in 1..width {
+                    pixels[x] = add_pixels(pixels[x], pixels[x - 1]);
+                }
+                for y in 1..height {
+                    let (top, cur) = pixels.split_at_mut(y * width);
+                    let top = &top[(y - 1) * width..];
+                    cur[0] = add_pixels(cur[0], top[0]);
+                    let mode_row = &modes[(y >> bits) * tiles_w..];
+                    for x in 1..width {
+                        let mode = (mode_row[x >> bits] >> 8) & 0xF;
+                        // the rightmost pixel uses the leftmost pixel of the current line as top-right
+                        let tr = if x + 1 < width { top[x + 1] } else { cur[0] };
+                        cur[x] = add_pixels(cur[x], predict(mode, cur[x - 1], top[x], top[x - 1], tr));
+                    }
+                }
+            },
+            Transform::CrossColor(bits, ref mults) => {
+                let tiles_w = subsample_size(width, bits);
+                for (y, line) in pixels.chunks_exact_mut(width).enumerate() {
+                    let mult_row = &mults[(y >> bits) * tiles_w..];
+                    for (x, pix) in line.iter_mut().enumerate() {
+                        let mult = mult_row[x >> bits];
+                        let green = *pix >> 8;
+                        let red  = (*pix >> 16).wrapping_add(color_delta(mult, green)) & 0xFF;
+                        let blue = pix.wrapping_add(color_delta(mult >> 8, green)).wrapping_add(color_delta(mult >> 16, red)) & 0xFF;
+                        *pix = (*pix & 0xFF00FF00) | (red << 16) | blue;
+                    }
+                }
+            },
+            Transform::SubtractGreen => {
+                for pix in pixels.iter_mut() {
+                    let green = (*pix >> 8) & 0xFF;
+                    *pix = add_pixels(*pix, (green << 16) | green);
+                }
+            },
+            Transform::ColorIndexing(bits, ref pal) => {
+                let packed_w = subsample_size(width, bits);
+                let idx_bits = 8 >> bits;
+                let mask = (1 << idx_bits) - 1;
+                let mut out = Vec::with_capacity(width * height);
+                for line in pixels.chunks_exact(packed_w) {
+                    for x in 0..width {
+                        let packed = (line[x >> bits] >> 8) & 0xFF;
+                        let idx = (packed >> ((x & ((1 << bits) - 1)) * idx_bits)) & mask;
+                        out.push(pal[idx as usize]);
+                    }
+                }
+                *pixels = out;
+            },
+        }
+    }
+}
+
+/// Decodes VP8L image stream (i.e. the image data following VP8L header) into ARGB pixels.
+pub(super) fn decode_image_stream(src: &[u8], width: usize, height: usize) -> DecoderResult<Vec<u32>> {
+    validate!(width > 0 && height > 0);
+    let mut br = BitReader::new(src, BitReaderMode::LE);
+    let mut transforms = Vec::with_capacity(4);
+    let mut seen = [false; 4];
+    let mut widths = Vec::with_capacity(4);
+    let mut cur_width = width;
+    while br.read_bool()? {
+        widths.push(cur_width);
+        transforms.push(Transform::read(&mut br, &mut cur_width, height, &mut seen)?);
+    }
+    let mut pixels = read_entropy_image(&mut br, cur_width, height, true)?;
+    for (transform, &twidth) in transforms.iter().zip(widths.iter()).rev() {
+        transform.apply(&mut pixels, twidth, height);
+    }
+    Ok(pixels)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::codecs::testsynth::RNG;
+    use crate::codecs::webpsynth::{VP8LConfig, encode_vp8l};
+
+    fn gen_image(rng: &mut RNG, width: usize, height: usize, ncolors: usize) -> Vec<u32> {
+        let colors: Vec<u32> = (0..ncolors).map(|_| rng.next()).collect();
+        let mut pixels = Vec::with_capacity(width * height);
+        for i in 0..width * height {
+            let pix = match rng.range(8) {
+                    0..=3 if i > 0 => pixels[i - 1],
+                    4 if i >= width => pixels[i - width],
+                    _ => colors[rng.range(ncolors as u32) as usize],
+                };
+            pixels.push(pix);
+        }
+        pixels
+    }
+
+    #[test]
+    fn test_vp8l_roundtrip() {
+        let mut rng = RNG::new(42);
+        let full = VP8LConfig { subtract_green: true, predictor: true, cross_color: true, cache_bits: 10, meta_bits: 2, backrefs: true, palette: false };
+        let configs = [
+            (VP8LConfig::default(), 1000),
+            (VP8LConfig { backrefs: true, cache_bits: 1, ..VP8LConfig::default() }, 40),
+            (VP8LConfig { predictor: true, ..VP8LConfig::default() }, 1000),
+            (VP8LConfig { cross_color: true, meta_bits: 4, ..VP8LConfig::default() }, 1000),
+            (full, 300),
+            (VP8LConfig { palette: true, ..VP8LConfig::default() }, 2),
+            (VP8LConfig { palette: true, predictor: true, ..VP8LConfig::default() }, 3),
+            (VP8LConfig { palette: true, backrefs: true, cache_bits: 4, ..VP8LConfig::default() }, 12),
+            (VP8LConfig { palette: true, ..full }, 200),
+        ];
+        for (i, (cfg, ncolors)) in configs.iter().enumerate() {
+            for &(width, height) in [(1, 1), (37, 23), (64, 16)].iter() {
+                let pixels = gen_image(&mut rng, width, height, *ncolors);
+                let data = encode_vp8l(&pixels, width, height, cfg, &mut rng);
+                let dec = decode_image_stream(&data, width, height).unwrap_or_else(|err| panic!("config {} {}x{}: error {:?}", i, width, height, err));
+                assert!(dec == pixels, "config {} {}x{}: decoded image differs", i, width, height);
+            }
+        }
+    }
+    #[test]
+    fn test_vp8l_errors() {
+        let mut rng = RNG::new(7);
+        let pixels = gen_image(&mut rng, 16, 16, 20);
+        let cfg = VP8LConfig { predictor: true, backrefs: true, cache_bits: 5, ..VP8LConfig::default() };
+        let data = encode_vp8l(&pixels, 16, 16, &cfg, &mut rng);
+        assert_eq!(decode_image_stream(&data[..data.len() / 2], 16, 16).err(), Some(DecoderError::ShortData));
+        // the same transform twice
+        assert_eq!(decode_image_stream(&[0x2D, 0x00], 16, 16).err(), Some(DecoderError::InvalidData));
+        // zero colour cache bits
+        assert_eq!(decode_image_stream(&[0x02, 0x00], 16, 16).err(), Some(DecoderError::InvalidData));
+        // a prefix code with all code lengths being zero
+        assert_eq!(decode_image_stream(&[0x00, 0x00, 0x00, 0x00], 16, 16).err(), Some(DecoderError::InvalidData));
+        for _ in 0..100 {
+            let mut corrupted = data.clone();
+            let pos = rng.range(data.len() as u32) as usize;
+            corrupted[pos] ^= 1 << rng.range(8);
+            let _ = decode_image_stream(&corrupted, 16, 16);
+        }
+    }
+}
@@ -0,0 +1,447 @@
+//! Synthetic VP8 stream generator for the decoder tests.
+//!
+//! It reuses the VP7 generator syntax element writers and adds VP8 frame header, segmentation,
+//! coefficient partitions and the VP8 way of coding inter macroblock modes and motion vectors.
+use nihav_codec_support::codecs::{MV, ZERO_MV};
+use super::testsynth::*;
+use super::vp78::*;
+use super::vp78data::*;
+use super::vp7synth::*;
+use super::vp8::{MVInfo, clamp_mv, find_near_mvs, sub_mv_ref_probs};
+use super::vp8data::*;
+
+const SPLIT_MODES: [MVSplitMode; 4] = [MVSplitMode::TopBottom, MVSplitMode::LeftRight, MVSplitMode::Quarters, MVSplitMode::Sixteenths];
+const SUB_MV_REFS: [SubMVRef; 4] = [SubMVRef::Left, SubMVRef::Above, SubMVRef::Zero, SubMVRef::New];
+const REFS: [VP8Ref; 3] = [VP8Ref::Last, VP8Ref::Golden, VP8Ref::AltRef];
+
+/// Stream parameters for the generator.
+#[derive(Clone,Copy)]
+pub struct VP8SynthConfig {
+    pub version:        u8,
+    pub width:          usize,
+    pub height:         usize,
+    pub nframes:        usize,
+    pub key_int:        usize,
+    pub segmentation:   bool,
+    pub lf_deltas:      bool,
+    /// Use several coefficient partitions.
+    pub partitions:     bool,
+    pub split:          bool,
+    /// Use golden and altref frames and their updates.
+    pub golden:         bool,
+    /// Produce frames that are not shown.
+    pub hidden:         bool,
+    pub seed:           u32,
+}
+
+#[derive(Clone,Copy)]
+struct Models {
+    coef_probs:     CoeffProbs,
+    y_mode_probs:   [u8; 4],
+    uv_mode_probs:  [u8; 3],
+    mv_probs:       [[u8; 19]; 2],
+}
+
+impl Default for Models {
+    fn default() -> Self {
+        Self {
+            coef_probs:     DEFAULT_COEF_PROBS,
+            y_mode_probs:   Y_MODE_PROBS,
+            uv_mode_probs:  UV_MODE_PROBS,
+            mv_probs:       DEFAULT_MV_PROBS,
+        }
+    }
+}
+
+/// Synthetic VP8 stream generator.
+pub struct VP8Synth {
+    cfg:            VP8SynthConfig,
+    rng:            RNG,
+    frameno:        usize,
+    mb_w:           usize,
+    mb_h:           usize,
+    models:         Models,
+    seg_tree_probs: Option<[u8; 3]>,
+    sign_bias:      [bool; 4],
+    prob_skip:      Option<u8>,
+    prob_intra:     u8,
+    prob_last:      u8,
+    prob_golden:    u8,
+
+    mv_info:        Vec<MVInfo>,
+    mvs:            Vec<[MV; 16]>,
+    top_bmodes:     Vec<PredMode>,
+    left_bmodes:    [PredMode; 4],
+    top_nz:         Vec<[bool; 9]>,
+    left_nz:        [bool; 9],
+}
+
+impl VP8Synth {
+    pub fn new(cfg: VP8SynthConfig) -> Self {
+        let mb_w = cfg.width.div_ceil(16);
+        let mb_h = cfg.height.div_ceil(16);
+        Self {
+            cfg,
+            rng:            RNG::new(cfg.seed),
+            frameno:        0,
+            mb_w, mb_h,
+            models:         Models::default(),
+            seg_tree_probs: None,
+            sign_bias:      [false; 4],
+            prob_skip:      None,
+            prob_intra:     0,
+            prob_last:      0,
+            prob_golden:    0,
+
+            mv_info:        vec![MVInfo::default(); mb_w * mb_h],
+            mvs:            vec![[ZERO_MV; 16]; mb_w * mb_h],
+            top_bmodes:     vec![PredMode::DCPred; mb_w * 4],
+            left_bmodes:    [PredMode::DCPred; 4],
+            top_nz:         vec![[false; 9]; mb_w],
+            left_nz:        [false; 9],
+        }
+    }
+    fn write_delta(&mut self, bc: &mut BoolEncoder, bits: u8, max: u32) {
+        let coded = self.rng.chance(3, 4);
+        bc.put_bool(coded);
+        if coded {
+            bc.put_bits(self.rng.range(max + 1), bits);
+            bc.put_bool(self.rng.chance(1, 2));
+        }
+    }
+    /// Generates the next frame packet.
+    pub fn gen_frame(&mut self) -> Vec<u8> {
+        let is_intra = self.frameno.is_multiple_of(self.cfg.key_int);
+        self.frameno += 1;
+        let show = is_intra || !self.cfg.hidden || self.rng.chance(4, 5);
+
+        let mut bc = BoolEncoder::new();
+        if is_intra {
+            // colour space and clamping type
+            bc.put_bool(false);
+            bc.put_bool(false);
+            self.models = Models::default();
+            self.sign_bias = [false; 4];
+        }
+        // segmentation
+        let seg_enabled = self.cfg.segmentation && self.rng.chance(2, 3);
+        bc.put_bool(seg_enabled);
+        self.seg_tree_probs = None;
+        if seg_enabled {
+            let update_map = is_intra || self.rng.chance(1, 2);
+            bc.put_bool(update_map);
+            // encoders send segment data on keyframes since decoders disagree on its default mode
+            let update_data = is_intra || self.rng.chance(1, 2);
+            bc.put_bool(update_data);
+            if update_data {
+                let absolute = self.rng.chance(1, 3);
+                bc.put_bool(absolute);
+                for _ in 0..4 {
+                    self.write_delta(&mut bc, 7, if absolute { 100 } else { 20 });
+                }
+                for _ in 0..4 {
+                    self.write_delta(&mut bc, 6, if absolute { 40 } else { 15 });
+                }
+            }
+            if update_map {
+                let mut probs = [255; 3];
+                for prob in probs.iter_mut() {
+                    let explicit = self.rng.chance(3, 4);
+                    bc.put_bool(explicit);
+                    if explicit {
+                        *prob = (1 + self.rng.range(254)) as u8;
+                        bc.put_bits(u32::from(*prob), 8);
+                    }
+                }
+                self.seg_tree_probs = Some(probs);
+            }
+        }
+        // loop filter type, level and sharpness
+        bc.put_bool(self.rng.chance(1, 3));
+        bc.put_bits(self.rng.range(40), 6);
+        bc.put_bits(self.rng.range(8), 3);
+        let lf_deltas = self.cfg.lf_deltas && self.rng.chance(2, 3);
+        bc.put_bool(lf_deltas);
+        if lf_deltas {
+            let update = self.rng.chance(1, 2);
+            bc.put_bool(update);
+            if update {
+                for _ in 0..8 {
+                    self.write_delta(&mut bc, 6, 15);
+                }
+            }
+        }
+        let part_bits = if self.cfg.partitions { self.rng.range(4) } else { 0 };
+        bc.put_bits(part_bits, 2);
+        let num_partitions = 1 << part_bits;
+        // quantisers
+        bc.put_bits(self.rng.range(100), 7);
+        for _ in 0..5 {
+            let coded = self.rng.chance(1, 4);
+            bc.put_bool(coded);
+            if coded {
+                bc.put_bits(self.rng.range(16), 4);
+                bc.put_bool(self.rng.chance(1, 2));
+            }
+        }
+        if !is_intra {
+            let refresh_golden = self.cfg.golden && self.rng.chance(1, 5);
+            let refresh_alt = self.cfg.golden && self.rng.chance(1, 5);
+            bc.put_bool(refresh_golden);
+            bc.put_bool(refresh_alt);
+            if !refresh_golden {
+                bc.put_bits(if self.cfg.golden { self.rng.range(3) } else { 0 }, 2);
+            }
+            if !refresh_alt {
+                bc.put_bits(if self.cfg.golden { self.rng.range(3) } else { 0 }, 2);
+            }
+            for &rframe in [VP8Ref::Golden, VP8Ref::AltRef].iter() {
+                let sign_bias = self.cfg.golden && self.rng.chance(1, 3);
+                bc.put_bool(sign_bias);
+                self.sign_bias[rframe as usize] = sign_bias;
+            }
+        }
+        let mut saved_models = None;
+        let refresh_entropy = self.rng.chance(2, 3);
+        bc.put_bool(refresh_entropy);
+        if !refresh_entropy {
+            saved_models = Some(self.models);
+        }
+        if !is_intra {
+            bc.put_bool(show || self.rng.chance(1, 3));
+        }
+        write_coef_prob_updates(&mut bc, &mut self.rng, &mut self.models.coef_probs);
+        let use_skip = self.rng.chance(2, 3);
+        bc.put_bool(use_skip);
+        self.prob_skip = if use_skip { Some((1 + self.rng.range(254)) as u8) } else { None };
+        if let Some(prob) = self.prob_skip {
+            bc.put_bits(u32::from(prob), 8);
+        }
+        if !is_intra {
+            self.write_inter_probs(&mut bc);
+        }
+
+        let mut parts: Vec<BoolEncoder> = (0..num_partitions).map(|_| BoolEncoder::new()).collect();
+        for el in self.top_bmodes.iter_mut() {
+            *el = PredMode::DCPred;
+        }
+        for el in self.top_nz.iter_mut() {
+            *el = [false; 9];
+        }
+        for mb_y in 0..self.mb_h {
+            self.left_bmodes = [PredMode::DCPred; 4];
+            self.left_nz = [false; 9];
+            let bc2 = &mut parts[mb_y % num_partitions];
+            for mb_x in 0..self.mb_w {
+                self.gen_mb(&mut bc, bc2, mb_x, mb_y, is_intra);
+            }
+        }
+        if let Some(models) = saved_models {
+            self.models = models;
+        }
+
+        let part1 = bc.finish();
+        let tag = ((part1.len() as u32) << 5) | (u32::from(show) << 4) | (u32::from(self.cfg.version) << 1) | (!is_intra as u32);
+        let mut pkt = vec![tag as u8, (tag >> 8) as u8, (tag >> 16) as u8];
+        if is_intra {
+            pkt.extend_from_slice(&[0x9D, 0x01, 0x2A]);
+            pkt.extend_from_slice(&(self.cfg.width as u16).to_le_bytes());
+            pkt.extend_from_slice(&(self.cfg.height as u16).to_le_bytes());
+        }
+        pkt.extend_from_slice(&part1);
+        let parts: Vec<Vec<u8>> = parts.into_iter().map(|part| part.finish()).collect();
+        for part in parts[..num_partitions - 1].iter() {
+            pkt.extend_from_slice(&(part.len() as u32).to_le_bytes()[..3]);
+        }
+        for part in parts.iter() {
+            pkt.extend_from_slice(part);
+        }
+        pkt
+    }
+    fn write_inter_probs(&mut self, bc: &mut BoolEncoder) {
+        self.prob_intra  = (20 + self.rng.range(200)) as u8;
+        self.prob_last   = (20 + self.rng.range(200)) as u8;
+        self.prob_golden = (20 + self.rng.range(200)) as u8;
+        bc.put_bits(u32::from(self.prob_intra), 8);
+        bc.put_bits(u32::from(self.prob_last), 8);
+        bc.put_bits(u32::from(self.prob_golden), 8);
+        let update = self.rng.chance(1, 3);
+        bc.put_bool(update);
+        if update {
+            for prob in self.models.y_mode_probs.iter_mut() {
+                *prob = (1 + self.rng.range(255)) as u8;
+                bc.put_bits(u32::from(*prob), 8);
+            }
+        }
+        let update = self.rng.chance(1, 3);
+        bc.put_bool(update);
+        if update {
+            for prob in self.models.uv_mode_probs.iter_mut() {
+                *prob = (1 + self.rng.range(255)) as u8;
+                bc.put_bits(u32::from(*prob), 8);
+            }
+        }
+        for (probs, upd_probs) in self.models.mv_probs.iter_mut().zip(MV_UPDATE_PROBS.iter()) {
+            for (prob, &upd_prob) in probs.iter_mut().zip(upd_probs.iter()) {
+                let update = self.rng.chance(1, 8);
+                bc.put_prob(update, upd_prob);
+                if update {
+                    *prob = ((1 + self.rng.range(127)) << 1) as u8;
+                    bc.put_probability(*prob);
+                }
+            }
+        }
+    }
+    fn gen_mv(&mut self, bc: &mut BoolEncoder, pred: MV) -> MV {
+        let range = if self.rng.chance(1, 8) { 255 } else { 40 };
+        let x = self.rng.range(range * 2 + 1) as i16 - range as i16;
+        let y = self.rng.range(range * 2 + 1) as i16 - range as i16;
+        write_mv_component(bc, &self.models.mv_probs[0], 10, y);
+        write_mv_component(bc, &self.models.mv_probs[1], 10, x);
+        MV { x: pred.x + x, y: pred.y + y }
+    }
+    fn gen_split_mvs(&mut self, bc: &mut BoolEncoder, mb_x: usize, mb_y: usize, best_mv: MV) -> [MV; 16] {
+        let mb_idx = mb_x + mb_y * self.mb_w;
+        let left_mvs  = if mb_x > 0 { self.mvs[mb_idx - 1] } else { [ZERO_MV; 16] };
+        let above_mvs = if mb_y > 0 { self.mvs[mb_idx - self.mb_w] } else { [ZERO_MV; 16] };
+        let split = SPLIT_MODES[self.rng.range(4) as usize];
+        write_mv_split_mode(bc, split);
+        let split = split as usize;
+        let mut mvs = [ZERO_MV; 16];
+        for (part, &blk) in MV_PARTITION_FIRST_BLOCK[split].iter().enumerate() {
+            let left  = if (blk & 3) != 0 { mvs[blk - 1] } else { left_mvs[blk + 3] };
+            let above = if blk >= 4 { mvs[blk - 4] } else { above_mvs[blk + 12] };
+            let mode = SUB_MV_REFS[self.rng.range(4) as usize];
+            write_sub_mv_ref(bc, sub_mv_ref_probs(left, above), mode);
+            let mv = match mode {
+                    SubMVRef::Left  => left,
+                    SubMVRef::Above => above,
+                    SubMVRef::Zero  => ZERO_MV,
+                    SubMVRef::New   => self.gen_mv(bc, best_mv),
+                };
+            for (dst, &bpart) in mvs.iter_mut().zip(MV_PARTITIONS[split].iter()) {
+                if usize::from(bpart) == part {
+                    *dst = mv;
+                }
+            }
+        }
+        mvs
+    }
+    fn gen_mb(&mut self, bc: &mut BoolEncoder, bc2: &mut BoolEncoder, mb_x: usize, mb_y: usize, is_intra: bool) {
+        let mb_idx = mb_x + mb_y * self.mb_w;
+        if let Some(probs) = self.seg_tree_probs {
+            let idx = self.rng.range(4) as usize;
+            bc.put_prob(idx >= 2, probs[0]);
+            bc.put_prob((idx & 1) != 0, probs[1 + idx / 2]);
+        }
+        let skip = if let Some(prob) = self.prob_skip {
+                let skip = self.rng.chance(1, 4);
+                bc.put_prob(skip, prob);
+                skip
+            } else {
+                false
+            };
+
+        let mut ymode = PredMode::Inter;
+        let mut rframe = VP8Ref::Intra;
+        let mut split = false;
+        self.mvs[mb_idx] = [ZERO_MV; 16];
+        let intra_mb = is_intra || self.rng.chance(1, 8);
+        if !is_intra {
+            bc.put_prob(!intra_mb, self.prob_intra);
+        }
+        if intra_mb {
+            ymode = Y_MODES[self.rng.range(5) as usize];
+            if is_intra {
+                write_kf_y_mode(bc, ymode);
+            } else {
+                write_y_mode(bc, &self.models.y_mode_probs, ymode);
+            }
+            let mut bmodes = [ymode; 16];
+            if ymode == PredMode::BPred {
+                for i in 0..16 {
+                    bmodes[i] = B_MODES[self.rng.range(10) as usize];
+                    if is_intra {
+                        let top  = if i >= 4 { bmodes[i - 4] } else { self.top_bmodes[mb_x * 4 + i] };
+                        let left = if (i & 3) != 0 { bmodes[i - 1] } else { self.left_bmodes[i >> 2] };
+                        write_b_mode(bc, &KF_B_MODE_PROBS[top.to_b_index()][left.to_b_index()], bmodes[i]);
+                    } else {
+                        write_b_mode(bc, &B_MODE_PROBS, bmodes[i]);
+                    }
+                }
+            }
+            let uvmode = UV_MODES[self.rng.range(4) as usize];
+            write_uv_mode(bc, if is_intra { &KF_UV_MODE_PROBS } else { &self.models.uv_mode_probs }, uvmode);
+            if is_intra {
+                self.top_bmodes[mb_x * 4..][..4].copy_from_slice(&bmodes[12..]);
+                for (left, row) in self.left_bmodes.iter_mut().zip(bmodes.chunks_exact(4)) {
+                    *left = row[3];
+                }
+            }
+        } else {
+            rframe = if self.cfg.golden { REFS[self.rng.range(3) as usize] } else { VP8Ref::Last };
+            bc.put_prob(rframe != VP8Ref::Last, self.prob_last);
+            if rframe != VP8Ref::Last {
+                bc.put_prob(rframe == VP8Ref::AltRef, self.prob_golden);
+            }
+            let (near_mvs, cnt) = find_near_mvs(&self.mv_info, self.mb_w, mb_x, mb_y, rframe, &self.sign_bias);
+            let [best_mv, nearest_mv, near_mv] = near_mvs.map(|mv| clamp_mv(mv, mb_x, mb_y, self.mb_w, self.mb_h));
+            let mode = self.rng.range(if self.cfg.split { 5 } else { 4 });
+            for i in 0..4 {
+                if mode >= i {
+                    bc.put_prob(mode > i, MODE_CONTEXTS[cnt[i as usize]][i as usize]);
+                }
+            }
+            split = mode == 4;
+            self.mvs[mb_idx] = match mode {
+                    0 => [ZERO_MV; 16],
+                    1 => [nearest_mv; 16],
+                    2 => [near_mv; 16],
+                    3 => [self.gen_mv(bc, best_mv); 16],
+                    _ => self.gen_split_mvs(bc, mb_x, mb_y, best_mv),
+                };
+        }
+        self.mv_info[mb_idx] = MVInfo { rframe, mv: self.mvs[mb_idx][15], split };
+
+        let has_y2 = ymode != PredMode::BPred && !split;
+        if !skip {
+            write_mb_coeffs(bc2, &mut self.rng, &self.models.coef_probs, &mut self.top_nz[mb_x], &mut self.left_nz, has_y2, false);
+        } else {
+            let nz_len = if has_y2 { 9 } else { 8 };
+            self.top_nz[mb_x][..nz_len].fill(false);
+            self.left_nz[..nz_len].fill(false);
+        }
+    }
+}
+
+/// Generates the whole stream in raw packet format (32-bit big-endian size followed by packet data).
+pub fn gen_stream(cfg: VP8SynthConfig) -> Vec<u8> {
+    let mut synth = VP8Synth::new(cfg);
+    let mut data = Vec::new();
+    for _ in 0..cfg.nframes {
+        put_packet(&mut data, &synth.gen_frame());
+    }
+    data
+}
+
+const fn synth_cfg(version: u8, seed: u32) -> VP8SynthConfig {
+    VP8SynthConfig {
+        version, seed,
+        width:          56,
+        height:         40,
+        nframes:        10,
+        key_int:        5,
+        segmentation:   false,
+        lf_deltas:      false,
+        partitions:     false,
+        split:          false,
+        golden:         false,
+        hidden:         false,
+    }
+}
+
+/// Test fixtures stored in `assets/` and the parameters they are generated from.
+pub const TEST_STREAMS: [(&str, VP8SynthConfig); 2] = [
+    ("vp8.vp8",             VP8SynthConfig { split: true, golden: true, partitions: true, ..synth_cfg(0, 1) }),
+    ("vp8_advanced.vp8",    VP8SynthConfig { segmentation: true, lf_deltas: true, partitions: true, split: true, golden: true, hidden: true, key_int: 10, ..synth_cfg(3, 2) }),
+];
@@ -1,4 +1,6 @@
 use nihav_core::codecs::*;
+use nihav_codec_support::codecs::MV;
+use nihav_codec_support::codecs::blockdsp::edge_emu;
 
 pub const VP_YUVA420_FORMAT: NAPixelFormaton = NAPixelFormaton{
         model:      ColorModel::YUV(YUVSubmodel::YUVJ),
@@ -50,30 +52,81 @@ impl VPMBType {
     }
 }
 
-#[derive(Default)]
+// colour and alpha are coded as independent streams (each with its own golden frame)
+// so their references are tracked separately even though they share picture buffers
+#[derive(Clone,Default)]
 pub struct VPShuffler {
     lastframe: Option<NAVideoBufferRef<u8>>,
     goldframe: Option<NAVideoBufferRef<u8>>,
+    // alternative reference frame is used only by VP8
+    altframe:  Option<NAVideoBufferRef<u8>>,
+    lastalpha: Option<NAVideoBufferRef<u8>>,
+    goldalpha: Option<NAVideoBufferRef<u8>>,
 }
 
 impl VPShuffler {
-    pub fn new() -> Self { VPShuffler { lastframe: None, goldframe: None } }
-    pub fn clear(&mut self) { self.lastframe = None; self.goldframe = None; }
+    pub fn new() -> Self { VPShuffler { lastframe: None, goldframe: None, altframe: None, lastalpha: None, goldalpha: None } }
+    pub fn clear(&mut self) {
+        self.clear_colour();
+        self.clear_alpha();
+    }
+    pub fn clear_colour(&mut self) { self.lastframe = None; self.goldframe = None; self.altframe = None; }
+    pub fn clear_alpha(&mut self) { self.lastalpha = None; self.goldalpha = None; }
+    pub fn clear_last(&mut self) { self.lastframe = None; self.lastalpha = None; }
     pub fn add_frame(&mut self, buf: NAVideoBufferRef<u8>) {
         self.lastframe = Some(buf);
     }
     pub fn add_golden_frame(&mut self, buf: NAVideoBufferRef<u8>) {
         self.goldframe = Some(buf);
     }
+    pub fn add_altref_frame(&mut self, buf: NAVideoBufferRef<u8>) {
+        self.altframe = Some(buf);
+    }
+    pub fn add_alpha_frame(&mut self, buf: NAVideoBufferRef<u8>) {
+        self.lastalpha = Some(buf);
+    }
+    pub fn add_golden_alpha_frame(&mut self, buf: NAVideoBufferRef<u8>) {
+        self.goldalpha = Some(buf);
+    }
     pub fn get_last(&mut self) -> Option<NAVideoBufferRef<u8>> {
         self.lastframe.as_ref().cloned()
     }
     pub fn get_golden(&mut self) -> Option<NAVideoBufferRef<u8>> {
         self.goldframe.as_ref().cloned()
     }
+    pub fn get_altref(&mut self) -> Option<NAVideoBufferRef<u8>> {
+        self.altframe.as_ref().cloned()
+    }
+    pub fn get_last_alpha(&mut self) -> Option<NAVideoBufferRef<u8>> {
+        self.lastalpha.as_ref().cloned()
+    }
+    pub fn get_golden_alpha(&mut self) -> Option<NAVideoBufferRef<u8>> {
+        self.goldalpha.as_ref().cloned()
+    }
     pub fn has_refs(&self) -> bool {
         self.lastframe.is_some()
     }
+    pub fn has_alpha_refs(&self) -> bool {
+        self.lastalpha.is_some()
+    }
+    // unlike clone() this does not keep the reference frames (usually owned by the frame pool) in use,
+    // references sharing the same buffer share the copy as well
+    pub fn copy_frames(&self) -> Self {
+        let refs = [&self.lastframe, &self.goldframe, &self.altframe, &self.lastalpha, &self.goldalpha];
+        let mut copies: [Option<NAVideoBufferRef<u8>>; 5] = [None, None, None, None, None];
+        for (i, cur) in refs.iter().enumerate() {
+            if let Some(ref buf) = cur {
+                let prev = refs[..i].iter().position(|pref| pref.as_ref().is_some_and(|pbuf| NABufferRef::ptr_eq(pbuf, buf)));
+                copies[i] = if let Some(idx) = prev {
+                        copies[idx].clone()
+                    } else {
+                        Some(buf.copy_buffer().into_ref())
+                    };
+            }
+        }
+        let [lastframe, goldframe, altframe, lastalpha, goldalpha] = copies;
+        VPShuffler { lastframe, goldframe, altframe, lastalpha, goldalpha }
+    }
 }
 
 pub const VP56_COEF_BASE: [i16; 6] = [ 5, 7, 11, 19, 35, 67 ];
@@ -98,10 +151,12 @@ pub struct BoolCoder<'a> {
 #[allow(dead_code)]
 impl<'a> BoolCoder<'a> {
     pub fn new(src: &'a [u8]) -> DecoderResult<Self> {
-        if src.len() < 3 { return Err(DecoderError::ShortData); }
+        if src.len() < 4 { return Err(DecoderError::ShortData); }
         let value = (u32::from(src[0]) << 24) | (u32::from(src[1]) << 16) | (u32::from(src[2]) << 8) | u32::from(src[3]);
         Ok(Self { src, pos: 4, value, range: 255, bits: 8 })
     }
+    // the coder keeps four bytes of lookahead, so report the position of the data actually being decoded
+    pub fn get_pos(&self) -> usize { self.pos.saturating_sub(4) }
     pub fn read_bool(&mut self) -> bool {
         self.read_prob(128)
     }
@@ -206,7 +261,7 @@ const C6S2: i32 = 25080;
 const C7S1: i32 = 12785;
 
 fn mul16(a: i32, b: i32) -> i32 {
-    (a * b) >> 16
+    a.wrapping_mul(b) >> 16
 }
 
 macro_rules! idct_step {
@@ -221,8 +276,8 @@ macro_rules! idct_step {
         let t_b1 = mul16(C4S4, t_b - t_d);
         let t_c  = t_a + t_c;
         let t_d  = t_b + t_d;
-        let t_e  = mul16(C4S4, i32::from($s0 + $s4)) + $bias;
-        let t_f  = mul16(C4S4, i32::from($s0 - $s4)) + $bias;
+        let t_e  = mul16(C4S4, i32::from($s0) + i32::from($s4)) + $bias;
+        let t_f  = mul16(C4S4, i32::from($s0) - i32::from($s4)) + $bias;
         let t_g  = mul16(C2S6, i32::from($s2)) + mul16(C6S2, i32::from($s6));
         let t_h  = mul16(C6S2, i32::from($s2)) - mul16(C2S6, i32::from($s6));
         let t_e1 = t_e  - t_g;
@@ -277,7 +332,7 @@ pub fn vp_put_block(coeffs: &mut [i16; 64], bx: usize, by: usize, plane: usize,
     let mut off = frm.offset[plane] + bx * 8 + by * 8 * frm.stride[plane];
     for y in 0..8 {
         for x in 0..8 {
-            frm.data[off + x] = (coeffs[x + y * 8] + 128).min(255).max(0) as u8;
+            frm.data[off + x] = (i32::from(coeffs[x + y * 8]) + 128).clamp(0, 255) as u8;
         }
         off += frm.stride[plane];
     }
@@ -288,7 +343,7 @@ pub fn vp_put_block_ilace(coeffs: &mut [i16; 64], bx: usize, by: usize, plane: u
     let mut off = frm.offset[plane] + bx * 8 + ((by & !1) * 8 + (by & 1)) * frm.stride[plane];
     for y in 0..8 {
         for x in 0..8 {
-            frm.data[off + x] = (coeffs[x + y * 8] + 128).min(255).max(0) as u8;
+            frm.data[off + x] = (i32::from(coeffs[x + y * 8]) + 128).clamp(0, 255) as u8;
         }
         off += frm.stride[plane] * 2;
     }
@@ -296,7 +351,7 @@ pub fn vp_put_block_ilace(coeffs: &mut [i16; 64], bx: usize, by: usize, plane: u
 
 pub fn vp_put_block_dc(coeffs: &mut [i16; 64], bx: usize, by: usize, plane: usize, frm: &mut NASimpleVideoFrame<u8>) {
     vp_idct_dc(coeffs);
-    let dc = (coeffs[0] + 128).min(255).max(0) as u8;
+    let dc = (coeffs[0] + 128).clamp(0, 255) as u8;
     let mut off = frm.offset[plane] + bx * 8 + by * 8 * frm.stride[plane];
     for _ in 0..8 {
         for x in 0..8 {
@@ -311,7 +366,7 @@ pub fn vp_add_block(coeffs: &mut [i16; 64], bx: usize, by: usize, plane: usize,
     let mut off = frm.offset[plane] + bx * 8 + by * 8 * frm.stride[plane];
     for y in 0..8 {
         for x in 0..8 {
-            frm.data[off + x] = (coeffs[x + y * 8] + i16::from(frm.data[off + x])).min(255).max(0) as u8;
+            frm.data[off + x] = (i32::from(coeffs[x + y * 8]) + i32::from(frm.data[off + x])).clamp(0, 255) as u8;
         }
         off += frm.stride[plane];
     }
@@ -322,7 +377,7 @@ pub fn vp_add_block_ilace(coeffs: &mut [i16; 64], bx: usize, by: usize, plane: u
     let mut off = frm.offset[plane] + bx * 8 + ((by & !1) * 8 + (by & 1)) * frm.stride[plane];
     for y in 0..8 {
         for x in 0..8 {
-            frm.data[off + x] = (coeffs[x + y * 8] + i16::from(frm.data[off + x])).min(255).max(0) as u8;
+            frm.data[off + x] = (i32::from(coeffs[x + y * 8]) + i32::from(frm.data[off + x])).clamp(0, 255) as u8;
         }
         off += frm.stride[plane] * 2;
     }
@@ -334,12 +389,116 @@ pub fn vp_add_block_dc(coeffs: &mut [i16; 64], bx: usize, by: usize, plane: usiz
     let mut off = frm.offset[plane] + bx * 8 + by * 8 * frm.stride[plane];
     for _ in 0..8 {
         for x in 0..8 {
-            frm.data[off + x] = (dc + i16::from(frm.data[off + x])).min(255).max(0) as u8;
+            frm.data[off + x] = (dc + i16::from(frm.data[off + x])).clamp(0, 255) as u8;
         }
         off += frm.stride[plane];
     }
 }
 
+// IDCT producing reduced-size output block from the low-frequency coefficients.
+// Basis functions are averaged over the output pixel footprint so the result approximates
+// the downscaled full-size reconstruction.
+#[derive(Clone)]
+pub struct VPLowResIDCT {
+    shift:  u8,
+    bsize:  usize,
+    tab:    [[i32; 8]; 8],
+}
+
+impl VPLowResIDCT {
+    pub fn new(shift: u8) -> Self {
+        let bsize = 8 >> shift;
+        let group = 1 << shift;
+        let mut tab = [[0; 8]; 8];
+        for (m, row) in tab.iter_mut().take(bsize).enumerate() {
+            for (k, el) in row.iter_mut().take(bsize).enumerate() {
+                let scale = if k == 0 { std::f64::consts::FRAC_1_SQRT_2 } else { 1.0 };
+                let mut sum = 0.0;
+                for n in m * group..(m + 1) * group {
+                    sum += (((2 * n + 1) * k) as f64 * std::f64::consts::PI / 16.0).cos();
+                }
+                *el = (sum * scale * 65536.0 / (group as f64)).round() as i32;
+            }
+        }
+        Self { shift, bsize, tab }
+    }
+    pub fn get_shift(&self) -> u8 { self.shift }
+    pub fn get_block_size(&self) -> usize { self.bsize }
+    fn transform(&self, coeffs: &[i16; 64], dst: &mut [i16; 64]) {
+        let n = self.bsize;
+        let mut tmp = [0i64; 64];
+        for (src, dline) in coeffs.chunks(8).zip(tmp.chunks_mut(8)).take(n) {
+            for (el, tab) in dline.iter_mut().zip(self.tab.iter()).take(n) {
+                *el = src.iter().zip(tab.iter()).take(n).fold(0i64, |acc, (&c, &t)| acc + i64::from(c) * i64::from(t)) >> 16;
+            }
+        }
+        for (dline, tab) in dst.chunks_mut(8).zip(self.tab.iter()).take(n) {
+            for (x, el) in dline.iter_mut().take(n).enumerate() {
+                let sum = tmp.chunks(8).zip(tab.iter()).take(n).fold(0i64, |acc, (line, &t)| acc + line[x] * i64::from(t)) >> 16;
+                *el = ((sum + 8) >> 4) as i16;
+            }
+        }
+    }
+    fn get_offset_and_step(&self, bx: usize, by: usize, plane: usize, ilace: bool, frm: &NASimpleVideoFrame<u8>) -> (usize, usize) {
+        let stride = frm.stride[plane];
+        if !ilace {
+            (frm.offset[plane] + bx * self.bsize + by * self.bsize * stride, stride)
+        } else {
+            (frm.offset[plane] + bx * self.bsize + ((by & !1) * self.bsize + (by & 1)) * stride, stride * 2)
+        }
+    }
+    pub fn put_block(&self, coeffs: &[i16; 64], bx: usize, by: usize, plane: usize, ilace: bool, frm: &mut NASimpleVideoFrame<u8>) {
+        let mut blk = [0i16; 64];
+        self.transform(coeffs, &mut blk);
+        let (mut off, step) = self.get_offset_and_step(bx, by, plane, ilace, frm);
+        for line in blk.chunks(8).take(self.bsize) {
+            for (dst, &src) in frm.data[off..][..self.bsize].iter_mut().zip(line.iter()) {
+                *dst = (i32::from(src) + 128).clamp(0, 255) as u8;
+            }
+            off += step;
+        }
+    }
+    pub fn add_block(&self, coeffs: &[i16; 64], bx: usize, by: usize, plane: usize, ilace: bool, frm: &mut NASimpleVideoFrame<u8>) {
+        let mut blk = [0i16; 64];
+        self.transform(coeffs, &mut blk);
+        let (mut off, step) = self.get_offset_and_step(bx, by, plane, ilace, frm);
+        for line in blk.chunks(8).take(self.bsize) {
+            for (dst, &src) in frm.data[off..][..self.bsize].iter_mut().zip(line.iter()) {
+                *dst = (i32::from(src) + i32::from(*dst)).clamp(0, 255) as u8;
+            }
+            off += step;
+        }
+    }
+    // simple bilinear motion compensation, motion vectors are scaled down to the output resolution
+    pub fn mc_block(&self, dst: &mut NASimpleVideoFrame<u8>, src: &NAVideoBuffer<u8>, plane: usize, x: usize, y: usize, mv: MV) {
+        let is_luma = (plane != 1) && (plane != 2);
+        // convert motion vector to 1/8th of output pixel precision
+        let (mvx, mvy) = if is_luma {
+                ((i32::from(mv.x) * 2) >> self.shift, (i32::from(mv.y) * 2) >> self.shift)
+            } else {
+                (i32::from(mv.x) >> self.shift, i32::from(mv.y) >> self.shift)
+            };
+        let bs = self.bsize;
+        let mut tmp = [0u8; 9 * 9];
+        edge_emu(src, (x as isize) + ((mvx >> 3) as isize), (y as isize) + ((mvy >> 3) as isize), bs + 1, bs + 1, &mut tmp, 9, plane, 0);
+        let mx = (mvx & 7) as u16;
+        let my = (mvy & 7) as u16;
+        let dstride = dst.stride[plane];
+        let doff = dst.offset[plane] + x + y * dstride;
+        for (j, dline) in dst.data[doff..].chunks_mut(dstride).take(bs).enumerate() {
+            for (i, el) in dline[..bs].iter_mut().enumerate() {
+                let a = u16::from(tmp[i + j * 9]);
+                let b = u16::from(tmp[i + 1 + j * 9]);
+                let c = u16::from(tmp[i + (j + 1) * 9]);
+                let d = u16::from(tmp[i + 1 + (j + 1) * 9]);
+                let top = a * (8 - mx) + b * mx;
+                let bot = c * (8 - mx) + d * mx;
+                *el = ((top * (8 - my) + bot * my + 32) >> 6) as u8;
+            }
+        }
+    }
+}
+
 pub fn vp31_loop_filter(data: &mut [u8], mut off: usize, step: usize, stride: usize,
                         len: usize, loop_str: i16) {
     for _ in 0..len {
@@ -358,8 +517,8 @@ pub fn vp31_loop_filter(data: &mut [u8], mut off: usize, step: usize, stride: us
             }
         }
         if diff != 0 {
-            data[off - step] = (b + diff).max(0).min(255) as u8;
-            data[off]        = (c - diff).max(0).min(255) as u8;
+            data[off - step] = (b + diff).clamp(0, 255) as u8;
+            data[off]        = (c - diff).clamp(0, 255) as u8;
         }
 
         off += stride;
@@ -0,0 +1,327 @@
+//! WebP still image decoding.
+//!
+//! Only lossy images are supported: VP8 keyframe with optional alpha plane that is stored either
+//! uncompressed or as VP8L image stream and may be additionally filtered.
+use nihav_core::codecs::*;
+use super::vpcommon::VP_YUVA420_FORMAT;
+use super::vp8::VP8Decoder;
+use super::vp8l::decode_image_stream;
+
+const VP8X_FLAG_ANIMATION: u8 = 0x02;
+
+const ALPHA_RAW: u8 = 0;
+const ALPHA_LOSSLESS: u8 = 1;
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+enum AlphaFilter {
+    None,
+    Horizontal,
+    Vertical,
+    Gradient,
+}
+
+fn read_u24le(src: &[u8]) -> usize {
+    usize::from(src[0]) | (usize::from(src[1]) << 8) | (usize::from(src[2]) << 16)
+}
+
+fn read_u32le(src: &[u8]) -> usize {
+    read_u24le(src) | (usize::from(src[3]) << 24)
+}
+
+fn unfilter_alpha(alpha: &mut [u8], width: usize, filter: AlphaFilter) {
+    if filter == AlphaFilter::None {
+        return;
+    }
+    // the first line is always predicted from the left and the first column from the top
+    for x in 1..width {
+        alpha[x] = alpha[x].wrapping_add(alpha[x - 1]);
+    }
+    for y in 1..alpha.len() / width {
+        let (top, cur) = alpha.split_at_mut(y * width);
+        let top = &top[(y - 1) * width..];
+        cur[0] = cur[0].wrapping_add(top[0]);
+        for x in 1..width {
+            let pred = match filter {
+                    AlphaFilter::Horizontal => cur[x - 1],
+                    AlphaFilter::Vertical   => top[x],
+                    _ => (i16::from(cur[x - 1]) + i16::from(top[x]) - i16::from(top[x - 1])).clamp(0, 255) as u8,
+                };
+            cur[x] = cur[x].wrapping_add(pred);
+        }
+    }
+}
+
+fn decode_alpha(src: &[u8], width: usize, height: usize) -> DecoderResult<Vec<u8>> {
+    validate!(!src.is_empty());
+    let method = src[0] & 3;
+    let filter = match (src[0] >> 2) & 3 {
+            0 => AlphaFilter::None,
+            1 => AlphaFilter::Horizontal,
+            2 => AlphaFilter::Vertical,
+            _ => AlphaFilter::Gradient,
+        };
+    // pre-processing flag only tells that alpha values were quantised so it can be ignored
+    let preprocessing = (src[0] >> 4) & 3;
+    let reserved = src[0] >> 6;
+    validate!(preprocessing <= 1 && reserved == 0);
+    let mut alpha = match method {
+            ALPHA_RAW => {
+                validate!(src.len() > width * height);
+                src[1..][..width * height].to_vec()
+            },
+            ALPHA_LOSSLESS => {
+                let pixels = decode_image_stream(&src[1..], width, height)?;
+                pixels.iter().map(|&pix| (pix >> 8) as u8).collect()
+            },
+            _ => return Err(DecoderError::InvalidData),
+        };
+    unfilter_alpha(&mut alpha, width, filter);
+    Ok(alpha)
+}
+
+/// Decodes still WebP image into a buffer in `VP_YUVA420_FORMAT`.
+///
+/// Images without alpha get an opaque alpha plane.
+/// Animated images and lossless images are not supported and `DecoderError::NotImplemented` is returned for them.
+pub fn decode_webp(src: &[u8], limits: &DecoderLimits) -> DecoderResult<NAVideoBufferRef<u8>> {
+    if !limits.check_packet_size(src.len()) {
+        return Err(DecoderError::LimitExceeded);
+    }
+    validate!(src.len() >= 12 && &src[..4] == b"RIFF" && &src[8..12] == b"WEBP");
+    let riff_size = read_u32le(&src[4..]);
+    validate!(riff_size >= 4 && riff_size <= src.len() - 8);
+
+    let mut chunks = &src[12..][..riff_size - 4];
+    let mut canvas_size = None;
+    let mut alpha_data = None;
+    let mut first = true;
+    let frame_data = loop {
+        validate!(chunks.len() >= 8);
+        let size = read_u32le(&chunks[4..]);
+        validate!(size <= chunks.len() - 8);
+        let chunk_id = &chunks[..4];
+        let data = &chunks[8..][..size];
+        // chunks are padded to even size
+        chunks = &chunks[(8 + size + (size & 1)).min(chunks.len())..];
+        match chunk_id {
+            b"VP8X" => {
+                validate!(first && size >= 10);
+                if (data[0] & VP8X_FLAG_ANIMATION) != 0 {
+                    return Err(DecoderError::NotImplemented);
+                }
+                canvas_size = Some((read_u24le(&data[4..]) + 1, read_u24le(&data[7..]) + 1));
+            },
+            // alpha chunk is recognised only in the extended format
+            b"ALPH" if canvas_size.is_some() && alpha_data.is_none() => {
+                alpha_data = Some(data);
+            },
+            b"VP8 " => break data,
+            b"VP8L" => return Err(DecoderError::NotImplemented),
+            _ => {},
+        }
+        first = false;
+    };
+    // lossy image is a single shown keyframe
+    validate!(frame_data.len() > 3 && (frame_data[0] & 0x01) == 0 && (frame_data[0] & 0x10) != 0);
+
+    // the intermediate frame does not outlive the call so it gets its own buffer pool
+    let mut supp = NADecoderSupport::new();
+    supp.limits = *limits;
+    supp.pool_u8.set_limits(*limits);
+    supp.pool_u8.set_dec_bufs(1);
+    let mut dec = VP8Decoder::new();
+    let (bufinfo, _) = dec.decode_frame(&mut supp, frame_data)?;
+    let sbuf = bufinfo.get_vbuf().ok_or(DecoderError::InvalidData)?;
+    let (width, height) = sbuf.get_dimensions(0);
+    if let Some(canvas_size) = canvas_size {
+        validate!(canvas_size == (width, height));
+    }
+    let alpha = if let Some(data) = alpha_data {
+            Some(decode_alpha(data, width, height)?)
+        } else {
+            None
+        };
+
+    let vinfo = NAVideoInfo::new(width, height, false, VP_YUVA420_FORMAT);
+    let mut dbuf = alloc_video_buffer_limited(vinfo, 4, limits)?.get_vbuf().ok_or(DecoderError::AllocError)?;
+    let dst = dbuf.as_mut().and_then(NASimpleVideoFrame::from_video_buf).ok_or(DecoderError::AllocError)?;
+    let sdata = sbuf.get_data();
+    for plane in 0..3 {
+        let sstride = sbuf.get_stride(plane);
+        for (dline, sline) in dst.data[dst.offset[plane]..].chunks_mut(dst.stride[plane]).zip(sdata[sbuf.get_offset(plane)..].chunks(sstride)).take(dst.height[plane]) {
+            dline[..dst.width[plane]].copy_from_slice(&sline[..dst.width[plane]]);
+        }
+    }
+    for (y, dline) in dst.data[dst.offset[3]..].chunks_mut(dst.stride[3]).take(height).enumerate() {
+        if let Some(ref alpha) = alpha {
+            dline[..width].copy_from_slice(&alpha[y * width..][..width]);
+        } else {
+            dline[..width].fill(255);
+        }
+    }
+    Ok(dbuf)
+}
+
+#[cfg(test)]
+mod test {
+    use nihav_core::codecs::*;
+    use nihav_codec_support::test::dec_video::*;
+    use super::*;
+    use crate::codecs::webpsynth::*;
+
+    fn asset_name(name: &str) -> String {
+        format!("{}/assets/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+    fn test_webp_size(name: &str, dims: (usize, usize), expected: &[[u32; 4]]) {
+        let data = std::fs::read(asset_name(name)).unwrap();
+        let buf = decode_webp(&data, &DecoderLimits::default()).unwrap_or_else(|err| panic!("{}: error {:?}", name, err));
+        assert_eq!(buf.get_dimensions(0), dims);
+        assert!(buf.get_info().get_format() == VP_YUVA420_FORMAT);
+        let hashes = frame_plane_md5(&NABufferType::Video(buf));
+        check_frame_hashes(name, &[hashes], &[expected.to_vec()]);
+    }
+    fn test_webp(name: &str, expected: &[[u32; 4]]) {
+        test_webp_size(name, (56, 40), expected);
+    }
+    #[test]
+    fn test_webp_simple() {
+        test_webp("webp_simple.webp", &[
+            [0xe3d8a9d0, 0xada8fdb3, 0xcfcbdf0c, 0xb9a8c54c],
+            [0xa4027ce0, 0x95af47f1, 0x281e51dd, 0xf2668188],
+            [0xd6506cae, 0xa8574f5c, 0x254ae19a, 0xb03ccd96],
+            [0x88e50298, 0x393c3e72, 0x9eff5bd2, 0x9b80d4cc]
+        ]);
+    }
+    #[test]
+    fn test_webp_alpha_raw() {
+        test_webp("webp_alpha_raw.webp", &[
+            [0x3ffb2b9b, 0x1b1f5177, 0xd1547023, 0x2993758a],
+            [0x6710056d, 0xe3d9329f, 0x7d858d97, 0xb3be8512],
+            [0xac6ffa89, 0xd45e71a3, 0x2c2f00ff, 0x1438bee5],
+            [0xf769ee1c, 0x7d364a95, 0x8345c690, 0x0c8553ba]
+        ]);
+    }
+    #[test]
+    fn test_webp_alpha_lossless() {
+        test_webp("webp_alpha_lossless.webp", &[
+            [0xa0aa3b3a, 0x84f035ff, 0xecd07954, 0xe7bfa0ea],
+            [0x68ac9579, 0x1b1dcd90, 0x0ae2ec6b, 0x185d0848],
+            [0xaf269436, 0x26d9fb36, 0xdbd702b5, 0x049d56c7],
+            [0x0c24465d, 0xe8aa8cc6, 0xc5517140, 0x96bf34de]
+        ]);
+    }
+    #[test]
+    fn test_webp_alpha_palette() {
+        test_webp("webp_alpha_palette.webp", &[
+            [0x21879c17, 0x29221dd9, 0x36bed6ba, 0xae80b2c3],
+            [0xc32bc6ec, 0xf142fe2f, 0x25d6c3d5, 0x6917ff08],
+            [0x6510edf2, 0x79cd00e2, 0x7410f36a, 0xb80ed730],
+            [0xe56c6601, 0x6ecf4e8d, 0x6ac8b829, 0x5e000dbb]
+        ]);
+    }
+    // The following images were produced by libwebp encoder (lossy with lossless-coded alpha)
+    // and the hashes were calculated from YUVA planes output by libwebp decoder.
+    #[test]
+    fn test_webp_libwebp_alpha_raw() {
+        test_webp_size("libwebp_alpha_raw.webp", (33, 17), &[
+            [0x3a16efd0, 0xd37a743d, 0x8697aef8, 0x79880abe],
+            [0x8439d278, 0xab44f0bf, 0xea85b533, 0x58e36869],
+            [0x42834581, 0xba43e26f, 0x15392c61, 0x6b9da3a2],
+            [0xa825eeb8, 0x8acbe449, 0x58d66df1, 0xbdc3837d]
+        ]);
+    }
+    #[test]
+    fn test_webp_libwebp_alpha_horizontal() {
+        test_webp_size("libwebp_alpha_horizontal.webp", (100, 77), &[
+            [0x2e287dd9, 0x9b91441e, 0x36b3397b, 0x3637f41d],
+            [0xf8e65509, 0x6e882662, 0xd37e99d9, 0x32c031e9],
+            [0x497a7f36, 0xb7db9717, 0x673293c5, 0xa3ddefb0],
+            [0xad42155f, 0xc31e2165, 0x9540cd91, 0xecfbb65e]
+        ]);
+    }
+    #[test]
+    fn test_webp_libwebp_alpha_vertical() {
+        test_webp_size("libwebp_alpha_vertical.webp", (56, 40), &[
+            [0xb3abae75, 0x1c13ea62, 0xe9b7ebee, 0x3d6f9d23],
+            [0xe745bf30, 0x4147b1d1, 0x39204119, 0x31496699],
+            [0xf074f905, 0x40a1370a, 0x7188d6a9, 0x96bcef4f],
+            [0xd9ce8c01, 0xf1a02d14, 0x880ee8f0, 0x2a679d33]
+        ]);
+    }
+    #[test]
+    fn test_webp_libwebp_alpha_gradient() {
+        test_webp_size("libwebp_alpha_gradient.webp", (33, 17), &[
+            [0x68875c8d, 0x0d1f0972, 0x68708954, 0x5cfe3207],
+            [0x5271ee3f, 0xceef9ec7, 0x615162ab, 0xdfa72a36],
+            [0xcb980a40, 0xa5ee85a6, 0x8579a385, 0xdc337df4],
+            [0x700e3084, 0x1973bf32, 0x4a9cc28b, 0xed7fe0e7]
+        ]);
+    }
+    #[test]
+    fn test_webp_libwebp_alpha_levels() {
+        test_webp_size("libwebp_alpha_levels.webp", (33, 17), &[
+            [0x68875c8d, 0x0d1f0972, 0x68708954, 0x5cfe3207],
+            [0x030be80e, 0x42a2fb36, 0x5e80f7a3, 0x29fc2a7a],
+            [0xa74036c1, 0xd838970f, 0xe62a2bbf, 0xf664fc3c],
+            [0x3ad81e20, 0x9e55c3e5, 0x40cdc281, 0x86dcca26]
+        ]);
+    }
+    #[test]
+    fn test_webp_alpha() {
+        for (name, cfg) in TEST_IMAGES.iter() {
+            let (data, alpha) = gen_image(cfg);
+            let buf = decode_webp(&data, &DecoderLimits::default()).unwrap();
+            let (w, h) = buf.get_dimensions(3);
+            let stride = buf.get_stride(3);
+            for (y, line) in buf.get_data()[buf.get_offset(3)..].chunks(stride).take(h).enumerate() {
+                if let Some(ref alpha) = alpha {
+                    assert!(line[..w] == alpha[y * w..][..w], "{}: alpha line {} differs", name, y);
+                } else {
+                    assert!(line[..w].iter().all(|&a| a == 255), "{}: alpha line {} is not opaque", name, y);
+                }
+            }
+        }
+    }
+    #[test]
+    fn test_webp_errors() {
+        let limits = DecoderLimits::default();
+        let simple = std::fs::read(asset_name("webp_simple.webp")).unwrap();
+        let alpha = std::fs::read(asset_name("webp_alpha_lossless.webp")).unwrap();
+        assert_eq!(decode_webp(&simple[..simple.len() - 10], &limits).err(), Some(DecoderError::InvalidData));
+        assert_eq!(decode_webp(&alpha[..alpha.len() / 2], &limits).err(), Some(DecoderError::InvalidData));
+        let mut bad = simple.clone();
+        bad[8..12].copy_from_slice(b"WEBQ");
+        assert_eq!(decode_webp(&bad, &limits).err(), Some(DecoderError::InvalidData));
+        // lossless image
+        let mut bad = simple.clone();
+        bad[12..16].copy_from_slice(b"VP8L");
+        assert_eq!(decode_webp(&bad, &limits).err(), Some(DecoderError::NotImplemented));
+        // animation
+        let mut bad = alpha.clone();
+        bad[20] |= 0x02;
+        assert_eq!(decode_webp(&bad, &limits).err(), Some(DecoderError::NotImplemented));
+        // canvas size differs from the frame size
+        let mut bad = alpha.clone();
+        bad[24] += 1;
+        assert_eq!(decode_webp(&bad, &limits).err(), Some(DecoderError::InvalidData));
+        // reserved bits in alpha header
+        let mut bad = alpha.clone();
+        bad[38] |= 0x40;
+        assert_eq!(decode_webp(&bad, &limits).err(), Some(DecoderError::InvalidData));
+        // not a keyframe
+        let mut bad = simple.clone();
+        bad[20] |= 0x01;
+        assert_eq!(decode_webp(&bad, &limits).err(), Some(DecoderError::InvalidData));
+
+        let small = DecoderLimits { max_width: 32, ..DecoderLimits::default() };
+        assert_eq!(decode_webp(&simple, &small).err(), Some(DecoderError::LimitExceeded));
+        let small = DecoderLimits { max_packet_size: 100, ..DecoderLimits::default() };
+        assert_eq!(decode_webp(&simple, &small).err(), Some(DecoderError::LimitExceeded));
+
+        for (i, el) in alpha.iter().enumerate().skip(12).step_by(7) {
+            let mut corrupted = alpha.clone();
+            corrupted[i] = el.wrapping_mul(37).wrapping_add(11);
+            let _ = decode_webp(&corrupted, &limits);
+        }
+    }
+}
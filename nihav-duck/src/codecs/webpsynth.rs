@@ -0,0 +1,626 @@
+//! Synthetic WebP image generator for the decoder tests.
+//!
+//! Images consist of a keyframe produced by the VP8 stream generator and a synthetic alpha plane
+//! that is filtered and stored either uncompressed or as VP8L image stream. The VP8L encoder
+//! below is very simple but it can use all coding tools: transforms, colour cache,
+//! backward references and several groups of prefix codes.
+use nihav_core::io::bitwriter::*;
+use super::testsynth::*;
+use super::vp8l::{CODE_TO_PLANE, color_cache_hash, color_delta, predict, subsample_size};
+use super::vp8synth::{VP8Synth, VP8SynthConfig};
+
+const NUM_LITERALS: usize = 256;
+const NUM_LENGTH_CODES: usize = 24;
+const NUM_DIST_CODES: usize = 40;
+const MAX_CODE_LEN: u8 = 15;
+const MAX_CL_CODE_LEN: u8 = 7;
+const MAX_COPY_LEN: usize = 4096;
+const CODE_LEN_ORDER: [usize; 19] = [17, 18, 0, 1, 2, 3, 4, 5, 16, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+
+/// Prefix code built for the symbol statistics.
+struct Code {
+    lens:   Vec<u8>,
+    codes:  Vec<u32>,
+    single: bool,
+}
+
+impl Code {
+    fn new(hist: &[u32], max_len: u8) -> Self {
+        let lens = build_lengths(hist, max_len);
+        let nsyms = lens.iter().filter(|&&len| len > 0).count();
+        let codes = canonical_codes(&lens);
+        Self { lens, codes, single: nsyms <= 1 }
+    }
+    fn write_sym(&self, bw: &mut BitWriter, sym: usize) {
+        if self.single {
+            return;
+        }
+        assert!(self.lens[sym] > 0);
+        // codes are stored starting from the most significant bit
+        for i in (0..self.lens[sym]).rev() {
+            bw.write(self.codes[sym] >> i, 1);
+        }
+    }
+}
+
+fn build_lengths(hist: &[u32], max_len: u8) -> Vec<u8> {
+    let mut lens = vec![0u8; hist.len()];
+    let used: Vec<usize> = (0..hist.len()).filter(|&sym| hist[sym] > 0).collect();
+    if used.len() == 1 {
+        lens[used[0]] = 1;
+    }
+    if used.len() < 2 {
+        return lens;
+    }
+    let mut weights: Vec<u32> = used.iter().map(|&sym| hist[sym]).collect();
+    loop {
+        // plain Huffman tree construction: nodes are (weight, parent)
+        let mut nodes: Vec<(u32, usize)> = weights.iter().map(|&w| (w, 0)).collect();
+        let mut active: Vec<usize> = (0..nodes.len()).collect();
+        while active.len() > 1 {
+            active.sort_by_key(|&idx| (nodes[idx].0, idx));
+            let (a, b) = (active[0], active[1]);
+            let parent = nodes.len();
+            nodes.push((nodes[a].0 + nodes[b].0, 0));
+            nodes[a].1 = parent;
+            nodes[b].1 = parent;
+            active.drain(..2);
+            active.push(parent);
+        }
+        let root = nodes.len() - 1;
+        let mut fits = true;
+        for (i, &sym) in used.iter().enumerate() {
+            let mut depth = 0;
+            let mut idx = i;
+            while idx != root {
+                idx = nodes[idx].1;
+                depth += 1;
+            }
+            fits &= depth <= max_len;
+            lens[sym] = depth;
+        }
+        if fits {
+            return lens;
+        }
+        for w in weights.iter_mut() {
+            *w = (*w >> 1) | 1;
+        }
+    }
+}
+
+fn canonical_codes(lens: &[u8]) -> Vec<u32> {
+    let mut counts = [0u32; MAX_CODE_LEN as usize + 1];
+    for &len in lens.iter() {
+        counts[usize::from(len)] += 1;
+    }
+    counts[0] = 0;
+    let mut next_code = [0u32; MAX_CODE_LEN as usize + 1];
+    for len in 1..next_code.len() {
+        next_code[len] = (next_code[len - 1] + counts[len - 1]) << 1;
+    }
+    lens.iter().map(|&len| {
+            if len == 0 {
+                0
+            } else {
+                let code = next_code[usize::from(len)];
+                next_code[usize::from(len)] += 1;
+                code
+            }
+        }).collect()
+}
+
+/// Writes prefix code description and returns the code to use.
+fn write_prefix_code(bw: &mut BitWriter, hist: &[u32], max_len: u8) -> Code {
+    let code = Code::new(hist, max_len);
+    let used: Vec<usize> = (0..hist.len()).filter(|&sym| code.lens[sym] > 0).collect();
+    if used.len() <= 2 && used.iter().all(|&sym| sym < 256) {
+        bw.write(1, 1);
+        let first = used.first().copied().unwrap_or(0);
+        bw.write(used.len().max(1) as u32 - 1, 1);
+        if first < 2 {
+            bw.write(0, 1);
+            bw.write(first as u32, 1);
+        } else {
+            bw.write(1, 1);
+            bw.write(first as u32, 8);
+        }
+        if used.len() == 2 {
+            bw.write(used[1] as u32, 8);
+        }
+        return code;
+    }
+    bw.write(0, 1);
+
+    // run-length coded code lengths in form (token, extra bits value, number of extra bits)
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < code.lens.len() {
+        let len = code.lens[pos];
+        let mut run = code.lens[pos..].iter().take_while(|&&l| l == len).count();
+        pos += run;
+        if len == 0 {
+            while run >= 11 {
+                let cnt = run.min(138);
+                tokens.push((18, cnt - 11, 7));
+                run -= cnt;
+            }
+            if run >= 3 {
+                tokens.push((17, run - 3, 3));
+                run = 0;
+            }
+        } else {
+            tokens.push((usize::from(len), 0, 0));
+            run -= 1;
+            while run >= 3 {
+                let cnt = run.min(6);
+                tokens.push((16, cnt - 3, 2));
+                run -= cnt;
+            }
+        }
+        for _ in 0..run {
+            tokens.push((usize::from(len), 0, 0));
+        }
+    }
+    // trailing zero lengths may be omitted
+    let mut ntokens = tokens.len();
+    while ntokens > 2 && matches!(tokens[ntokens - 1].0, 0 | 17 | 18) {
+        ntokens -= 1;
+    }
+    let trimmed = ntokens < tokens.len();
+    tokens.truncate(ntokens);
+
+    let mut cl_hist = [0u32; 19];
+    for &(tok, _, _) in tokens.iter() {
+        cl_hist[tok] += 1;
+    }
+    let cl_code = Code::new(&cl_hist, MAX_CL_CODE_LEN);
+    let num_codes = CODE_LEN_ORDER.iter().rposition(|&idx| cl_code.lens[idx] > 0).unwrap_or(0).max(3) + 1;
+    bw.write(num_codes as u32 - 4, 4);
+    for &idx in CODE_LEN_ORDER[..num_codes].iter() {
+        bw.write(u32::from(cl_code.lens[idx]), 3);
+    }
+    if trimmed {
+        bw.write(1, 1);
+        let mut nbits_code = 0u8;
+        while (ntokens - 2) >> (2 + 2 * nbits_code) != 0 {
+            nbits_code += 1;
+        }
+        bw.write(u32::from(nbits_code), 3);
+        bw.write(ntokens as u32 - 2, 2 + 2 * nbits_code);
+    } else {
+        bw.write(0, 1);
+    }
+    for &(tok, val, bits) in tokens.iter() {
+        cl_code.write_sym(bw, tok);
+        bw.write(val as u32, bits);
+    }
+    code
+}
+
+/// Converts length or distance value into a symbol and extra bits.
+fn lz_value_to_sym(val: usize) -> (usize, u32, u8) {
+    let val = val - 1;
+    if val < 4 {
+        return (val, 0, 0);
+    }
+    let hbit = (usize::BITS - 1 - val.leading_zeros()) as usize;
+    let second = (val >> (hbit - 1)) & 1;
+    let extra_bits = hbit - 1;
+    (2 * hbit + second, (val & ((1 << extra_bits) - 1)) as u32, extra_bits as u8)
+}
+
+fn distance_to_plane_code(width: usize, dist: usize) -> usize {
+    for (i, &dist_code) in CODE_TO_PLANE.iter().enumerate() {
+        let yoff = usize::from(dist_code >> 4);
+        let xoff = 8 - isize::from(dist_code & 0xF);
+        if ((yoff * width) as isize + xoff).max(1) as usize == dist {
+            return i + 1;
+        }
+    }
+    dist + CODE_TO_PLANE.len()
+}
+
+enum Token {
+    Literal(u32),
+    Cache(usize),
+    Copy(usize, usize),
+}
+
+fn write_entropy_image(bw: &mut BitWriter, pixels: &[u32], width: usize, height: usize, cfg: &VP8LConfig, is_main: bool, rng: &mut RNG) {
+    let cache_bits = if is_main { cfg.cache_bits } else { 0 };
+    if cache_bits > 0 {
+        bw.write(1, 1);
+        bw.write(u32::from(cache_bits), 4);
+    } else {
+        bw.write(0, 1);
+    }
+    let meta_bits = if is_main { cfg.meta_bits } else { 0 };
+    let mut meta_w = 1;
+    let mut meta_image = vec![0];
+    if is_main {
+        if meta_bits > 0 {
+            bw.write(1, 1);
+            bw.write(u32::from(meta_bits - 2), 3);
+            meta_w = subsample_size(width, meta_bits);
+            let meta_h = subsample_size(height, meta_bits);
+            meta_image = (0..meta_w * meta_h).map(|_| rng.range(3) as usize).collect();
+            let meta_pixels: Vec<u32> = meta_image.iter().map(|&grp| (grp as u32) << 8).collect();
+            write_entropy_image(bw, &meta_pixels, meta_w, meta_h, cfg, false, rng);
+        } else {
+            bw.write(0, 1);
+        }
+    }
+    let group_at = |pos: usize| if meta_bits > 0 {
+            meta_image[((pos % width) >> meta_bits) + ((pos / width) >> meta_bits) * meta_w]
+        } else {
+            0
+        };
+
+    let backrefs = cfg.backrefs && is_main;
+    let dists = [1, 2, 3, 4, 7, width - 1, width, width + 1, width * 2, width * 3 + 5, (width * 5).saturating_sub(9), 150];
+    let mut cache = vec![0u32; if cache_bits > 0 { 1 << cache_bits } else { 0 }];
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < pixels.len() {
+        let mut best = (0, 0);
+        if backrefs {
+            for &dist in dists.iter().filter(|&&dist| dist > 0 && dist <= pos) {
+                let len = (pos..pixels.len()).take(MAX_COPY_LEN).take_while(|&i| pixels[i] == pixels[i - dist]).count();
+                if len > best.0 {
+                    best = (len, dist);
+                }
+            }
+        }
+        let start = pos;
+        if best.0 >= 3 {
+            tokens.push((group_at(pos), Token::Copy(best.0, distance_to_plane_code(width, best.1))));
+            pos += best.0;
+        } else {
+            let pix = pixels[pos];
+            if cache_bits > 0 && cache[color_cache_hash(pix, cache_bits)] == pix {
+                tokens.push((group_at(pos), Token::Cache(color_cache_hash(pix, cache_bits))));
+            } else {
+                tokens.push((group_at(pos), Token::Literal(pix)));
+            }
+            pos += 1;
+        }
+        if cache_bits > 0 {
+            for &pix in pixels[start..pos].iter() {
+                cache[color_cache_hash(pix, cache_bits)] = pix;
+            }
+        }
+    }
+
+    let num_groups = meta_image.iter().fold(0, |acc, &grp| acc.max(grp)) + 1;
+    let green_size = NUM_LITERALS + NUM_LENGTH_CODES + cache.len();
+    let mut hists = vec![[vec![0u32; green_size], vec![0; NUM_LITERALS], vec![0; NUM_LITERALS], vec![0; NUM_LITERALS], vec![0; NUM_DIST_CODES]]; num_groups];
+    for (grp, token) in tokens.iter() {
+        let hist = &mut hists[*grp];
+        match *token {
+            Token::Literal(pix) => {
+                hist[0][((pix >> 8) & 0xFF) as usize] += 1;
+                hist[1][((pix >> 16) & 0xFF) as usize] += 1;
+                hist[2][(pix & 0xFF) as usize] += 1;
+                hist[3][(pix >> 24) as usize] += 1;
+            },
+            Token::Cache(idx) => hist[0][NUM_LITERALS + NUM_LENGTH_CODES + idx] += 1,
+            Token::Copy(len, dist) => {
+                hist[0][NUM_LITERALS + lz_value_to_sym(len).0] += 1;
+                hist[4][lz_value_to_sym(dist).0] += 1;
+            },
+        }
+    }
+    let codes: Vec<Vec<Code>> = hists.iter().map(|hist|
+            hist.iter().map(|h| write_prefix_code(bw, h, MAX_CODE_LEN)).collect()
+        ).collect();
+    for (grp, token) in tokens.iter() {
+        let code = &codes[*grp];
+        match *token {
+            Token::Literal(pix) => {
+                code[0].write_sym(bw, ((pix >> 8) & 0xFF) as usize);
+                code[1].write_sym(bw, ((pix >> 16) & 0xFF) as usize);
+                code[2].write_sym(bw, (pix & 0xFF) as usize);
+                code[3].write_sym(bw, (pix >> 24) as usize);
+            },
+            Token::Cache(idx) => code[0].write_sym(bw, NUM_LITERALS + NUM_LENGTH_CODES + idx),
+            Token::Copy(len, dist) => {
+                let (sym, val, bits) = lz_value_to_sym(len);
+                code[0].write_sym(bw, NUM_LITERALS + sym);
+                bw.write(val, bits);
+                let (sym, val, bits) = lz_value_to_sym(dist);
+                code[4].write_sym(bw, sym);
+                bw.write(val, bits);
+            },
+        }
+    }
+}
+
+fn sub_pixels(a: u32, b: u32) -> u32 {
+    ((a | 0x00FF00FF).wrapping_sub(b & 0xFF00FF00) & 0xFF00FF00) | ((a | 0xFF00FF00).wrapping_sub(b & 0x00FF00FF) & 0x00FF00FF)
+}
+
+/// VP8L coding tools to use.
+#[derive(Clone,Copy,Default)]
+pub struct VP8LConfig {
+    /// Use colour indexing transform (the image should not have more than 256 colours).
+    pub palette:        bool,
+    pub subtract_green: bool,
+    pub predictor:      bool,
+    pub cross_color:    bool,
+    pub cache_bits:     u8,
+    /// Use several groups of prefix codes for the tiles of this size.
+    pub meta_bits:      u8,
+    pub backrefs:       bool,
+}
+
+/// Encodes ARGB image into VP8L image stream (without VP8L header).
+pub fn encode_vp8l(src: &[u32], width: usize, height: usize, cfg: &VP8LConfig, rng: &mut RNG) -> Vec<u8> {
+    let mut bw = BitWriter::new(Vec::new(), BitWriterMode::LE);
+    let mut pixels = src.to_vec();
+    let mut cur_w = width;
+    let sub_cfg = VP8LConfig::default();
+    if cfg.palette {
+        let mut pal = pixels.clone();
+        pal.sort_unstable();
+        pal.dedup();
+        assert!(pal.len() <= 256);
+        bw.write(1, 1);
+        bw.write(3, 2);
+        bw.write(pal.len() as u32 - 1, 8);
+        let mut deltas = pal.clone();
+        for i in 1..pal.len() {
+            deltas[i] = sub_pixels(pal[i], pal[i - 1]);
+        }
+        write_entropy_image(&mut bw, &deltas, pal.len(), 1, &sub_cfg, false, rng);
+        let bits = match pal.len() {
+                0..=2  => 3,
+                3..=4  => 2,
+                5..=16 => 1,
+                _      => 0,
+            };
+        let idx_bits = 8 >> bits;
+        cur_w = subsample_size(width, bits);
+        let mut packed = vec![0xFF000000; cur_w * height];
+        for (y, line) in pixels.chunks_exact(width).enumerate() {
+            for (x, pix) in line.iter().enumerate() {
+                let idx = pal.binary_search(pix).unwrap() as u32;
+                packed[(x >> bits) + y * cur_w] |= idx << (8 + (x & ((1 << bits) - 1)) * idx_bits);
+            }
+        }
+        pixels = packed;
+    }
+    if cfg.subtract_green {
+        bw.write(1, 1);
+        bw.write(2, 2);
+        for pix in pixels.iter_mut() {
+            let green = (*pix >> 8) & 0xFF;
+            *pix = sub_pixels(*pix, (green << 16) | green);
+        }
+    }
+    if cfg.predictor {
+        let bits = 2 + rng.range(3) as u8;
+        bw.write(1, 1);
+        bw.write(0, 2);
+        bw.write(u32::from(bits - 2), 3);
+        let tiles_w = subsample_size(cur_w, bits);
+        let modes: Vec<u32> = (0..tiles_w * subsample_size(height, bits)).map(|_| rng.range(14)).collect();
+        let mode_pixels: Vec<u32> = modes.iter().map(|&mode| 0xFF000000 | (mode << 8)).collect();
+        write_entropy_image(&mut bw, &mode_pixels, tiles_w, subsample_size(height, bits), &sub_cfg, false, rng);
+        let orig = pixels.clone();
+        for (i, pix) in pixels.iter_mut().enumerate() {
+            let (x, y) = (i % cur_w, i / cur_w);
+            let pred = match (x, y) {
+                    (0, 0) => 0xFF000000,
+                    (_, 0) => orig[i - 1],
+                    (0, _) => orig[i - cur_w],
+                    _ => {
+                        let tr = if x + 1 < cur_w { orig[i - cur_w + 1] } else { orig[i - x] };
+                        predict(modes[(x >> bits) + (y >> bits) * tiles_w], orig[i - 1], orig[i - cur_w], orig[i - cur_w - 1], tr)
+                    },
+                };
+            *pix = sub_pixels(*pix, pred);
+        }
+    }
+    if cfg.cross_color {
+        let bits = 2 + rng.range(3) as u8;
+        bw.write(1, 1);
+        bw.write(1, 2);
+        bw.write(u32::from(bits - 2), 3);
+        let tiles_w = subsample_size(cur_w, bits);
+        let mults: Vec<u32> = (0..tiles_w * subsample_size(height, bits)).map(|_| 0xFF000000 | (rng.next() & 0xFFFFFF)).collect();
+        write_entropy_image(&mut bw, &mults, tiles_w, subsample_size(height, bits), &sub_cfg, false, rng);
+        for (i, pix) in pixels.iter_mut().enumerate() {
+            let mult = mults[((i % cur_w) >> bits) + ((i / cur_w) >> bits) * tiles_w];
+            let green = *pix >> 8;
+            let red = *pix >> 16;
+            let new_red  = red.wrapping_sub(color_delta(mult, green)) & 0xFF;
+            let new_blue = pix.wrapping_sub(color_delta(mult >> 8, green)).wrapping_sub(color_delta(mult >> 16, red)) & 0xFF;
+            *pix = (*pix & 0xFF00FF00) | (new_red << 16) | new_blue;
+        }
+    }
+    bw.write(0, 1);
+    write_entropy_image(&mut bw, &pixels, cur_w, height, cfg, true, rng);
+    bw.end()
+}
+
+/// Alpha plane coding method.
+#[derive(Clone,Copy)]
+pub enum AlphaCoding {
+    None,
+    Raw,
+    Lossless(VP8LConfig),
+}
+
+/// Image parameters for the generator.
+#[derive(Clone,Copy)]
+pub struct WebPSynthConfig {
+    pub alpha:          AlphaCoding,
+    /// Alpha filter (0 - none, 1 - horizontal, 2 - vertical, 3 - gradient).
+    pub filter:         u8,
+    /// Limit the number of different alpha values.
+    pub alpha_levels:   Option<u8>,
+    /// Use extended format even without alpha and add metadata chunks.
+    pub extended:       bool,
+    pub seed:           u32,
+}
+
+fn gen_alpha(rng: &mut RNG, width: usize, height: usize, levels: Option<u8>) -> Vec<u8> {
+    let (cx, cy) = (rng.range(width as u32) as isize, rng.range(height as u32) as isize);
+    let radius = 6 + rng.range(10) as isize;
+    let mut alpha = Vec::with_capacity(width * height);
+    for y in 0..height as isize {
+        for x in 0..width as isize {
+            let val = if (x - cx) * (x - cx) + (y - cy) * (y - cy) < radius * radius {
+                    255
+                } else if x < (width as isize) / 2 {
+                    (x * 9 + y * 3) as u32 & 0xFF
+                } else {
+                    rng.range(256)
+                };
+            alpha.push(val as u8);
+        }
+    }
+    if let Some(levels) = levels {
+        let step = 256 / u32::from(levels);
+        for el in alpha.iter_mut() {
+            *el = ((u32::from(*el) / step).min(u32::from(levels) - 1) * 255 / (u32::from(levels) - 1).max(1)) as u8;
+        }
+    }
+    alpha
+}
+
+fn filter_alpha(alpha: &[u8], width: usize, filter: u8) -> Vec<u8> {
+    let mut dst = alpha.to_vec();
+    if filter == 0 {
+        return dst;
+    }
+    for (i, el) in dst.iter_mut().enumerate() {
+        let (x, y) = (i % width, i / width);
+        let pred = match (x, y, filter) {
+                (0, 0, _) => 0,
+                (_, 0, _) | (_, _, 1) if x > 0 => alpha[i - 1],
+                (0, _, _) | (_, _, 2) => alpha[i - width],
+                _ => (i16::from(alpha[i - 1]) + i16::from(alpha[i - width]) - i16::from(alpha[i - width - 1])).clamp(0, 255) as u8,
+            };
+        *el = el.wrapping_sub(pred);
+    }
+    dst
+}
+
+fn put_chunk(dst: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+    dst.extend_from_slice(id);
+    dst.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    dst.extend_from_slice(data);
+    if (data.len() & 1) != 0 {
+        dst.push(0);
+    }
+}
+
+/// Generates WebP image and returns it along with the original alpha plane.
+pub fn gen_image(cfg: &WebPSynthConfig) -> (Vec<u8>, Option<Vec<u8>>) {
+    let (width, height) = (56, 40);
+    let mut synth = VP8Synth::new(VP8SynthConfig {
+            version:        0,
+            width, height,
+            nframes:        1,
+            key_int:        1,
+            segmentation:   cfg.extended,
+            lf_deltas:      false,
+            partitions:     false,
+            split:          false,
+            golden:         false,
+            hidden:         false,
+            seed:           cfg.seed,
+        });
+    let frame = synth.gen_frame();
+    let mut rng = RNG::new(cfg.seed);
+
+    let mut alpha = None;
+    let mut alpha_chunk = Vec::new();
+    if !matches!(cfg.alpha, AlphaCoding::None) {
+        let plane = gen_alpha(&mut rng, width, height, cfg.alpha_levels);
+        let filtered = filter_alpha(&plane, width, cfg.filter);
+        match cfg.alpha {
+            AlphaCoding::Raw => {
+                alpha_chunk.push(cfg.filter << 2);
+                alpha_chunk.extend_from_slice(&filtered);
+            },
+            AlphaCoding::Lossless(ref lcfg) => {
+                alpha_chunk.push((cfg.filter << 2) | 1 | (u8::from(cfg.alpha_levels.is_some()) << 4));
+                let pixels: Vec<u32> = filtered.iter().map(|&a| u32::from(a) << 8).collect();
+                alpha_chunk.extend_from_slice(&encode_vp8l(&pixels, width, height, lcfg, &mut rng));
+            },
+            AlphaCoding::None => unreachable!(),
+        }
+        alpha = Some(plane);
+    }
+
+    let mut data = Vec::new();
+    if cfg.extended || alpha.is_some() {
+        let mut flags = 0;
+        if alpha.is_some() {
+            flags |= 0x10;
+        }
+        if cfg.extended {
+            // ICC profile and EXIF metadata
+            flags |= 0x20 | 0x08;
+        }
+        let mut vp8x = vec![flags, 0, 0, 0];
+        vp8x.extend_from_slice(&((width - 1) as u32).to_le_bytes()[..3]);
+        vp8x.extend_from_slice(&((height - 1) as u32).to_le_bytes()[..3]);
+        put_chunk(&mut data, b"VP8X", &vp8x);
+        if cfg.extended {
+            put_chunk(&mut data, b"ICCP", b"not a real profile");
+        }
+        if alpha.is_some() {
+            put_chunk(&mut data, b"ALPH", &alpha_chunk);
+        }
+    }
+    put_chunk(&mut data, b"VP8 ", &frame);
+    if cfg.extended {
+        put_chunk(&mut data, b"EXIF", b"odd");
+    }
+
+    let mut file = Vec::with_capacity(data.len() + 12);
+    file.extend_from_slice(b"RIFF");
+    file.extend_from_slice(&(data.len() as u32 + 4).to_le_bytes());
+    file.extend_from_slice(b"WEBP");
+    file.extend_from_slice(&data);
+    (file, alpha)
+}
+
+const fn synth_cfg(alpha: AlphaCoding, filter: u8, seed: u32) -> WebPSynthConfig {
+    WebPSynthConfig {
+        alpha, filter, seed,
+        alpha_levels:   None,
+        extended:       false,
+    }
+}
+
+const LOSSLESS_CFG: VP8LConfig = VP8LConfig {
+    palette:        false,
+    subtract_green: true,
+    predictor:      true,
+    cross_color:    true,
+    cache_bits:     6,
+    meta_bits:      3,
+    backrefs:       true,
+};
+
+const PALETTE_CFG: VP8LConfig = VP8LConfig {
+    palette:        true,
+    subtract_green: false,
+    predictor:      false,
+    cross_color:    false,
+    cache_bits:     3,
+    meta_bits:      0,
+    backrefs:       true,
+};
+
+/// Test fixtures stored in `assets/` and the parameters they are generated from.
+pub const TEST_IMAGES: [(&str, WebPSynthConfig); 4] = [
+    ("webp_simple.webp",            synth_cfg(AlphaCoding::None, 0, 1)),
+    ("webp_alpha_raw.webp",         WebPSynthConfig { extended: true, ..synth_cfg(AlphaCoding::Raw, 1, 2) }),
+    ("webp_alpha_lossless.webp",    synth_cfg(AlphaCoding::Lossless(LOSSLESS_CFG), 3, 5)),
+    ("webp_alpha_palette.webp",     WebPSynthConfig { alpha_levels: Some(12), ..synth_cfg(AlphaCoding::Lossless(PALETTE_CFG), 2, 6) }),
+];